@@ -80,6 +80,8 @@ pub enum SetErrorType {
     ForbiddenToSend,
     #[serde(rename = "cannotUnsend")]
     CannotUnsend,
+    #[serde(rename = "alreadyExists")]
+    AlreadyExists,
 }
 
 impl SetErrorType {
@@ -107,6 +109,7 @@ impl SetErrorType {
             SetErrorType::ForbiddenMailFrom => "forbiddenMailFrom",
             SetErrorType::ForbiddenToSend => "forbiddenToSend",
             SetErrorType::CannotUnsend => "cannotUnsend",
+            SetErrorType::AlreadyExists => "alreadyExists",
         }
     }
 }
@@ -23,12 +23,13 @@
 
 use super::Object;
 use crate::{
+    error::method::MethodError,
     request::changes::{ChangesRequest, ChangesResponse},
     types::json_pointer::JSONPointerEval,
     types::state::JMAPState,
 };
 use store::{
-    core::{collection::Collection, error::StoreError},
+    core::collection::Collection,
     log::changes::{Change, Query},
     AccountId, JMAPStore, Store,
 };
@@ -81,7 +82,12 @@ where
         let (items_sent, mut changelog) = match &request.since_state {
             JMAPState::Initial => {
                 let changelog = self
-                    .get_changes(request.account_id.into(), collection, Query::All)?
+                    .get_changes(
+                        request.account_id.into(),
+                        collection,
+                        Query::All,
+                        max_changes,
+                    )?
                     .unwrap();
                 if changelog.changes.is_empty() && changelog.from_change_id == 0 {
                     return Ok(ChangesResponse::empty(request.account_id));
@@ -95,12 +101,9 @@ where
                     request.account_id.into(),
                     collection,
                     Query::Since(*change_id),
+                    max_changes,
                 )?
-                .ok_or_else(|| {
-                    StoreError::InvalidArguments(
-                        "The specified stateId does could not be found.".to_string(),
-                    )
-                })?,
+                .ok_or(MethodError::CannotCalculateChanges)?,
             ),
             JMAPState::Intermediate(intermediate_state) => {
                 let mut changelog = self
@@ -108,12 +111,9 @@ where
                         request.account_id.into(),
                         collection,
                         Query::RangeInclusive(intermediate_state.from_id, intermediate_state.to_id),
+                        0,
                     )?
-                    .ok_or_else(|| {
-                        StoreError::InvalidArguments(
-                            "The specified stateId does could not be found.".to_string(),
-                        )
-                    })?;
+                    .ok_or(MethodError::CannotCalculateChanges)?;
                 if intermediate_state.items_sent >= changelog.changes.len() {
                     (
                         0,
@@ -121,12 +121,9 @@ where
                             request.account_id.into(),
                             collection,
                             Query::Since(intermediate_state.to_id),
+                            max_changes,
                         )?
-                        .ok_or_else(|| {
-                            StoreError::InvalidArguments(
-                                "The specified stateId does could not be found.".to_string(),
-                            )
-                        })?,
+                        .ok_or(MethodError::CannotCalculateChanges)?,
                     )
                 } else {
                     changelog.changes.drain(
@@ -138,7 +135,9 @@ where
             }
         };
 
-        let has_more_changes = if max_changes > 0 && changelog.changes.len() > max_changes {
+        // The changelog is read in pages that end between change ids, a change id
+        // with more changes than fit in a response is split using an intermediate state
+        let is_intermediate = if max_changes > 0 && changelog.changes.len() > max_changes {
             changelog
                 .changes
                 .drain(0..(changelog.changes.len() - max_changes));
@@ -171,9 +170,9 @@ where
             account_id: request.account_id,
             total_changes,
             has_children_changes: !updated.is_empty() && !items_changed,
-            has_more_changes,
+            has_more_changes: is_intermediate || changelog.has_more_changes,
             old_state: request.since_state,
-            new_state: if has_more_changes {
+            new_state: if is_intermediate {
                 JMAPState::new_intermediate(
                     changelog.from_change_id,
                     changelog.to_change_id,
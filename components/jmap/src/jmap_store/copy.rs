@@ -85,6 +85,11 @@ where
                 return Err(MethodError::StateMismatch);
             }
         }
+        if let Some(if_from_in_state) = request.if_from_in_state.take() {
+            if store.get_state(from_account_id, collection)? != if_from_in_state {
+                return Err(MethodError::StateMismatch);
+            }
+        }
 
         Ok(CopyHelper {
             store,
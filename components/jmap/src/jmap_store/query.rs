@@ -21,15 +21,20 @@
  * for more details.
 */
 
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{Instant, SystemTime},
+};
 
 use store::{
     core::JMAPIdPrefix,
     read::{
         comparator::Comparator,
+        deadline::Deadline,
         filter::{Filter, FilterOperator, LogicalOperator},
     },
     roaring::RoaringBitmap,
+    tracing::debug,
     AccountId, DocumentId, JMAPStore, SharedBitmap, Store,
 };
 
@@ -39,7 +44,7 @@ use crate::{
         query::{self, FilterDeserializer, QueryRequest, QueryResponse},
         ACLEnforce,
     },
-    types::jmap::JMAPId,
+    types::{cursor::JMAPQueryCursor, jmap::JMAPId},
 };
 
 use super::{changes::JMAPChanges, Object};
@@ -106,6 +111,21 @@ where
         mut parse_fnc: impl FnMut(O::Filter) -> crate::Result<Filter>,
     ) -> crate::Result<()> {
         if let Some(state) = self.request.filter.take() {
+            let max_conditions = self.store.config.query_max_conditions;
+            let check_conditions = |total: usize| {
+                if max_conditions > 0 && total > max_conditions {
+                    Err(MethodError::UnsupportedFilter(format!(
+                        "Filter operators may not have more than {} conditions.",
+                        max_conditions
+                    )))
+                } else {
+                    Ok(())
+                }
+            };
+
+            if let query::Filter::FilterOperator(op) = &state {
+                check_conditions(op.conditions.len())?;
+            }
             let mut state = match state {
                 query::Filter::FilterOperator(op) => QueryState::<O> {
                     op: op.operator.into(),
@@ -126,6 +146,7 @@ where
                 while let Some(term) = state.it.next() {
                     match term {
                         query::Filter::FilterOperator(op) => {
+                            check_conditions(op.conditions.len())?;
                             state_stack.push(state);
                             state = QueryState {
                                 op: op.operator.into(),
@@ -134,7 +155,13 @@ where
                             };
                         }
                         query::Filter::FilterCondition(cond) => {
-                            state.terms.push(parse_fnc(cond)?);
+                            // A single condition, such as inMailboxOtherThan, may expand
+                            // into several terms.
+                            let term = parse_fnc(cond)?;
+                            if let Filter::Operator(op) = &term {
+                                check_conditions(op.conditions.len())?;
+                            }
+                            state.terms.push(term);
                         }
                         query::Filter::Empty => (),
                     }
@@ -171,6 +198,14 @@ where
         if let Some(sort) = self.request.sort.take() {
             let mut terms: Vec<Comparator> = Vec::with_capacity(sort.len());
             for comp in sort {
+                if let Some(collation) = &comp.collation {
+                    if !query::SUPPORTED_COLLATIONS.contains(&collation.as_str()) {
+                        return Err(MethodError::UnsupportedSort(format!(
+                            "Unsupported collation '{}'.",
+                            collation
+                        )));
+                    }
+                }
                 terms.push(parse_fnc(comp)?);
             }
             self.comparator = Comparator::List(terms);
@@ -178,6 +213,44 @@ where
         Ok(())
     }
 
+    /// Sorts by the given comma separated list of `property [asc|desc]` terms
+    /// when the request does not include any comparators.
+    pub fn default_sort(&mut self, sort: &str) {
+        if !self
+            .request
+            .sort
+            .as_ref()
+            .map_or(true, |sort| sort.is_empty())
+        {
+            return;
+        }
+
+        let mut terms = Vec::new();
+        for term in sort.split(',') {
+            let mut parts = term.split_whitespace();
+            let property = if let Some(property) = parts.next() {
+                property
+            } else {
+                continue;
+            };
+            let is_ascending = !parts
+                .next()
+                .map_or(false, |order| order.eq_ignore_ascii_case("desc"));
+
+            match serde_json::from_value::<query::Comparator<O::Comparator>>(serde_json::json!({
+                "property": property,
+                "isAscending": is_ascending,
+            })) {
+                Ok(comparator) => terms.push(comparator),
+                Err(err) => debug!("Ignoring invalid default sort term {:?}: {}", term, err),
+            }
+        }
+
+        if !terms.is_empty() {
+            self.request.sort = terms.into();
+        }
+    }
+
     pub fn query<X, W>(
         self,
         filter_map_fnc: X,
@@ -194,11 +267,27 @@ where
             query_state: self.store.get_state(self.account_id, collection)?,
             total: None,
             limit: None,
+            next_cursor: None,
             ids: Vec::with_capacity(0),
             is_immutable: false,
             can_calculate_changes: true,
         };
 
+        if let Some(cursor) = &self.request.cursor {
+            if self.request.position.is_some() || self.request.anchor.is_some() {
+                return Err(MethodError::InvalidArguments(
+                    "A cursor cannot be combined with a position or an anchor.".to_string(),
+                ));
+            } else if !cursor.is_start()
+                && unix_timestamp().saturating_sub(cursor.issued_at)
+                    > self.store.config.query_cursor_ttl
+            {
+                return Err(MethodError::InvalidArguments(
+                    "Query cursor has expired.".to_string(),
+                ));
+            }
+        }
+
         // Do not run the query if there are no shared documents to include
         if let Some(shared_documents) = &self.shared_documents {
             if !shared_documents.has_some_access() {
@@ -206,12 +295,12 @@ where
             }
         }
 
-        let results_it = self.store.query_store::<X>(
-            self.account_id,
-            collection,
-            self.filter,
-            self.comparator,
-        )?;
+        let mut results_it = self
+            .store
+            .query_store::<X>(self.account_id, collection, self.filter, self.comparator)?
+            .with_deadline();
+        let stats = results_it.stats.take();
+        let sort_started = Instant::now();
 
         let limit = if let Some(limit) = &self.request.limit {
             if *limit > 0 {
@@ -232,10 +321,6 @@ where
             results_it.len()
         });
 
-        let position = self.request.position.unwrap_or(0);
-        let anchor = self.request.anchor;
-        let anchor_offset = self.request.anchor_offset.unwrap_or(0);
-
         let total_results = if let Some(mut extra_filters) = extra_filters {
             let results = if let Some(shared_documents) = self.shared_documents {
                 // Filter out documents that are not shared
@@ -261,14 +346,14 @@ where
             let results = extra_filters(results)?;
             let total_results = results.len();
 
-            result.paginate(results.into_iter(), limit, position, anchor, anchor_offset)?;
+            result.paginate_request(results.into_iter(), limit, &self.request)?;
 
             total_results
         } else {
             let total_results = results_it.len();
             if let Some(shared_documents) = self.shared_documents {
                 // Filter out documents that are not shared
-                result.paginate(
+                result.paginate_request(
                     results_it
                         .set_filter_map(filter_map_fnc)
                         .into_iter()
@@ -280,26 +365,25 @@ where
                             }
                         }),
                     limit,
-                    position,
-                    anchor,
-                    anchor_offset,
+                    &self.request,
                 )?;
             } else {
-                result.paginate(
+                result.paginate_request(
                     results_it
                         .set_filter_map(filter_map_fnc)
                         .into_iter()
                         .map(|id| id.into()),
                     limit,
-                    position,
-                    anchor,
-                    anchor_offset,
+                    &self.request,
                 )?;
             }
 
             total_results
         };
 
+        // Results are incomplete if the deadline expired while iterating
+        Deadline::check()?;
+
         if limit > 0 && limit < total_results {
             result.limit = limit.into();
         }
@@ -308,11 +392,98 @@ where
             result.total = Some(total_results);
         }
 
+        if let Some(mut stats) = stats {
+            stats.sort_time = sort_started.elapsed();
+            stats.total_time = stats.filter_time + stats.sort_time;
+            debug!(
+                "Query on account {} collection {:?}: conditions {:?}, filter {:?}, sort {:?}, total {:?}.",
+                self.account_id,
+                collection,
+                stats.conditions,
+                stats.filter_time,
+                stats.sort_time,
+                stats.total_time
+            );
+        }
+
         Ok(result)
     }
 }
 
 impl QueryResponse {
+    pub fn paginate_request<O, W>(
+        &mut self,
+        jmap_ids: W,
+        limit: usize,
+        request: &QueryRequest<O>,
+    ) -> crate::Result<()>
+    where
+        O: QueryObject,
+        W: Iterator<Item = JMAPId>,
+    {
+        if let Some(cursor) = &request.cursor {
+            self.resume(jmap_ids, limit, cursor);
+            Ok(())
+        } else {
+            self.paginate(
+                jmap_ids,
+                limit,
+                request.position.unwrap_or(0),
+                request.anchor,
+                request.anchor_offset.unwrap_or(0),
+            )
+        }
+    }
+
+    /// Returns the batch of results following the cursor, together with the
+    /// cursor for the next batch when there are more results left.
+    pub fn resume<W>(&mut self, jmap_ids: W, limit: usize, cursor: &JMAPQueryCursor)
+    where
+        W: Iterator<Item = JMAPId>,
+    {
+        // Results from the position of the last id returned are kept aside
+        // in case that id was removed since, so that iteration continues from
+        // the position alone.
+        let mut fallback_ids = Vec::new();
+        let mut anchor_found = cursor.is_start();
+        let mut position = cursor.position;
+        let mut has_more = false;
+
+        for (pos, jmap_id) in jmap_ids.enumerate() {
+            if anchor_found {
+                if limit > 0 && self.ids.len() == limit {
+                    has_more = true;
+                    break;
+                }
+                self.ids.push(jmap_id);
+            } else if cursor.last_id.as_ref() == Some(&jmap_id) {
+                anchor_found = true;
+                position = pos + 1;
+            } else if pos + 1 >= cursor.position && (limit == 0 || fallback_ids.len() <= limit) {
+                fallback_ids.push(jmap_id);
+            }
+        }
+
+        if !anchor_found {
+            position = cursor.position.saturating_sub(1);
+            if limit > 0 && fallback_ids.len() > limit {
+                fallback_ids.truncate(limit);
+                has_more = true;
+            }
+            self.ids = fallback_ids;
+        }
+
+        self.position = position as i32;
+        if has_more {
+            self.next_cursor = JMAPQueryCursor::new(
+                position + self.ids.len(),
+                *self.ids.last().unwrap(),
+                unix_timestamp(),
+            )
+            .into();
+        }
+    }
+
     pub fn paginate<W>(
         &mut self,
         jmap_ids: W,
@@ -328,6 +499,12 @@ impl QueryResponse {
         let mut anchor_found = false;
         let requested_position = position;
 
+        // The position of anchored windows is counted from the first result,
+        // an anchor takes precedence over any requested position.
+        if has_anchor {
+            position = 0;
+        }
+
         for jmap_id in jmap_ids {
             if !has_anchor {
                 if position >= 0 {
@@ -345,6 +522,7 @@ impl QueryResponse {
             } else if anchor_offset >= 0 {
                 if !anchor_found {
                     if &jmap_id != anchor.as_ref().unwrap() {
+                        position += 1;
                         continue;
                     }
                     anchor_found = true;
@@ -352,6 +530,7 @@ impl QueryResponse {
 
                 if anchor_offset > 0 {
                     anchor_offset -= 1;
+                    position += 1;
                 } else {
                     self.ids.push(jmap_id);
                     if limit > 0 && self.ids.len() == limit {
@@ -410,3 +589,123 @@ impl From<query::Operator> for LogicalOperator {
         }
     }
 }
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::{
+        error::method::MethodError,
+        request::query::QueryResponse,
+        types::{jmap::JMAPId, state::JMAPState},
+    };
+
+    #[test]
+    fn test_paginate_anchor() {
+        // (anchor, anchor_offset, limit, position, expected ids, expected position)
+        for (anchor, anchor_offset, limit, position, expected_ids, expected_position) in [
+            (5, 0, 3, 0, vec![5, 6, 7], 5),
+            (5, 2, 3, 0, vec![7, 8, 9], 7),
+            (5, 2, 0, 0, vec![7, 8, 9], 7),
+            (5, 10, 3, 0, vec![], 10),
+            (5, -2, 0, 0, vec![4, 5], 4),
+            (5, -3, 2, 0, vec![3, 4], 3),
+            (5, -10, 3, 0, vec![0, 1, 2], 0),
+            (0, -1, 3, 0, vec![0], 0),
+            // The anchor takes precedence over the position
+            (5, 0, 2, 8, vec![5, 6], 5),
+            (5, -2, 0, -3, vec![4, 5], 4),
+        ] {
+            let mut response = response();
+            response
+                .paginate(
+                    (0..10).map(JMAPId::new),
+                    limit,
+                    position,
+                    JMAPId::new(anchor).into(),
+                    anchor_offset,
+                )
+                .unwrap();
+            assert_eq!(
+                response.ids,
+                expected_ids
+                    .into_iter()
+                    .map(JMAPId::new)
+                    .collect::<Vec<_>>(),
+                "anchor {}, offset {}, limit {}",
+                anchor,
+                anchor_offset,
+                limit
+            );
+            assert_eq!(
+                response.position, expected_position,
+                "anchor {}, offset {}, limit {}",
+                anchor, anchor_offset, limit
+            );
+        }
+
+        // Anchors missing from the results are rejected
+        for anchor_offset in [0, 3, -3] {
+            assert!(matches!(
+                response().paginate(
+                    (0..10).map(JMAPId::new),
+                    0,
+                    0,
+                    JMAPId::new(100).into(),
+                    anchor_offset
+                ),
+                Err(MethodError::AnchorNotFound)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_paginate_stops_early() {
+        // (position, anchor, anchor_offset, limit, expected ids read)
+        for (position, anchor, anchor_offset, limit, expected_reads) in [
+            (0, None, 0, 10, 10),
+            (500, None, 0, 10, 510),
+            (0, Some(100), 5, 10, 115),
+            // Windows counted from the end need every result
+            (-10, None, 0, 10, 100_000),
+            (0, None, 0, 0, 100_000),
+        ] {
+            let mut reads = 0;
+            let mut response = response();
+            response
+                .paginate(
+                    (0..100_000).map(JMAPId::new).inspect(|_| reads += 1),
+                    limit,
+                    position,
+                    anchor.map(JMAPId::new),
+                    anchor_offset,
+                )
+                .unwrap();
+            assert_eq!(
+                reads, expected_reads,
+                "position {}, anchor {:?}, limit {}",
+                position, anchor, limit
+            );
+        }
+    }
+
+    fn response() -> QueryResponse {
+        QueryResponse {
+            account_id: JMAPId::new(0),
+            query_state: JMAPState::Initial,
+            can_calculate_changes: false,
+            position: 0,
+            ids: Vec::new(),
+            total: None,
+            limit: None,
+            next_cursor: None,
+            is_immutable: false,
+        }
+    }
+}
@@ -85,6 +85,7 @@ where
                 anchor_offset: None,
                 limit: None,
                 calculate_total: None,
+                cursor: None,
                 arguments: request.arguments,
             }
             .into()
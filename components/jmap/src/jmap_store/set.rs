@@ -62,6 +62,7 @@ where
 {
     pub store: &'y JMAPStore<T>,
     pub lock: MutexGuard<'y, ()>,
+    pub account_lock: MutexGuard<'y, ()>,
     pub changes: WriteBatch,
     pub document_ids: RoaringBitmap,
     pub account_id: AccountId,
@@ -86,6 +87,14 @@ where
         let collection = O::collection();
         let account_id = request.account_id.get_document_id();
 
+        // Hold the account write lock from the state check until the changes are
+        // written, so that concurrent sets on the same account run one after another.
+        // Batches reading this account are let finish before making any changes they
+        // would observe half way through.
+        let account_lock = store.lock_account(account_id);
+        store.read_snapshots.wait(account_id);
+        let lock = store.lock_collection(account_id, collection);
+
         let old_state = store.get_state(account_id, collection)?;
         if let Some(if_in_state) = request.if_in_state.take() {
             if old_state != if_in_state {
@@ -97,9 +106,11 @@ where
             .take()
             .and_then(|d| d.unwrap_value())
             .unwrap_or_default();
+
         Ok(SetHelper {
             store,
-            lock: store.lock_collection(account_id, collection),
+            lock,
+            account_lock,
             changes: WriteBatch::new(account_id),
             document_ids: store
                 .get_document_ids(account_id, collection)?
@@ -129,6 +140,35 @@ where
         })
     }
 
+    /// Answers a request that makes no changes with a single state read,
+    /// skipping the account locks and the document id lookup done by `new`.
+    pub fn no_changes(
+        store: &'y JMAPStore<T>,
+        request: SetRequest<O>,
+    ) -> crate::Result<SetResponse<O>> {
+        let state = store.get_state(request.account_id.get_document_id(), O::collection())?;
+        if let Some(if_in_state) = request.if_in_state {
+            if state != if_in_state {
+                return Err(MethodError::StateMismatch);
+            }
+        }
+
+        Ok(SetResponse {
+            account_id: request.account_id.into(),
+            new_state: state.clone().into(),
+            old_state: state.into(),
+            created: AHashMap::with_capacity(0),
+            not_created: VecMap::with_capacity(0),
+            updated: VecMap::with_capacity(0),
+            not_updated: VecMap::with_capacity(0),
+            destroyed: Vec::with_capacity(0),
+            not_destroyed: VecMap::with_capacity(0),
+            next_call: None,
+            change_id: None,
+            state_changes: None,
+        })
+    }
+
     pub fn disable_write_batch(&mut self) {
         self.batch_writes = false;
     }
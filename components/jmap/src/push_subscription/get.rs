@@ -82,6 +82,10 @@ where
             let mut push_subscription = VecMap::with_capacity(properties.len());
 
             for property in properties {
+                // Encryption keys are write-only and never returned to clients
+                if property == &Property::Keys {
+                    continue;
+                }
                 push_subscription.append(
                     *property,
                     match property {
@@ -149,6 +149,18 @@ pub enum MaybeIdReference {
 }
 
 impl MaybeIdReference {
+    pub fn parse(value: &str) -> Option<Self> {
+        if let Some(create_id) = value.strip_prefix('#') {
+            if !create_id.is_empty() {
+                MaybeIdReference::Reference(create_id.to_string()).into()
+            } else {
+                None
+            }
+        } else {
+            JMAPId::parse(value).map(MaybeIdReference::Value)
+        }
+    }
+
     pub fn unwrap_value(self) -> Option<JMAPId> {
         match self {
             MaybeIdReference::Value(id) => id.into(),
@@ -334,19 +346,8 @@ impl<'de> serde::de::Visitor<'de> for MaybeIdReferenceVisitor {
     where
         E: serde::de::Error,
     {
-        Ok(if !v.starts_with('#') {
-            MaybeIdReference::Value(JMAPId::parse(v).ok_or_else(|| {
-                serde::de::Error::custom(format!("Failed to parse JMAP id '{}'", v))
-            })?)
-        } else {
-            MaybeIdReference::Reference(
-                v.get(1..)
-                    .ok_or_else(|| {
-                        serde::de::Error::custom(format!("Failed to parse JMAP id '{}'", v))
-                    })?
-                    .to_string(),
-            )
-        })
+        MaybeIdReference::parse(v)
+            .ok_or_else(|| serde::de::Error::custom(format!("Failed to parse JMAP id '{}'", v)))
     }
 }
 
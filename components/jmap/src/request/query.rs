@@ -33,7 +33,7 @@ use store::core::acl::ACLToken;
 use crate::{
     jmap_store::query::QueryObject,
     types::json_pointer::{JSONPointer, JSONPointerEval},
-    types::{jmap::JMAPId, state::JMAPState},
+    types::{cursor::JMAPQueryCursor, jmap::JMAPId, state::JMAPState},
 };
 
 #[derive(Debug, Clone, serde::Deserialize)]
@@ -72,6 +72,10 @@ pub struct QueryRequest<O: QueryObject> {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub calculate_total: Option<bool>,
 
+    #[serde(rename = "cursor")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<JMAPQueryCursor>,
+
     #[serde(flatten)]
     pub arguments: O::QueryArguments,
 }
@@ -96,6 +100,37 @@ pub enum Operator {
     Not,
 }
 
+/// Collation algorithms accepted in sort comparators.
+pub const SUPPORTED_COLLATIONS: [&str; 3] =
+    ["i;ascii-numeric", "i;ascii-casemap", "i;unicode-casemap"];
+
+/// Collation used by comparators that do not request one. Indexed text sort
+/// keys, such as e-mail participants and subjects, are folded the same way.
+pub const DEFAULT_COLLATION: &str = "i;unicode-casemap";
+
+/// Returns the sort key of `text` under `collation`. Strings that do not start
+/// with a digit sort after all numbers under `i;ascii-numeric`.
+pub fn collate<'x>(collation: Option<&str>, text: &'x str) -> Cow<'x, str> {
+    match collation.unwrap_or(DEFAULT_COLLATION) {
+        "i;ascii-numeric" => {
+            let digits = text
+                .bytes()
+                .take_while(|ch| ch.is_ascii_digit())
+                .skip_while(|ch| *ch == b'0')
+                .map(char::from)
+                .collect::<String>();
+            if text.starts_with(|ch: char| ch.is_ascii_digit()) {
+                format!("0{:0>20}", digits).into()
+            } else {
+                "1".into()
+            }
+        }
+        "i;ascii-casemap" => text.to_ascii_lowercase().into(),
+        "i;unicode-casemap" => text.to_lowercase().into(),
+        _ => text.into(),
+    }
+}
+
 #[derive(Debug, Clone, serde::Deserialize)]
 pub struct Comparator<A> {
     #[serde(rename = "isAscending")]
@@ -138,6 +173,10 @@ pub struct QueryResponse {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub limit: Option<usize>,
 
+    #[serde(rename = "nextCursor")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<JMAPQueryCursor>,
+
     #[serde(skip)]
     pub is_immutable: bool,
 }
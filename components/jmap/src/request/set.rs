@@ -101,6 +101,21 @@ pub struct SetResponse<O: SetObject> {
 }
 
 impl<O: SetObject> SetRequest<O> {
+    /// Returns true if the request does not create, update or destroy any objects.
+    pub fn is_empty(&self) -> bool {
+        self.create
+            .as_ref()
+            .map_or(true, |create| create.is_empty())
+            && self
+                .update
+                .as_ref()
+                .map_or(true, |update| update.is_empty())
+            && self.destroy.as_ref().map_or(
+                true,
+                |destroy| matches!(destroy, MaybeResultReference::Value(ids) if ids.is_empty()),
+            )
+    }
+
     pub fn eval_references(
         &mut self,
         mut result_map_fnc: impl FnMut(&ResultReference) -> Option<Vec<u64>>,
@@ -0,0 +1,148 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use store::serialize::{
+    base32::{Base32Reader, Base32Writer},
+    leb128::{Leb128Iterator, Leb128Writer},
+};
+
+use super::jmap::JMAPId;
+
+/// Continuation token used to iterate over large `/query` results in batches.
+///
+/// Clients request the first batch with an empty cursor and pass the returned
+/// `nextCursor` to obtain the following one. The cursor records the position
+/// and id of the last result returned, so iteration resumes after that id even
+/// if earlier results were added or removed in the meantime.
+///
+/// The string is a `c` prefix followed by the Base32 encoded LEB128 values
+/// `<position><last_id><issued_at>`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct JMAPQueryCursor {
+    pub position: usize,
+    pub last_id: Option<JMAPId>,
+    pub issued_at: u64,
+}
+
+impl JMAPQueryCursor {
+    pub fn new(position: usize, last_id: JMAPId, issued_at: u64) -> Self {
+        JMAPQueryCursor {
+            position,
+            last_id: last_id.into(),
+            issued_at,
+        }
+    }
+
+    pub fn is_start(&self) -> bool {
+        self.last_id.is_none()
+    }
+
+    pub fn parse(cursor: &str) -> Option<Self> {
+        match cursor.as_bytes().first() {
+            None => JMAPQueryCursor::default().into(),
+            Some(b'c') => {
+                let mut it = Base32Reader::new(cursor.get(1..)?.as_bytes());
+                JMAPQueryCursor {
+                    position: it.next_leb128()?,
+                    last_id: JMAPId::new(it.next_leb128()?).into(),
+                    issued_at: it.next_leb128()?,
+                }
+                .into()
+            }
+            _ => None,
+        }
+    }
+}
+
+impl serde::Serialize for JMAPQueryCursor {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(self.to_string().as_str())
+    }
+}
+
+struct JMAPQueryCursorVisitor;
+
+impl<'de> serde::de::Visitor<'de> for JMAPQueryCursorVisitor {
+    type Value = JMAPQueryCursor;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a valid JMAP query cursor")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        JMAPQueryCursor::parse(v).ok_or_else(|| {
+            serde::de::Error::custom(format!("Failed to parse JMAP query cursor '{}'", v))
+        })
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for JMAPQueryCursor {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_str(JMAPQueryCursorVisitor)
+    }
+}
+
+impl std::fmt::Display for JMAPQueryCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(last_id) = &self.last_id {
+            let mut writer = Base32Writer::with_capacity(20);
+            writer.push_char('c');
+            writer.write_leb128(self.position).unwrap();
+            writer.write_leb128(u64::from(last_id)).unwrap();
+            writer.write_leb128(self.issued_at).unwrap();
+            f.write_str(&writer.finalize())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::types::jmap::JMAPId;
+
+    use super::JMAPQueryCursor;
+
+    #[test]
+    fn test_query_cursor() {
+        for cursor in [
+            JMAPQueryCursor::default(),
+            JMAPQueryCursor::new(0, JMAPId::new(0), 0),
+            JMAPQueryCursor::new(100, JMAPId::from_parts(12, 345), 1660000000),
+            JMAPQueryCursor::new(usize::MAX, JMAPId::new(u64::MAX), u64::MAX),
+        ] {
+            assert_eq!(JMAPQueryCursor::parse(&cursor.to_string()).unwrap(), cursor);
+        }
+        assert!(JMAPQueryCursor::parse("s1").is_none());
+    }
+}
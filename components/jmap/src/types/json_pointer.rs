@@ -128,6 +128,14 @@ impl JSONPointer {
         }
     }
 
+    pub fn depth(&self) -> usize {
+        match self {
+            JSONPointer::Root => 0,
+            JSONPointer::Path(path) => path.len(),
+            _ => 1,
+        }
+    }
+
     pub fn to_string(&self) -> Option<&str> {
         match self {
             JSONPointer::String(s) => s.as_str().into(),
@@ -249,4 +257,22 @@ mod tests {
             assert_eq!(JSONPointer::parse(input), Some(output), "{}", input);
         }
     }
+
+    #[test]
+    fn json_pointer_depth() {
+        for (input, depth) in [
+            ("", 0),
+            ("ids", 1),
+            ("/ids", 1),
+            ("/list/*/threadId", 3),
+            ("/a/b/c/d/e/f/g/h/i", 9),
+        ] {
+            assert_eq!(
+                JSONPointer::parse(input).unwrap().depth(),
+                depth,
+                "{}",
+                input
+            );
+        }
+    }
 }
@@ -22,6 +22,7 @@
 */
 
 pub mod blob;
+pub mod cursor;
 pub mod date;
 pub mod jmap;
 pub mod json_pointer;
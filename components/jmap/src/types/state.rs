@@ -36,6 +36,18 @@ pub struct JMAPIntermediateState {
     pub items_sent: usize,
 }
 
+/// State string returned by `/get`, `/changes` and `/query` methods.
+///
+/// States are derived exclusively from changelog ids, which are the raft log
+/// indexes assigned by the leader when a change is committed. Every node in
+/// a cluster therefore produces the same string for the same logical state.
+///
+/// The string is a one letter prefix followed by Base32 encoded LEB128 values:
+///
+/// - `n`: no changes have been recorded for the collection.
+/// - `s<change_id>`: the state after the change with the given id.
+/// - `r<from_id><to_id - from_id><items_sent>`: an intermediate state returned
+///   while paginating through `/changes` results.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum JMAPState {
     Initial,
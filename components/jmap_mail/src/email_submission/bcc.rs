@@ -0,0 +1,55 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+/// Returns a copy of the raw message without its Bcc header fields, or `None`
+/// if the message has none. Only the header section, which ends at
+/// `body_offset`, is inspected.
+pub fn strip_bcc(raw_message: &[u8], body_offset: usize) -> Option<Vec<u8>> {
+    let headers = raw_message.get(..body_offset)?;
+    let mut message = Vec::with_capacity(raw_message.len());
+    let mut is_bcc = false;
+    let mut has_bcc = false;
+
+    for line in headers.split_inclusive(|&ch| ch == b'\n') {
+        // Folded lines belong to the previous header field
+        if !matches!(line.first(), Some(b' ' | b'\t')) {
+            is_bcc = line.len() > 4
+                && line[..3].eq_ignore_ascii_case(b"bcc")
+                && line[3..]
+                    .iter()
+                    .find(|&&ch| ch != b' ' && ch != b'\t')
+                    .map_or(false, |&ch| ch == b':');
+            has_bcc |= is_bcc;
+        }
+        if !is_bcc {
+            message.extend_from_slice(line);
+        }
+    }
+
+    if has_bcc {
+        message.extend_from_slice(&raw_message[body_offset..]);
+        Some(message)
+    } else {
+        None
+    }
+}
@@ -21,6 +21,7 @@
  * for more details.
 */
 
+pub mod bcc;
 pub mod changes;
 pub mod get;
 pub mod query;
@@ -28,6 +29,7 @@ pub mod raft;
 pub mod schema;
 pub mod serialize;
 pub mod set;
+pub mod signature;
 
 use jmap::{jmap_store::Object, types::jmap::JMAPId};
 use store::{core::collection::Collection, write::options::Options};
@@ -191,6 +191,8 @@ impl ArgumentDeserializer for SetArguments {
             self.on_success_update_email = value.next_value().map_err(|err| err.to_string())?;
         } else if property == "onSuccessDestroyEmail" {
             self.on_success_destroy_email = value.next_value().map_err(|err| err.to_string())?;
+        } else if property == "appendSignature" {
+            self.append_signature = value.next_value().map_err(|err| err.to_string())?;
         } else {
             value
                 .next_value::<IgnoredAny>()
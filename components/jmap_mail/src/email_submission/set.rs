@@ -21,10 +21,14 @@
  * for more details.
 */
 
+use super::bcc::strip_bcc;
 use super::schema::{Address, EmailSubmission, Envelope, Property, Value};
+use super::signature::append_signature;
 use crate::identity;
 use crate::identity::schema::Identity;
-use crate::mail::schema::Email;
+use crate::mail::import::JMAPMailImport;
+use crate::mail::schema::{Email, Property as MailProperty};
+use crate::mail::set::SetArguments as EmailSetArguments;
 use crate::mail::{MessageData, MessageField};
 use jmap::error::set::{SetError, SetErrorType};
 use jmap::jmap_store::set::SetHelper;
@@ -35,7 +39,7 @@ use jmap::request::{MaybeIdReference, MaybeResultReference, ResultReference};
 use jmap::types::date::JMAPDate;
 use jmap::types::jmap::JMAPId;
 use jmap::{jmap_store::set::SetObject, request::set::SetRequest};
-use mail_parser::RfcHeader;
+use mail_parser::{Message, RfcHeader};
 use std::time::SystemTime;
 use store::ahash::{AHashMap, AHashSet};
 use store::blob::BlobId;
@@ -43,7 +47,11 @@ use store::core::collection::Collection;
 use store::core::document::Document;
 use store::core::error::StoreError;
 use store::core::vec_map::VecMap;
+use store::read::comparator::Comparator;
+use store::read::filter::{Filter, Query};
+use store::read::FilterMapper;
 use store::serialize::{StoreDeserialize, StoreSerialize};
+use store::write::batch::WriteBatch;
 use store::write::options::{IndexOptions, Options};
 use store::{AccountId, JMAPStore, Store};
 
@@ -51,6 +59,7 @@ use store::{AccountId, JMAPStore, Store};
 pub struct SetArguments {
     pub on_success_update_email: Option<VecMap<MaybeIdReference, Email>>,
     pub on_success_destroy_email: Option<Vec<MaybeIdReference>>,
+    pub append_signature: bool,
 }
 
 impl SetObject for EmailSubmission {
@@ -98,6 +107,19 @@ where
         account_id: AccountId,
         document: &mut Document,
     ) -> store::Result<()>;
+
+    fn email_submission_is_pending(
+        &self,
+        account_id: AccountId,
+        email_id: JMAPId,
+    ) -> store::Result<bool>;
+
+    fn email_submission_strip_bcc(
+        &self,
+        account_id: AccountId,
+        email_id: JMAPId,
+        message_data: &MessageData,
+    ) -> store::Result<Option<JMAPId>>;
 }
 
 impl<T> JMAPSetEmailSubmission<T> for JMAPStore<T>
@@ -171,26 +193,27 @@ where
             }
 
             // Fetch mailFrom
-            let mail_from = helper
+            let mut identity = helper
                 .store
                 .get_orm::<Identity>(helper.account_id, identity_id)?
                 .ok_or_else(|| {
                     SetError::invalid_property(Property::IdentityId, "Identity not found.")
-                })?
-                .remove(&identity::schema::Property::Email)
-                .and_then(|v| {
+                })?;
+            let mut identity_text = |property: identity::schema::Property| {
+                identity.remove(&property).and_then(|v| {
                     if let identity::schema::Value::Text { value } = v {
                         Some(value)
                     } else {
                         None
                     }
                 })
-                .ok_or_else(|| {
-                    SetError::invalid_property(
-                        Property::IdentityId,
-                        "The speficied identity does not have a valid e-mail address.",
-                    )
-                })?;
+            };
+            let mail_from = identity_text(identity::schema::Property::Email).ok_or_else(|| {
+                SetError::invalid_property(
+                    Property::IdentityId,
+                    "The speficied identity does not have a valid e-mail address.",
+                )
+            })?;
 
             // Make sure the envelope address matches the identity email address
             let mut send_at = SystemTime::now()
@@ -315,24 +338,86 @@ where
                     .map(|(email, parameters)| Address { email, parameters })
                     .collect::<Vec<_>>();
             }
+            if envelope.rcpt_to.len() > helper.store.config.mail_max_recipients {
+                return Err(SetError::new(
+                    SetErrorType::TooLarge,
+                    format!(
+                        "Envelope exceeds the maximum of {} recipients.",
+                        helper.store.config.mail_max_recipients
+                    ),
+                ));
+            }
+
+            // Append the identity's signature, if requested
+            let mut raw_message = message_data.raw_message.clone();
+            if helper.request.arguments.append_signature {
+                let text_signature = identity_text(identity::schema::Property::TextSignature);
+                let html_signature = identity_text(identity::schema::Property::HtmlSignature);
+                if text_signature.is_some() || html_signature.is_some() {
+                    let raw_bytes = helper.store.blob_get(&raw_message)?.ok_or_else(|| {
+                        StoreError::NotFound(format!(
+                            "Raw message for {}:{} not found.",
+                            helper.account_id,
+                            email_id.get_document_id()
+                        ))
+                    })?;
+                    if let Some(signed_message) = append_signature(
+                        &raw_bytes,
+                        &message_data,
+                        text_signature.as_deref(),
+                        html_signature.as_deref(),
+                    ) {
+                        raw_message = BlobId::new_external(&signed_message);
+                        helper.store.blob_store(&raw_message, signed_message)?;
+                    }
+                }
+            }
 
             // Add and link blob
             document.binary(
                 Property::EmailId,
-                message_data.raw_message.serialize().unwrap(),
+                raw_message.serialize().unwrap(),
                 IndexOptions::new(),
             );
-            document.blob(message_data.raw_message, IndexOptions::new());
+            document.blob(raw_message, IndexOptions::new());
 
             // Insert envelope
             fields.set(Property::Envelope, Value::Envelope { value: envelope });
 
+            // When Bcc is not kept, the message saved by onSuccessUpdateEmail is
+            // replaced with a copy without it and the original is destroyed
+            let id_ref = MaybeIdReference::Reference(create_id.to_string());
+            let mut stored_email_id = email_id;
+            if has_on_success
+                && !helper.store.config.mail_sent_keep_bcc
+                && helper
+                    .request
+                    .arguments
+                    .on_success_update_email
+                    .as_ref()
+                    .map_or(false, |p| p.contains_key(&id_ref))
+            {
+                if let Some(copy_id) = helper.store.email_submission_strip_bcc(
+                    helper.account_id,
+                    email_id,
+                    &message_data,
+                )? {
+                    fields.set(Property::EmailId, Value::Id { value: copy_id });
+                    fields.set(
+                        Property::ThreadId,
+                        Value::Id {
+                            value: copy_id.get_prefix_id().into(),
+                        },
+                    );
+                    stored_email_id = copy_id;
+                }
+            }
+
             // Validate fields
             fields.insert_validate(document)?;
 
             // Update onSuccess actions
             if has_on_success {
-                let id_ref = MaybeIdReference::Reference(create_id.to_string());
                 if let Some(update) = helper
                     .request
                     .arguments
@@ -340,15 +425,16 @@ where
                     .as_mut()
                     .and_then(|p| p.remove(&id_ref))
                 {
-                    update_emails.append(email_id, update);
+                    update_emails.append(stored_email_id, update);
                 }
 
-                if helper
-                    .request
-                    .arguments
-                    .on_success_destroy_email
-                    .as_ref()
-                    .map_or(false, |p| p.contains(&id_ref))
+                if stored_email_id != email_id
+                    || helper
+                        .request
+                        .arguments
+                        .on_success_destroy_email
+                        .as_ref()
+                        .map_or(false, |p| p.contains(&id_ref))
                 {
                     destroy_emails.push(email_id);
                 }
@@ -399,7 +485,12 @@ where
                     } else {
                         None
                     },
-                    arguments: (),
+                    arguments: EmailSetArguments {
+                        // The client asked for these messages to be destroyed
+                        // once submitted, pending or not
+                        force_destroy: true.into(),
+                        ..Default::default()
+                    },
                 }
                 .into();
             }
@@ -448,4 +539,89 @@ where
             )))
         }
     }
+
+    fn email_submission_is_pending(
+        &self,
+        account_id: AccountId,
+        email_id: JMAPId,
+    ) -> store::Result<bool> {
+        Ok(self
+            .query_store::<FilterMapper>(
+                account_id,
+                Collection::EmailSubmission,
+                Filter::and(vec![
+                    Filter::eq(
+                        Property::EmailId.into(),
+                        Query::LongInteger(email_id.into()),
+                    ),
+                    Filter::eq(Property::UndoStatus.into(), Query::Keyword("p".to_string())),
+                ]),
+                Comparator::None,
+            )?
+            .next()
+            .is_some())
+    }
+
+    fn email_submission_strip_bcc(
+        &self,
+        account_id: AccountId,
+        email_id: JMAPId,
+        message_data: &MessageData,
+    ) -> store::Result<Option<JMAPId>> {
+        let raw_message = self.blob_get(&message_data.raw_message)?.ok_or_else(|| {
+            StoreError::NotFound(format!(
+                "Raw message for {}:{} not found.",
+                account_id,
+                email_id.get_document_id()
+            ))
+        })?;
+        let raw_message =
+            if let Some(raw_message) = strip_bcc(&raw_message, message_data.body_offset) {
+                raw_message
+            } else {
+                return Ok(None);
+            };
+        let current_fields = self
+            .get_orm::<Email>(account_id, email_id.get_document_id())?
+            .ok_or_else(|| StoreError::NotFound("ORM not found for Email.".to_string()))?;
+
+        // Store the copy in the same mailboxes and with the same keywords
+        let document_id = self.assign_document_id(account_id, Collection::Mail)?;
+        let mut batch = WriteBatch::new(account_id);
+        let mut document = Document::new(Collection::Mail, document_id);
+        let blob_id = BlobId::new_external(&raw_message);
+        self.mail_parse_item(
+            &mut document,
+            blob_id.clone(),
+            Message::parse(&raw_message).ok_or_else(|| {
+                StoreError::DataCorruption(format!(
+                    "Failed to parse message {}:{} without Bcc.",
+                    account_id,
+                    email_id.get_document_id()
+                ))
+            })?,
+            message_data.received_at.into(),
+        )?;
+        let mut fields = TinyORM::<Email>::new();
+        for property in [MailProperty::MailboxIds, MailProperty::Keywords] {
+            if let Some(tags) = current_fields.get_tags(&property) {
+                for tag in tags {
+                    if property == MailProperty::MailboxIds {
+                        batch.log_child_update(Collection::Mailbox, tag.as_id());
+                    }
+                    fields.tag(property.clone(), tag.clone());
+                }
+            }
+        }
+        fields.insert(&mut document)?;
+        self.blob_store(&blob_id, raw_message)?;
+
+        let thread_id = self.mail_set_thread(&mut batch, &mut document)?;
+        let id = JMAPId::from_parts(thread_id, document_id);
+        batch.log_insert(Collection::Mail, id);
+        batch.insert_document(document);
+        self.write(batch)?;
+
+        Ok(Some(id))
+    }
 }
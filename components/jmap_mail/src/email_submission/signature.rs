@@ -0,0 +1,154 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use jmap::base64;
+use mail_parser::{decoders::html::html_to_text, Encoding};
+use store::ahash::AHashSet;
+
+use crate::mail::{encode_quoted_printable, MessageData, MessagePart, MimePartType};
+
+const SIGNATURE_DELIMITER: &str = "-- ";
+
+/// Returns a copy of the raw message with the identity's signature appended to
+/// its text and HTML bodies, or `None` if no body part had to be changed.
+/// Parts that already contain a signature delimiter are left untouched.
+pub fn append_signature(
+    raw_message: &[u8],
+    message_data: &MessageData,
+    text_signature: Option<&str>,
+    html_signature: Option<&str>,
+) -> Option<Vec<u8>> {
+    let mut edits = Vec::new();
+    let mut seen_parts = AHashSet::new();
+
+    for part_id in message_data
+        .text_body
+        .iter()
+        .chain(message_data.html_body.iter())
+    {
+        if !seen_parts.insert(*part_id) {
+            continue;
+        }
+        let mime_part = message_data.mime_parts.get(*part_id)?;
+        let (part, signature, is_html) =
+            match (&mime_part.mime_type, text_signature, html_signature) {
+                (MimePartType::Text { part }, Some(signature), _) => (part, signature, false),
+                (MimePartType::Html { part }, _, Some(signature)) => (part, signature, true),
+                _ => continue,
+            };
+
+        // Bodies are re-encoded as UTF-8, parts using other charsets can only be
+        // rewritten when both the body and the signature are plain ASCII.
+        let body = part.decode_text(raw_message, mime_part.charset.as_deref(), false)?;
+        if !mime_part
+            .charset
+            .as_ref()
+            .map_or(true, |charset| charset.eq_ignore_ascii_case("utf-8"))
+            && !(body.is_ascii() && signature.is_ascii())
+        {
+            continue;
+        }
+        if has_delimiter(&body, is_html) {
+            continue;
+        }
+        edits.push((
+            part,
+            if is_html {
+                append_html(body, signature)
+            } else {
+                append_text(body, signature)
+            },
+        ));
+    }
+
+    if edits.is_empty() {
+        return None;
+    }
+
+    edits.sort_unstable_by_key(|(part, _)| part.offset_start);
+    let mut message = Vec::with_capacity(raw_message.len() + edits.len() * 256);
+    let mut offset = 0;
+    for (part, body) in edits {
+        message.extend_from_slice(raw_message.get(offset..part.offset_start)?);
+        message.extend_from_slice(&encode_body(part, body.as_bytes()));
+        offset = part.offset_end;
+    }
+    message.extend_from_slice(raw_message.get(offset..)?);
+
+    Some(message)
+}
+
+fn has_delimiter(body: &str, is_html: bool) -> bool {
+    let is_delimiter = |line: &str| line.trim_end_matches('\r') == SIGNATURE_DELIMITER;
+    if !is_html {
+        body.lines().any(is_delimiter)
+    } else {
+        html_to_text(body)
+            .lines()
+            .any(|line| is_delimiter(line) || line.trim_end() == SIGNATURE_DELIMITER.trim_end())
+    }
+}
+
+fn append_text(mut body: String, signature: &str) -> String {
+    if !body.is_empty() && !body.ends_with('\n') {
+        body.push_str("\r\n");
+    }
+    body.push_str(SIGNATURE_DELIMITER);
+    body.push_str("\r\n");
+    for (pos, line) in signature.lines().enumerate() {
+        if pos > 0 {
+            body.push_str("\r\n");
+        }
+        body.push_str(line);
+    }
+    body.push_str("\r\n");
+    body
+}
+
+fn append_html(mut body: String, signature: &str) -> String {
+    let signature = format!(
+        "<div>{}<br>\r\n{}</div>\r\n",
+        SIGNATURE_DELIMITER, signature
+    );
+    if let Some(pos) = body.to_ascii_lowercase().rfind("</body>") {
+        body.insert_str(pos, &signature);
+    } else {
+        body.push_str(&signature);
+    }
+    body
+}
+
+fn encode_body(part: &MessagePart, body: &[u8]) -> Vec<u8> {
+    match part.encoding {
+        Encoding::None => body.to_vec(),
+        Encoding::Base64 => {
+            let encoded = base64::encode(body);
+            let mut result = Vec::with_capacity(encoded.len() + (encoded.len() / 76 + 1) * 2);
+            for line in encoded.as_bytes().chunks(76) {
+                result.extend_from_slice(line);
+                result.extend_from_slice(b"\r\n");
+            }
+            result
+        }
+        Encoding::QuotedPrintable => encode_quoted_printable(body).into_bytes(),
+    }
+}
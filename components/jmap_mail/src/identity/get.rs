@@ -23,13 +23,15 @@
 
 use jmap::jmap_store::get::{default_mapper, GetHelper, GetObject, SharedDocsFnc};
 use jmap::orm::serialize::JMAPOrm;
+use jmap::principal::schema::{self as principal, Principal};
 use jmap::request::get::{GetRequest, GetResponse};
 use jmap::types::jmap::JMAPId;
+use jmap::SUPERUSER_ID;
 
 use store::core::error::StoreError;
 use store::core::vec_map::VecMap;
-use store::JMAPStore;
 use store::Store;
+use store::{AccountId, JMAPStore};
 
 use super::schema::{Identity, Property, Value};
 
@@ -46,6 +48,7 @@ impl GetObject for Identity {
             Property::TextSignature,
             Property::HtmlSignature,
             Property::MayDelete,
+            Property::IsVerified,
         ]
     }
 
@@ -59,6 +62,10 @@ where
     T: for<'x> Store<'x> + 'static,
 {
     fn identity_get(&self, request: GetRequest<Identity>) -> jmap::Result<GetResponse<Identity>>;
+    fn identity_addresses(
+        &self,
+        account_id: AccountId,
+    ) -> store::Result<(Option<String>, Vec<String>)>;
 }
 
 impl<T> JMAPGetIdentity<T> for JMAPStore<T>
@@ -75,19 +82,54 @@ where
             helper.properties.push(Property::Id);
         }
 
+        // Obtain the primary and verified addresses of the account, which
+        // include the addresses of its groups when it may send as them.
+        let (primary_email, mut verified_emails) = if helper
+            .properties
+            .iter()
+            .any(|p| matches!(p, Property::MayDelete | Property::IsVerified))
+        {
+            self.identity_addresses(account_id)?
+        } else {
+            (None, Vec::new())
+        };
+        if helper.properties.contains(&Property::IsVerified)
+            && self.config.identity_send_as_groups
+            && account_id == helper.acl.primary_id()
+        {
+            for group_id in &helper.acl.member_of {
+                if *group_id != account_id {
+                    verified_emails.extend(self.identity_addresses(*group_id)?.1);
+                }
+            }
+        }
+
         helper.get(|id, properties| {
             let document_id = id.get_document_id();
             let mut fields = self
                 .get_orm::<Identity>(account_id, document_id)?
                 .ok_or_else(|| StoreError::NotFound("Identity data not found".to_string()))?;
             let mut identity = VecMap::with_capacity(properties.len());
+            let email = match fields.get(&Property::Email) {
+                Some(Value::Text { value }) => Some(value.to_string()),
+                _ => None,
+            };
 
             for property in properties {
                 identity.append(
                     *property,
                     match property {
                         Property::Id => Value::Id { value: id },
-                        Property::MayDelete => Value::Bool { value: true },
+                        Property::MayDelete => Value::Bool {
+                            value: identity_may_delete(email.as_deref(), primary_email.as_deref()),
+                        },
+                        Property::IsVerified => Value::Bool {
+                            value: email.as_ref().map_or(false, |email| {
+                                verified_emails
+                                    .iter()
+                                    .any(|verified| verified.eq_ignore_ascii_case(email))
+                            }),
+                        },
                         _ => fields.remove(property).unwrap_or_default(),
                     },
                 );
@@ -97,4 +139,41 @@ where
             }))
         })
     }
+
+    fn identity_addresses(
+        &self,
+        account_id: AccountId,
+    ) -> store::Result<(Option<String>, Vec<String>)> {
+        let mut fields =
+            if let Some(fields) = self.get_orm::<Principal>(SUPERUSER_ID, account_id)? {
+                fields
+            } else {
+                return Ok((None, Vec::new()));
+            };
+        let mut addresses = Vec::new();
+
+        let primary_email = if let Some(principal::Value::Text { value }) =
+            fields.remove(&principal::Property::Email)
+        {
+            addresses.push(value.clone());
+            Some(value)
+        } else {
+            None
+        };
+        if let Some(principal::Value::TextList { value }) =
+            fields.remove(&principal::Property::Aliases)
+        {
+            addresses.extend(value);
+        }
+
+        Ok((primary_email, addresses))
+    }
+}
+
+/// The identity of the account's primary address cannot be deleted.
+pub fn identity_may_delete(email: Option<&str>, primary_email: Option<&str>) -> bool {
+    match (email, primary_email) {
+        (Some(email), Some(primary_email)) => !email.eq_ignore_ascii_case(primary_email),
+        _ => true,
+    }
 }
@@ -86,7 +86,8 @@ pub enum Property {
     TextSignature = 5,
     HtmlSignature = 6,
     MayDelete = 7,
-    Invalid = 8,
+    IsVerified = 8,
+    Invalid = 9,
 }
 
 impl Property {
@@ -100,6 +101,7 @@ impl Property {
             "textSignature" => Property::TextSignature,
             "htmlSignature" => Property::HtmlSignature,
             "mayDelete" => Property::MayDelete,
+            "isVerified" => Property::IsVerified,
             _ => Property::Invalid,
         }
     }
@@ -116,6 +118,7 @@ impl Display for Property {
             Property::TextSignature => write!(f, "textSignature"),
             Property::HtmlSignature => write!(f, "htmlSignature"),
             Property::MayDelete => write!(f, "mayDelete"),
+            Property::IsVerified => write!(f, "isVerified"),
             Property::Invalid => Ok(()),
         }
     }
@@ -138,6 +141,7 @@ impl From<FieldId> for Property {
             5 => Property::TextSignature,
             6 => Property::HtmlSignature,
             7 => Property::MayDelete,
+            8 => Property::IsVerified,
             _ => Property::Invalid,
         }
     }
@@ -21,13 +21,14 @@
  * for more details.
 */
 
+use crate::identity::get::{identity_may_delete, JMAPGetIdentity};
 use crate::identity::schema::Identity;
 use jmap::error::set::{SetError, SetErrorType};
 use jmap::jmap_store::set::SetHelper;
 use jmap::jmap_store::Object;
 use jmap::orm::{serialize::JMAPOrm, TinyORM};
 use jmap::request::set::SetResponse;
-use jmap::request::ResultReference;
+use jmap::request::{ACLEnforce, ResultReference};
 use jmap::types::jmap::JMAPId;
 use jmap::{jmap_store::set::SetObject, request::set::SetRequest};
 use jmap::{principal, sanitize_email, SUPERUSER_ID};
@@ -104,11 +105,19 @@ where
                                     Comparator::None,
                                 )?
                                 .into_iter()
-                                .any(|id| id.get_document_id() == helper.account_id)
+                                .any(|id| {
+                                    // Besides its own addresses, an account may send as
+                                    // any group its owner belongs to.
+                                    let id = id.get_document_id();
+                                    id == helper.account_id
+                                        || (self.config.identity_send_as_groups
+                                            && helper.account_id == helper.acl.primary_id()
+                                            && helper.acl.member_of.contains(&id))
+                                })
                             {
-                                return Err(SetError::invalid_property(
-                                    Property::Email,
-                                    "E-mail address not configured for this account.".to_string(),
+                                return Err(SetError::new(
+                                    SetErrorType::ForbiddenFrom,
+                                    format!("You are not allowed to send as {}.", value),
                                 ));
                             }
                             Value::Text { value }
@@ -181,8 +190,22 @@ where
             Ok(None)
         })?;
 
+        let primary_email = if !helper.will_destroy.is_empty() {
+            self.identity_addresses(helper.account_id)?.0
+        } else {
+            None
+        };
         helper.destroy(|_id, helper, document| {
             if let Some(orm) = self.get_orm::<Identity>(helper.account_id, document.document_id)? {
+                let email = match orm.get(&Property::Email) {
+                    Some(Value::Text { value }) => Some(value.as_str()),
+                    _ => None,
+                };
+                if !identity_may_delete(email, primary_email.as_deref()) {
+                    return Err(SetError::forbidden(
+                        "The identity of the primary address cannot be deleted.",
+                    ));
+                }
                 orm.delete(document);
             }
             Ok(())
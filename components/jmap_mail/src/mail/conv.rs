@@ -531,13 +531,17 @@ impl HeaderForm {
                 (raw_message
                     .get(*start..*end)
                     .map_or(HeaderValue::Empty, |bytes| match self {
-                        HeaderForm::Raw => {
-                            HeaderValue::Text(std::str::from_utf8(bytes).map_or_else(
-                                |_| String::from_utf8_lossy(bytes).trim_end().to_string().into(),
-                                |str| str.trim_end().to_string().into(),
-                            ))
+                        HeaderForm::Raw => HeaderValue::Text(decode_lossy(bytes)),
+                        HeaderForm::Text => {
+                            match parse_unstructured(&mut MessageStream::new(bytes)) {
+                                HeaderValue::Text(text) => HeaderValue::Text(text),
+                                // Fall back to the raw value rather than dropping undecodable headers
+                                _ if !bytes.iter().all(|b| b.is_ascii_whitespace()) => {
+                                    HeaderValue::Text(decode_lossy(bytes).trim().to_string().into())
+                                }
+                                value => value,
+                            }
                         }
-                        HeaderForm::Text => parse_unstructured(&mut MessageStream::new(bytes)),
                         HeaderForm::Addresses => parse_address(&mut MessageStream::new(bytes)),
                         HeaderForm::GroupedAddresses => {
                             parse_address(&mut MessageStream::new(bytes))
@@ -551,3 +555,12 @@ impl HeaderForm {
             .collect()
     }
 }
+
+/// Decodes a raw header value, replacing any bytes that are not valid UTF-8
+/// with U+FFFD so that a single malformed header cannot fail the request.
+fn decode_lossy(bytes: &[u8]) -> Cow<'_, str> {
+    match String::from_utf8_lossy(bytes) {
+        Cow::Borrowed(text) => text.trim_end().into(),
+        Cow::Owned(text) => text.trim_end().to_string().into(),
+    }
+}
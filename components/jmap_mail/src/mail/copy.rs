@@ -240,7 +240,7 @@ where
             // Copy properties and build index
             let raw_blob = JMAPBlob::from(&message_data.raw_message);
             let size = message_data.size;
-            message_data.build_index(document, true)?;
+            message_data.build_index(document, true, self.config.mail_address_search)?;
 
             // Link metadata blob
             document.binary(
@@ -250,6 +250,16 @@ where
             );
             document.blob(metadata_blob_id, IndexOptions::new());
 
+            // Copy the stored preview
+            if let Some(preview) = self.get_document_value::<Vec<u8>>(
+                helper.from_account_id,
+                Collection::Mail,
+                document_id,
+                MessageField::Preview.into(),
+            )? {
+                document.binary(MessageField::Preview, preview, IndexOptions::new());
+            }
+
             // Add fields
             fields.insert(document)?;
 
@@ -287,7 +297,7 @@ where
                     create: None,
                     update: None,
                     destroy: Some(MaybeResultReference::Value(destroy_ids)),
-                    arguments: (),
+                    arguments: Default::default(),
                 }
                 .into()
             }
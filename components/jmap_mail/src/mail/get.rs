@@ -30,7 +30,7 @@ use super::{
     sharing::JMAPShareMail,
     GetRawHeader, HeaderName, MessagePart,
 };
-use crate::mail::{MessageData, MessageField, MimePart, MimePartType};
+use crate::mail::{deserialize_preview, MessageData, MessageField, MimePart, MimePartType};
 use jmap::{
     error::method::MethodError,
     jmap_store::get::{GetHelper, GetObject},
@@ -46,14 +46,20 @@ use mail_parser::{
     parsers::preview::{preview_html, preview_text, truncate_html, truncate_text},
     Encoding, HeaderValue, RfcHeader,
 };
-use std::{borrow::Cow, sync::Arc};
+use std::{
+    borrow::Cow,
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    io::Cursor,
+    sync::Arc,
+};
 use store::{
-    blob::BlobId,
+    blob::{BlobId, BlobReader},
     core::{
         acl::{ACLToken, ACL},
         vec_map::VecMap,
     },
-    AccountId, JMAPStore,
+    AccountId, CachedObject, JMAPStore, ObjectCacheKey,
 };
 use store::{
     core::{collection::Collection, error::StoreError},
@@ -137,8 +143,8 @@ impl Email {
     }
 }
 
-pub enum BlobResult {
-    Blob(Vec<u8>),
+pub enum BlobResult<B = Vec<u8>> {
+    Blob(B),
     Unauthorized,
     NotFound,
 }
@@ -154,6 +160,19 @@ where
         acl: &Arc<ACLToken>,
         blob: &JMAPBlob,
     ) -> store::Result<BlobResult>;
+    fn mail_blob_get_reader(
+        &self,
+        account_id: AccountId,
+        acl: &Arc<ACLToken>,
+        blob: &JMAPBlob,
+    ) -> store::Result<BlobResult<BlobReader>>;
+    fn mail_preview(
+        &self,
+        account_id: AccountId,
+        document_id: DocumentId,
+        message_data: &MessageData,
+        raw_message: Option<&[u8]>,
+    ) -> store::Result<String>;
 }
 
 impl<T> JMAPGetMail<T> for JMAPStore<T>
@@ -218,7 +237,8 @@ where
         let mut has_id = false;
         for property in &helper.properties {
             match property {
-                Property::Header(HeaderProperty {
+                Property::Headers
+                | Property::Header(HeaderProperty {
                     form: HeaderForm::Raw,
                     ..
                 })
@@ -230,7 +250,7 @@ where
                         fetch_raw = FetchRaw::Header;
                     }
                 }
-                Property::BodyStructure | Property::BodyValues | Property::Preview => {
+                Property::BodyValues => {
                     fetch_raw = FetchRaw::All;
                 }
                 Property::Id => {
@@ -240,7 +260,18 @@ where
             }
         }
 
+        // The MIME structure stored at import time describes every part, so
+        // body parts only need the raw message when their headers are requested
         if fetch_raw != FetchRaw::All
+            && helper.properties.iter().any(|prop| {
+                matches!(
+                    prop,
+                    Property::BodyStructure
+                        | Property::TextBody
+                        | Property::HtmlBody
+                        | Property::Attachments
+                )
+            })
             && body_properties
                 .iter()
                 .any(|prop| matches!(prop, BodyProperty::Headers | BodyProperty::Header(_)))
@@ -253,9 +284,51 @@ where
             helper.properties.push(Property::Id);
         }
 
+        // Sharees are only shown the mailboxes they are allowed to read
+        let readable_mailboxes = if helper.acl.is_shared(account_id) {
+            Some(self.mail_shared_folders(account_id, &helper.acl.member_of, ACL::ReadItems)?)
+        } else {
+            None
+        };
+
+        // Assembled emails are cached per set of requested properties, shared
+        // accounts are excluded as their mailboxIds depend on the sharee
+        let object_cache = self
+            .objects
+            .as_ref()
+            .filter(|_| readable_mailboxes.is_none());
+        let (change_id, properties_hash) = if object_cache.is_some() {
+            let mut hasher = DefaultHasher::new();
+            helper.properties.hash(&mut hasher);
+            body_properties.hash(&mut hasher);
+            fetch_text_body_values.hash(&mut hasher);
+            fetch_html_body_values.hash(&mut hasher);
+            fetch_all_body_values.hash(&mut hasher);
+            max_body_value_bytes.hash(&mut hasher);
+            (
+                self.get_last_change_id(account_id, Collection::Mail)?,
+                hasher.finish(),
+            )
+        } else {
+            (None, 0)
+        };
+
         // Get items
         helper.get(|id, properties| {
             let document_id = id.get_document_id();
+            let cache_key = ObjectCacheKey {
+                account_id,
+                collection: Collection::Mail,
+                id: id.into(),
+                properties_hash,
+            };
+            if let Some(cached) = object_cache.and_then(|cache| cache.get(&cache_key)) {
+                if cached.change_id == change_id {
+                    if let Some(email) = cached.object.downcast_ref::<Email>() {
+                        return Ok(Some(email.clone()));
+                    }
+                }
+            }
 
             // Fetch message metadata
             let message_data_bytes = self
@@ -314,6 +387,8 @@ where
                 ),
                 FetchRaw::None => None,
             };
+            let object_size =
+                message_data_bytes.len() + raw_message.as_ref().map_or(0, |raw| raw.len());
 
             // Fetch ORM
             let fields = self
@@ -331,7 +406,17 @@ where
                     }
                     .into(),
                     Property::ThreadId => Value::Id {
-                        value: id.get_prefix_id().into(),
+                        // Use the stored thread id, the requested id might carry the
+                        // prefix of a thread that was merged since it was issued.
+                        value: self
+                            .get_document_value::<DocumentId>(
+                                account_id,
+                                Collection::Mail,
+                                document_id,
+                                MessageField::ThreadId.into(),
+                            )?
+                            .unwrap_or_else(|| id.get_prefix_id())
+                            .into(),
                     }
                     .into(),
                     Property::MailboxIds => {
@@ -340,6 +425,13 @@ where
                             .map(|tags| Value::MailboxIds {
                                 value: tags
                                     .iter()
+                                    .filter(|tag| {
+                                        readable_mailboxes.as_ref().map_or(true, |mailboxes| {
+                                            mailboxes.as_ref().as_ref().map_or(false, |mailboxes| {
+                                                mailboxes.contains(tag.as_id())
+                                            })
+                                        })
+                                    })
                                     .map(|tag| (MaybeIdReference::Value(tag.as_id().into()), true))
                                     .collect(),
                                 set: true,
@@ -427,55 +519,15 @@ where
                     Property::Preview => {
                         if !message_data.text_body.is_empty() || !message_data.html_body.is_empty()
                         {
-                            let parts = if !message_data.text_body.is_empty() {
-                                &message_data.text_body
-                            } else {
-                                &message_data.html_body
-                            };
-
-                            let mime_part = parts
-                                .first()
-                                .and_then(|p| message_data.mime_parts.get(*p))
-                                .ok_or_else(|| {
-                                    StoreError::DataCorruption(format!(
-                                        "Missing message part for {}/{}",
-                                        account_id, document_id
-                                    ))
-                                })?;
-
-                            #[allow(clippy::type_complexity)]
-                            let (preview_fnc, part): (
-                                fn(Cow<str>, usize) -> Cow<str>,
-                                _,
-                            ) = match &mime_part.mime_type {
-                                MimePartType::Text { part } => (preview_text, part),
-                                MimePartType::Html { part } => (preview_html, part),
-                                _ => {
-                                    return Err(StoreError::NotFound(format!(
-                                        "Message part blobId not found for {}/{}.",
-                                        account_id, document_id
-                                    ))
-                                    .into());
-                                }
-                            };
-
                             Value::Text {
-                                value: preview_fnc(
-                                    part.decode_text(
-                                        raw_message.as_ref().unwrap(),
-                                        mime_part.charset.as_deref(),
-                                        true,
-                                    )
-                                    .ok_or_else(|| {
-                                        StoreError::DataCorruption(format!(
-                                            "Failed to decode part for {}/{}.",
-                                            account_id, document_id
-                                        ))
-                                    })?
-                                    .into(),
-                                    256,
-                                )
-                                .into_owned(),
+                                value: self.mail_preview(
+                                    account_id,
+                                    document_id,
+                                    &message_data,
+                                    raw_message
+                                        .as_deref()
+                                        .filter(|_| fetch_raw == FetchRaw::All),
+                                )?,
                             }
                             .into()
                         } else {
@@ -567,7 +619,18 @@ where
                 email.append(property.clone(), value.unwrap_or_default());
             }
 
-            Ok(Some(Email { properties: email }))
+            let email = Email { properties: email };
+            if let Some(cache) = object_cache {
+                cache.insert(
+                    cache_key,
+                    CachedObject {
+                        change_id,
+                        size: object_size,
+                        object: Arc::new(email.clone()),
+                    },
+                );
+            }
+            Ok(Some(email))
         })
     }
 
@@ -577,23 +640,8 @@ where
         acl: &Arc<ACLToken>,
         blob: &JMAPBlob,
     ) -> store::Result<BlobResult> {
-        if !self.blob_account_has_access(&blob.id, &acl.member_of)? && !acl.is_member(SUPERUSER_ID)
-        {
-            if let Some(shared_ids) = self
-                .mail_shared_messages(account_id, &acl.member_of, ACL::ReadItems)?
-                .as_ref()
-            {
-                if !self.blob_document_has_access(
-                    &blob.id,
-                    account_id,
-                    Collection::Mail,
-                    shared_ids,
-                )? {
-                    return Ok(BlobResult::Unauthorized);
-                }
-            } else {
-                return Ok(BlobResult::Unauthorized);
-            }
+        if let Some(result) = blob_access_denied(self, account_id, acl, &blob.id)? {
+            return Ok(result);
         }
 
         Ok(if let Some(section) = &blob.section {
@@ -621,6 +669,149 @@ where
         .map(BlobResult::Blob)
         .unwrap_or(BlobResult::NotFound))
     }
+
+    fn mail_blob_get_reader(
+        &self,
+        account_id: AccountId,
+        acl: &Arc<ACLToken>,
+        blob: &JMAPBlob,
+    ) -> store::Result<BlobResult<BlobReader>> {
+        if let Some(result) = blob_access_denied(self, account_id, acl, &blob.id)? {
+            return Ok(result);
+        }
+
+        Ok(match &blob.section {
+            // Encoded sections have to be decoded in memory
+            Some(section) if Encoding::from(section.encoding) != Encoding::None => self
+                .blob_get_range(
+                    &blob.id,
+                    (section.offset_start as u32)
+                        ..(section.offset_start.saturating_add(section.size) as u32),
+                )?
+                .and_then(|bytes| {
+                    MessagePart {
+                        offset_start: 0,
+                        offset_end: section.size,
+                        encoding: Encoding::from(section.encoding),
+                    }
+                    .decode(&bytes)
+                })
+                .map(|bytes| Box::new(Cursor::new(bytes)) as BlobReader),
+            Some(section) => self.blob_get_reader(
+                &blob.id,
+                (section.offset_start as u32)
+                    ..(section.offset_start.saturating_add(section.size) as u32),
+            )?,
+            None => self.blob_get_reader(&blob.id, 0..u32::MAX)?,
+        }
+        .map(BlobResult::Blob)
+        .unwrap_or(BlobResult::NotFound))
+    }
+
+    fn mail_preview(
+        &self,
+        account_id: AccountId,
+        document_id: DocumentId,
+        message_data: &MessageData,
+        raw_message: Option<&[u8]>,
+    ) -> store::Result<String> {
+        let preview_length = self.config.mail_preview_length;
+
+        // Use the preview stored at import time, unless it was generated for
+        // a different length or the message predates stored previews
+        if let Some(preview) = self
+            .get_document_value::<Vec<u8>>(
+                account_id,
+                Collection::Mail,
+                document_id,
+                MessageField::Preview.into(),
+            )?
+            .and_then(|bytes| deserialize_preview(&bytes, preview_length))
+        {
+            return Ok(preview);
+        }
+
+        let parts = if !message_data.text_body.is_empty() {
+            &message_data.text_body
+        } else {
+            &message_data.html_body
+        };
+        let mime_part = parts
+            .first()
+            .and_then(|p| message_data.mime_parts.get(*p))
+            .ok_or_else(|| {
+                StoreError::DataCorruption(format!(
+                    "Missing message part for {}/{}",
+                    account_id, document_id
+                ))
+            })?;
+
+        #[allow(clippy::type_complexity)]
+        let (preview_fnc, part): (fn(Cow<str>, usize) -> Cow<str>, _) = match &mime_part.mime_type {
+            MimePartType::Text { part } => (preview_text, part),
+            MimePartType::Html { part } => (preview_html, part),
+            _ => {
+                return Err(StoreError::NotFound(format!(
+                    "Message part blobId not found for {}/{}.",
+                    account_id, document_id
+                )));
+            }
+        };
+
+        let fetched_message;
+        let raw_message = if let Some(raw_message) = raw_message {
+            raw_message
+        } else {
+            fetched_message = self.blob_get(&message_data.raw_message)?.ok_or_else(|| {
+                StoreError::NotFound(format!(
+                    "Raw email message not found for {}/{}.",
+                    account_id, document_id
+                ))
+            })?;
+            &fetched_message
+        };
+
+        Ok(preview_fnc(
+            part.decode_text(raw_message, mime_part.charset.as_deref(), true)
+                .ok_or_else(|| {
+                    StoreError::DataCorruption(format!(
+                        "Failed to decode part for {}/{}.",
+                        account_id, document_id
+                    ))
+                })?
+                .into(),
+            preview_length,
+        )
+        .into_owned())
+    }
+}
+
+fn blob_access_denied<T, B>(
+    store: &JMAPStore<T>,
+    account_id: AccountId,
+    acl: &Arc<ACLToken>,
+    blob_id: &BlobId,
+) -> store::Result<Option<BlobResult<B>>>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    if !store.blob_account_has_access(blob_id, &acl.member_of)? && !acl.is_member(SUPERUSER_ID) {
+        if acl.is_member(account_id) {
+            // Do not reveal the existence of blobs owned by other accounts
+            return Ok(Some(BlobResult::NotFound));
+        } else if let Some(shared_ids) = store
+            .mail_shared_messages(account_id, &acl.member_of, ACL::ReadItems)?
+            .as_ref()
+        {
+            if !store.blob_document_has_access(blob_id, account_id, Collection::Mail, shared_ids)? {
+                return Ok(Some(BlobResult::NotFound));
+            }
+        } else {
+            return Ok(Some(BlobResult::Unauthorized));
+        }
+    }
+
+    Ok(None)
 }
 
 impl MimePart {
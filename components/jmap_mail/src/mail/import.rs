@@ -37,39 +37,45 @@ use jmap::types::jmap::JMAPId;
 use jmap::types::state::JMAPState;
 use mail_parser::decoders::html::html_to_text;
 use mail_parser::parsers::fields::thread::thread_name;
+use mail_parser::parsers::preview::{preview_html, preview_text};
 use mail_parser::{
-    GetHeader, HeaderName, HeaderValue, Message, MessageAttachment, PartType, RfcHeader,
+    Encoding, GetHeader, HeaderName, HeaderValue, Message, MessageAttachment, PartType, RfcHeader,
 };
 use store::ahash::AHashMap;
 use store::ahash::AHashSet;
 use store::blob::BlobId;
 use store::core::acl::{ACLToken, ACL};
 use store::core::collection::Collection;
-use store::core::document::{Document, MAX_ID_LENGTH, MAX_SORT_FIELD_LENGTH};
+use store::core::document::{Document, MAX_ID_LENGTH, MAX_SORT_FIELD_LENGTH, MAX_TOKEN_LENGTH};
 use store::core::error::StoreError;
 use store::core::tag::Tag;
 use store::core::vec_map::VecMap;
 use store::core::JMAPIdPrefix;
 use store::log::changes::ChangeId;
+use store::nlp::tokenizers::Tokenizer;
 use store::nlp::Language;
 use store::read::comparator::Comparator;
 use store::read::filter::{Filter, Query};
 use store::read::FilterMapper;
-use store::serialize::StoreSerialize;
+use store::serialize::{StoreDeserialize, StoreSerialize};
 
-use store::tracing::error;
+use store::tracing::{debug, error};
 use store::write::batch::WriteBatch;
 use store::write::options::{IndexOptions, Options};
-use store::{AccountId, JMAPStore, SharedBitmap, Store, ThreadId};
+use store::{AccountId, FieldId, JMAPStore, SharedBitmap, Store, ThreadId};
 use store::{DocumentId, Integer, LongInteger};
 
 use crate::mail::MessageField;
+use crate::mailbox::schema::Property as MailboxProperty;
 
 use super::conv::HeaderValueInto;
 use super::get::{BlobResult, JMAPGetMail};
 use super::schema::{Email, Keyword, Property};
 use super::sharing::JMAPShareMail;
-use super::{MessageData, MessagePart, MimePart, MimePartType, MAX_MESSAGE_PARTS};
+use super::{
+    address_grams, address_language, serialize_preview, MessageData, MessagePart, MimePart,
+    MimePartType, MAX_MESSAGE_PARTS,
+};
 
 #[derive(Debug, Clone, serde::Deserialize)]
 pub struct EmailImportRequest {
@@ -92,6 +98,7 @@ pub struct EmailImport {
     pub mailbox_ids: Option<MaybeResultReference<VecMap<MaybeIdReference, bool>>>,
     pub keywords: Option<VecMap<Keyword, bool>>,
     pub received_at: Option<JMAPDate>,
+    pub received_at_from_date: bool,
 }
 
 #[derive(Debug, Clone, serde::Serialize)]
@@ -128,6 +135,29 @@ pub trait JMAPMailImport {
         received_at: Option<i64>,
     ) -> jmap::Result<Email>;
 
+    fn mail_sent_at(&self, blob: &[u8]) -> Option<i64>;
+
+    fn mail_has_drafts_mailbox(
+        &self,
+        account_id: AccountId,
+        mailbox_ids: &[DocumentId],
+    ) -> store::Result<bool>;
+
+    fn mail_find_duplicate(
+        &self,
+        account_id: AccountId,
+        message_id: Option<&str>,
+        mailbox_ids: &[DocumentId],
+    ) -> store::Result<Option<DocumentId>>;
+
+    fn mail_import_duplicate(
+        &self,
+        account_id: AccountId,
+        document_id: DocumentId,
+        mailbox_ids: Vec<DocumentId>,
+        keywords: Vec<Tag>,
+    ) -> jmap::Result<Email>;
+
     fn mail_parse_item(
         &self,
         document: &mut Document,
@@ -136,17 +166,50 @@ pub trait JMAPMailImport {
         received_at: Option<i64>,
     ) -> store::Result<()>;
 
+    fn mail_parse_unparsed_item(
+        &self,
+        document: &mut Document,
+        blob_id: BlobId,
+        size: usize,
+        received_at: Option<i64>,
+    ) -> store::Result<()>;
+
+    fn mail_store_data(
+        &self,
+        document: &mut Document,
+        message_data: MessageData,
+    ) -> store::Result<()>;
+
     fn mail_set_thread(
         &self,
         batch: &mut WriteBatch,
         document: &mut Document,
     ) -> store::Result<DocumentId>;
 
+    fn mail_thread_ids(
+        &self,
+        account_id: AccountId,
+        filter: Filter,
+    ) -> store::Result<AHashSet<ThreadId>>;
+
     fn mail_merge_threads(
         &self,
         documents: &mut WriteBatch,
         thread_ids: Vec<ThreadId>,
     ) -> store::Result<ThreadId>;
+
+    fn mail_move_thread(
+        &self,
+        batch: &mut WriteBatch,
+        document_id: DocumentId,
+        thread_id: ThreadId,
+    ) -> store::Result<Option<ThreadId>>;
+
+    fn mail_log_mailboxes(
+        &self,
+        batch: &mut WriteBatch,
+        document_id: DocumentId,
+    ) -> store::Result<()>;
 }
 
 impl<T> JMAPMailImport for JMAPStore<T>
@@ -211,6 +274,32 @@ where
                     }
                 }
 
+                let mailbox_ids = mailbox_ids
+                    .into_iter()
+                    .filter_map(|(id, set)| {
+                        if set {
+                            id.get_document_id().into()
+                        } else {
+                            None
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                let keywords = item
+                    .keywords
+                    .map(|keywords| {
+                        keywords
+                            .into_iter()
+                            .filter_map(|(k, set)| {
+                                if set {
+                                    k.truncate(self.config.mail_keyword_max_length).tag.into()
+                                } else {
+                                    None
+                                }
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default();
+
                 // Make sure the message does not exist already
                 {
                     let _lock = self.lock_collection(account_id, Collection::Mail);
@@ -219,84 +308,49 @@ where
                         account_id,
                         Collection::Mail,
                     )? {
-                        if let (Some(thread_id), Some(current_fields)) = (
-                            self.get_document_value::<DocumentId>(
+                        created.append(
+                            id,
+                            self.mail_import_duplicate(
                                 account_id,
-                                Collection::Mail,
                                 document_id,
-                                MessageField::ThreadId.into(),
+                                mailbox_ids,
+                                keywords,
                             )?,
-                            self.get_orm::<Email>(account_id, document_id)?,
-                        ) {
-                            let email_id = JMAPId::from_parts(thread_id, document_id);
-                            let mut fields = TinyORM::track_changes(&current_fields);
-                            for mailbox_id in mailbox_ids.keys() {
-                                fields.tag(
-                                    Property::MailboxIds,
-                                    Tag::Id(mailbox_id.get_document_id()),
-                                );
-                            }
-                            let added_mailboxes =
-                                current_fields.get_added_tags(&fields, &Property::MailboxIds);
-                            if !added_mailboxes.is_empty() {
-                                let mut batch = WriteBatch::new(account_id);
-                                let mut document = Document::new(Collection::Mail, document_id);
-
-                                for added_mailbox in added_mailboxes {
-                                    batch.log_child_update(
-                                        Collection::Mailbox,
-                                        added_mailbox.as_id(),
-                                    );
-                                }
-                                current_fields.merge(&mut document, fields)?;
-                                debug_assert!(!document.is_empty());
-                                batch.update_document(document);
-                                batch.log_update(Collection::Mail, email_id);
-                                self.write(batch)?;
-                            }
-
-                            let mut email = Email::default();
-                            email.insert(Property::Id, email_id);
-                            email.insert(Property::BlobId, JMAPBlob::new(item.blob_id.id));
-                            email.insert(Property::ThreadId, JMAPId::from(thread_id));
-
-                            created.append(id, email);
-                            continue 'outer;
-                        }
+                        );
+                        continue 'outer;
                     }
                 }
 
                 match self.mail_blob_get(account_id, &acl, &item.blob_id)? {
                     BlobResult::Blob(blob) => {
-                        created.append(
-                            id,
-                            self.mail_import_item(
-                                account_id,
-                                item.blob_id.id,
-                                &blob,
-                                mailbox_ids
-                                    .into_iter()
-                                    .filter_map(|(id, set)| {
-                                        if set {
-                                            id.get_document_id().into()
-                                        } else {
-                                            None
-                                        }
-                                    })
-                                    .collect(),
-                                item.keywords
-                                    .map(|keywords| {
-                                        keywords
-                                            .into_iter()
-                                            .filter_map(
-                                                |(k, set)| if set { k.tag.into() } else { None },
-                                            )
-                                            .collect()
-                                    })
-                                    .unwrap_or_default(),
-                                item.received_at.map(|t| t.timestamp()),
-                            )?,
-                        );
+                        // Use the time the message was sent if requested, so that
+                        // threads are ordered by when their messages were written
+                        let received_at = if item.received_at_from_date {
+                            self.mail_sent_at(&blob)
+                        } else {
+                            None
+                        }
+                        .or_else(|| item.received_at.map(|t| t.timestamp()));
+
+                        match self.mail_import_item(
+                            account_id,
+                            item.blob_id.id,
+                            &blob,
+                            mailbox_ids,
+                            keywords,
+                            received_at,
+                        ) {
+                            Ok(email) => {
+                                created.append(id, email);
+                            }
+                            Err(MethodError::InvalidArguments(description)) => {
+                                not_created.append(
+                                    id,
+                                    SetError::new(SetErrorType::InvalidEmail, description),
+                                );
+                            }
+                            Err(err) => return Err(err),
+                        }
                     }
                     BlobResult::Unauthorized => {
                         not_created.append(
@@ -358,24 +412,55 @@ where
         keywords: Vec<Tag>,
         received_at: Option<i64>,
     ) -> jmap::Result<Email> {
+        // Parse message, blobs without any headers or content are either rejected
+        // or stored as-is so the raw message can still be retrieved.
+        let message = Message::parse(blob).filter(|message| !is_unrecognized(message));
+        if message.is_none() && !self.config.import_store_unparsed {
+            return Err(MethodError::InvalidArguments(
+                "Failed to parse e-mail message.".to_string(),
+            ));
+        }
+
+        // Skip parsing messages that already exist, the lookup is repeated
+        // under the lock in case a copy is being imported concurrently.
+        let message_id = message
+            .as_ref()
+            .and_then(|message| message.get_message_id())
+            .map(|message_id| message_id.to_string());
+        if let Some(document_id) =
+            self.mail_find_duplicate(account_id, message_id.as_deref(), &mailbox_ids)?
+        {
+            let _lock = self.lock_collection(account_id, Collection::Mail);
+            return self.mail_import_duplicate(account_id, document_id, mailbox_ids, keywords);
+        }
+
         let document_id = self.assign_document_id(account_id, Collection::Mail)?;
         let mut batch = WriteBatch::new(account_id);
         let mut document = Document::new(Collection::Mail, document_id);
         let size = blob.len();
 
-        // Parse message
         let raw_blob: JMAPBlob = (&blob_id).into();
-        self.mail_parse_item(
-            &mut document,
-            blob_id,
-            Message::parse(blob).ok_or_else(|| {
-                MethodError::InvalidArguments("Failed to parse e-mail message.".to_string())
-            })?,
-            received_at,
-        )?;
+        let mut orm = TinyORM::<Email>::new();
+        if let Some(message) = message {
+            self.mail_parse_item(&mut document, blob_id, message, received_at)?;
+        } else {
+            debug!(
+                "Storing unparsed message {} for account {}.",
+                raw_blob, account_id
+            );
+            self.mail_parse_unparsed_item(&mut document, blob_id, size, received_at)?;
+            orm.tag(Property::Keywords, Keyword::parse(UNPARSED_KEYWORD).tag);
+        }
+
+        // Lock collection while duplicates are looked up and threads are merged
+        let _lock = self.lock_collection(account_id, Collection::Mail);
+        if let Some(duplicate_id) =
+            self.mail_find_duplicate(account_id, message_id.as_deref(), &mailbox_ids)?
+        {
+            return self.mail_import_duplicate(account_id, duplicate_id, mailbox_ids, keywords);
+        }
 
         // Add keyword tags
-        let mut orm = TinyORM::<Email>::new();
         for keyword in keywords {
             orm.tag(Property::Keywords, keyword);
         }
@@ -389,9 +474,6 @@ where
         // Serialize ORM
         orm.insert(&mut document)?;
 
-        // Lock account while threads are merged
-        let _lock = self.lock_collection(batch.account_id, Collection::Mail);
-
         // Obtain thread Id
         let thread_id = self.mail_set_thread(&mut batch, &mut document)?;
 
@@ -411,6 +493,169 @@ where
         Ok(email)
     }
 
+    fn mail_sent_at(&self, blob: &[u8]) -> Option<i64> {
+        let sent_at = Message::parse(blob)?.get_date()?.to_timestamp();
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0) as i64;
+
+        // Dates before the epoch or too far in the future are most likely bogus
+        if sent_at > 0 && sent_at <= now + self.config.mail_sent_at_max_skew as i64 {
+            Some(sent_at)
+        } else {
+            debug!("Ignoring implausible Date header timestamp {}.", sent_at);
+            None
+        }
+    }
+
+    fn mail_has_drafts_mailbox(
+        &self,
+        account_id: AccountId,
+        mailbox_ids: &[DocumentId],
+    ) -> store::Result<bool> {
+        Ok(self
+            .query_store::<FilterMapper>(
+                account_id,
+                Collection::Mailbox,
+                Filter::eq(
+                    MailboxProperty::Role.into(),
+                    Query::Keyword("drafts".to_string()),
+                ),
+                Comparator::None,
+            )?
+            .into_iter()
+            .any(|id| mailbox_ids.contains(&id.get_document_id())))
+    }
+
+    fn mail_find_duplicate(
+        &self,
+        account_id: AccountId,
+        message_id: Option<&str>,
+        mailbox_ids: &[DocumentId],
+    ) -> store::Result<Option<DocumentId>> {
+        // Look for messages with the same Message-ID
+        let message_id = if let Some(message_id) = message_id {
+            message_id
+        } else {
+            return Ok(None);
+        };
+        if let Some(document_id) = self
+            .query_store::<FilterMapper>(
+                account_id,
+                Collection::Mail,
+                Filter::eq(
+                    RfcHeader::MessageId as FieldId,
+                    Query::Keyword(message_id.to_string()),
+                ),
+                Comparator::None,
+            )?
+            .into_iter()
+            .next()
+            .map(|id| id.get_document_id())
+        {
+            // Drafts are saved repeatedly under the same Message-ID, so every
+            // version imported into a Drafts mailbox is stored separately.
+            if self.config.import_dedup_by_message_id
+                && !self.mail_has_drafts_mailbox(account_id, mailbox_ids)?
+            {
+                return Ok(Some(document_id));
+            }
+            debug!(
+                "Account {} already contains a message with Message-ID {:?}.",
+                account_id, message_id
+            );
+        }
+        Ok(None)
+    }
+
+    fn mail_import_duplicate(
+        &self,
+        account_id: AccountId,
+        document_id: DocumentId,
+        mailbox_ids: Vec<DocumentId>,
+        keywords: Vec<Tag>,
+    ) -> jmap::Result<Email> {
+        let thread_id = self
+            .get_document_value::<DocumentId>(
+                account_id,
+                Collection::Mail,
+                document_id,
+                MessageField::ThreadId.into(),
+            )?
+            .ok_or_else(|| {
+                StoreError::NotFound(format!(
+                    "Thread id for {}/{} does not exist.",
+                    account_id, document_id
+                ))
+            })?;
+        let message_data = self
+            .get_document_value::<BlobId>(
+                account_id,
+                Collection::Mail,
+                document_id,
+                MessageField::Metadata.into(),
+            )?
+            .map(|blob_id| self.blob_get(&blob_id))
+            .transpose()?
+            .flatten()
+            .and_then(|bytes| MessageData::deserialize(&bytes))
+            .ok_or_else(|| {
+                StoreError::DataCorruption(format!(
+                    "Failed to fetch email metadata for {}/{}.",
+                    account_id, document_id
+                ))
+            })?;
+        let current_fields = self
+            .get_orm::<Email>(account_id, document_id)?
+            .ok_or_else(|| StoreError::NotFound("ORM not found for Email.".to_string()))?;
+        let email_id = JMAPId::from_parts(thread_id, document_id);
+
+        // Add the existing message to the requested mailboxes and keywords
+        let mut fields = TinyORM::track_changes(&current_fields);
+        for mailbox_id in mailbox_ids {
+            fields.tag(Property::MailboxIds, Tag::Id(mailbox_id));
+        }
+        for keyword in keywords {
+            fields.tag(Property::Keywords, keyword);
+        }
+        let mut changed_mailboxes = current_fields
+            .get_added_tags(&fields, &Property::MailboxIds)
+            .into_iter()
+            .map(|tag| tag.as_id())
+            .collect::<AHashSet<_>>();
+        let added_keywords = current_fields.get_added_tags(&fields, &Property::Keywords);
+        if !changed_mailboxes.is_empty() || !added_keywords.is_empty() {
+            let mut batch = WriteBatch::new(account_id);
+            let mut document = Document::new(Collection::Mail, document_id);
+
+            // Marking the message as seen changes the unread counts of its mailboxes
+            if added_keywords
+                .iter()
+                .any(|keyword| matches!(keyword, Tag::Static(k_id) if k_id == &Keyword::SEEN))
+            {
+                for mailbox_tag in fields.get_tags(&Property::MailboxIds).into_iter().flatten() {
+                    changed_mailboxes.insert(mailbox_tag.as_id());
+                }
+            }
+            for changed_mailbox in changed_mailboxes {
+                batch.log_child_update(Collection::Mailbox, changed_mailbox);
+            }
+            current_fields.merge(&mut document, fields)?;
+            batch.update_document(document);
+            batch.log_update(Collection::Mail, email_id);
+            self.write(batch)?;
+        }
+
+        let mut email = Email::default();
+        email.insert(Property::Id, email_id);
+        email.insert(Property::BlobId, JMAPBlob::from(&message_data.raw_message));
+        email.insert(Property::ThreadId, JMAPId::from(thread_id));
+        email.insert(Property::Size, message_data.size);
+
+        Ok(email)
+    }
+
     fn mail_parse_item(
         &self,
         document: &mut Document,
@@ -437,6 +682,14 @@ where
             has_attachments: false,
         };
         let mut has_attachments = false;
+        let mut preview = None;
+
+        // The preview is taken from the first text part, or the first HTML part otherwise
+        let preview_part_id = message_data
+            .text_body
+            .first()
+            .or_else(|| message_data.html_body.first())
+            .copied();
 
         if message.parts.len() > MAX_MESSAGE_PARTS {
             return Err(StoreError::InvalidArguments(
@@ -537,6 +790,13 @@ where
                         MessageField::Attachment
                     };
 
+                    if preview_part_id == Some(part_id) {
+                        preview =
+                            preview_html(html.as_ref().into(), self.config.mail_preview_length)
+                                .into_owned()
+                                .into();
+                    }
+
                     document.text(
                         field,
                         html_to_text(html.as_ref()),
@@ -544,7 +804,8 @@ where
                         IndexOptions::new().full_text((part_id + 1) as u32),
                     );
 
-                    (MimePartType::Html { part }, html.len())
+                    let part_len = part.decoded_len(&message.raw_message);
+                    (MimePartType::Html { part }, part_len)
                 }
                 PartType::Text(text) => {
                     let field = if message_data.text_body.contains(&part_id)
@@ -556,14 +817,22 @@ where
                         MessageField::Attachment
                     };
 
-                    let text_len = text.len();
+                    if preview_part_id == Some(part_id) {
+                        preview =
+                            preview_text(text.as_ref().into(), self.config.mail_preview_length)
+                                .into_owned()
+                                .into();
+                    }
+
                     document.text(
                         field,
                         text.into_owned(),
                         part_language,
                         IndexOptions::new().full_text((part_id + 1) as u32),
                     );
-                    (MimePartType::Text { part }, text_len)
+
+                    let part_len = part.decoded_len(&message.raw_message);
+                    (MimePartType::Text { part }, part_len)
                 }
                 PartType::Binary(binary) => {
                     if !has_attachments {
@@ -571,6 +840,7 @@ where
                     }
                     (MimePartType::Other { part }, binary.len())
                 }
+                // Inline images are rendered as part of the body, not as attachments.
                 PartType::InlineBinary(binary) => (MimePartType::Other { part }, binary.len()),
                 PartType::Message(nested_message) => {
                     if !has_attachments {
@@ -608,6 +878,62 @@ where
             message_data.has_attachments = true;
         }
 
+        if let Some(preview) = preview {
+            document.binary(
+                MessageField::Preview,
+                serialize_preview(self.config.mail_preview_length, &preview),
+                IndexOptions::new(),
+            );
+        }
+
+        self.mail_store_data(document, message_data)
+    }
+
+    fn mail_parse_unparsed_item(
+        &self,
+        document: &mut Document,
+        blob_id: BlobId,
+        size: usize,
+        received_at: Option<i64>,
+    ) -> store::Result<()> {
+        // The whole blob is exposed as a single opaque part
+        let message_data = MessageData {
+            headers: VecMap::new(),
+            body_offset: 0,
+            mime_parts: vec![MimePart::from_headers(
+                Vec::new(),
+                MimePartType::Other {
+                    part: MessagePart {
+                        offset_start: 0,
+                        offset_end: size,
+                        encoding: Encoding::None,
+                    },
+                },
+                false,
+                size,
+            )],
+            html_body: Vec::new(),
+            text_body: Vec::new(),
+            attachments: Vec::new(),
+            raw_message: blob_id,
+            size,
+            received_at: received_at.unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0) as i64
+            }),
+            has_attachments: false,
+        };
+
+        self.mail_store_data(document, message_data)
+    }
+
+    fn mail_store_data(
+        &self,
+        document: &mut Document,
+        message_data: MessageData,
+    ) -> store::Result<()> {
         // Link blob and set message data field
         let metadata_bytes = message_data
             .serialize()
@@ -623,7 +949,7 @@ where
         document.blob(metadata_blob_id, IndexOptions::new());
 
         // Build index
-        message_data.build_index(document, true)
+        message_data.build_index(document, true, self.config.mail_address_search)
     }
 
     fn mail_set_thread(
@@ -632,68 +958,59 @@ where
         document: &mut Document,
     ) -> store::Result<DocumentId> {
         // Obtain thread name and reference ids
-        let mut reference_ids = Vec::new();
+        // (deduplicated, as References may repeat ids or list the message's own Message-ID)
+        let mut reference_ids = AHashSet::new();
         let mut thread_name = None;
         for field in &document.text_fields {
             if field.field == MessageField::ThreadName as u8 {
                 thread_name = field.value.text.as_str().into();
             } else if field.field == MessageField::MessageIdRef as u8 {
-                reference_ids.push(field.value.text.as_str());
+                reference_ids.insert(field.value.text.as_str());
             }
         }
 
-        // Obtain thread id
-        let thread_id = if !reference_ids.is_empty() {
-            // Obtain thread ids for all matching document ids
-            let thread_ids = self
-                .get_multi_document_value(
-                    batch.account_id,
-                    Collection::Mail,
-                    self.query_store::<FilterMapper>(
-                        batch.account_id,
-                        Collection::Mail,
-                        Filter::and(vec![
+        // Obtain the threads of any messages sharing a reference id, regardless
+        // of the order in which they arrived, and optionally fall back to the subject
+        let mut thread_ids = if !reference_ids.is_empty() {
+            self.mail_thread_ids(
+                batch.account_id,
+                Filter::or(
+                    reference_ids
+                        .iter()
+                        .map(|id| {
                             Filter::eq(
-                                MessageField::ThreadName.into(),
-                                Query::Keyword(thread_name.unwrap_or("!").to_string()),
-                            ),
-                            Filter::or(
-                                reference_ids
-                                    .iter()
-                                    .map(|id| {
-                                        Filter::eq(
-                                            MessageField::MessageIdRef.into(),
-                                            Query::Keyword(id.to_string()),
-                                        )
-                                    })
-                                    .collect(),
-                            ),
-                        ]),
-                        Comparator::None,
-                    )?
-                    .into_iter()
-                    .map(|id| id.get_document_id())
-                    .collect::<Vec<DocumentId>>()
-                    .into_iter(),
-                    MessageField::ThreadId.into(),
-                )?
-                .into_iter()
-                .flatten()
-                .collect::<AHashSet<ThreadId>>();
+                                MessageField::MessageIdRef.into(),
+                                Query::Keyword(id.to_string()),
+                            )
+                        })
+                        .collect(),
+                ),
+            )?
+        } else {
+            AHashSet::new()
+        };
+        if thread_ids.is_empty() && self.config.mail_thread_subject_fallback {
+            if let Some(thread_name) = thread_name.filter(|name| *name != "!") {
+                thread_ids = self.mail_thread_ids(
+                    batch.account_id,
+                    Filter::eq(
+                        MessageField::ThreadName.into(),
+                        Query::Keyword(thread_name.to_string()),
+                    ),
+                )?;
+            }
+        }
 
-            match thread_ids.len() {
-                1 => {
-                    // There was just one match, use it as the thread id
-                    thread_ids.into_iter().next()
-                }
-                0 => None,
-                _ => {
-                    // Merge all matching threads
-                    Some(self.mail_merge_threads(batch, thread_ids.into_iter().collect())?)
-                }
+        let thread_id = match thread_ids.len() {
+            1 => {
+                // There was just one match, use it as the thread id
+                thread_ids.into_iter().next()
+            }
+            0 => None,
+            _ => {
+                // Merge all matching threads
+                Some(self.mail_merge_threads(batch, thread_ids.into_iter().collect())?)
             }
-        } else {
-            None
         };
 
         let thread_id = if let Some(thread_id) = thread_id {
@@ -720,6 +1037,32 @@ where
         Ok(thread_id)
     }
 
+    fn mail_thread_ids(
+        &self,
+        account_id: AccountId,
+        filter: Filter,
+    ) -> store::Result<AHashSet<ThreadId>> {
+        Ok(self
+            .get_multi_document_value(
+                account_id,
+                Collection::Mail,
+                self.query_store::<FilterMapper>(
+                    account_id,
+                    Collection::Mail,
+                    filter,
+                    Comparator::None,
+                )?
+                .into_iter()
+                .map(|id| id.get_document_id())
+                .collect::<Vec<DocumentId>>()
+                .into_iter(),
+                MessageField::ThreadId.into(),
+            )?
+            .into_iter()
+            .flatten()
+            .collect::<AHashSet<ThreadId>>())
+    }
+
     fn mail_merge_threads(
         &self,
         batch: &mut WriteBatch,
@@ -781,6 +1124,7 @@ where
                     JMAPId::from_parts(thread_id, document_id),
                 );
                 batch.update_document(document);
+                self.mail_log_mailboxes(batch, document_id)?;
             }
 
             batch.log_delete(Collection::Thread, delete_thread_id);
@@ -788,6 +1132,88 @@ where
 
         Ok(thread_id)
     }
+
+    fn mail_move_thread(
+        &self,
+        batch: &mut WriteBatch,
+        document_id: DocumentId,
+        thread_id: ThreadId,
+    ) -> store::Result<Option<ThreadId>> {
+        let prev_thread_id = self
+            .get_document_value::<DocumentId>(
+                batch.account_id,
+                Collection::Mail,
+                document_id,
+                MessageField::ThreadId.into(),
+            )?
+            .ok_or_else(|| {
+                StoreError::NotFound(format!(
+                    "Failed to fetch threadId for {}:{}.",
+                    batch.account_id, document_id
+                ))
+            })?;
+        if prev_thread_id == thread_id {
+            return Ok(None);
+        }
+
+        let mut document = Document::new(Collection::Mail, document_id);
+        document.tag(
+            MessageField::ThreadId,
+            Tag::Id(thread_id),
+            IndexOptions::new(),
+        );
+        document.tag(
+            MessageField::ThreadId,
+            Tag::Id(prev_thread_id),
+            IndexOptions::new().clear(),
+        );
+        document.number(
+            MessageField::ThreadId,
+            thread_id,
+            IndexOptions::new().store(),
+        );
+        batch.update_document(document);
+        batch.log_move(
+            Collection::Mail,
+            JMAPId::from_parts(prev_thread_id, document_id),
+            JMAPId::from_parts(thread_id, document_id),
+        );
+        self.mail_log_mailboxes(batch, document_id)?;
+
+        // Both threads change membership, the previous one is removed once empty
+        let thread_tags = self.get_tags(
+            batch.account_id,
+            Collection::Mail,
+            MessageField::ThreadId.into(),
+            &[Tag::Id(prev_thread_id), Tag::Id(thread_id)],
+        )?;
+        if thread_tags[0].as_ref().map_or(0, |ids| ids.len()) > 1 {
+            batch.log_child_update(Collection::Thread, prev_thread_id);
+        } else {
+            batch.log_delete(Collection::Thread, prev_thread_id);
+        }
+        if thread_tags[1].is_some() {
+            batch.log_child_update(Collection::Thread, thread_id);
+        } else {
+            batch.log_insert(Collection::Thread, thread_id);
+        }
+
+        Ok(Some(prev_thread_id))
+    }
+
+    fn mail_log_mailboxes(
+        &self,
+        batch: &mut WriteBatch,
+        document_id: DocumentId,
+    ) -> store::Result<()> {
+        // Moving a message to another thread changes the thread counts of its mailboxes
+        if let Some(fields) = self.get_orm::<Email>(batch.account_id, document_id)? {
+            for mailbox_tag in fields.get_tags(&Property::MailboxIds).into_iter().flatten() {
+                batch.log_child_update(Collection::Mailbox, mailbox_tag.as_id());
+            }
+        }
+        Ok(())
+    }
 }
 
 impl EmailImport {
@@ -896,13 +1322,45 @@ impl AddMessage for Document {
     }
 }
 
+/// Keyword added to messages imported without being parsed.
+pub const UNPARSED_KEYWORD: &str = "$unparsed";
+
+// Garbage blobs usually parse into a single headerless part without any content.
+fn is_unrecognized(message: &Message) -> bool {
+    message
+        .parts
+        .first()
+        .map_or(true, |root_part| root_part.headers.is_empty())
+        && message.parts.iter().all(|part| match &part.body {
+            PartType::Text(text) | PartType::Html(text) => text.trim().is_empty(),
+            PartType::Binary(binary) | PartType::InlineBinary(binary) => binary.is_empty(),
+            PartType::Message(_) => false,
+            PartType::Multipart(_) => true,
+        })
+}
+
 impl MessageData {
-    pub fn build_index(self, document: &mut Document, is_insert: bool) -> store::Result<()> {
+    /// Adds or clears the index entries of a message. Address prefixes and
+    /// trigrams are only added when `address_search` is enabled, but they are
+    /// always cleared as they might have been indexed with a prior setting.
+    pub fn build_index(
+        self,
+        document: &mut Document,
+        is_insert: bool,
+        address_search: bool,
+    ) -> store::Result<()> {
         let options = if is_insert {
             IndexOptions::new()
         } else {
             IndexOptions::new().clear()
         };
+        let index_address_grams = address_search || !is_insert;
+        let message_language = self
+            .mime_parts
+            .first()
+            .and_then(|part| part.language.as_ref()?.first())
+            .and_then(|language| Language::from_iso_639(language))
+            .unwrap_or(Language::Unknown);
 
         document.number(
             MessageField::Size,
@@ -924,6 +1382,31 @@ impl MessageData {
             );
         }
 
+        // Index attachment content types, along with their top-level type for prefix matches
+        let mut attachment_types = AHashSet::new();
+        for part_id in &self.attachments {
+            if let Some(mime_type) = self.mime_parts.get(*part_id).and_then(|part| {
+                part.type_.as_deref().or(match &part.mime_type {
+                    MimePartType::Text { .. } => Some("text/plain"),
+                    MimePartType::Html { .. } => Some("text/html"),
+                    _ => None,
+                })
+            }) {
+                let mime_type = mime_type.to_lowercase();
+                if let Some((type_, _)) = mime_type.split_once('/') {
+                    attachment_types.insert(format!("{}/", type_));
+                }
+                attachment_types.insert(mime_type);
+            }
+        }
+        for attachment_type in attachment_types {
+            document.tag(
+                MessageField::AttachmentType,
+                Tag::Text(attachment_type),
+                IndexOptions::new() | options,
+            );
+        }
+
         for (header_name, mut values) in self.headers {
             document.tag(
                 MessageField::HasHeader,
@@ -964,6 +1447,7 @@ impl MessageData {
                     let mut sort_text = String::with_capacity(MAX_SORT_FIELD_LENGTH);
                     let mut found_addr = false;
                     let mut last_is_space = true;
+                    let mut address_tags = AHashSet::new();
 
                     for value in values {
                         value.visit_addresses(|value, is_addr| {
@@ -989,6 +1473,17 @@ impl MessageData {
                                 }
                             }
 
+                            // Index prefixes and trigrams of each token for partial matches
+                            if index_address_grams {
+                                for token in Tokenizer::new(
+                                    &value,
+                                    address_language(&value, message_language),
+                                    MAX_TOKEN_LENGTH,
+                                ) {
+                                    address_tags.extend(address_grams(header_name, &token.word));
+                                }
+                            }
+
                             document.text(
                                 header_name,
                                 value,
@@ -1000,6 +1495,10 @@ impl MessageData {
                         });
                     }
 
+                    for (field, tag) in address_tags {
+                        document.tag(field, Tag::Text(tag), IndexOptions::new() | options);
+                    }
+
                     document.text(
                         header_name,
                         if !sort_text.is_empty() {
@@ -1034,6 +1533,26 @@ impl MessageData {
                             Language::Unknown,
                             IndexOptions::new().keyword().index() | options,
                         );
+
+                        // Case-fold the base subject for sorting
+                        let mut sort_text = String::with_capacity(MAX_SORT_FIELD_LENGTH);
+                        for ch in thread_name.chars().flat_map(char::to_lowercase) {
+                            if sort_text.len() < MAX_SORT_FIELD_LENGTH {
+                                sort_text.push(ch);
+                            } else {
+                                break;
+                            }
+                        }
+                        document.text(
+                            MessageField::SubjectSort,
+                            if !sort_text.is_empty() {
+                                sort_text
+                            } else {
+                                "!".to_string()
+                            },
+                            Language::Unknown,
+                            IndexOptions::new().index() | options,
+                        );
                     }
                 }
                 RfcHeader::Keywords => {
@@ -0,0 +1,118 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap::{orm::serialize::JMAPOrm, types::jmap::JMAPId};
+use store::{
+    blob::BlobId,
+    core::{collection::Collection, error::StoreError, tag::Tag},
+    serialize::StoreDeserialize,
+    AccountId, DocumentId, JMAPStore, Store,
+};
+
+use super::{
+    schema::{Email, Property},
+    MessageData, MessageField,
+};
+
+#[derive(Debug, Clone)]
+pub struct MessageSummary {
+    pub id: JMAPId,
+    pub blob_id: BlobId,
+    pub mailbox_ids: Vec<DocumentId>,
+    pub keywords: Vec<Tag>,
+    pub received_at: i64,
+    pub size: usize,
+}
+
+pub trait JMAPMailList<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn mail_summary(
+        &self,
+        account_id: AccountId,
+        document_id: DocumentId,
+    ) -> store::Result<Option<MessageSummary>>;
+}
+
+impl<T> JMAPMailList<T> for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn mail_summary(
+        &self,
+        account_id: AccountId,
+        document_id: DocumentId,
+    ) -> store::Result<Option<MessageSummary>> {
+        // Messages deleted after their id was obtained are skipped
+        let (thread_id, metadata_blob_id, fields) =
+            if let (Some(thread_id), Some(metadata_blob_id), Some(fields)) = (
+                self.get_document_value::<DocumentId>(
+                    account_id,
+                    Collection::Mail,
+                    document_id,
+                    MessageField::ThreadId.into(),
+                )?,
+                self.get_document_value::<BlobId>(
+                    account_id,
+                    Collection::Mail,
+                    document_id,
+                    MessageField::Metadata.into(),
+                )?,
+                self.get_orm::<Email>(account_id, document_id)?,
+            ) {
+                (thread_id, metadata_blob_id, fields)
+            } else {
+                return Ok(None);
+            };
+
+        let message_data =
+            MessageData::deserialize(&self.blob_get(&metadata_blob_id)?.ok_or_else(|| {
+                StoreError::NotFound(format!(
+                    "Email metadata blob linked to {}/{} does not exist.",
+                    account_id, document_id
+                ))
+            })?)
+            .ok_or_else(|| {
+                StoreError::DataCorruption(format!(
+                    "Failed to deserialize email metadata for {}/{}",
+                    account_id, document_id
+                ))
+            })?;
+
+        Ok(Some(MessageSummary {
+            id: JMAPId::from_parts(thread_id, document_id),
+            blob_id: message_data.raw_message,
+            mailbox_ids: fields
+                .get_tags(&Property::MailboxIds)
+                .map(|tags| tags.iter().filter_map(|tag| tag.unwrap_id()).collect())
+                .unwrap_or_default(),
+            keywords: fields
+                .get_tags(&Property::Keywords)
+                .map(|tags| tags.iter().cloned().collect())
+                .unwrap_or_default(),
+            received_at: message_data.received_at,
+            size: message_data.size,
+        }))
+    }
+}
@@ -26,14 +26,18 @@ pub mod conv;
 pub mod copy;
 pub mod get;
 pub mod import;
+pub mod list;
 pub mod parse;
 pub mod query;
 pub mod raft;
+pub mod redact;
+pub mod reindex;
 pub mod schema;
 pub mod search_snippet;
 pub mod serialize;
 pub mod set;
 pub mod sharing;
+pub mod tombstone;
 
 use jmap::{jmap_store::Object, types::jmap::JMAPId};
 use serde::{Deserialize, Serialize};
@@ -51,7 +55,11 @@ use store::{
     bincode,
     blob::BlobId,
     core::{collection::Collection, vec_map::VecMap},
-    serialize::{StoreDeserialize, StoreSerialize},
+    nlp::{lang::LanguageDetector, Language},
+    serialize::{
+        leb128::{Leb128Reader, Leb128Vec},
+        StoreDeserialize, StoreSerialize,
+    },
     FieldId,
 };
 
@@ -242,6 +250,15 @@ impl MessagePart {
         }
     }
 
+    // Number of octets after removing the transfer encoding, which can differ
+    // from the length of the text once converted to UTF-8
+    pub fn decoded_len(&self, raw_message: &[u8]) -> usize {
+        match self.encoding {
+            Encoding::None => self.offset_end.saturating_sub(self.offset_start),
+            _ => self.decode(raw_message).map_or(0, |bytes| bytes.len()),
+        }
+    }
+
     pub fn decode_text(
         &self,
         raw_message: &[u8],
@@ -327,6 +344,12 @@ pub enum MessageField {
     ThreadId = 136,
     Mailbox = 137,
     HasHeader = 138,
+    Tombstone = 139,
+    AttachmentType = 140,
+    AddressPrefix = 141,
+    AddressTrigram = 142,
+    SubjectSort = 143,
+    Preview = 144,
 }
 
 impl From<MessageField> for FieldId {
@@ -341,6 +364,66 @@ impl Display for MessageField {
     }
 }
 
+pub const MAX_ADDRESS_PREFIX_LEN: usize = 16;
+
+/// Returns the language used to split addresses into tokens, which is the
+/// language of the message or, when unknown, the one detected in the text.
+pub fn address_language(text: &str, message_language: Language) -> Language {
+    if message_language != Language::Unknown {
+        message_language
+    } else {
+        LanguageDetector::detect_single(text)
+            .and_then(|(language, confidence)| {
+                if confidence > 0.3 {
+                    Some(language)
+                } else {
+                    None
+                }
+            })
+            .unwrap_or(Language::Unknown)
+    }
+}
+
+/// Returns the prefix and trigram tags used for partial matches of an
+/// address token on the given header.
+pub fn address_grams(header: RfcHeader, word: &str) -> Vec<(MessageField, String)> {
+    let header = FieldId::from(header);
+    let chars = word.char_indices().map(|(pos, _)| pos).collect::<Vec<_>>();
+    let mut grams = Vec::with_capacity(MAX_ADDRESS_PREFIX_LEN + chars.len());
+
+    for (num, end) in chars
+        .iter()
+        .skip(1)
+        .copied()
+        .chain([word.len()])
+        .enumerate()
+    {
+        if num == MAX_ADDRESS_PREFIX_LEN {
+            break;
+        }
+        grams.push((
+            MessageField::AddressPrefix,
+            format!("{}:{}", header, &word[..end]),
+        ));
+    }
+    for (pos, start) in chars.iter().enumerate() {
+        if let Some(end) = chars.get(pos + 3).copied().or_else(|| {
+            if pos + 3 == chars.len() {
+                Some(word.len())
+            } else {
+                None
+            }
+        }) {
+            grams.push((
+                MessageField::AddressTrigram,
+                format!("{}:{}", header, &word[*start..end]),
+            ));
+        }
+    }
+
+    grams
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Eq, PartialEq)]
 pub enum HeaderValue {
     Timestamp(i64),
@@ -412,3 +495,63 @@ impl HeaderValue {
         }
     }
 }
+
+/// Serializes a preview along with the length it was generated for.
+pub fn serialize_preview(preview_length: usize, preview: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(preview.len() + std::mem::size_of::<usize>());
+    bytes.push_leb128(preview_length);
+    bytes.extend_from_slice(preview.as_bytes());
+    bytes
+}
+
+/// Returns a stored preview if it was generated for `preview_length`.
+pub fn deserialize_preview(bytes: &[u8], preview_length: usize) -> Option<String> {
+    let (stored_length, bytes_read) = bytes.read_leb128::<usize>()?;
+    if stored_length == preview_length {
+        String::from_utf8(bytes.get(bytes_read..)?.to_vec()).ok()
+    } else {
+        None
+    }
+}
+
+/// Encodes a body as quoted-printable (RFC 2045), normalizing line breaks to
+/// CRLF and inserting soft line breaks to keep lines within 76 characters.
+pub fn encode_quoted_printable(body: &[u8]) -> String {
+    const HEX: &[u8; 16] = b"0123456789ABCDEF";
+    let mut result = String::with_capacity(body.len() + body.len() / 20);
+    let mut line_len = 0;
+    let mut iter = body.iter().peekable();
+
+    while let Some(&ch) = iter.next() {
+        if ch == b'\n' || (ch == b'\r' && iter.peek() == Some(&&b'\n')) {
+            if ch == b'\r' {
+                iter.next();
+            }
+            result.push_str("\r\n");
+            line_len = 0;
+            continue;
+        }
+
+        // Whitespace is only safe when it is not the last character of a line
+        let is_line_end = matches!(iter.peek(), None | Some(b'\r') | Some(b'\n'));
+        let is_literal =
+            matches!(ch, b'!'..=b'<' | b'>'..=b'~') || (matches!(ch, b' ' | b'\t') && !is_line_end);
+        let ch_len = if is_literal { 1 } else { 3 };
+
+        // Leave room for the soft line break
+        if line_len + ch_len > 75 {
+            result.push_str("=\r\n");
+            line_len = 0;
+        }
+        if is_literal {
+            result.push(ch as char);
+        } else {
+            result.push('=');
+            result.push(HEX[(ch >> 4) as usize] as char);
+            result.push(HEX[(ch & 0x0f) as usize] as char);
+        }
+        line_len += ch_len;
+    }
+
+    result
+}
@@ -103,6 +103,7 @@ struct EmailParseProperties {
     fetch_html_body_values: bool,
     fetch_all_body_values: bool,
     max_body_value_bytes: usize,
+    preview_length: usize,
 }
 
 pub trait JMAPMailParse<T>
@@ -140,6 +141,7 @@ where
             fetch_html_body_values: request.fetch_html_body_values.unwrap_or(false),
             fetch_all_body_values: request.fetch_all_body_values.unwrap_or(false),
             max_body_value_bytes: request.max_body_value_bytes.unwrap_or(0),
+            preview_length: self.config.mail_preview_length,
         };
 
         let acl = request.acl.unwrap();
@@ -358,7 +360,7 @@ impl IntoParsedEmail for Message<'_> {
                         Value::Text {
                             value: preview_fnc(
                                 String::from_utf8_lossy(self.parts[part_id].get_contents()),
-                                256,
+                                request.preview_length,
                             )
                             .into_owned(),
                         }
@@ -23,18 +23,21 @@
 
 use super::schema::{Comparator, Email, Filter};
 use super::sharing::JMAPShareMail;
-use crate::mail::MessageField;
+use crate::mail::{address_grams, address_language, MessageField};
 use jmap::error::method::MethodError;
+use jmap::jmap_store::changes::JMAPChanges;
 use jmap::jmap_store::query::{ExtraFilterFnc, QueryHelper, QueryObject};
-use jmap::request::query::{QueryRequest, QueryResponse};
+use jmap::request::query::{self, QueryRequest, QueryResponse};
 use jmap::types::jmap::JMAPId;
 use mail_parser::parsers::header::{parse_header_name, HeaderParserResult};
 use mail_parser::RfcHeader;
 use store::ahash::AHashSet;
 use store::core::acl::ACL;
 use store::core::collection::Collection;
+use store::core::document::MAX_TOKEN_LENGTH;
 use store::core::error::StoreError;
 use store::core::tag::Tag;
+use store::nlp::tokenizers::Tokenizer;
 use store::nlp::Language;
 use store::read::comparator::{self, DocumentSetComparator, FieldComparator};
 use store::read::filter::{self, Query};
@@ -45,6 +48,19 @@ use store::{FieldId, Integer, LongInteger};
 pub struct QueryArguments {
     #[serde(rename = "collapseThreads")]
     collapse_threads: Option<bool>,
+    #[serde(rename = "matchType")]
+    match_type: Option<MatchType>,
+}
+
+/// How the from, to, cc and bcc filters match addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+pub enum MatchType {
+    #[serde(rename = "exact")]
+    Exact,
+    #[serde(rename = "prefix")]
+    Prefix,
+    #[serde(rename = "contains")]
+    Contains,
 }
 
 impl QueryObject for Email {
@@ -83,6 +99,22 @@ where
         )?;
         let account_id = helper.account_id;
         let collapse_threads = helper.request.arguments.collapse_threads.unwrap_or(false);
+        let match_type = helper
+            .request
+            .arguments
+            .match_type
+            .unwrap_or(MatchType::Exact);
+        if match_type != MatchType::Exact && !self.config.mail_address_search {
+            return Err(MethodError::UnsupportedFilter(
+                "Address prefix and contains matching is disabled on this server.".to_string(),
+            ));
+        }
+        let received_after = match &helper.request.filter {
+            Some(query::Filter::FilterCondition(Filter::After { value })) => {
+                Some(value.timestamp() as LongInteger)
+            }
+            _ => None,
+        };
         let mut document_ids = None;
         let mut is_immutable_filter = true;
         let mut is_immutable_sort = true;
@@ -196,18 +228,10 @@ where
                         Query::match_text(value, Language::Unknown),
                     ),
                 ]),
-                Filter::From { value } => {
-                    filter::Filter::eq(RfcHeader::From.into(), Query::Tokenize(value))
-                }
-                Filter::To { value } => {
-                    filter::Filter::eq(RfcHeader::To.into(), Query::Tokenize(value))
-                }
-                Filter::Cc { value } => {
-                    filter::Filter::eq(RfcHeader::Cc.into(), Query::Tokenize(value))
-                }
-                Filter::Bcc { value } => {
-                    filter::Filter::eq(RfcHeader::Bcc.into(), Query::Tokenize(value))
-                }
+                Filter::From { value } => address_filter(RfcHeader::From, value, match_type),
+                Filter::To { value } => address_filter(RfcHeader::To, value, match_type),
+                Filter::Cc { value } => address_filter(RfcHeader::Cc, value, match_type),
+                Filter::Bcc { value } => address_filter(RfcHeader::Bcc, value, match_type),
                 Filter::Subject { value } => filter::Filter::eq(
                     RfcHeader::Subject.into(),
                     Query::match_text(value, Language::Unknown),
@@ -293,6 +317,17 @@ where
                         Query::Tag(Tag::Id(value.get_document_id())),
                     )
                 }
+                Filter::AttachmentType { value } => {
+                    // Types without a subtype, such as 'image/', match by prefix
+                    let mut value = value.to_lowercase();
+                    if !value.contains('/') {
+                        value.push('/');
+                    }
+                    filter::Filter::eq(
+                        MessageField::AttachmentType.into(),
+                        Query::Tag(Tag::Text(value)),
+                    )
+                }
 
                 Filter::Unsupported { value } => {
                     return Err(MethodError::UnsupportedFilter(value));
@@ -300,7 +335,79 @@ where
             })
         })?;
 
+        helper.default_sort(&self.config.mail_default_sort);
+
+        // Incremental fetches of the newest messages received after a given
+        // date walk the receivedAt index directly, skipping filter evaluation.
+        if let Some(received_after) = received_after {
+            if !collapse_threads
+                && helper.shared_documents.is_none()
+                && helper.request.position.unwrap_or(0) == 0
+                && helper.request.anchor.is_none()
+                && helper.request.cursor.is_none()
+                && helper.request.limit != Some(0)
+                && !helper.request.calculate_total.unwrap_or(false)
+                && matches!(helper.request.sort.as_deref(), Some([comparator])
+                    if matches!(comparator.property, Comparator::ReceivedAt)
+                        && !comparator.is_ascending
+                        && comparator.collation.is_none())
+            {
+                let limit = helper
+                    .request
+                    .limit
+                    .map_or(self.config.query_max_results, |limit| {
+                        std::cmp::min(limit, self.config.query_max_results)
+                    });
+                let mut document_ids = self.query_index_newer_than(
+                    account_id,
+                    Collection::Mail,
+                    MessageField::ReceivedAt.into(),
+                    received_after,
+                    limit + 1,
+                )?;
+                let has_more = document_ids.len() > limit;
+                document_ids.truncate(limit);
+
+                let mut ids = Vec::with_capacity(document_ids.len());
+                for document_id in document_ids {
+                    if let Some(thread_id) = self.get_document_value(
+                        account_id,
+                        Collection::Mail,
+                        document_id,
+                        MessageField::ThreadId.into(),
+                    )? {
+                        ids.push(JMAPId::from_parts(thread_id, document_id));
+                    }
+                }
+
+                return Ok(QueryResponse {
+                    account_id: helper.request.account_id,
+                    query_state: self.get_state(account_id, Collection::Mail)?,
+                    can_calculate_changes: true,
+                    position: 0,
+                    ids,
+                    total: None,
+                    limit: if has_more { limit.into() } else { None },
+                    next_cursor: None,
+                    is_immutable: true,
+                });
+            }
+        }
+
         helper.parse_comparator(|comparator| {
+            // Participants and subjects are indexed case-folded
+            if comparator.collation.as_deref() == Some("i;ascii-numeric")
+                && matches!(
+                    comparator.property,
+                    Comparator::From | Comparator::To | Comparator::Subject
+                )
+            {
+                return Err(MethodError::UnsupportedSort(
+                    "Collation i;ascii-numeric is not supported for participants and subjects."
+                        .to_string(),
+                ));
+            }
+
             Ok(match comparator.property {
                 Comparator::ReceivedAt => comparator::Comparator::Field(FieldComparator {
                     field: MessageField::ReceivedAt.into(),
@@ -319,7 +426,7 @@ where
                     ascending: comparator.is_ascending,
                 }),
                 Comparator::Subject => comparator::Comparator::Field(FieldComparator {
-                    field: MessageField::ThreadName.into(),
+                    field: MessageField::SubjectSort.into(),
                     ascending: comparator.is_ascending,
                 }),
                 Comparator::SentAt => comparator::Comparator::Field(FieldComparator {
@@ -369,6 +476,16 @@ where
             })
         })?;
 
+        // Collapsed results are fully iterated so that the total only counts
+        // the first message encountered in each thread. Without a total, threads
+        // are collapsed as results are read and iteration stops at the limit.
+        let extra_filters: Option<ExtraFilterFnc> =
+            if collapse_threads && helper.request.calculate_total.unwrap_or(false) {
+                Some(Ok)
+            } else {
+                None
+            };
+
         let mut seen_threads = AHashSet::default();
         helper
             .query(
@@ -390,7 +507,7 @@ where
                         },
                     )
                 },
-                None::<ExtraFilterFnc>,
+                extra_filters,
             )
             .map(|mut r| {
                 r.is_immutable = is_immutable_filter && is_immutable_sort;
@@ -455,3 +572,43 @@ where
         }
     }
 }
+
+fn address_filter(header: RfcHeader, text: String, match_type: MatchType) -> filter::Filter {
+    if match_type != MatchType::Exact {
+        let mut conditions = Vec::new();
+        for token in Tokenizer::new(
+            &text,
+            address_language(&text, Language::Unknown),
+            MAX_TOKEN_LENGTH,
+        ) {
+            let mut prefix = None;
+            let mut trigrams = Vec::new();
+            for (field, gram) in address_grams(header, &token.word) {
+                if field == MessageField::AddressPrefix {
+                    prefix = Some(gram);
+                } else {
+                    trigrams.push(gram);
+                }
+            }
+
+            // Tokens shorter than a trigram can only be matched by prefix
+            if match_type == MatchType::Contains && !trigrams.is_empty() {
+                conditions.extend(trigrams.into_iter().map(|trigram| {
+                    filter::Filter::eq(
+                        MessageField::AddressTrigram.into(),
+                        Query::Tag(Tag::Text(trigram)),
+                    )
+                }));
+            } else if let Some(prefix) = prefix {
+                conditions.push(filter::Filter::eq(
+                    MessageField::AddressPrefix.into(),
+                    Query::Tag(Tag::Text(prefix)),
+                ));
+            }
+        }
+        if !conditions.is_empty() {
+            return filter::Filter::and(conditions);
+        }
+    }
+    filter::Filter::eq(header.into(), Query::Tokenize(text))
+}
@@ -71,7 +71,7 @@ where
                     document.document_id
                 ))
             })?
-            .build_index(document, true)?;
+            .build_index(document, true, store.config.mail_address_search)?;
 
             // Add thread id
             let thread_id = jmap_id.get_prefix_id();
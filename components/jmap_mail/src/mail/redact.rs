@@ -0,0 +1,150 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap::error::method::MethodError;
+use jmap::types::blob::JMAPBlob;
+use jmap::types::jmap::JMAPId;
+use mail_parser::Message;
+use store::blob::BlobId;
+use store::core::collection::Collection;
+use store::core::document::Document;
+use store::core::error::StoreError;
+use store::serialize::StoreDeserialize;
+use store::write::batch::WriteBatch;
+use store::write::options::{IndexOptions, Options};
+use store::{AccountId, DocumentId, JMAPStore, Store};
+
+use super::import::JMAPMailImport;
+use super::schema::{Email, Property};
+use super::{MessageData, MessageField};
+
+pub trait JMAPMailRedact<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn mail_redact(
+        &self,
+        account_id: AccountId,
+        document_id: DocumentId,
+        blob: &[u8],
+    ) -> jmap::Result<Email>;
+}
+
+impl<T> JMAPMailRedact<T> for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    // Replaces the raw message of an email with a redacted copy. The id, thread,
+    // mailboxes and keywords are kept while the metadata and the full-text index
+    // are rebuilt from the new message, all of it in a single write.
+    fn mail_redact(
+        &self,
+        account_id: AccountId,
+        document_id: DocumentId,
+        blob: &[u8],
+    ) -> jmap::Result<Email> {
+        let message = Message::parse(blob).ok_or_else(|| {
+            MethodError::InvalidArguments("Failed to parse e-mail message.".to_string())
+        })?;
+
+        let _lock = self.lock_collection(account_id, Collection::Mail);
+
+        let thread_id = self
+            .get_document_value::<DocumentId>(
+                account_id,
+                Collection::Mail,
+                document_id,
+                MessageField::ThreadId.into(),
+            )?
+            .ok_or_else(|| {
+                StoreError::NotFound(format!(
+                    "Thread id for {}/{} does not exist.",
+                    account_id, document_id
+                ))
+            })?;
+        let metadata_blob_id = self
+            .get_document_value::<BlobId>(
+                account_id,
+                Collection::Mail,
+                document_id,
+                MessageField::Metadata.into(),
+            )?
+            .ok_or_else(|| {
+                StoreError::NotFound(format!(
+                    "Email metadata blobId for {}/{} does not exist.",
+                    account_id, document_id
+                ))
+            })?;
+        let message_data = self
+            .blob_get(&metadata_blob_id)?
+            .and_then(|bytes| MessageData::deserialize(&bytes))
+            .ok_or_else(|| {
+                StoreError::DataCorruption(format!(
+                    "Failed to fetch email metadata for {}/{}.",
+                    account_id, document_id
+                ))
+            })?;
+        let received_at = message_data.received_at;
+        let mut document = Document::new(Collection::Mail, document_id);
+
+        // Remove the index entries, full-text terms and blob links of the current
+        // message. These have to be added before the new ones, which replace them
+        // when both refer to the same key.
+        message_data.build_index(&mut document, false, self.config.mail_address_search)?;
+        if let Some(term_index_id) =
+            self.get_term_index_id(account_id, Collection::Mail, document_id)?
+        {
+            document.term_index(term_index_id, IndexOptions::new().clear());
+        }
+        document.blob(metadata_blob_id, IndexOptions::new().clear());
+        document.binary(
+            MessageField::Metadata,
+            Vec::with_capacity(0),
+            IndexOptions::new().clear(),
+        );
+        document.binary(
+            MessageField::Preview,
+            Vec::with_capacity(0),
+            IndexOptions::new().clear(),
+        );
+
+        // Index the redacted message
+        let blob_id = BlobId::new_external(blob);
+        self.blob_store(&blob_id, blob.to_vec())?;
+        self.mail_parse_item(&mut document, blob_id.clone(), message, received_at.into())?;
+
+        let email_id = JMAPId::from_parts(thread_id, document_id);
+        let mut batch = WriteBatch::new(account_id);
+        batch.update_document(document);
+        batch.log_update(Collection::Mail, email_id);
+        self.write(batch)?;
+
+        let mut email = Email::default();
+        email.insert(Property::Id, email_id);
+        email.insert(Property::BlobId, JMAPBlob::from(&blob_id));
+        email.insert(Property::ThreadId, JMAPId::from(thread_id));
+        email.insert(Property::Size, blob.len());
+
+        Ok(email)
+    }
+}
@@ -0,0 +1,173 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap::SUPERUSER_ID;
+use store::blob::BlobId;
+use store::core::collection::Collection;
+use store::core::document::Document;
+use store::serialize::key::BACKFILLED_FIELDS_KEY;
+use store::serialize::StoreDeserialize;
+use store::tracing::{debug, info};
+use store::write::batch::WriteBatch;
+use store::{AccountId, ColumnFamily, FieldId, JMAPStore, Store};
+
+use super::{MessageData, MessageField};
+
+const REINDEX_BATCH_SIZE: usize = 100;
+
+pub trait JMAPMailReindex<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn mail_reindex_pending(&self) -> store::Result<()>;
+    fn mail_reindex(&self, account_id: AccountId, fields: &[FieldId]) -> store::Result<usize>;
+}
+
+impl<T> JMAPMailReindex<T> for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    // Adds the index entries of fields introduced after messages were imported.
+    // The fields that were already backfilled are kept in the store, so each
+    // field is only indexed once for all existing messages.
+    fn mail_reindex_pending(&self) -> store::Result<()> {
+        let mut backfilled_fields = self
+            .db
+            .get::<Vec<u8>>(ColumnFamily::Values, BACKFILLED_FIELDS_KEY)?
+            .unwrap_or_default();
+        let address_fields = [
+            FieldId::from(MessageField::AddressPrefix),
+            FieldId::from(MessageField::AddressTrigram),
+        ];
+        let mut wanted_fields = vec![FieldId::from(MessageField::SubjectSort)];
+        if self.config.mail_address_search {
+            wanted_fields.extend(address_fields);
+        } else if backfilled_fields
+            .iter()
+            .any(|field| address_fields.contains(field))
+        {
+            // Address grams are no longer added to new messages, they have to be
+            // indexed again once the setting is turned back on.
+            backfilled_fields.retain(|field| !address_fields.contains(field));
+            self.db.set(
+                ColumnFamily::Values,
+                BACKFILLED_FIELDS_KEY,
+                &backfilled_fields,
+            )?;
+        }
+        let fields = wanted_fields
+            .into_iter()
+            .filter(|field| !backfilled_fields.contains(field))
+            .collect::<Vec<_>>();
+        if fields.is_empty() {
+            return Ok(());
+        }
+
+        info!("Indexing fields {:?} of existing messages.", fields);
+        let mut total_messages = 0;
+        for account_id in self
+            .get_document_ids(SUPERUSER_ID, Collection::Principal)?
+            .unwrap_or_default()
+        {
+            total_messages += self.mail_reindex(account_id, &fields)?;
+        }
+        info!("Indexed {} existing messages.", total_messages);
+
+        backfilled_fields.extend(fields);
+        self.db.set(
+            ColumnFamily::Values,
+            BACKFILLED_FIELDS_KEY,
+            &backfilled_fields,
+        )
+    }
+
+    fn mail_reindex(&self, account_id: AccountId, fields: &[FieldId]) -> store::Result<usize> {
+        let document_ids =
+            if let Some(document_ids) = self.get_document_ids(account_id, Collection::Mail)? {
+                document_ids.into_iter().collect::<Vec<_>>()
+            } else {
+                return Ok(0);
+            };
+
+        let mut total_messages = 0;
+        for document_ids in document_ids.chunks(REINDEX_BATCH_SIZE) {
+            // Messages destroyed in the meantime must not get new index entries
+            let _lock = self.lock_collection(account_id, Collection::Mail);
+            let current_document_ids = self
+                .get_document_ids(account_id, Collection::Mail)?
+                .unwrap_or_default();
+            let mut batch = WriteBatch::new(account_id);
+
+            for &document_id in document_ids {
+                if !current_document_ids.contains(document_id) {
+                    continue;
+                }
+                let message_data = if let Some(message_data) = self
+                    .get_document_value::<BlobId>(
+                        account_id,
+                        Collection::Mail,
+                        document_id,
+                        MessageField::Metadata.into(),
+                    )?
+                    .and_then(|blob_id| self.blob_get(&blob_id).transpose())
+                    .transpose()?
+                    .and_then(|bytes| MessageData::deserialize(&bytes))
+                {
+                    message_data
+                } else {
+                    debug!(
+                        "Skipping message {}/{} without metadata.",
+                        account_id, document_id
+                    );
+                    continue;
+                };
+
+                // Rebuild the index of the message and keep only the new fields
+                let mut document = Document::new(Collection::Mail, document_id);
+                message_data.build_index(&mut document, true, self.config.mail_address_search)?;
+                document
+                    .text_fields
+                    .retain(|field| fields.contains(&field.field));
+                document
+                    .number_fields
+                    .retain(|field| fields.contains(&field.field));
+                document
+                    .tag_fields
+                    .retain(|field| fields.contains(&field.field));
+                document.binary_fields.clear();
+                document.blobs.clear();
+
+                if !document.is_empty() {
+                    batch.update_document(document);
+                    total_messages += 1;
+                }
+            }
+
+            if !batch.is_empty() {
+                self.write(batch)?;
+            }
+        }
+
+        Ok(total_messages)
+    }
+}
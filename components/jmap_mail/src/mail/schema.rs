@@ -109,8 +109,6 @@ pub struct Keyword {
     pub tag: Tag,
 }
 
-const MAX_KEYWORD_LENGTH: usize = 100;
-
 impl Keyword {
     pub const SEEN: u8 = 0;
     pub const DRAFT: u8 = 1;
@@ -158,17 +156,46 @@ impl Keyword {
             }
         }
 
-        Keyword::new(if value.len() < MAX_KEYWORD_LENGTH {
-            Tag::Text(value.to_lowercase())
-        } else {
-            Tag::Text(
-                value
-                    .to_lowercase()
-                    .chars()
-                    .take(MAX_KEYWORD_LENGTH)
-                    .collect(),
-            )
-        })
+        Keyword::new(Tag::Text(value.to_lowercase()))
+    }
+
+    /// Shortens user keywords exceeding the maximum length, used when importing
+    /// messages where keywords are accepted as-is.
+    pub fn truncate(self, max_length: usize) -> Self {
+        match self.tag {
+            Tag::Text(value) if value.len() > max_length => {
+                Keyword::new(Tag::Text(value.chars().take(max_length).collect()))
+            }
+            tag => Keyword::new(tag),
+        }
+    }
+
+    /// Validates a keyword received from a client against the RFC 8621 keyword
+    /// grammar. System keywords are always stored in their canonical form.
+    pub fn validate(&self, max_length: usize) -> Result<(), String> {
+        match &self.tag {
+            Tag::Static(_) => Ok(()),
+            Tag::Text(value) if value.is_empty() => Err("Keywords cannot be empty.".to_string()),
+            Tag::Text(value) if value.len() > max_length => Err(format!(
+                "Keyword \"{}...\" exceeds the maximum length of {} characters.",
+                value.chars().take(16).collect::<String>(),
+                max_length
+            )),
+            Tag::Text(value) => {
+                if value
+                    .bytes()
+                    .all(|ch| (0x21..=0x7e).contains(&ch) && !b"(){]%*\"\\".contains(&ch))
+                {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "Keyword \"{}\" contains invalid characters.",
+                        value
+                    ))
+                }
+            }
+            _ => Err("Invalid keyword.".to_string()),
+        }
     }
 }
 
@@ -775,6 +802,7 @@ pub enum Filter {
     SentBefore { value: JMAPDate },
     SentAfter { value: JMAPDate },
     InThread { value: JMAPId },
+    AttachmentType { value: String },
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
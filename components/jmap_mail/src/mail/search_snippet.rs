@@ -275,8 +275,11 @@ where
 
             // Tokenize and stem terms
             for term in &terms {
+                // Terms have to be split the same way the indexer did, or the
+                // highlighted offsets won't line up with the matched words.
+                let language = term.tokenizer_language(self.config.default_language);
                 if !term.match_phrase {
-                    for token in Stemmer::new(&term.text, term.language, MAX_TOKEN_LENGTH) {
+                    for token in Stemmer::new(&term.text, language, MAX_TOKEN_LENGTH) {
                         match_terms.push(term_index.get_match_term(
                             token.word.as_ref(),
                             token.stemmed_word.as_ref().map(|w| w.as_ref()),
@@ -284,7 +287,7 @@ where
                     }
                 } else {
                     match_phrase = true;
-                    for token in Tokenizer::new(&term.text, term.language, MAX_TOKEN_LENGTH) {
+                    for token in Tokenizer::new(&term.text, language, MAX_TOKEN_LENGTH) {
                         match_terms.push(term_index.get_match_term(token.word.as_ref(), None));
                     }
                 }
@@ -41,6 +41,7 @@ use super::{
         HeaderProperty, Keyword, Property, Value,
     },
     search_snippet::SearchSnippetGetRequest,
+    set::SetArguments,
 };
 
 // Email de/serialization
@@ -333,31 +334,43 @@ impl<'de> serde::de::Visitor<'de> for EmailVisitor {
                                     let value = map.next_value::<Option<bool>>()?.unwrap_or(false);
                                     match Property::parse(property) {
                                         Property::MailboxIds => {
-                                            if let Some(id) = JMAPId::parse(id) {
-                                                properties
-                                                    .get_mut_or_insert_with(
+                                            if let Some(id) = MaybeIdReference::parse(id) {
+                                                if let Some(Value::MailboxIds {
+                                                    value: patch,
+                                                    ..
+                                                }) =
+                                                    get_patch(&mut properties, Property::MailboxIds)
+                                                {
+                                                    patch.append(id, value);
+                                                } else {
+                                                    let mut patch = VecMap::new();
+                                                    patch.append(id, value);
+                                                    properties.append(
                                                         Property::MailboxIds,
-                                                        || Value::MailboxIds {
-                                                            value: VecMap::new(),
+                                                        Value::MailboxIds {
+                                                            value: patch,
                                                             set: false,
                                                         },
-                                                    )
-                                                    .get_mailbox_ids()
-                                                    .unwrap()
-                                                    .append(MaybeIdReference::Value(id), value);
+                                                    );
+                                                }
                                             }
                                         }
                                         Property::Keywords => {
-                                            properties
-                                                .get_mut_or_insert_with(Property::Keywords, || {
+                                            if let Some(Value::Keywords { value: patch, .. }) =
+                                                get_patch(&mut properties, Property::Keywords)
+                                            {
+                                                patch.append(Keyword::parse(id), value);
+                                            } else {
+                                                let mut patch = VecMap::new();
+                                                patch.append(Keyword::parse(id), value);
+                                                properties.append(
+                                                    Property::Keywords,
                                                     Value::Keywords {
-                                                        value: VecMap::new(),
+                                                        value: patch,
                                                         set: false,
-                                                    }
-                                                })
-                                                .get_keywords()
-                                                .unwrap()
-                                                .append(Keyword::parse(id), value);
+                                                    },
+                                                );
+                                            }
                                         }
                                         _ => {
                                             map.next_value::<IgnoredAny>()?;
@@ -380,6 +393,23 @@ impl<'de> serde::de::Visitor<'de> for EmailVisitor {
     }
 }
 
+// Patches are kept apart from full replacements of the same property,
+// which allows Email/set to reject requests that combine both forms.
+fn get_patch(properties: &mut VecMap<Property, Value>, property: Property) -> Option<&mut Value> {
+    properties.iter_mut().find_map(|(p, v)| {
+        if *p == property
+            && matches!(
+                v,
+                Value::Keywords { set: false, .. } | Value::MailboxIds { set: false, .. }
+            )
+        {
+            Some(v)
+        } else {
+            None
+        }
+    })
+}
+
 impl<'de> Deserialize<'de> for Email {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -760,6 +790,29 @@ impl ArgumentDeserializer for GetArguments {
     }
 }
 
+impl ArgumentDeserializer for SetArguments {
+    fn deserialize<'x: 'y, 'y, 'z>(
+        &'y mut self,
+        property: &'z str,
+        value: &mut impl serde::de::MapAccess<'x>,
+    ) -> Result<(), String> {
+        if property == "restore" {
+            self.restore = value.next_value().map_err(|err| err.to_string())?;
+        } else if property == "trash" {
+            self.trash = value.next_value().map_err(|err| err.to_string())?;
+        } else if property == "inReplyToEmailId" {
+            self.in_reply_to_email_id = value.next_value().map_err(|err| err.to_string())?;
+        } else if property == "forceDestroy" {
+            self.force_destroy = value.next_value().map_err(|err| err.to_string())?;
+        } else {
+            value
+                .next_value::<IgnoredAny>()
+                .map_err(|err| err.to_string())?;
+        }
+        Ok(())
+    }
+}
+
 // Filter deserializer
 impl FilterDeserializer for Filter {
     fn deserialize<'x>(property: &str, map: &mut impl serde::de::MapAccess<'x>) -> Option<Self> {
@@ -838,6 +891,9 @@ impl FilterDeserializer for Filter {
             "inThread" => Filter::InThread {
                 value: map.next_value().ok()?,
             },
+            "attachmentType" => Filter::AttachmentType {
+                value: map.next_value().ok()?,
+            },
 
             unsupported => {
                 map.next_value::<IgnoredAny>().ok()?;
@@ -869,6 +925,7 @@ impl<'de> serde::de::Visitor<'de> for EmailImportVisitor {
             mailbox_ids: None,
             keywords: None,
             received_at: None,
+            received_at_from_date: false,
         };
 
         while let Some(key) = map.next_key::<Cow<str>>()? {
@@ -882,6 +939,9 @@ impl<'de> serde::de::Visitor<'de> for EmailImportVisitor {
                 "receivedAt" => {
                     request.received_at = map.next_value()?;
                 }
+                "receivedAtFromDate" => {
+                    request.received_at_from_date = map.next_value()?;
+                }
                 "mailboxIds" => {
                     request.mailbox_ids = if request.mailbox_ids.is_none() {
                         map.next_value::<Option<VecMap<MaybeIdReference, bool>>>()?
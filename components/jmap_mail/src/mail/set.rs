@@ -23,17 +23,24 @@
 
 use super::get::{BlobResult, JMAPGetMail};
 use super::schema::{
-    BodyProperty, Email, EmailBodyPart, EmailBodyValue, HeaderForm, Keyword, Property, Value,
+    BodyProperty, Email, EmailAddress, EmailBodyPart, EmailBodyValue, HeaderForm, Keyword,
+    Property, Value,
 };
 use super::sharing::JMAPShareMail;
-use super::{HeaderName, MessageData, MessageField};
+use super::tombstone::{JMAPMailTombstone, TombstoneChanges};
+use super::{encode_quoted_printable, HeaderName, MessageData, MessageField};
+use crate::email_submission::set::JMAPSetEmailSubmission;
+use crate::identity::get::JMAPGetIdentity;
 use crate::mail::import::JMAPMailImport;
+use crate::mailbox::schema::Property as MailboxProperty;
 use jmap::error::set::{SetError, SetErrorType};
 use jmap::jmap_store::set::{SetHelper, SetObject};
+use jmap::jmap_store::Object;
 use jmap::orm::{serialize::JMAPOrm, TinyORM};
 use jmap::request::set::{SetRequest, SetResponse};
 use jmap::request::{ACLEnforce, MaybeIdReference, ResultReference};
 use jmap::types::blob::JMAPBlob;
+use jmap::types::date::JMAPDate;
 use jmap::types::jmap::JMAPId;
 use mail_builder::headers::address::Address;
 use mail_builder::headers::content_type::ContentType;
@@ -46,6 +53,7 @@ use mail_builder::mime::{BodyPart, MimePart};
 use mail_builder::MessageBuilder;
 use mail_parser::{Message, RfcHeader};
 use std::sync::Arc;
+use std::time::SystemTime;
 use store::ahash::AHashSet;
 use store::blob::BlobId;
 use store::core::acl::{ACLToken, ACL};
@@ -54,14 +62,29 @@ use store::core::document::Document;
 use store::core::error::StoreError;
 use store::core::tag::Tag;
 use store::core::vec_map::VecMap;
+use store::read::comparator::Comparator;
+use store::read::filter::{ComparisonOperator, Filter, Query};
+use store::read::FilterMapper;
 use store::serialize::StoreDeserialize;
 use store::tracing::error;
 use store::write::batch::WriteBatch;
 use store::write::options::{IndexOptions, Options};
 use store::{AccountId, DocumentId, JMAPStore, SharedBitmap, Store};
 
+const MAX_LINE_LENGTH: usize = 998;
+
+#[derive(Debug, Clone, Default)]
+pub struct SetArguments {
+    /// Ids of destroyed messages to restore. Restored messages keep their document
+    /// id and are returned in `updated` keyed by the id that was restored.
+    pub restore: Option<Vec<JMAPId>>,
+    pub trash: Option<Vec<JMAPId>>,
+    pub in_reply_to_email_id: Option<VecMap<String, JMAPId>>,
+    pub force_destroy: Option<bool>,
+}
+
 impl SetObject for Email {
-    type SetArguments = ();
+    type SetArguments = SetArguments;
 
     type NextCall = SetRequest<Email>;
 
@@ -121,6 +144,12 @@ where
         batch: Option<&mut WriteBatch>,
         document: &mut Document,
     ) -> store::Result<Option<JMAPId>>;
+    fn mail_reply_headers(
+        &self,
+        account_id: AccountId,
+        acl: &ACLToken,
+        email_id: JMAPId,
+    ) -> jmap::error::set::Result<(Vec<String>, Vec<String>), Property>;
 }
 
 impl<T> JMAPSetMail<T> for JMAPStore<T>
@@ -128,7 +157,26 @@ where
     T: for<'x> Store<'x> + 'static,
 {
     fn mail_set(&self, request: SetRequest<Email>) -> jmap::Result<SetResponse<Email>> {
+        // Polling with an empty request only needs the current state
+        if request.is_empty()
+            && request
+                .arguments
+                .restore
+                .as_ref()
+                .map_or(true, |ids| ids.is_empty())
+            && request
+                .arguments
+                .trash
+                .as_ref()
+                .map_or(true, |ids| ids.is_empty())
+        {
+            return SetHelper::no_changes(self, request);
+        }
+
         let mut helper = SetHelper::new(self, request)?;
+
+        // The account write lock held by the helper keeps mailboxes from being
+        // destroyed after they were validated, which would leave orphaned messages.
         let mailbox_ids = self
             .get_document_ids(helper.account_id, Collection::Mailbox)?
             .unwrap_or_default();
@@ -136,7 +184,7 @@ where
 
         helper.disable_write_batch();
 
-        helper.create(|_create_id, item, helper, document| {
+        helper.create(|create_id, item, helper, document| {
             let mut builder = MessageBuilder::new();
             let mut fields = TinyORM::<Email>::new();
 
@@ -149,8 +197,40 @@ where
                     _ => None,
                 });
             let max_size_attachments = helper.store.config.mail_attachments_max_size;
+            let max_keyword_length = helper.store.config.mail_keyword_max_length;
+            let normalize_sender = helper.store.config.mail_normalize_sender;
             let mut size_attachments = 0;
 
+            // Enforce the recipient limit, optionally dropping addresses that
+            // were already listed in a previous recipient field
+            let mut recipients = AHashSet::default();
+            let mut num_recipients = 0;
+            let mut dedup_recipients = VecMap::new();
+            for property in [Property::To, Property::Cc, Property::Bcc] {
+                if let Some(Value::Addresses { value }) = item.properties.get(&property) {
+                    if helper.store.config.mail_dedup_recipients {
+                        let value = value
+                            .iter()
+                            .filter(|addr| recipients.insert(addr.email.trim().to_lowercase()))
+                            .cloned()
+                            .collect::<Vec<_>>();
+                        num_recipients += value.len();
+                        dedup_recipients.append(property, value);
+                    } else {
+                        num_recipients += value.len();
+                    }
+                }
+            }
+            if num_recipients > helper.store.config.mail_max_recipients {
+                return Err(SetError::new(
+                    SetErrorType::TooLarge,
+                    format!(
+                        "Message exceeds the maximum of {} recipients.",
+                        helper.store.config.mail_max_recipients
+                    ),
+                ));
+            }
+
             for (property, value) in &item.properties {
                 match (property, value) {
                     (Property::MailboxIds, Value::MailboxIds { value, set }) => {
@@ -195,17 +275,14 @@ where
                     (Property::Keywords, Value::Keywords { value, set }) => {
                         if *set {
                             fields.untag_all(&Property::Keywords);
+                        }
 
-                            for (keyword, set) in value {
-                                if *set {
-                                    fields.tag(Property::Keywords, keyword.tag.clone());
-                                }
-                            }
-                        } else {
-                            for (keyword, set) in value {
-                                if *set {
-                                    fields.tag(Property::Keywords, keyword.tag.clone());
-                                }
+                        for (keyword, set) in value {
+                            if *set {
+                                keyword.validate(max_keyword_length).map_err(|err| {
+                                    SetError::invalid_property(Property::Keywords, err)
+                                })?;
+                                fields.tag(Property::Keywords, keyword.tag.clone());
                             }
                         }
                     }
@@ -219,6 +296,9 @@ where
                         builder = builder
                             .header(property.as_rfc_header(), MessageId::from(value.as_slice()));
                     }
+                    (Property::Sender, Value::Addresses { .. }) if normalize_sender => {
+                        // Added once the From addresses are known
+                    }
                     (
                         Property::Sender
                         | Property::From
@@ -228,6 +308,11 @@ where
                         | Property::ReplyTo,
                         Value::Addresses { value },
                     ) => {
+                        let value = match dedup_recipients.get(property) {
+                            Some(value) if value.is_empty() => continue,
+                            Some(value) => value,
+                            None => value,
+                        };
                         builder = builder.header(
                             property.as_rfc_header(),
                             Address::new_list(value.iter().map(|x| x.into()).collect()),
@@ -262,18 +347,6 @@ where
                                     "text/plain".into(),
                                 )?
                                 .0;
-                            if max_size_attachments > 0 {
-                                size_attachments += text_body.size();
-                                if size_attachments > max_size_attachments {
-                                    return Err(SetError::invalid_property(
-                                        Property::TextBody,
-                                        format!(
-                                            "Message exceeds maximum size of {} bytes.",
-                                            max_size_attachments
-                                        ),
-                                    ));
-                                }
-                            }
                             builder.text_body = text_body.into();
                         }
                     }
@@ -300,18 +373,6 @@ where
                                     "text/html".into(),
                                 )?
                                 .0;
-                            if max_size_attachments > 0 {
-                                size_attachments += html_body.size();
-                                if size_attachments > max_size_attachments {
-                                    return Err(SetError::invalid_property(
-                                        Property::HtmlBody,
-                                        format!(
-                                            "Message exceeds maximum size of {} bytes.",
-                                            max_size_attachments
-                                        ),
-                                    ));
-                                }
-                            }
                             builder.html_body = html_body.into();
                         }
                     }
@@ -325,19 +386,14 @@ where
 
                         let mut attachments = Vec::with_capacity(value.len());
                         for attachment in value {
+                            let is_inline = attachment.has_disposition("inline");
                             let attachment = attachment
                                 .parse(self, &helper.acl, account_id, body_values, None)?
                                 .0;
-                            if max_size_attachments > 0 {
+                            if max_size_attachments > 0 && !is_inline {
                                 size_attachments += attachment.size();
                                 if size_attachments > max_size_attachments {
-                                    return Err(SetError::invalid_property(
-                                        Property::Attachments,
-                                        format!(
-                                            "Message exceeds maximum size of {} bytes.",
-                                            max_size_attachments
-                                        ),
-                                    ));
+                                    return Err(attachments_too_large(max_size_attachments));
                                 }
                             }
                             attachments.push(attachment);
@@ -362,15 +418,15 @@ where
                                         None,
                                     )?;
 
-                                    if max_size_attachments > 0 {
+                                    // Only parts explicitly marked as attachments count
+                                    // towards the limit, inline and body parts do not.
+                                    if max_size_attachments > 0
+                                        && part.has_disposition("attachment")
+                                    {
                                         size_attachments += sub_mime_part.size();
                                         if size_attachments > max_size_attachments {
-                                            return Err(SetError::invalid_property(
-                                                Property::BodyStructure,
-                                                format!(
-                                                    "Message exceeds maximum size of {} bytes.",
-                                                    max_size_attachments
-                                                ),
+                                            return Err(attachments_too_large(
+                                                max_size_attachments,
                                             ));
                                         }
                                     }
@@ -501,6 +557,73 @@ where
                 }
             }
 
+            // A Sender is required when there are multiple From addresses and
+            // redundant when it matches the only one (RFC 5322, section 3.6.2)
+            if normalize_sender {
+                let from = match item.properties.get(&Property::From) {
+                    Some(Value::Addresses { value }) => value.as_slice(),
+                    _ => &[],
+                };
+                match item.properties.get(&Property::Sender) {
+                    Some(Value::Addresses { value: sender })
+                        if !(from.len() == 1
+                            && sender.len() == 1
+                            && from[0].email.eq_ignore_ascii_case(&sender[0].email)) =>
+                    {
+                        builder = builder.header(
+                            "Sender",
+                            Address::new_list(sender.iter().map(|x| x.into()).collect()),
+                        );
+                    }
+                    None if from.len() > 1 => {
+                        if let Some(email) = helper.store.identity_addresses(helper.account_id)?.0 {
+                            builder = builder.header(
+                                "Sender",
+                                Address::from(EmailAddress { name: None, email }),
+                            );
+                        } else {
+                            return Err(SetError::invalid_property(
+                                Property::Sender,
+                                "A sender is required when there are multiple from addresses.",
+                            ));
+                        }
+                    }
+                    _ => (),
+                }
+            }
+
+            // Populate the threading headers of replies that don't include them
+            if let Some(email_id) = helper
+                .request
+                .arguments
+                .in_reply_to_email_id
+                .as_ref()
+                .and_then(|ids| ids.get(create_id))
+                .copied()
+            {
+                let has_in_reply_to = builder
+                    .headers
+                    .iter()
+                    .any(|(name, _)| name.eq_ignore_ascii_case("In-Reply-To"));
+                let has_references = builder
+                    .headers
+                    .iter()
+                    .any(|(name, _)| name.eq_ignore_ascii_case("References"));
+
+                if !has_in_reply_to || !has_references {
+                    let (in_reply_to, references) =
+                        self.mail_reply_headers(helper.account_id, &helper.acl, email_id)?;
+                    if !has_in_reply_to && !in_reply_to.is_empty() {
+                        builder =
+                            builder.header("In-Reply-To", MessageId::from(in_reply_to.as_slice()));
+                    }
+                    if !has_references && !references.is_empty() {
+                        builder =
+                            builder.header("References", MessageId::from(references.as_slice()));
+                    }
+                }
+            }
+
             // Make sure the message is not empty
             if builder.headers.is_empty()
                 && builder.body.is_none()
@@ -540,15 +663,22 @@ where
                     .log_child_update(Collection::Mailbox, mailbox_tag.as_id() as store::JMAPId);
             }
 
-            // Parse message
+            // Parse message, assigning the reception time here so that it can be
+            // returned along with the other server-set properties
             let size = blob.len();
+            let received_at = received_at.unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0) as i64
+            });
             self.mail_parse_item(
                 document,
                 blob_id.clone(),
                 Message::parse(&blob).ok_or_else(|| {
                     SetError::new(SetErrorType::InvalidProperties, "Failed to parse e-mail.")
                 })?,
-                received_at,
+                received_at.into(),
             )?;
             fields.insert(document)?;
 
@@ -567,16 +697,101 @@ where
             email.insert(Property::BlobId, raw_blob);
             email.insert(Property::ThreadId, JMAPId::from(thread_id));
             email.insert(Property::Size, size);
+            email.insert(
+                Property::ReceivedAt,
+                Value::Date {
+                    value: JMAPDate::from_timestamp(received_at),
+                },
+            );
 
             Ok(email)
         })?;
 
+        // Moving a message to Trash replaces all its mailboxes with the Trash mailbox
+        if let Some(trash) = helper.request.arguments.trash.take() {
+            let trash_id = self
+                .query_store::<FilterMapper>(
+                    account_id,
+                    Collection::Mailbox,
+                    Filter::new_condition(
+                        MailboxProperty::Role.into(),
+                        ComparisonOperator::Equal,
+                        Query::Keyword("trash".into()),
+                    ),
+                    Comparator::None,
+                )?
+                .next();
+            let update = helper.request.update.get_or_insert_with(VecMap::new);
+
+            for id in trash {
+                if let Some(trash_id) = trash_id {
+                    if !update.contains_key(&id) {
+                        let mut item = Email::default();
+                        item.properties.append(
+                            Property::MailboxIds,
+                            Value::MailboxIds {
+                                value: VecMap::from_iter([(
+                                    MaybeIdReference::Value(JMAPId::new(trash_id)),
+                                    true,
+                                )]),
+                                set: true,
+                            },
+                        );
+                        update.append(id, item);
+                    } else {
+                        helper.response.not_updated.append(
+                            id,
+                            SetError::new(
+                                SetErrorType::InvalidPatch,
+                                "Message cannot be updated and moved to Trash at the same time.",
+                            ),
+                        );
+                    }
+                } else {
+                    helper.response.not_updated.append(
+                        id,
+                        SetError::invalid_property(
+                            Property::MailboxIds,
+                            "No mailbox with the 'trash' role exists.",
+                        ),
+                    );
+                }
+            }
+        }
+
         helper.update(|id, item, helper, document| {
             let current_fields = self
                 .get_orm::<Email>(account_id, id.get_document_id())?
                 .ok_or_else(|| SetError::new_err(SetErrorType::NotFound))?;
             let mut fields = TinyORM::track_changes(&current_fields);
 
+            // Full replacements can't be combined with patches to the same property
+            for property in [Property::MailboxIds, Property::Keywords] {
+                let mut has_set = false;
+                let mut has_patch = false;
+                for (_, value) in item.properties.iter().filter(|(p, _)| **p == property) {
+                    match value {
+                        Value::MailboxIds { set, .. } | Value::Keywords { set, .. } => {
+                            if *set {
+                                has_set = true;
+                            } else {
+                                has_patch = true;
+                            }
+                        }
+                        _ => (),
+                    }
+                }
+                if has_set && has_patch {
+                    return Err(SetError::new(
+                        SetErrorType::InvalidPatch,
+                        format!(
+                            "Property '{}' cannot be set and patched in the same update.",
+                            property
+                        ),
+                    ));
+                }
+            }
+
             for (property, value) in item.properties {
                 match (property, value) {
                     (Property::MailboxIds, Value::MailboxIds { value, set }) => {
@@ -629,12 +844,22 @@ where
 
                             for (keyword, set) in value {
                                 if set {
+                                    keyword
+                                        .validate(helper.store.config.mail_keyword_max_length)
+                                        .map_err(|err| {
+                                            SetError::invalid_property(Property::Keywords, err)
+                                        })?;
                                     fields.tag(Property::Keywords, keyword.tag);
                                 }
                             }
                         } else {
                             for (keyword, set) in value {
                                 if set {
+                                    keyword
+                                        .validate(helper.store.config.mail_keyword_max_length)
+                                        .map_err(|err| {
+                                            SetError::invalid_property(Property::Keywords, err)
+                                        })?;
                                     fields.tag(Property::Keywords, keyword.tag);
                                 } else {
                                     fields.untag(&Property::Keywords, &keyword.tag);
@@ -746,7 +971,35 @@ where
             Ok(None)
         })?;
 
-        helper.destroy(|_id, helper, document| {
+        // Restore destroyed messages that are still within the grace period
+        let grace_period = self.config.mail_destroy_grace_period;
+        let restore = helper.request.arguments.restore.take();
+        let mut tombstones = TombstoneChanges::new(account_id);
+        if let Some(restore) = restore {
+            for id in restore {
+                let result = if !helper.acl.is_shared(account_id) {
+                    self.mail_restore(&mut helper.changes, &mut tombstones, id, &mailbox_ids)
+                } else {
+                    Err(SetError::forbidden(
+                        "You are not allowed to restore messages.",
+                    ))
+                };
+                match result {
+                    Ok(email) => {
+                        helper
+                            .document_ids
+                            .insert(email.id().unwrap().get_document_id());
+                        helper.response.updated.append(id, email.into());
+                    }
+                    Err(err) => {
+                        helper.response.not_updated.append(id, err);
+                    }
+                }
+            }
+        }
+
+        let force_destroy = helper.request.arguments.force_destroy.unwrap_or(false);
+        helper.destroy(|id, helper, document| {
             // Check ACLs
             if helper.acl.is_shared(helper.account_id)
                 && !helper
@@ -763,13 +1016,103 @@ where
                 ));
             }
 
+            // Messages a pending submission still has to send are kept unless forced
+            if !force_destroy && self.email_submission_is_pending(account_id, id)? {
+                return Err(SetError::forbidden(
+                    "This message is referenced by a pending submission.",
+                ));
+            }
+
+            // Keep a tombstone to allow restoring the message
+            if grace_period > 0 {
+                if let Some(tombstone) = self.mail_tombstone(account_id, document.document_id)? {
+                    tombstones
+                        .get_mut(self, document.document_id)?
+                        .insert(tombstone);
+                }
+            }
+
             self.mail_delete(account_id, Some(&mut helper.changes), document)?;
             Ok(())
         })?;
 
+        self.mail_tombstones_write(&mut helper.changes, tombstones)?;
+
         helper.into_response()
     }
 
+    fn mail_reply_headers(
+        &self,
+        account_id: AccountId,
+        acl: &ACLToken,
+        email_id: JMAPId,
+    ) -> jmap::error::set::Result<(Vec<String>, Vec<String>), Property> {
+        let document_id = email_id.get_document_id();
+        let not_found = || {
+            SetError::new(
+                SetErrorType::InvalidProperties,
+                format!("inReplyToEmailId {} does not exist.", email_id),
+            )
+        };
+
+        if acl.is_shared(account_id)
+            && !self
+                .mail_shared_messages(account_id, &acl.member_of, ACL::ReadItems)?
+                .has_access(document_id)
+        {
+            return Err(not_found());
+        }
+
+        let mut message_data = MessageData::deserialize(
+            &self
+                .blob_get(
+                    &self
+                        .get_document_value::<BlobId>(
+                            account_id,
+                            Collection::Mail,
+                            document_id,
+                            MessageField::Metadata.into(),
+                        )?
+                        .ok_or_else(not_found)?,
+                )?
+                .ok_or_else(|| {
+                    StoreError::NotFound(format!(
+                        "Message data for {}:{} not found.",
+                        account_id, document_id
+                    ))
+                })?,
+        )
+        .ok_or_else(|| {
+            StoreError::DataCorruption(format!(
+                "Failed to deserialize Message data for {}:{}",
+                account_id, document_id
+            ))
+        })?;
+        let mut header_ids = |header| {
+            message_data
+                .headers
+                .remove(&header)
+                .and_then(|values| values.into_iter().last())
+                .and_then(|value| value.unwrap_textlist())
+                .unwrap_or_default()
+        };
+
+        // As per RFC 5322 section 3.6.4, the references of a reply are those of the
+        // parent (or its In-Reply-To if it has none) followed by the parent's Message-ID.
+        let in_reply_to = header_ids(RfcHeader::MessageId);
+        let mut references = header_ids(RfcHeader::References);
+        if references.is_empty() {
+            references = header_ids(RfcHeader::InReplyTo);
+        }
+        if in_reply_to.is_empty() {
+            references.clear();
+        } else {
+            references.extend(in_reply_to.iter().cloned());
+        }
+
+        Ok((in_reply_to, references))
+    }
+
     fn mail_delete(
         &self,
         account_id: AccountId,
@@ -801,7 +1144,7 @@ where
                 account_id, document_id
             ))
         })?
-        .build_index(document, false)?;
+        .build_index(document, false, self.config.mail_address_search)?;
 
         // Remove thread related data
         let thread_id = self
@@ -835,6 +1178,11 @@ where
             Vec::with_capacity(0),
             IndexOptions::new().clear(),
         );
+        document.binary(
+            MessageField::Preview,
+            Vec::with_capacity(0),
+            IndexOptions::new().clear(),
+        );
 
         // Fetch ORM
         let fields = self
@@ -876,7 +1224,22 @@ where
     }
 }
 
+fn attachments_too_large(max_size_attachments: usize) -> SetError<Property> {
+    SetError::new(
+        SetErrorType::TooLarge,
+        format!(
+            "Message attachments exceed maximum size of {} bytes.",
+            max_size_attachments
+        ),
+    )
+}
+
 impl EmailBodyPart {
+    fn has_disposition(&self, disposition: &str) -> bool {
+        self.get_text(BodyProperty::Disposition)
+            .map_or(false, |value| value.eq_ignore_ascii_case(disposition))
+    }
+
     fn parse<'y, T>(
         &'y self,
         store: &JMAPStore<T>,
@@ -919,6 +1282,15 @@ impl EmailBodyPart {
                         SetErrorType::InvalidProperties,
                         "Cannot specify a character set when providing a \"partId\".".to_string(),
                     ));
+                } else if store.config.mail_strict_part_types && !content_type.starts_with("text/")
+                {
+                    return Err(SetError::new(
+                        SetErrorType::InvalidProperties,
+                        format!(
+                            "Body parts of type \"{}\" have to be provided as a \"blobId\".",
+                            content_type
+                        ),
+                    ));
                 }
                 BodyPart::Text(
                     body_values
@@ -942,7 +1314,41 @@ impl EmailBodyPart {
                 )
             } else if let Some(blob_id) = self.get_blob(BodyProperty::BlobId) {
                 BodyPart::Binary(match store.mail_blob_get(account_id, acl, blob_id) {
-                    Ok(BlobResult::Blob(bytes)) => bytes.into(),
+                    Ok(BlobResult::Blob(bytes)) => {
+                        // Text blobs without a charset have to be valid UTF-8
+                        if store.config.mail_strict_part_types
+                            && content_type.starts_with("text/")
+                            && !self.properties.contains_key(&BodyProperty::Charset)
+                            && std::str::from_utf8(&bytes).is_err()
+                        {
+                            return Err(SetError::new(
+                                SetErrorType::InvalidProperties,
+                                format!(
+                                    concat!(
+                                        "Blob {} does not contain valid UTF-8 text, ",
+                                        "specify a \"charset\" or a non-text type."
+                                    ),
+                                    blob_id
+                                ),
+                            ));
+                        }
+
+                        // Forwarded messages have to contain at least one header
+                        if content_type.eq_ignore_ascii_case("message/rfc822")
+                            && !Message::parse(&bytes).map_or(false, |message| {
+                                message
+                                    .parts
+                                    .first()
+                                    .map_or(false, |part| !part.headers.is_empty())
+                            })
+                        {
+                            return Err(SetError::new(
+                                SetErrorType::InvalidProperties,
+                                format!("Blob {} is not a valid e-mail message.", blob_id),
+                            ));
+                        }
+                        bytes.into()
+                    }
                     Ok(BlobResult::NotFound) => {
                         return Err(SetError::new(
                             SetErrorType::BlobNotFound,
@@ -1016,6 +1422,35 @@ impl EmailBodyPart {
             .headers
             .push(("Content-Type".into(), content_type.into()));
 
+        // Embedded messages can only use an identity transfer encoding (RFC 2046)
+        if let BodyPart::Binary(bytes) = &mime_part.contents {
+            if content_type.c_type.eq_ignore_ascii_case("message/rfc822") {
+                mime_part.headers.push((
+                    "Content-Transfer-Encoding".into(),
+                    Raw::new(if bytes.is_ascii() { "7bit" } else { "8bit" }).into(),
+                ));
+            }
+        }
+
+        // Lines over the RFC 5322 limit are quoted-printable encoded. Setting the
+        // Content-Transfer-Encoding header marks the contents as already encoded,
+        // mail-builder then writes them as they are rather than picking an encoding.
+        if store.config.enforce_line_length {
+            if let BodyPart::Text(text) = &mime_part.contents {
+                if text
+                    .split('\n')
+                    .any(|line| line.trim_end_matches('\r').len() > MAX_LINE_LENGTH)
+                {
+                    mime_part.contents =
+                        BodyPart::Text(encode_quoted_printable(text.as_bytes()).into());
+                    mime_part.headers.push((
+                        "Content-Transfer-Encoding".into(),
+                        Raw::new("quoted-printable").into(),
+                    ));
+                }
+            }
+        }
+
         let mut sub_parts = None;
 
         for (property, value) in self.properties.iter() {
@@ -0,0 +1,479 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::SystemTime;
+
+use jmap::error::set::{SetError, SetErrorType};
+use jmap::orm::serialize::JMAPOrm;
+use jmap::orm::TinyORM;
+use jmap::types::blob::JMAPBlob;
+use jmap::types::jmap::JMAPId;
+use jmap::SUPERUSER_ID;
+use mail_parser::Message;
+use serde::{Deserialize, Serialize};
+use store::ahash::{AHashMap, AHashSet};
+use store::blob::BlobId;
+use store::core::collection::Collection;
+use store::core::document::Document;
+use store::core::error::StoreError;
+use store::core::tag::Tag;
+use store::roaring::RoaringBitmap;
+use store::serialize::{StoreDeserialize, StoreSerialize};
+use store::write::batch::WriteBatch;
+use store::write::options::{IndexOptions, Options};
+use store::{bincode, AccountId, DocumentId, JMAPStore, Store};
+
+use super::import::JMAPMailImport;
+use super::schema::{Email, Property};
+use super::{MessageData, MessageField};
+
+// Destroyed messages are kept under a reserved document id derived from their
+// own id, counting down from the end of the id space.
+#[inline(always)]
+pub fn tombstone_document_id(document_id: DocumentId) -> DocumentId {
+    DocumentId::MAX - document_id
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageTombstone {
+    pub id: store::JMAPId,
+    pub blob_id: BlobId,
+    pub mailbox_ids: Vec<DocumentId>,
+    pub keywords: Vec<Tag>,
+    pub received_at: i64,
+    pub destroyed_at: u64,
+}
+
+/// Tombstones of the messages that were destroyed while holding the same
+/// document id, usually just one.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MessageTombstones {
+    pub items: Vec<MessageTombstone>,
+    #[serde(skip)]
+    unlinked: Vec<BlobId>,
+    #[serde(skip)]
+    has_changes: bool,
+}
+
+/// Tombstones read or modified while processing a request, keyed by the
+/// document id of the destroyed messages.
+#[derive(Debug, Default)]
+pub struct TombstoneChanges {
+    account_id: AccountId,
+    tombstones: AHashMap<DocumentId, MessageTombstones>,
+}
+
+impl StoreSerialize for MessageTombstones {
+    fn serialize(&self) -> Option<Vec<u8>> {
+        bincode::serialize(self).ok()
+    }
+}
+
+impl StoreDeserialize for MessageTombstones {
+    fn deserialize(bytes: &[u8]) -> Option<Self> {
+        bincode::deserialize_from(bytes).ok()
+    }
+}
+
+impl MessageTombstones {
+    pub fn insert(&mut self, tombstone: MessageTombstone) {
+        self.has_changes = true;
+        self.items.push(tombstone);
+    }
+
+    pub fn get(&self, id: JMAPId) -> Option<&MessageTombstone> {
+        let id = u64::from(id);
+        self.items.iter().find(|t| t.id == id)
+    }
+
+    pub fn remove(&mut self, id: JMAPId) -> Option<MessageTombstone> {
+        let id = u64::from(id);
+        let tombstone = self
+            .items
+            .swap_remove(self.items.iter().position(|t| t.id == id)?);
+        self.unlink(tombstone.blob_id.clone());
+        Some(tombstone)
+    }
+
+    pub fn purge(&mut self, grace_period: u64, now: u64) {
+        let mut expired = Vec::new();
+        self.items.retain(|t| {
+            if t.destroyed_at + grace_period > now {
+                true
+            } else {
+                expired.push(t.blob_id.clone());
+                false
+            }
+        });
+        for blob_id in expired {
+            self.unlink(blob_id);
+        }
+    }
+
+    pub fn has_changes(&self) -> bool {
+        self.has_changes
+    }
+
+    fn unlink(&mut self, blob_id: BlobId) {
+        self.has_changes = true;
+        if !self.unlinked.contains(&blob_id) {
+            self.unlinked.push(blob_id);
+        }
+    }
+
+    pub fn build_document(self, document: &mut Document) {
+        let mut linked_blobs = AHashSet::with_capacity(self.items.len());
+        for tombstone in &self.items {
+            if linked_blobs.insert(tombstone.blob_id.clone()) {
+                document.blob(tombstone.blob_id.clone(), IndexOptions::new());
+            }
+        }
+        for blob_id in &self.unlinked {
+            if !linked_blobs.contains(blob_id) {
+                document.blob(blob_id.clone(), IndexOptions::new().clear());
+            }
+        }
+
+        if !self.items.is_empty() {
+            document.binary(
+                MessageField::Tombstone,
+                self.serialize().unwrap(),
+                IndexOptions::new(),
+            );
+            document.tag(MessageField::Tombstone, Tag::Default, IndexOptions::new());
+        } else {
+            document.binary(
+                MessageField::Tombstone,
+                Vec::with_capacity(0),
+                IndexOptions::new().clear(),
+            );
+            document.tag(
+                MessageField::Tombstone,
+                Tag::Default,
+                IndexOptions::new().clear(),
+            );
+        }
+    }
+}
+
+impl TombstoneChanges {
+    pub fn new(account_id: AccountId) -> Self {
+        TombstoneChanges {
+            account_id,
+            tombstones: AHashMap::default(),
+        }
+    }
+
+    pub fn get_mut<T>(
+        &mut self,
+        store: &JMAPStore<T>,
+        document_id: DocumentId,
+    ) -> store::Result<&mut MessageTombstones>
+    where
+        T: for<'x> Store<'x> + 'static,
+    {
+        if !self.tombstones.contains_key(&document_id) {
+            let tombstones = store.mail_tombstones(self.account_id, document_id)?;
+            self.tombstones.insert(document_id, tombstones);
+        }
+        Ok(self.tombstones.get_mut(&document_id).unwrap())
+    }
+}
+
+pub trait JMAPMailTombstone<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn mail_tombstones(
+        &self,
+        account_id: AccountId,
+        document_id: DocumentId,
+    ) -> store::Result<MessageTombstones>;
+    fn mail_tombstone_ids(&self, account_id: AccountId) -> store::Result<RoaringBitmap>;
+    fn mail_tombstone(
+        &self,
+        account_id: AccountId,
+        document_id: DocumentId,
+    ) -> store::Result<Option<MessageTombstone>>;
+    fn mail_restore(
+        &self,
+        batch: &mut WriteBatch,
+        tombstones: &mut TombstoneChanges,
+        id: JMAPId,
+        mailbox_ids: &RoaringBitmap,
+    ) -> jmap::error::set::Result<Email, Property>;
+    fn mail_tombstones_write(
+        &self,
+        batch: &mut WriteBatch,
+        tombstones: TombstoneChanges,
+    ) -> store::Result<()>;
+    fn mail_purge_tombstones(&self) -> store::Result<()>;
+}
+
+impl<T> JMAPMailTombstone<T> for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn mail_tombstones(
+        &self,
+        account_id: AccountId,
+        document_id: DocumentId,
+    ) -> store::Result<MessageTombstones> {
+        Ok(self
+            .get_document_value::<MessageTombstones>(
+                account_id,
+                Collection::Mail,
+                tombstone_document_id(document_id),
+                MessageField::Tombstone.into(),
+            )?
+            .unwrap_or_default())
+    }
+
+    /// Returns the document ids of the destroyed messages that have a tombstone.
+    fn mail_tombstone_ids(&self, account_id: AccountId) -> store::Result<RoaringBitmap> {
+        Ok(self
+            .get_tag(
+                account_id,
+                Collection::Mail,
+                MessageField::Tombstone.into(),
+                Tag::Default,
+            )?
+            .map(|tombstone_ids| tombstone_ids.iter().map(tombstone_document_id).collect())
+            .unwrap_or_default())
+    }
+
+    fn mail_tombstone(
+        &self,
+        account_id: AccountId,
+        document_id: DocumentId,
+    ) -> store::Result<Option<MessageTombstone>> {
+        let metadata_blob_id = if let Some(metadata_blob_id) = self.get_document_value::<BlobId>(
+            account_id,
+            Collection::Mail,
+            document_id,
+            MessageField::Metadata.into(),
+        )? {
+            metadata_blob_id
+        } else {
+            return Ok(None);
+        };
+        let message_data =
+            MessageData::deserialize(&self.blob_get(&metadata_blob_id)?.ok_or_else(|| {
+                StoreError::NotFound(format!(
+                    "Message data blob for {}:{} not found.",
+                    account_id, document_id
+                ))
+            })?)
+            .ok_or_else(|| {
+                StoreError::DataCorruption(format!(
+                    "Failed to deserialize message data for {}:{}.",
+                    account_id, document_id
+                ))
+            })?;
+        let thread_id = self
+            .get_document_value::<DocumentId>(
+                account_id,
+                Collection::Mail,
+                document_id,
+                MessageField::ThreadId.into(),
+            )?
+            .ok_or_else(|| {
+                StoreError::NotFound(format!(
+                    "Failed to fetch threadId for {}:{}.",
+                    account_id, document_id
+                ))
+            })?;
+        let fields = self
+            .get_orm::<Email>(account_id, document_id)?
+            .ok_or_else(|| {
+                StoreError::DataCorruption(format!(
+                    "Failed to fetch Email ORM for {}:{}.",
+                    account_id, document_id
+                ))
+            })?;
+
+        Ok(MessageTombstone {
+            id: JMAPId::from_parts(thread_id, document_id).into(),
+            blob_id: message_data.raw_message,
+            mailbox_ids: fields
+                .get_tags(&Property::MailboxIds)
+                .map(|tags| tags.iter().map(|tag| tag.as_id()).collect())
+                .unwrap_or_default(),
+            keywords: fields
+                .get_tags(&Property::Keywords)
+                .map(|tags| tags.iter().cloned().collect())
+                .unwrap_or_default(),
+            received_at: message_data.received_at,
+            destroyed_at: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        }
+        .into())
+    }
+
+    /// Inserts a destroyed message back into the mailboxes it belonged to, under
+    /// its original document id. Fails if the id was reused by another message
+    /// since. The tombstone is only removed once the message was parsed again.
+    fn mail_restore(
+        &self,
+        batch: &mut WriteBatch,
+        tombstones: &mut TombstoneChanges,
+        id: JMAPId,
+        mailbox_ids: &RoaringBitmap,
+    ) -> jmap::error::set::Result<Email, Property> {
+        let tombstone = tombstones
+            .get_mut(self, id.get_document_id())?
+            .get(id)
+            .cloned()
+            .ok_or_else(|| {
+                SetError::new(
+                    SetErrorType::NotFound,
+                    format!("Message {} is not available for restoring.", id),
+                )
+            })?;
+
+        // Make sure the grace period has not expired
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        if tombstone.destroyed_at + self.config.mail_destroy_grace_period <= now {
+            return Err(SetError::new(
+                SetErrorType::NotFound,
+                format!("Message {} can no longer be restored.", id),
+            ));
+        }
+
+        // Restore the message to the mailboxes that still exist
+        let mut fields = TinyORM::<Email>::new();
+        for mailbox_id in &tombstone.mailbox_ids {
+            if mailbox_ids.contains(*mailbox_id) {
+                fields.tag(Property::MailboxIds, Tag::Id(*mailbox_id));
+            }
+        }
+        if !fields.has_tags(&Property::MailboxIds) {
+            return Err(SetError::new(
+                SetErrorType::InvalidProperties,
+                "None of the mailboxes this message belonged to exist anymore.",
+            ));
+        }
+        for keyword in &tombstone.keywords {
+            fields.tag(Property::Keywords, keyword.clone());
+        }
+
+        // Parse message
+        let blob = self.blob_get(&tombstone.blob_id)?.ok_or_else(|| {
+            SetError::new(
+                SetErrorType::BlobNotFound,
+                format!("Message {} is no longer available.", id),
+            )
+        })?;
+        let message = Message::parse(&blob).ok_or_else(|| {
+            SetError::new(SetErrorType::InvalidProperties, "Failed to parse e-mail.")
+        })?;
+        let document_id = id.get_document_id();
+        if !self.reserve_document_id(batch.account_id, Collection::Mail, document_id)? {
+            return Err(SetError::new(
+                SetErrorType::AlreadyExists,
+                format!(
+                    "Message {} cannot be restored, its id is in use by another message.",
+                    id
+                ),
+            ));
+        }
+        let mut document = Document::new(Collection::Mail, document_id);
+        let raw_blob: JMAPBlob = (&tombstone.blob_id).into();
+        self.mail_parse_item(
+            &mut document,
+            tombstone.blob_id,
+            message,
+            tombstone.received_at.into(),
+        )?;
+        fields.insert(&mut document)?;
+
+        // Obtain thread Id
+        let thread_id = self.mail_set_thread(batch, &mut document)?;
+
+        // The tombstone is removed in the same batch that inserts the message
+        tombstones.get_mut(self, document_id)?.remove(id);
+        for mailbox_tag in fields.get_tags(&Property::MailboxIds).unwrap() {
+            batch.log_child_update(Collection::Mailbox, mailbox_tag.as_id());
+        }
+        let id = JMAPId::from_parts(thread_id, document.document_id);
+        batch.log_insert(Collection::Mail, id);
+        batch.insert_document(document);
+
+        // Build email result
+        let mut email = Email::default();
+        email.insert(Property::Id, id);
+        email.insert(Property::BlobId, raw_blob);
+        email.insert(Property::ThreadId, JMAPId::from(thread_id));
+        email.insert(Property::Size, blob.len());
+
+        Ok(email)
+    }
+
+    fn mail_tombstones_write(
+        &self,
+        batch: &mut WriteBatch,
+        tombstones: TombstoneChanges,
+    ) -> store::Result<()> {
+        for (document_id, tombstones) in tombstones.tombstones {
+            if tombstones.has_changes() {
+                let mut document =
+                    Document::new(Collection::Mail, tombstone_document_id(document_id));
+                tombstones.build_document(&mut document);
+                batch.update_document(document);
+            }
+        }
+        Ok(())
+    }
+
+    fn mail_purge_tombstones(&self) -> store::Result<()> {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        for account_id in self
+            .get_document_ids(SUPERUSER_ID, Collection::Principal)?
+            .unwrap_or_default()
+        {
+            let _lock = self.lock_collection(account_id, Collection::Mail);
+            let mut changes = TombstoneChanges::new(account_id);
+            for document_id in self.mail_tombstone_ids(account_id)? {
+                changes
+                    .get_mut(self, document_id)?
+                    .purge(self.config.mail_destroy_grace_period, now);
+            }
+            if changes.tombstones.values().any(|t| t.has_changes()) {
+                let mut batch = WriteBatch::new(account_id);
+                self.mail_tombstones_write(&mut batch, changes)?;
+                self.write(batch)?;
+            }
+        }
+
+        Ok(())
+    }
+}
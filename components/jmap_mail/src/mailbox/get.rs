@@ -37,10 +37,18 @@ use store::core::collection::Collection;
 use store::core::error::StoreError;
 use store::core::tag::Tag;
 use store::core::vec_map::VecMap;
+use store::log::changes::{Change, ChangeId, Query};
 use store::roaring::RoaringBitmap;
-use store::{AccountId, JMAPStore, SharedBitmap};
+use store::{AccountId, JMAPStore, MailboxCounters, SharedBitmap};
 use store::{DocumentId, Store};
 
+// Number of mailbox counters computed by the current thread, used by tests
+// to verify that counters are only calculated when requested.
+#[cfg(feature = "debug")]
+thread_local! {
+    pub static COUNTS_COMPUTED: std::cell::Cell<usize> = std::cell::Cell::new(0);
+}
+
 impl GetObject for Mailbox {
     type GetArguments = ();
 
@@ -68,16 +76,29 @@ impl GetObject for Mailbox {
     }
 }
 
+/// Validity of the cached mailbox counters of an account for a request.
+#[derive(Debug, Clone, Default)]
+pub struct CountersState {
+    /// Last change id of the Mailbox collection.
+    pub change_id: Option<ChangeId>,
+    /// Cached counters calculated at or after this change id are valid, the
+    /// ones of mailboxes changed since then have been invalidated.
+    pub valid_since: Option<ChangeId>,
+}
+
 pub trait JMAPGetMailbox<T>
 where
     T: for<'x> Store<'x> + 'static,
 {
     fn mailbox_get(&self, request: GetRequest<Mailbox>) -> jmap::Result<GetResponse<Mailbox>>;
-    fn mailbox_count_threads(
+    fn mailbox_counters_validate(&self, account_id: AccountId) -> store::Result<CountersState>;
+    fn mailbox_counters(
         &self,
         account_id: AccountId,
-        document_ids: Option<RoaringBitmap>,
-    ) -> store::Result<usize>;
+        document_id: DocumentId,
+        mail_document_ids: Option<&RoaringBitmap>,
+        state: &CountersState,
+    ) -> store::Result<MailboxCounters>;
     fn mailbox_tags(
         &self,
         account_id: AccountId,
@@ -112,12 +133,38 @@ where
                     | Property::ParentId
                     | Property::Role
                     | Property::SortOrder
+                    | Property::IsSubscribed
                     | Property::ACL
             )
         });
         let account_id = helper.account_id;
         let acl = helper.acl.clone();
-        let mail_document_ids = self.get_document_ids(account_id, Collection::Mail)?;
+
+        // Parents that are not shared with the requester are not disclosed
+        let shared_folders = if acl.is_shared(account_id) {
+            Some(self.mail_shared_folders(account_id, &acl.member_of, ACL::ReadItems)?)
+        } else {
+            None
+        };
+
+        // Only the counters need the message ids
+        let fetch_counters = helper.properties.iter().any(|p| {
+            matches!(
+                p,
+                Property::TotalEmails
+                    | Property::UnreadEmails
+                    | Property::TotalThreads
+                    | Property::UnreadThreads
+            )
+        });
+        let (mail_document_ids, counters_state) = if fetch_counters {
+            (
+                self.get_document_ids(account_id, Collection::Mail)?,
+                self.mailbox_counters_validate(account_id)?,
+            )
+        } else {
+            (None, CountersState::default())
+        };
 
         // Add Id Property
         if !helper.properties.contains(&Property::Id) {
@@ -136,9 +183,30 @@ where
             } else {
                 None
             };
+            let counters = if fetch_counters {
+                Some(self.mailbox_counters(
+                    account_id,
+                    document_id,
+                    mail_document_ids.as_ref(),
+                    &counters_state,
+                )?)
+            } else {
+                None
+            };
             let mut mailbox = VecMap::with_capacity(properties.len());
 
             for property in properties {
+                #[cfg(feature = "debug")]
+                if matches!(
+                    property,
+                    Property::TotalEmails
+                        | Property::UnreadEmails
+                        | Property::TotalThreads
+                        | Property::UnreadThreads
+                ) {
+                    COUNTS_COMPUTED.with(|counts| counts.set(counts.get() + 1));
+                }
+
                 let value = match property {
                     Property::Id => Value::Id { value: id },
                     Property::Name | Property::Role => fields
@@ -156,43 +224,30 @@ where
                         .unwrap()
                         .get(property)
                         .map(|parent_id| match parent_id {
-                            Value::Id { value } if value.get_document_id() > 0 => Value::Id {
-                                value: (value.get_document_id() - 1).into(),
-                            },
+                            Value::Id { value }
+                                if value.get_document_id() > 0
+                                    && shared_folders.as_ref().map_or(true, |shared_folders| {
+                                        shared_folders.has_access(value.get_document_id() - 1)
+                                    }) =>
+                            {
+                                Value::Id {
+                                    value: (value.get_document_id() - 1).into(),
+                                }
+                            }
                             _ => Value::Null,
                         })
                         .unwrap_or_default(),
                     Property::TotalEmails => Value::Number {
-                        value: self
-                            .mailbox_tags(account_id, document_id)?
-                            .map(|v| v.len() as u32)
-                            .unwrap_or(0),
+                        value: counters.as_ref().unwrap().total_emails,
                     },
                     Property::UnreadEmails => Value::Number {
-                        value: self
-                            .mailbox_unread_tags(
-                                account_id,
-                                document_id,
-                                mail_document_ids.as_ref(),
-                            )?
-                            .map(|v| v.len() as u32)
-                            .unwrap_or(0),
+                        value: counters.as_ref().unwrap().unread_emails,
                     },
                     Property::TotalThreads => Value::Number {
-                        value: self.mailbox_count_threads(
-                            account_id,
-                            self.mailbox_tags(account_id, document_id)?,
-                        )? as u32,
+                        value: counters.as_ref().unwrap().total_threads,
                     },
                     Property::UnreadThreads => Value::Number {
-                        value: self.mailbox_count_threads(
-                            account_id,
-                            self.mailbox_unread_tags(
-                                account_id,
-                                document_id,
-                                mail_document_ids.as_ref(),
-                            )?,
-                        )? as u32,
+                        value: counters.as_ref().unwrap().unread_threads,
                     },
                     Property::MyRights => Value::MailboxRights {
                         value: if acl.is_shared(account_id) {
@@ -242,29 +297,108 @@ where
         })
     }
 
-    fn mailbox_count_threads(
+    fn mailbox_counters_validate(&self, account_id: AccountId) -> store::Result<CountersState> {
+        // Cached counters are kept until the mailbox shows up in the changelog,
+        // which every change to its messages, their $seen keyword or their
+        // threads is logged to. The changes are read once for all mailboxes.
+        let change_id = self.get_last_change_id(account_id, Collection::Mailbox)?;
+        let valid_since = match (self.mailbox_counters_validated.get(&account_id), change_id) {
+            (Some(from_change_id), Some(to_change_id)) if from_change_id == to_change_id => {
+                Some(from_change_id)
+            }
+            (Some(from_change_id), Some(to_change_id)) if from_change_id < to_change_id => {
+                if let Some(changes) = self.get_changes(
+                    account_id,
+                    Collection::Mailbox,
+                    Query::Since(from_change_id),
+                    0,
+                )? {
+                    for change in changes.changes {
+                        if let Change::Insert(id)
+                        | Change::Update(id)
+                        | Change::ChildUpdate(id)
+                        | Change::Delete(id) = change
+                        {
+                            self.mailbox_counters
+                                .invalidate(&(account_id, id as DocumentId));
+                        }
+                    }
+                    Some(from_change_id)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+
+        // Counters have to be invalidated before other requests may rely on them
+        if let Some(change_id) = change_id {
+            self.mailbox_counters_validated
+                .insert(account_id, change_id);
+        }
+
+        Ok(CountersState {
+            change_id,
+            valid_since,
+        })
+    }
+
+    fn mailbox_counters(
         &self,
         account_id: AccountId,
-        document_ids: Option<RoaringBitmap>,
-    ) -> store::Result<usize> {
-        if let Some(document_ids) = document_ids {
+        document_id: DocumentId,
+        mail_document_ids: Option<&RoaringBitmap>,
+        state: &CountersState,
+    ) -> store::Result<MailboxCounters> {
+        let change_id = state.change_id;
+        let key = (account_id, document_id);
+        if let Some(mut counters) = self.mailbox_counters.get(&key) {
+            if counters.change_id == change_id {
+                return Ok(counters);
+            } else if matches!((counters.change_id, state.valid_since),
+                    (Some(cached_change_id), Some(valid_since)) if cached_change_id >= valid_since)
+            {
+                counters.change_id = change_id;
+                self.mailbox_counters.insert(key, counters.clone());
+                return Ok(counters);
+            }
+        }
+
+        let mut counters = MailboxCounters {
+            change_id,
+            ..Default::default()
+        };
+        if let Some(mailbox_ids) = self.mailbox_tags(account_id, document_id)? {
+            let unread_ids = self
+                .mailbox_unread_tags(account_id, document_id, mail_document_ids)?
+                .unwrap_or_default();
             let mut thread_ids = AHashSet::default();
-            self.get_multi_document_value(
+            let mut unread_thread_ids = AHashSet::default();
+
+            // A thread is unread if any of its messages in the mailbox is unread
+            let mailbox_thread_ids = self.get_multi_document_value::<DocumentId>(
                 account_id,
                 Collection::Mail,
-                document_ids.into_iter(),
+                mailbox_ids.iter(),
                 MessageField::ThreadId.into(),
-            )?
-            .into_iter()
-            .for_each(|thread_id: Option<DocumentId>| {
+            )?;
+            for (mail_document_id, thread_id) in mailbox_ids.iter().zip(mailbox_thread_ids) {
                 if let Some(thread_id) = thread_id {
+                    if unread_ids.contains(mail_document_id) {
+                        unread_thread_ids.insert(thread_id);
+                    }
                     thread_ids.insert(thread_id);
                 }
-            });
-            Ok(thread_ids.len())
-        } else {
-            Ok(0)
+            }
+
+            counters.total_emails = mailbox_ids.len() as u32;
+            counters.unread_emails = unread_ids.len() as u32;
+            counters.total_threads = thread_ids.len() as u32;
+            counters.unread_threads = unread_thread_ids.len() as u32;
         }
+        self.mailbox_counters.insert(key, counters.clone());
+
+        Ok(counters)
     }
 
     fn mailbox_tags(
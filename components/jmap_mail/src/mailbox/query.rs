@@ -26,7 +26,7 @@ use crate::mail::sharing::JMAPShareMail;
 use jmap::error::method::MethodError;
 use jmap::jmap_store::query::{ExtraFilterFnc, QueryHelper, QueryObject};
 use jmap::orm::serialize::JMAPOrm;
-use jmap::request::query::{QueryRequest, QueryResponse};
+use jmap::request::query::{self, collate, QueryRequest, QueryResponse};
 use jmap::request::ACLEnforce;
 use jmap::types::jmap::JMAPId;
 use store::ahash::{AHashMap, AHashSet};
@@ -36,8 +36,11 @@ use store::core::tag::Tag;
 use store::read::comparator::{self, FieldComparator};
 use store::read::default_filter_mapper;
 use store::read::filter::{self, Query};
+use store::roaring::RoaringBitmap;
 use store::Store;
-use store::{AccountId, JMAPStore};
+use store::{AccountId, DocumentId, JMAPStore};
+
+use std::cmp::Ordering;
 
 #[derive(Debug, Clone, serde::Deserialize, Default)]
 pub struct QueryArguments {
@@ -80,6 +83,7 @@ where
         let sort_as_tree = helper.request.arguments.sort_as_tree.unwrap_or(false);
         let filter_as_tree = helper.request.arguments.filter_as_tree.unwrap_or(false);
 
+        let mut mailbox_names: Option<Vec<(DocumentId, String)>> = None;
         helper.parse_filter(|filter| {
             Ok(match filter {
                 Filter::ParentId { value } => filter::Filter::eq(
@@ -94,7 +98,40 @@ where
                             std::thread::sleep(std::time::Duration::from_secs(1));
                         }
                     }
-                    filter::Filter::eq(Property::Name.into(), Query::Tokenize(value.to_lowercase()))
+
+                    // Names are matched as substrings, which the token index can't do,
+                    // so matching mailboxes are passed on as a set to be combined with
+                    // the other conditions.
+                    if mailbox_names.is_none() {
+                        let mut names = Vec::new();
+                        for document_id in self
+                            .get_document_ids(account_id, Collection::Mailbox)?
+                            .unwrap_or_default()
+                        {
+                            if let Some(name) = self
+                                .get_orm::<Mailbox>(account_id, document_id)?
+                                .and_then(|fields| {
+                                    fields
+                                        .get(&Property::Name)
+                                        .and_then(|name| name.as_text())
+                                        .map(|name| name.to_lowercase())
+                                })
+                            {
+                                names.push((document_id, name));
+                            }
+                        }
+                        mailbox_names = names.into();
+                    }
+                    let value = value.to_lowercase();
+                    filter::Filter::DocumentSet(
+                        mailbox_names
+                            .as_ref()
+                            .unwrap()
+                            .iter()
+                            .filter(|(_, name)| name.contains(&value))
+                            .map(|(document_id, _)| *document_id)
+                            .collect::<RoaringBitmap>(),
+                    )
                 }
                 Filter::Role { value } => {
                     if let Some(value) = value {
@@ -135,6 +172,20 @@ where
             })
         })?;
 
+        helper.default_sort(&self.config.mailbox_default_sort);
+
+        // Names are indexed as-is, so name sorts are collated in memory once
+        // the results are known.
+        let collated_sort = helper.request.sort.as_ref().and_then(|sort| {
+            if sort
+                .iter()
+                .any(|comparator| matches!(comparator.property, Comparator::Name))
+            {
+                Some(sort.clone())
+            } else {
+                None
+            }
+        });
         helper.parse_comparator(|comparator| {
             Ok(comparator::Comparator::Field(FieldComparator {
                 field: {
@@ -148,11 +199,22 @@ where
                 ascending: comparator.is_ascending,
             }))
         })?;
+        if collated_sort.is_some() {
+            helper.comparator = comparator::Comparator::None;
+        }
 
-        if filter_as_tree || sort_as_tree {
+        if filter_as_tree || sort_as_tree || collated_sort.is_some() {
             helper.query(
                 default_filter_mapper,
                 Some(|mut results: Vec<JMAPId>| {
+                    if let Some(sort) = &collated_sort {
+                        sort_collated(self, account_id, &mut results, sort)?;
+                    }
+
+                    if !filter_as_tree && !sort_as_tree {
+                        return Ok(results);
+                    }
+
                     let mut hierarchy = AHashMap::default();
                     let mut tree = AHashMap::default();
 
@@ -247,3 +309,76 @@ where
         }
     }
 }
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum SortKey {
+    Text(String),
+    Number(u64),
+}
+
+fn sort_collated<T>(
+    store: &JMAPStore<T>,
+    account_id: AccountId,
+    results: &mut Vec<JMAPId>,
+    sort: &[query::Comparator<Comparator>],
+) -> store::Result<()>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut keyed_results = Vec::with_capacity(results.len());
+    for &jmap_id in results.iter() {
+        let fields = store.get_orm::<Mailbox>(account_id, jmap_id.get_document_id())?;
+        let keys = sort
+            .iter()
+            .map(|comparator| {
+                let fields = fields.as_ref();
+                match comparator.property {
+                    Comparator::Name => SortKey::Text(
+                        collate(
+                            comparator.collation.as_deref(),
+                            fields
+                                .and_then(|fields| fields.get(&Property::Name))
+                                .and_then(|name| name.as_text())
+                                .unwrap_or_default(),
+                        )
+                        .into_owned(),
+                    ),
+                    Comparator::SortOrder => SortKey::Number(
+                        fields
+                            .and_then(|fields| fields.get(&Property::SortOrder))
+                            .and_then(|sort_order| sort_order.as_number())
+                            .unwrap_or_default() as u64,
+                    ),
+                    Comparator::ParentId => SortKey::Number(
+                        fields
+                            .and_then(|fields| fields.get(&Property::ParentId))
+                            .and_then(|parent_id| parent_id.as_id())
+                            .unwrap_or_default(),
+                    ),
+                }
+            })
+            .collect::<Vec<_>>();
+        keyed_results.push((jmap_id, keys));
+    }
+
+    // Stable sort, ties keep the document id order the store returned.
+    keyed_results.sort_by(|(_, a), (_, b)| {
+        for (comparator, (a, b)) in sort.iter().zip(a.iter().zip(b.iter())) {
+            let ordering = if comparator.is_ascending {
+                a.cmp(b)
+            } else {
+                b.cmp(a)
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    });
+
+    *results = keyed_results
+        .into_iter()
+        .map(|(jmap_id, _)| jmap_id)
+        .collect();
+    Ok(())
+}
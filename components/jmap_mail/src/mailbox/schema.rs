@@ -140,6 +140,13 @@ impl Value {
         }
     }
 
+    pub fn as_number(&self) -> Option<u32> {
+        match self {
+            Value::Number { value } => Some(*value),
+            _ => None,
+        }
+    }
+
     pub fn as_id(&self) -> Option<u64> {
         match self {
             Value::Id { value } => Some(value.into()),
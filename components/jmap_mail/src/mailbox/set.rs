@@ -23,7 +23,7 @@
 
 use std::time::Duration;
 
-use super::schema::{Mailbox, Property, Value};
+use super::schema::{Mailbox, MailboxRights, Property, Value};
 use crate::mail::schema::Email;
 use crate::mail::set::JMAPSetMail;
 use crate::mail::sharing::JMAPShareMail;
@@ -39,6 +39,7 @@ use jmap::request::set::{SetRequest, SetResponse};
 use jmap::request::{ACLEnforce, ResultReference};
 use jmap::types::jmap::JMAPId;
 use jmap::SUPERUSER_ID;
+use store::ahash::{AHashMap, AHashSet};
 use store::core::acl::ACL;
 use store::core::collection::Collection;
 use store::core::document::Document;
@@ -52,6 +53,35 @@ use store::tracing::debug;
 use store::{AccountId, DocumentId, JMAPStore, LongInteger, SharedResource};
 use store::{SharedBitmap, Store};
 
+// IANA registered mailbox roles, plus 'spam' kept for compatibility.
+pub const MAILBOX_ROLES: &[&str] = &[
+    "all",
+    "archive",
+    "drafts",
+    "flagged",
+    "important",
+    "inbox",
+    "junk",
+    "memos",
+    "scheduled",
+    "sent",
+    "snoozed",
+    "spam",
+    "subscribed",
+    "trash",
+];
+
+/// Mailbox changes made within a single Mailbox/set request, which are only
+/// written to the store once all creates, updates and destroys were processed.
+#[derive(Debug)]
+struct PendingChanges {
+    released_roles: AHashSet<DocumentId>,
+    assigned_roles: AHashSet<String>,
+    released_names: AHashSet<DocumentId>,
+    assigned_names: AHashSet<(store::JMAPId, String)>,
+    parent_ids: AHashMap<DocumentId, store::JMAPId>,
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct SetArguments {
     pub on_destroy_remove_emails: Option<bool>,
@@ -108,9 +138,48 @@ where
             .on_destroy_remove_emails
             .unwrap_or(false);
 
+        // Roles cleared or changed by an update, or held by a destroyed mailbox,
+        // can be taken over by other mailboxes in the same request
+        let mut pending = PendingChanges {
+            released_roles: helper
+                .will_destroy
+                .iter()
+                .map(|id| id.get_document_id())
+                .chain(helper.request.update.iter().flat_map(|update| {
+                    update
+                        .iter()
+                        .filter(|(_, mailbox)| mailbox.properties.contains_key(&Property::Role))
+                        .map(|(id, _)| id.get_document_id())
+                }))
+                .collect(),
+            assigned_roles: AHashSet::new(),
+            // Likewise for names held by mailboxes that are renamed, moved or destroyed
+            released_names: helper
+                .will_destroy
+                .iter()
+                .map(|id| id.get_document_id())
+                .chain(helper.request.update.iter().flat_map(|update| {
+                    update
+                        .iter()
+                        .filter(|(_, mailbox)| {
+                            mailbox.properties.contains_key(&Property::Name)
+                                || mailbox.properties.contains_key(&Property::ParentId)
+                        })
+                        .map(|(id, _)| id.get_document_id())
+                }))
+                .collect(),
+            assigned_names: AHashSet::new(),
+            parent_ids: AHashMap::new(),
+        };
+
         helper.create(|_create_id, mailbox, helper, document| {
             // Set values
-            let mut mailbox = TinyORM::<Mailbox>::new().mailbox_set(helper, mailbox, None, None)?;
+            let mut mailbox =
+                TinyORM::<Mailbox>::new().mailbox_set(helper, mailbox, None, None, &pending)?;
+            let role = mailbox
+                .get(&Property::Role)
+                .and_then(|v| v.as_text())
+                .map(|v| v.to_string());
 
             // Check ACLs
             if helper.acl.is_shared(helper.account_id) {
@@ -142,11 +211,100 @@ where
             if !mailbox.has_property(&Property::ParentId) {
                 mailbox.set(Property::ParentId, Value::Id { value: 0u64.into() });
             }
+            let parent_id = mailbox
+                .get(&Property::ParentId)
+                .and_then(|v| v.as_id())
+                .unwrap_or(0);
+            let name = mailbox
+                .get(&Property::Name)
+                .and_then(|v| v.as_text())
+                .map(|v| v.to_string());
             mailbox.insert_validate(document)?;
+            if let Some(role) = role {
+                pending.assigned_roles.insert(role);
+            }
+            if let Some(name) = name {
+                pending.assigned_names.insert((parent_id, name));
+            }
+            pending.parent_ids.insert(document.document_id, parent_id);
+
+            // Include computed properties, a new mailbox is always empty
+            let mut created = Mailbox::new(document.document_id.into());
+            for property in [
+                Property::TotalEmails,
+                Property::UnreadEmails,
+                Property::TotalThreads,
+                Property::UnreadThreads,
+            ] {
+                created
+                    .properties
+                    .append(property, Value::Number { value: 0 });
+            }
+            if !helper.acl.is_shared(helper.account_id) {
+                created.properties.append(
+                    Property::MyRights,
+                    Value::MailboxRights {
+                        value: MailboxRights::owner(),
+                    },
+                );
+            }
 
-            Ok(Mailbox::new(document.document_id.into()))
+            Ok(created)
         })?;
 
+        // Apply updates ordered by their depth in the resulting tree, so that
+        // moves are validated against the hierarchy as it will be after this
+        // request rather than rejected because of a move that comes later.
+        if let Some(update) = helper.request.update.take() {
+            let mut final_parent_ids = AHashMap::with_capacity(update.len());
+            for (id, mailbox) in update.iter() {
+                if let Some(parent_id) = match mailbox.properties.get(&Property::ParentId) {
+                    Some(Value::Id { value }) => Some(value.get_document_id() as u64 + 1),
+                    Some(Value::IdReference { value }) => helper
+                        .get_id_reference(Property::ParentId, value)
+                        .ok()
+                        .map(|id| u64::from(id) + 1),
+                    Some(Value::Null) => Some(0),
+                    _ => None,
+                } {
+                    final_parent_ids.insert(id.get_document_id(), parent_id);
+                }
+            }
+
+            let mut sorted_update = Vec::with_capacity(update.len());
+            for (id, mailbox) in update {
+                let mut document_id = id.get_document_id();
+                let mut depth = 0;
+                while depth < self.config.mailbox_max_depth {
+                    let parent_id = if let Some(parent_id) = final_parent_ids
+                        .get(&document_id)
+                        .or_else(|| pending.parent_ids.get(&document_id))
+                    {
+                        *parent_id
+                    } else {
+                        self.get_orm::<Mailbox>(helper.account_id, document_id)?
+                            .and_then(|fields| {
+                                fields.get(&Property::ParentId).and_then(|v| v.as_id())
+                            })
+                            .unwrap_or(0)
+                    };
+                    if parent_id == 0 {
+                        break;
+                    }
+                    document_id = (parent_id - 1).get_document_id();
+                    depth += 1;
+                }
+                sorted_update.push((depth, id, mailbox));
+            }
+            sorted_update.sort_by_key(|(depth, _, _)| *depth);
+            helper.request.update = Some(
+                sorted_update
+                    .into_iter()
+                    .map(|(_, id, mailbox)| (id, mailbox))
+                    .collect(),
+            );
+        }
+
         helper.update(|id, mailbox, helper, document| {
             let document_id = id.get_document_id();
             let current_fields = self
@@ -158,6 +316,7 @@ where
                 mailbox,
                 document_id.into(),
                 Some(&current_fields),
+                &pending,
             )?;
 
             // Role of internal folders cannot be modified
@@ -199,7 +358,41 @@ where
             }
 
             // Merge changes
+            let role = fields
+                .get(&Property::Role)
+                .and_then(|v| v.as_text())
+                .map(|v| v.to_string());
+            let parent_id = fields.get(&Property::ParentId).and_then(|v| v.as_id());
+            let name = if fields.has_property(&Property::Name) || parent_id.is_some() {
+                fields
+                    .get(&Property::Name)
+                    .or_else(|| current_fields.get(&Property::Name))
+                    .and_then(|v| v.as_text())
+                    .map(|name| {
+                        (
+                            parent_id
+                                .or_else(|| {
+                                    current_fields
+                                        .get(&Property::ParentId)
+                                        .and_then(|v| v.as_id())
+                                })
+                                .unwrap_or(0),
+                            name.to_string(),
+                        )
+                    })
+            } else {
+                None
+            };
             current_fields.merge_validate(document, fields)?;
+            if let Some(role) = role {
+                pending.assigned_roles.insert(role);
+            }
+            if let Some(name) = name {
+                pending.assigned_names.insert(name);
+            }
+            if let Some(parent_id) = parent_id {
+                pending.parent_ids.insert(document_id, parent_id);
+            }
 
             Ok(None)
         })?;
@@ -286,9 +479,11 @@ where
                         }
                     };
 
+                    // Fetch all ORMs before making any changes, so that a message whose
+                    // mailbox tags are corrupt fails the request instead of being deleted
+                    // or kept based on the wrong mailbox membership.
+                    let mut messages = Vec::with_capacity(message_doc_ids.len() as usize);
                     for message_document_id in message_doc_ids {
-                        let mut document = Document::new(Collection::Mail, message_document_id);
-                        // Fetch Email's ORM
                         let current_fields = if let Some(current_fields) =
                             self.get_orm::<Email>(helper.account_id, message_document_id)?
                         {
@@ -300,6 +495,21 @@ where
                             );
                             continue;
                         };
+                        if !current_fields
+                            .get_tags(&mail::schema::Property::MailboxIds)
+                            .map_or(false, |tags| tags.contains(&Tag::Id(document_id)))
+                        {
+                            return Err(StoreError::DataCorruption(format!(
+                                "Mailbox tags of Email {}:{} do not include mailbox {}.",
+                                helper.account_id, message_document_id, document_id
+                            ))
+                            .into());
+                        }
+                        messages.push((message_document_id, current_fields));
+                    }
+
+                    for (message_document_id, current_fields) in messages {
+                        let mut document = Document::new(Collection::Mail, message_document_id);
 
                         // If the message is in multiple mailboxes, untag it from the current mailbox,
                         // otherwise delete it.
@@ -389,6 +599,7 @@ where
         mailbox: Mailbox,
         mailbox_id: Option<DocumentId>,
         fields: Option<&TinyORM<Mailbox>>,
+        pending: &PendingChanges,
     ) -> jmap::error::set::Result<Self, Property>;
 }
 
@@ -402,6 +613,7 @@ where
         mailbox: Mailbox,
         mailbox_id: Option<DocumentId>,
         current_fields: Option<&TinyORM<Mailbox>>,
+        pending: &PendingChanges,
     ) -> jmap::error::set::Result<Self, Property> {
         // Set properties
         for (property, value) in mailbox.properties {
@@ -487,10 +699,8 @@ where
                 (Property::ParentId, Value::Null) => Value::Id { value: 0u64.into() },
                 (Property::Role, Value::Text { value }) => {
                     let role = value.to_lowercase();
-                    if [
-                        "inbox", "trash", "spam", "junk", "drafts", "archive", "sent",
-                    ]
-                    .contains(&role.as_str())
+                    if MAILBOX_ROLES.contains(&role.as_str())
+                        || helper.store.config.mailbox_extra_roles.contains(&role)
                     {
                         self.tag(property, Tag::Default);
                         Value::Text { value: role }
@@ -552,13 +762,14 @@ where
             mailbox_id,
             self.get(&Property::ParentId).and_then(|v| v.as_id()),
         ) {
-            // Validate circular parent-child relationship
+            // Validate circular parent-child relationship, following the parents
+            // set earlier in this request before the stored ones
             let mut success = false;
             for _ in 0..helper.store.config.mailbox_max_depth {
                 if mailbox_parent_id == (mailbox_id as store::JMAPId) + 1 {
-                    return Err(SetError::new(
-                        SetErrorType::InvalidProperties,
-                        "Mailbox cannot be a parent of itself.",
+                    return Err(SetError::invalid_property(
+                        Property::ParentId,
+                        "Mailbox cannot be moved under itself or one of its descendants.",
                     ));
                 } else if mailbox_parent_id == 0 {
                     success = true;
@@ -566,7 +777,9 @@ where
                 }
                 let parent_document_id = (mailbox_parent_id - 1).get_document_id();
 
-                if let Some(fields) = helper
+                if let Some(parent_id) = pending.parent_ids.get(&parent_document_id) {
+                    mailbox_parent_id = *parent_id;
+                } else if let Some(fields) = helper
                     .store
                     .get_orm::<Mailbox>(helper.account_id, parent_document_id)?
                 {
@@ -579,42 +792,49 @@ where
                     success = true;
                     break;
                 } else {
-                    return Err(SetError::new(
-                        SetErrorType::InvalidProperties,
+                    return Err(SetError::invalid_property(
+                        Property::ParentId,
                         "Mailbox parent does not exist.",
                     ));
                 }
             }
 
             if !success {
-                return Err(SetError::new(
-                    SetErrorType::InvalidProperties,
+                return Err(SetError::invalid_property(
+                    Property::ParentId,
                     "Mailbox parent-child relationship is too deep.",
                 ));
             }
         }
 
-        // Verify that the mailbox role is unique.
+        // Verify that the mailbox role is unique, ignoring mailboxes that
+        // release their role within this request.
         if let Some(Value::Text {
             value: mailbox_role,
         }) = self.get(&Property::Role)
         {
-            if !helper
-                .store
-                .query_store::<FilterMapper>(
-                    helper.account_id,
-                    Collection::Mailbox,
-                    Filter::new_condition(
-                        Property::Role.into(),
-                        ComparisonOperator::Equal,
-                        Query::Keyword(mailbox_role.into()),
-                    ),
-                    Comparator::None,
-                )?
-                .is_empty()
+            if pending.assigned_roles.contains(mailbox_role)
+                || helper
+                    .store
+                    .query_store::<FilterMapper>(
+                        helper.account_id,
+                        Collection::Mailbox,
+                        Filter::new_condition(
+                            Property::Role.into(),
+                            ComparisonOperator::Equal,
+                            Query::Keyword(mailbox_role.into()),
+                        ),
+                        Comparator::None,
+                    )?
+                    .into_iter()
+                    .any(|id| {
+                        let document_id = id.get_document_id();
+                        mailbox_id != Some(document_id)
+                            && !pending.released_roles.contains(&document_id)
+                    })
             {
-                return Err(SetError::new(
-                    SetErrorType::InvalidProperties,
+                return Err(SetError::invalid_property(
+                    Property::Role,
                     format!("A mailbox with role '{}' already exists.", mailbox_role),
                 ));
             }
@@ -647,6 +867,16 @@ where
             } else {
                 0.into()
             } {
+                if pending
+                    .assigned_names
+                    .contains(&(parent_mailbox_id, mailbox_name.to_string()))
+                {
+                    return Err(SetError::new(
+                        SetErrorType::InvalidProperties,
+                        format!("A mailbox with name '{}' already exists.", mailbox_name),
+                    ));
+                }
+
                 for jmap_id in helper.store.query_store::<FilterMapper>(
                     helper.account_id,
                     Collection::Mailbox,
@@ -657,13 +887,16 @@ where
                     ),
                     Comparator::None,
                 )? {
-                    if helper
-                        .store
-                        .get_orm::<Mailbox>(helper.account_id, jmap_id.get_document_id())?
-                        .unwrap_or_default()
-                        .get(&Property::Name)
-                        .and_then(|n| n.as_text())
-                        == Some(mailbox_name)
+                    let document_id = jmap_id.get_document_id();
+                    if mailbox_id != Some(document_id)
+                        && !pending.released_names.contains(&document_id)
+                        && helper
+                            .store
+                            .get_orm::<Mailbox>(helper.account_id, document_id)?
+                            .unwrap_or_default()
+                            .get(&Property::Name)
+                            .and_then(|n| n.as_text())
+                            == Some(mailbox_name)
                     {
                         return Err(SetError::new(
                             SetErrorType::InvalidProperties,
@@ -27,10 +27,11 @@ use jmap::{
     jmap_store::get::{GetHelper, GetObject, IdMapper, SharedDocsFnc},
     request::{
         get::{GetRequest, GetResponse},
-        ACLEnforce,
+        ACLEnforce, ArgumentDeserializer,
     },
     types::jmap::JMAPId,
 };
+use serde::de::IgnoredAny;
 use store::{
     core::{acl::ACL, collection::Collection, tag::Tag, JMAPIdPrefix},
     read::{
@@ -41,8 +42,14 @@ use store::{
     JMAPStore, Store,
 };
 
+#[derive(Debug, Clone, Default)]
+pub struct GetArguments {
+    pub email_ids_position: Option<usize>,
+    pub email_ids_limit: Option<usize>,
+}
+
 impl GetObject for Thread {
-    type GetArguments = ();
+    type GetArguments = GetArguments;
 
     fn default_properties() -> Vec<Self::Property> {
         vec![Property::Id, Property::EmailIds]
@@ -69,6 +76,8 @@ where
     T: for<'x> Store<'x> + 'static,
 {
     fn thread_get(&self, request: GetRequest<Thread>) -> jmap::Result<GetResponse<Thread>> {
+        let position = request.arguments.email_ids_position;
+        let limit = request.arguments.email_ids_limit;
         let mut helper = GetHelper::new(self, request, None::<IdMapper>, None::<SharedDocsFnc>)?;
         let account_id = helper.account_id;
         let shared_messages = if helper.acl.is_shared(account_id) {
@@ -99,21 +108,37 @@ where
                     }
                 }
 
+                let mut email_ids = self
+                    .query_store::<FilterMapper>(
+                        account_id,
+                        Collection::Mail,
+                        Filter::DocumentSet(doc_ids),
+                        Comparator::Field(FieldComparator {
+                            field: MessageField::ReceivedAt.into(),
+                            ascending: true,
+                        }),
+                    )?
+                    .into_iter()
+                    .map(|doc_id| JMAPId::from_parts(thread_id, doc_id.get_document_id()))
+                    .collect::<Vec<_>>();
+
+                // Return a page of emailIds along with the thread size when requested
+                let email_ids_total = if position.is_some() || limit.is_some() {
+                    let total = email_ids.len();
+                    email_ids = email_ids
+                        .into_iter()
+                        .skip(position.unwrap_or(0))
+                        .take(limit.unwrap_or(usize::MAX))
+                        .collect();
+                    Some(total)
+                } else {
+                    None
+                };
+
                 Ok(Some(Thread {
                     id,
-                    email_ids: self
-                        .query_store::<FilterMapper>(
-                            account_id,
-                            Collection::Mail,
-                            Filter::DocumentSet(doc_ids),
-                            Comparator::Field(FieldComparator {
-                                field: MessageField::ReceivedAt.into(),
-                                ascending: true,
-                            }),
-                        )?
-                        .into_iter()
-                        .map(|doc_id| JMAPId::from_parts(thread_id, doc_id.get_document_id()))
-                        .collect(),
+                    email_ids,
+                    email_ids_total,
                 }))
             } else {
                 Ok(None)
@@ -123,3 +148,26 @@ where
         Ok(response)
     }
 }
+
+impl ArgumentDeserializer for GetArguments {
+    fn deserialize<'x: 'y, 'y, 'z>(
+        &'y mut self,
+        property: &'z str,
+        value: &mut impl serde::de::MapAccess<'x>,
+    ) -> Result<(), String> {
+        match property {
+            "emailIdsPosition" => {
+                self.email_ids_position = value.next_value().map_err(|err| err.to_string())?;
+            }
+            "emailIdsLimit" => {
+                self.email_ids_limit = value.next_value().map_err(|err| err.to_string())?;
+            }
+            _ => {
+                value
+                    .next_value::<IgnoredAny>()
+                    .map_err(|err| err.to_string())?;
+            }
+        }
+        Ok(())
+    }
+}
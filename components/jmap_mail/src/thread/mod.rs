@@ -39,6 +39,7 @@ impl Object for Thread {
         Thread {
             id,
             email_ids: Vec::new(),
+            email_ids_total: None,
         }
     }
 
@@ -30,6 +30,10 @@ pub struct Thread {
     pub id: JMAPId,
     #[serde(rename = "emailIds")]
     pub email_ids: Vec<JMAPId>,
+    #[serde(rename = "emailIdsTotal")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub email_ids_total: Option<usize>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, Copy)]
@@ -23,19 +23,46 @@
 
 use std::{
     fs::{self, File},
-    io::{Read, Seek, SeekFrom, Write},
+    io::{Cursor, Read, Seek, SeekFrom, Write},
     ops::Range,
     path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
 };
 
-use crate::{config::env_settings::EnvSettings, write::mutex_map::MutexMap};
+use ahash::AHashSet;
+use parking_lot::Mutex;
+use tracing::debug;
 
-use super::{BlobId, BlobStore};
+use crate::{
+    config::env_settings::EnvSettings, core::error::StoreError, write::mutex_map::MutexMap,
+};
+
+use super::{BlobId, BlobReader, BlobStore};
+
+/// Prefix of blobs stored zstd compressed, which allows blobs written
+/// before compression was enabled to be read back as they are.
+const COMPRESSED_MAGIC: &[u8] = b"\0STZ1";
+const COMPRESSION_LEVEL: i32 = 3;
 
 pub struct LocalBlobStore {
     pub lock: MutexMap<()>,
     pub base_path: PathBuf,
+    pub temp_path: PathBuf,
+    pub temp_ttl: Duration,
     pub hash_levels: usize,
+    pub compress: bool,
+    pub verify_on_read: bool,
+    temp_files: Mutex<AHashSet<PathBuf>>,
+    temp_seq: AtomicU64,
+}
+
+/// A temporary file a blob is being written to. The file is pinned while the
+/// write is in progress so the reaper leaves it alone, and it is removed when
+/// dropped unless it was moved to its final path.
+pub struct TempFile<'x> {
+    store: &'x LocalBlobStore,
+    pub path: PathBuf,
 }
 
 impl BlobStore for LocalBlobStore {
@@ -46,16 +73,37 @@ impl BlobStore for LocalBlobStore {
                 .unwrap_or_else(|| "/usr/local/stalwart-jmap/data".to_string()),
         );
         base_path.push("blobs");
+        let mut temp_path = base_path.clone();
+        temp_path.push("tmp");
         Ok(LocalBlobStore {
             lock: MutexMap::with_capacity(1024),
             base_path,
+            temp_path,
+            temp_ttl: Duration::from_secs(settings.parse("blob-temp-file-ttl").unwrap_or(3600)),
             hash_levels: std::cmp::min(settings.parse("blob-nested-levels").unwrap_or(2), 5),
+            compress: settings.parse("blob-compress").unwrap_or(false),
+            verify_on_read: settings.parse("blob-verify-on-read").unwrap_or(false),
+            temp_files: Mutex::new(AHashSet::default()),
+            temp_seq: AtomicU64::new(0),
         })
     }
 
     fn put(&self, blob_id: &BlobId, blob: &[u8]) -> crate::Result<bool> {
         let blob_path = self.get_path(blob_id)?;
 
+        // Blobs that happen to start with the magic are compressed as well,
+        // otherwise they would be mistaken for compressed blobs when read.
+        let compressed_blob;
+        let blob = if self.compress || blob.starts_with(COMPRESSED_MAGIC) {
+            let mut buf = Vec::with_capacity(COMPRESSED_MAGIC.len() + blob.len() / 2);
+            buf.extend_from_slice(COMPRESSED_MAGIC);
+            zstd::stream::copy_encode(blob, &mut buf, COMPRESSION_LEVEL)?;
+            compressed_blob = buf;
+            &compressed_blob[..]
+        } else {
+            blob
+        };
+
         if blob_path.exists() {
             let metadata = fs::metadata(&blob_path)?;
             if metadata.len() as usize == blob.len() {
@@ -63,10 +111,16 @@ impl BlobStore for LocalBlobStore {
             }
         }
 
-        fs::create_dir_all(blob_path.parent().unwrap())?;
-        let mut blob_file = File::create(&blob_path)?;
+        // Write to a temporary file first, so an interrupted write never
+        // leaves a partial blob at its final path
+        let temp_file = self.temp_file(blob_id)?;
+        let mut blob_file = File::create(&temp_file.path)?;
         blob_file.write_all(blob)?;
         blob_file.flush()?;
+        drop(blob_file);
+
+        fs::create_dir_all(blob_path.parent().unwrap())?;
+        fs::rename(&temp_file.path, &blob_path)?;
 
         Ok(true)
     }
@@ -79,6 +133,31 @@ impl BlobStore for LocalBlobStore {
 
         let blob_size = fs::metadata(&blob_path)?.len();
         let mut blob = File::open(&blob_path)?;
+
+        // Compressed streams can't be seeked into, so the whole blob is
+        // decompressed and the requested range is sliced from the result.
+        if blob_size >= COMPRESSED_MAGIC.len() as u64 {
+            let mut magic = [0u8; COMPRESSED_MAGIC.len()];
+            blob.read_exact(&mut magic)?;
+            if magic == COMPRESSED_MAGIC {
+                let mut buf = zstd::stream::decode_all(&mut blob)?;
+                if range.start != 0 || range.end != u32::MAX {
+                    let blob_size = buf.len() as u32;
+                    let from_offset = if range.start < blob_size {
+                        range.start
+                    } else {
+                        0
+                    };
+                    buf.truncate(std::cmp::min(range.end, blob_size) as usize);
+                    buf.drain(..from_offset as usize);
+                } else {
+                    self.verify(blob_id, &buf)?;
+                }
+                return Ok(Some(buf));
+            }
+            blob.seek(SeekFrom::Start(0))?;
+        }
+
         Ok(Some(if range.start != 0 || range.end != u32::MAX {
             let from_offset = if range.start < blob_size as u32 {
                 range.start
@@ -96,10 +175,56 @@ impl BlobStore for LocalBlobStore {
         } else {
             let mut buf = Vec::with_capacity(blob_size as usize);
             blob.read_to_end(&mut buf)?;
+            self.verify(blob_id, &buf)?;
             buf
         }))
     }
 
+    fn get_reader(&self, blob_id: &BlobId, range: Range<u32>) -> crate::Result<Option<BlobReader>> {
+        let blob_path = self.get_path(blob_id)?;
+        if !blob_path.exists() {
+            return Ok(None);
+        }
+
+        // Compressed blobs and verified full reads need the whole blob in memory
+        let blob_size = fs::metadata(&blob_path)?.len();
+        let mut blob = File::open(&blob_path)?;
+        let is_compressed = if blob_size >= COMPRESSED_MAGIC.len() as u64 {
+            let mut magic = [0u8; COMPRESSED_MAGIC.len()];
+            blob.read_exact(&mut magic)?;
+            magic == COMPRESSED_MAGIC
+        } else {
+            false
+        };
+        if is_compressed || (self.verify_on_read && range.start == 0 && range.end == u32::MAX) {
+            return Ok(self
+                .get_range(blob_id, range)?
+                .map(|bytes| Box::new(Cursor::new(bytes)) as BlobReader));
+        }
+
+        let from_offset = if range.start < blob_size as u32 {
+            range.start
+        } else {
+            0
+        };
+        let to_offset = std::cmp::min(range.end as u64, blob_size);
+        blob.seek(SeekFrom::Start(from_offset as u64))?;
+
+        Ok(Some(Box::new(
+            blob.take(to_offset.saturating_sub(from_offset as u64)),
+        )))
+    }
+
+    fn size(&self, blob_id: &BlobId) -> crate::Result<Option<u64>> {
+        // Compressed blobs are accounted for by their size on disk
+        let blob_path = self.get_path(blob_id)?;
+        Ok(if blob_path.exists() {
+            Some(fs::metadata(&blob_path)?.len())
+        } else {
+            None
+        })
+    }
+
     fn delete(&self, blob_id: &BlobId) -> crate::Result<bool> {
         let blob_path = self.get_path(blob_id)?;
         if blob_path.exists() {
@@ -112,6 +237,64 @@ impl BlobStore for LocalBlobStore {
 }
 
 impl LocalBlobStore {
+    /// Returns a new temporary file for the blob, pinned until it is dropped.
+    pub fn temp_file(&self, blob_id: &BlobId) -> crate::Result<TempFile> {
+        fs::create_dir_all(&self.temp_path)?;
+        let mut path = self.temp_path.clone();
+        path.push(format!(
+            "{}.{}",
+            blob_id,
+            self.temp_seq.fetch_add(1, Ordering::Relaxed)
+        ));
+        self.temp_files.lock().insert(path.clone());
+        Ok(TempFile { store: self, path })
+    }
+
+    /// Deletes the temporary files older than `blob-temp-file-ttl` left behind
+    /// by interrupted writes. Files with a write in progress are skipped.
+    pub fn purge_temp_files(&self) -> crate::Result<usize> {
+        let entries = match fs::read_dir(&self.temp_path) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut num_deleted = 0;
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+            if self.temp_files.lock().contains(&path) {
+                continue;
+            }
+            let metadata = entry.metadata()?;
+            if metadata.is_file()
+                && metadata
+                    .modified()?
+                    .elapsed()
+                    .map_or(false, |age| age > self.temp_ttl)
+            {
+                debug!("Deleting stale temporary blob file {:?}.", path);
+                fs::remove_file(&path)?;
+                num_deleted += 1;
+            }
+        }
+
+        Ok(num_deleted)
+    }
+
+    // Partial reads can't be checked against the hash, so only full reads
+    // are verified.
+    fn verify(&self, blob_id: &BlobId, bytes: &[u8]) -> crate::Result<()> {
+        if !self.verify_on_read || blob_id.is_valid(bytes) {
+            Ok(())
+        } else {
+            Err(StoreError::InternalError(format!(
+                "Blob {} is corrupted: contents do not match its hash.",
+                blob_id
+            )))
+        }
+    }
+
     fn get_path(&self, blob_id: &BlobId) -> crate::Result<PathBuf> {
         let mut path = self.base_path.clone();
         let hash = blob_id.hash();
@@ -123,3 +306,12 @@ impl LocalBlobStore {
         Ok(path)
     }
 }
+
+impl Drop for TempFile<'_> {
+    fn drop(&mut self) {
+        if self.path.exists() {
+            let _ = fs::remove_file(&self.path);
+        }
+        self.store.temp_files.lock().remove(&self.path);
+    }
+}
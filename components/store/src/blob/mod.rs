@@ -21,7 +21,12 @@
  * for more details.
 */
 
-use std::{convert::TryInto, fmt::Display, io::Write, ops::Range};
+use std::{
+    convert::TryInto,
+    fmt::Display,
+    io::{Cursor, Read, Write},
+    ops::Range,
+};
 
 use sha2::{Digest, Sha256};
 
@@ -33,6 +38,7 @@ use crate::{
 pub mod local;
 pub mod purge;
 pub mod store;
+pub mod usage;
 
 pub const BLOB_HASH_LEN: usize = 32;
 pub const BLOB_LOCAL: u8 = 0;
@@ -79,6 +85,12 @@ impl BlobId {
             BlobId::External { hash } => hash,
         }
     }
+
+    pub fn is_valid(&self, bytes: &[u8]) -> bool {
+        let mut hasher = Sha256::new();
+        hasher.update(bytes);
+        hasher.finalize().as_slice() == self.hash()
+    }
 }
 
 impl Display for BlobId {
@@ -117,12 +129,23 @@ impl StoreDeserialize for BlobId {
     }
 }
 
+pub type BlobReader = Box<dyn Read + Send + Sync>;
+
 pub trait BlobStore: Sized {
     fn new(settings: &EnvSettings) -> crate::Result<Self>;
     fn get_range(&self, blob_id: &BlobId, range: Range<u32>) -> crate::Result<Option<Vec<u8>>>;
     fn get(&self, blob_id: &BlobId) -> crate::Result<Option<Vec<u8>>> {
         self.get_range(blob_id, 0..u32::MAX)
     }
+    fn get_reader(&self, blob_id: &BlobId, range: Range<u32>) -> crate::Result<Option<BlobReader>> {
+        Ok(self
+            .get_range(blob_id, range)?
+            .map(|bytes| Box::new(Cursor::new(bytes)) as BlobReader))
+    }
+    /// Returns the number of bytes the blob takes in storage.
+    fn size(&self, blob_id: &BlobId) -> crate::Result<Option<u64>> {
+        Ok(self.get(blob_id)?.map(|bytes| bytes.len() as u64))
+    }
     fn put(&self, blob_id: &BlobId, blob: &[u8]) -> crate::Result<bool>;
     fn delete(&self, blob_id: &BlobId) -> crate::Result<bool>;
 }
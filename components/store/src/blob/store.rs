@@ -21,7 +21,7 @@
  * for more details.
 */
 
-use std::{ops::Range, time::SystemTime};
+use std::{io::Cursor, ops::Range, time::SystemTime};
 
 use roaring::RoaringBitmap;
 use tracing::error;
@@ -34,7 +34,7 @@ use crate::{
     AccountId, ColumnFamily, Direction, DocumentId, JMAPStore, Store,
 };
 
-use super::{BlobId, BlobStore};
+use super::{BlobId, BlobReader, BlobStore};
 
 impl<T> JMAPStore<T>
 where
@@ -142,6 +142,23 @@ where
         }
     }
 
+    pub fn blob_get_reader(
+        &self,
+        blob_id: &BlobId,
+        range: Range<u32>,
+    ) -> crate::Result<Option<BlobReader>> {
+        if !blob_id.is_local() {
+            self.blob_store.get_reader(blob_id, range)
+        } else {
+            Ok(if range.start == 0 && range.end == u32::MAX {
+                self.blob_get(blob_id)?
+            } else {
+                self.blob_get_range(blob_id, range)?
+            }
+            .map(|bytes| Box::new(Cursor::new(bytes)) as BlobReader))
+        }
+    }
+
     pub fn blob_account_has_access(
         &self,
         blob_id: &BlobId,
@@ -0,0 +1,247 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use ahash::{AHashMap, AHashSet};
+use parking_lot::MutexGuard;
+use tracing::debug;
+
+use crate::serialize::key::{BlobKey, ValueKey};
+use crate::serialize::leb128::{Leb128Reader, Leb128Vec};
+use crate::serialize::StoreSerialize;
+use crate::write::operation::WriteOperation;
+use crate::{AccountId, ColumnFamily, Direction, JMAPStore, Store};
+
+use super::{BlobId, BlobStore};
+
+pub const USAGE_BLOB_BYTES: u8 = 0;
+pub const USAGE_SHARED_BLOB_BYTES: u8 = 1;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccountUsage {
+    /// Bytes taken by the blobs linked to the account's documents, blobs
+    /// linked more than once are only counted once.
+    pub blob_bytes: u64,
+    /// Part of `blob_bytes` taken by blobs also linked by other accounts,
+    /// which are stored only once.
+    pub shared_blob_bytes: u64,
+    /// Bytes taken by the account's document values and sort indexes.
+    pub index_bytes: u64,
+}
+
+#[derive(Debug)]
+pub struct BlobLinkChange {
+    pub account_id: AccountId,
+    pub blob_id: BlobId,
+    pub key: Vec<u8>,
+    pub is_set: bool,
+}
+
+impl<T> JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    /// Returns the storage currently used by an account. Blob usage is read
+    /// from counters that are updated whenever an account links its first
+    /// document to a blob or unlinks its last one. Uploaded blobs not yet
+    /// linked to any document are not included.
+    pub fn account_usage(&self, account_id: AccountId) -> crate::Result<AccountUsage> {
+        let mut usage = AccountUsage {
+            blob_bytes: self.get_usage(account_id, USAGE_BLOB_BYTES)?,
+            shared_blob_bytes: self.get_usage(account_id, USAGE_SHARED_BLOB_BYTES)?,
+            index_bytes: 0,
+        };
+
+        // Values are prefixed by the LEB128 encoded account id, indexes by
+        // its big-endian representation.
+        let mut values_prefix = Vec::with_capacity(std::mem::size_of::<AccountId>() + 1);
+        values_prefix.push_leb128(account_id);
+        for (cf, prefix) in [
+            (ColumnFamily::Values, values_prefix),
+            (ColumnFamily::Indexes, account_id.to_be_bytes().to_vec()),
+        ] {
+            for (key, value) in self.db.iterator(cf, &prefix, Direction::Forward)? {
+                if !key.starts_with(&prefix) {
+                    break;
+                }
+                usage.index_bytes += (key.len() + value.len()) as u64;
+            }
+        }
+
+        debug!("Storage usage for account {}: {:?}", account_id, usage);
+
+        Ok(usage)
+    }
+
+    fn get_usage(&self, account_id: AccountId, counter: u8) -> crate::Result<u64> {
+        Ok(self
+            .db
+            .get::<i64>(
+                ColumnFamily::Values,
+                &ValueKey::serialize_usage(account_id, counter),
+            )?
+            .unwrap_or(0)
+            .max(0) as u64)
+    }
+
+    /// Adds the usage counter updates caused by the blob links of a batch to
+    /// its write operations. The returned guard has to be held until the
+    /// operations are written, so that concurrent batches see each other's links.
+    pub fn update_blob_usage(
+        &self,
+        ops: &mut Vec<WriteOperation>,
+        blob_links: Vec<BlobLinkChange>,
+    ) -> crate::Result<Option<MutexGuard<'_, ()>>> {
+        if blob_links.is_empty() {
+            return Ok(None);
+        }
+        let lock = self.usage_lock.lock();
+
+        // Group link changes by account and blob, the last change to a link wins
+        let mut link_changes: Vec<((AccountId, BlobId), AHashMap<Vec<u8>, bool>)> = Vec::new();
+        for link in blob_links {
+            let link_key = (link.account_id, link.blob_id);
+            if let Some((_, changes)) = link_changes.iter_mut().find(|(key, _)| key == &link_key) {
+                changes.insert(link.key, link.is_set);
+            } else {
+                link_changes.push((link_key, AHashMap::from_iter([(link.key, link.is_set)])));
+            }
+        }
+
+        let mut is_linked_by: AHashMap<(AccountId, BlobId), bool> = AHashMap::default();
+        let mut deltas: AHashMap<(AccountId, u8), i64> = AHashMap::default();
+        for ((account_id, blob_id), changes) in link_changes {
+            let mut links = self.blob_document_links(&blob_id, account_id)?;
+            let was_linked = !links.is_empty();
+            for (key, is_set) in changes {
+                if is_set {
+                    links.insert(key);
+                } else {
+                    links.remove(&key);
+                }
+            }
+            let is_linked = !links.is_empty();
+            if was_linked == is_linked {
+                continue;
+            }
+
+            // Obtain the other accounts linking the blob, including the ones
+            // whose links were changed earlier in this batch.
+            let mut other_accounts = self.blob_linked_accounts(&blob_id)?;
+            for ((linked_account_id, linked_blob_id), is_linked) in &is_linked_by {
+                if linked_blob_id == &blob_id {
+                    if *is_linked {
+                        other_accounts.insert(*linked_account_id);
+                    } else {
+                        other_accounts.remove(linked_account_id);
+                    }
+                }
+            }
+            other_accounts.remove(&account_id);
+            is_linked_by.insert((account_id, blob_id.clone()), is_linked);
+
+            let size = self.blob_size(&blob_id)? as i64;
+            let delta = if is_linked { size } else { -size };
+            *deltas.entry((account_id, USAGE_BLOB_BYTES)).or_insert(0) += delta;
+            if !other_accounts.is_empty() {
+                *deltas
+                    .entry((account_id, USAGE_SHARED_BLOB_BYTES))
+                    .or_insert(0) += delta;
+            }
+            if other_accounts.len() == 1 {
+                // The only other account starts or stops sharing the blob
+                *deltas
+                    .entry((
+                        other_accounts.into_iter().next().unwrap(),
+                        USAGE_SHARED_BLOB_BYTES,
+                    ))
+                    .or_insert(0) += delta;
+            }
+        }
+
+        for ((account_id, counter), delta) in deltas {
+            if delta != 0 {
+                ops.push(WriteOperation::merge(
+                    ColumnFamily::Values,
+                    ValueKey::serialize_usage(account_id, counter),
+                    delta.serialize().unwrap(),
+                ));
+            }
+        }
+
+        Ok(Some(lock))
+    }
+
+    /// Returns the size a blob takes in the store, external blobs are
+    /// measured by their size on disk.
+    pub fn blob_size(&self, blob_id: &BlobId) -> crate::Result<u64> {
+        Ok(if blob_id.is_external() {
+            self.blob_store.size(blob_id)?.unwrap_or(0)
+        } else {
+            self.db
+                .get::<Vec<u8>>(ColumnFamily::Blobs, &BlobKey::serialize(blob_id))?
+                .map_or(0, |bytes| bytes.len() as u64)
+        })
+    }
+
+    fn blob_document_links(
+        &self,
+        blob_id: &BlobId,
+        account_id: AccountId,
+    ) -> crate::Result<AHashSet<Vec<u8>>> {
+        let prefix = BlobKey::serialize_prefix(blob_id, account_id);
+        let mut links = AHashSet::new();
+        for (key, _) in self
+            .db
+            .iterator(ColumnFamily::Blobs, &prefix, Direction::Forward)?
+        {
+            if !key.starts_with(&prefix) {
+                break;
+            } else if key.len() > prefix.len() {
+                links.insert(key.to_vec());
+            }
+        }
+        Ok(links)
+    }
+
+    /// Returns the accounts that link the blob from at least one document,
+    /// ephemeral links only contain the account id and are not included.
+    pub fn blob_linked_accounts(&self, blob_id: &BlobId) -> crate::Result<AHashSet<AccountId>> {
+        let prefix = BlobKey::serialize(blob_id);
+        let mut accounts = AHashSet::new();
+        for (key, _) in self
+            .db
+            .iterator(ColumnFamily::Blobs, &prefix, Direction::Forward)?
+        {
+            if !key.starts_with(&prefix) {
+                break;
+            } else if let Some((account_id, bytes_read)) =
+                (&key[prefix.len()..]).read_leb128::<AccountId>()
+            {
+                if key.len() > prefix.len() + bytes_read {
+                    accounts.insert(account_id);
+                }
+            }
+        }
+        Ok(accounts)
+    }
+}
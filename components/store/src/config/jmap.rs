@@ -34,23 +34,52 @@ pub struct JMAPConfig {
     pub max_size_request: usize,
     pub max_concurrent_requests: usize,
     pub max_calls_in_request: usize,
+    pub max_result_ref_depth: usize,
+    pub max_result_refs_in_request: usize,
     pub max_objects_in_get: usize,
     pub max_objects_in_set: usize,
+    pub response_compression: bool,
+    pub response_compression_threshold: usize,
 
     pub rate_limit_authenticated: (u64, u64),
     pub rate_limit_anonymous: (u64, u64),
     pub rate_limit_auth: (u64, u64),
+    pub rate_limit_upload: (u64, u64),
     pub use_forwarded_header: bool,
 
     pub query_max_results: usize,
+    pub query_cursor_ttl: u64,
+    pub query_stats: bool,
+    pub query_max_conditions: usize,
+    pub request_timeout: u64,
+    pub idempotency_grace_period: u64,
+    pub idempotency_cache_size: u64,
     pub changes_max_results: usize,
     pub mailbox_name_max_len: usize,
     pub mailbox_max_total: usize,
     pub mailbox_max_depth: usize,
+    pub mailbox_extra_roles: Vec<String>,
+    pub mailbox_default_sort: String,
     pub mail_max_size: usize,
     pub mail_attachments_max_size: usize,
+    pub mail_keyword_max_length: usize,
     pub mail_import_max_items: usize,
     pub mail_parse_max_items: usize,
+    pub mail_preview_length: usize,
+    pub mail_destroy_grace_period: u64,
+    pub mail_strict_part_types: bool,
+    pub mail_normalize_sender: bool,
+    pub mail_max_recipients: usize,
+    pub mail_dedup_recipients: bool,
+    pub mail_thread_subject_fallback: bool,
+    pub mail_sent_at_max_skew: u64,
+    pub mail_sent_keep_bcc: bool,
+    pub mail_default_sort: String,
+    pub mail_address_search: bool,
+    pub enforce_line_length: bool,
+    pub import_dedup_by_message_id: bool,
+    pub import_store_unparsed: bool,
+    pub identity_send_as_groups: bool,
 
     pub push_max_total: usize,
     pub ws_heartbeat_interval: u64,
@@ -59,36 +88,92 @@ pub struct JMAPConfig {
     pub event_source_throttle: u64,
 
     pub raft_commit_timeout: u64,
+    pub raft_linearizable_reads: bool,
+
+    pub log_compact_threshold: u64,
+    pub log_compact_interval: u64,
 }
 
 impl From<&EnvSettings> for JMAPConfig {
     fn from(settings: &EnvSettings) -> Self {
         JMAPConfig {
-            max_size_upload: settings.parse("max-size-upload").unwrap_or(50000000),
+            max_size_upload: settings
+                .parse("blob-max-upload-size")
+                .or_else(|| settings.parse("max-size-upload"))
+                .unwrap_or(50000000),
             max_concurrent_uploads: settings.parse("max-concurrent-uploads").unwrap_or(4),
             max_concurrent_requests: settings.parse("max-concurrent-requests").unwrap_or(4),
             max_size_request: settings.parse("max-size-request").unwrap_or(10000000),
             max_calls_in_request: settings.parse("max-calls-in-request").unwrap_or(16),
+            max_result_ref_depth: settings.parse("max-result-ref-depth").unwrap_or(8),
+            max_result_refs_in_request: settings.parse("max-result-refs-in-request").unwrap_or(64),
             max_objects_in_get: settings.parse("max-objects-in-get").unwrap_or(500),
             max_objects_in_set: settings.parse("max-objects-in-set").unwrap_or(500),
+            response_compression: settings.parse("response-compression").unwrap_or(true),
+            response_compression_threshold: settings
+                .parse("response-compression-threshold")
+                .unwrap_or(1024),
             blob_temp_ttl: settings.parse("blob-temp-ttl").unwrap_or(3600),
             changes_max_results: settings.parse("changes-max-results").unwrap_or(5000),
             query_max_results: settings.parse("query-max-results").unwrap_or(5000),
+            query_cursor_ttl: settings.parse("query-cursor-ttl").unwrap_or(3600),
+            query_stats: settings.parse("query-stats").unwrap_or(false),
+            query_max_conditions: settings.parse("max-filter-conditions").unwrap_or(1000),
+            request_timeout: settings.parse("request-timeout").unwrap_or(300000),
+            idempotency_grace_period: settings.parse("idempotency-grace-period").unwrap_or(300),
+            idempotency_cache_size: settings
+                .parse("idempotency-cache-size")
+                .unwrap_or(32 * 1024 * 1024),
             mailbox_name_max_len: settings.parse("mailbox-name-max-len").unwrap_or(255),
             mailbox_max_total: settings.parse("mailbox-max-total").unwrap_or(1000),
             mailbox_max_depth: settings.parse("mailbox-max-depth").unwrap_or(10),
+            mailbox_extra_roles: settings
+                .parse_list("mailbox-extra-roles")
+                .unwrap_or_default()
+                .into_iter()
+                .map(|role| role.trim().to_lowercase())
+                .filter(|role| !role.is_empty())
+                .collect(),
+            mailbox_default_sort: settings
+                .get("mailbox-default-sort")
+                .unwrap_or_else(|| "sortOrder asc, name asc".to_string()),
             mail_attachments_max_size: settings
                 .parse("mail-attachments-max-size")
                 .unwrap_or(50000000),
             mail_max_size: settings.parse("mail-max-size").unwrap_or(104857600),
+            mail_keyword_max_length: settings.parse("mail-keyword-max-length").unwrap_or(100),
             mail_import_max_items: settings.parse("mail-import-max-items").unwrap_or(5),
             mail_parse_max_items: settings.parse("mail-parse-max-items").unwrap_or(5),
+            mail_preview_length: settings.parse("mail-preview-length").unwrap_or(256),
+            mail_destroy_grace_period: settings.parse("mail-destroy-grace-period").unwrap_or(0),
+            mail_strict_part_types: settings.parse("mail-strict-part-types").unwrap_or(false),
+            mail_normalize_sender: settings.parse("mail-normalize-sender").unwrap_or(true),
+            mail_max_recipients: settings.parse("mail-max-recipients").unwrap_or(1000),
+            mail_dedup_recipients: settings.parse("mail-dedup-recipients").unwrap_or(false),
+            mail_thread_subject_fallback: settings
+                .parse("mail-thread-subject-fallback")
+                .unwrap_or(false),
+            mail_sent_at_max_skew: settings.parse("mail-sent-at-max-skew").unwrap_or(86400),
+            mail_sent_keep_bcc: settings.parse("mail-sent-keep-bcc").unwrap_or(true),
+            mail_default_sort: settings
+                .get("mail-default-sort")
+                .unwrap_or_else(|| "receivedAt desc".to_string()),
+            mail_address_search: settings.parse("mail-address-search").unwrap_or(false),
+            enforce_line_length: settings.parse("enforce-line-length").unwrap_or(true),
+            import_dedup_by_message_id: settings
+                .parse("import-dedup-by-message-id")
+                .unwrap_or(false),
+            import_store_unparsed: settings.parse("import-store-unparsed").unwrap_or(false),
+            identity_send_as_groups: settings.parse("identity-send-as-groups").unwrap_or(true),
             push_max_total: settings.parse("push-max-total").unwrap_or(100),
             ws_client_timeout: settings.parse("ws-client-timeout").unwrap_or(10 * 1000),
             ws_heartbeat_interval: settings.parse("ws-heartbeat-interval").unwrap_or(5 * 1000),
             ws_throttle: settings.parse("ws-throttle").unwrap_or(1000),
             event_source_throttle: settings.parse("event-source-throttle").unwrap_or(1000),
             raft_commit_timeout: settings.parse("raft-commit-timeout").unwrap_or(1000),
+            raft_linearizable_reads: settings.parse("raft-linearizable-reads").unwrap_or(false),
+            log_compact_threshold: settings.parse("log-compact-threshold").unwrap_or(50000),
+            log_compact_interval: settings.parse("log-compact-interval").unwrap_or(6 * 3600),
             default_language: Language::from_iso_639(
                 &settings
                     .get("default-language")
@@ -125,6 +210,16 @@ impl From<&EnvSettings> for JMAPConfig {
                         .map(|a| (a, b.parse::<u64>().unwrap_or(60)))
                 })
                 .unwrap_or((100, 60)),
+            rate_limit_upload: settings
+                .get("rate-limit-upload")
+                .unwrap_or_else(|| "1000000000/3600".to_string())
+                .split_once('/')
+                .and_then(|(a, b)| {
+                    a.parse::<u64>()
+                        .ok()
+                        .map(|a| (a, b.parse::<u64>().unwrap_or(3600)))
+                })
+                .unwrap_or((1000000000, 3600)),
             use_forwarded_header: settings.parse("use-forwarded-header").unwrap_or(false),
         }
     }
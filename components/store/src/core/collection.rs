@@ -76,23 +76,20 @@ impl From<Collection> for u64 {
 
 impl From<u64> for Collection {
     fn from(value: u64) -> Self {
-        match value {
-            0 => Collection::Principal,
-            1 => Collection::PushSubscription,
-            2 => Collection::Mail,
-            3 => Collection::Mailbox,
-            4 => Collection::Thread,
-            5 => Collection::Identity,
-            6 => Collection::EmailSubmission,
-            7 => Collection::VacationResponse,
-            _ => {
+        u8::try_from(value).map_or_else(
+            |_| {
                 debug_assert!(false, "Invalid collection value: {}", value);
                 Collection::None
-            }
-        }
+            },
+            Collection::from,
+        )
     }
 }
 
+// Collections are serialized as a single byte in keys and are also
+// stored in 64-bit bitmaps, make sure a new variant doesn't overflow either.
+const _: () = assert!((Collection::None as u64) < 64);
+
 impl BitmapItem for Collection {
     fn max() -> u64 {
         Collection::None as u64
@@ -102,3 +99,18 @@ impl BitmapItem for Collection {
         !matches!(self, Collection::None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Collection;
+
+    #[test]
+    fn collection_round_trip() {
+        for value in 0..Collection::None as u8 {
+            let collection = Collection::from(value);
+            assert_ne!(collection, Collection::None, "{}", value);
+            assert_eq!(u8::from(collection), value);
+            assert_eq!(Collection::from(u64::from(collection)), collection);
+        }
+    }
+}
@@ -32,6 +32,7 @@ pub enum StoreError {
     AnchorNotFound,
     DataCorruption(String),
     NotFound(String),
+    Cancelled,
 }
 
 impl StoreError {
@@ -50,6 +51,7 @@ impl Display for StoreError {
             StoreError::AnchorNotFound => write!(f, "Anchor not found."),
             StoreError::DataCorruption(s) => write!(f, "Data corruption: {}", s),
             StoreError::NotFound(s) => write!(f, "Not found: {}", s),
+            StoreError::Cancelled => write!(f, "Request cancelled or timed out."),
         }
     }
 }
@@ -36,11 +36,16 @@ use crate::nlp::Language;
 use blob::local::LocalBlobStore;
 use blob::BlobStore;
 use config::{env_settings::EnvSettings, jmap::JMAPConfig};
+use log::changes::ChangeId;
+use log::metrics::LogMetrics;
 use log::raft::{LogIndex, RaftId};
+use log::scheduler::CompactionScheduler;
 use moka::sync::Cache;
 use parking_lot::{Mutex, MutexGuard};
+use read::cache::{ReadSnapshotGuard, ReadSnapshots};
 use roaring::RoaringBitmap;
 use serialize::StoreDeserialize;
+use std::any::Any;
 use std::sync::atomic::AtomicBool;
 use std::{
     sync::{atomic::AtomicU64, Arc},
@@ -135,21 +140,59 @@ pub enum RecipientType {
     NotFound,
 }
 
+/// Message counts of a mailbox, valid for as long as the mailbox does not
+/// appear in the Mailbox changelog after `change_id`. The changelog is checked
+/// once per account and request, see `mailbox_counters_validated`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MailboxCounters {
+    pub change_id: Option<ChangeId>,
+    pub total_emails: u32,
+    pub unread_emails: u32,
+    pub total_threads: u32,
+    pub unread_threads: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ObjectCacheKey {
+    pub account_id: AccountId,
+    pub collection: Collection,
+    pub id: JMAPId,
+    pub properties_hash: u64,
+}
+
+/// An object assembled by a get method, valid for as long as the collection
+/// state is still `change_id`.
+#[derive(Clone)]
+pub struct CachedObject {
+    pub change_id: Option<ChangeId>,
+    pub size: usize,
+    pub object: Arc<dyn Any + Send + Sync>,
+}
+
 pub struct JMAPStore<T> {
     pub db: T,
     pub blob_store: LocalBlobStore,
     pub config: JMAPConfig,
 
     pub account_lock: MutexMap<()>,
+    pub write_lock: MutexMap<()>,
+    pub usage_lock: Mutex<()>,
+    pub read_snapshots: Arc<ReadSnapshots>,
 
     pub id_assigner: Cache<IdCacheKey, Arc<Mutex<IdAssigner>>>,
     pub shared_documents: Cache<SharedResource, Arc<Option<RoaringBitmap>>>,
     pub acl_tokens: Cache<AccountId, Arc<ACLToken>>,
     pub recipients: Cache<String, Arc<RecipientType>>,
+    pub mailbox_counters: Cache<(AccountId, DocumentId), MailboxCounters>,
+    pub mailbox_counters_validated: Cache<AccountId, ChangeId>,
+    pub objects: Option<Cache<ObjectCacheKey, CachedObject>>,
 
     pub raft_term: AtomicU64,
     pub raft_index: AtomicU64,
     pub tombstone_deletions: AtomicBool,
+
+    pub log_metrics: LogMetrics,
+    pub log_scheduler: CompactionScheduler,
 }
 
 impl<T> JMAPStore<T>
@@ -185,10 +228,49 @@ where
                     settings.parse("cache-tti-recipients").unwrap_or(86400),
                 ))
                 .build(),
+            mailbox_counters: Cache::builder()
+                .initial_capacity(128)
+                .time_to_idle(Duration::from_secs(
+                    settings.parse("cache-tti-mailbox-counters").unwrap_or(3600),
+                ))
+                .build(),
+            mailbox_counters_validated: Cache::builder()
+                .initial_capacity(128)
+                .time_to_idle(Duration::from_secs(
+                    settings.parse("cache-tti-mailbox-counters").unwrap_or(3600),
+                ))
+                .build(),
+            objects: match settings.parse::<u64>("cache-size-objects").unwrap_or(0) {
+                0 => None,
+                max_size => {
+                    // Small objects weigh at least their share of the entry limit
+                    let min_weight = max_size
+                        / settings
+                            .parse::<u64>("cache-entries-objects")
+                            .unwrap_or(1024)
+                            .max(1);
+                    Cache::builder()
+                        .initial_capacity(128)
+                        .max_capacity(max_size)
+                        .weigher(move |_, object: &CachedObject| {
+                            (object.size as u64).max(min_weight).min(u32::MAX as u64) as u32
+                        })
+                        .time_to_live(Duration::from_secs(
+                            settings.parse("cache-ttl-objects").unwrap_or(300),
+                        ))
+                        .build()
+                        .into()
+                }
+            },
             account_lock: MutexMap::with_capacity(1024),
+            write_lock: MutexMap::with_capacity(1024),
+            usage_lock: Mutex::new(()),
+            read_snapshots: Arc::new(ReadSnapshots::default()),
             raft_index: 0.into(),
             raft_term: 0.into(),
             tombstone_deletions: false.into(),
+            log_metrics: LogMetrics::default(),
+            log_scheduler: CompactionScheduler::default(),
             db,
         };
 
@@ -218,6 +300,22 @@ where
         self.account_lock.lock_hash((account, collection))
     }
 
+    /// Serializes set operations on an account. Kept apart from the collection
+    /// and blob locks so that it can always be acquired first without colliding
+    /// with them.
+    #[inline(always)]
+    pub fn lock_account(&self, account: AccountId) -> MutexGuard<'_, ()> {
+        self.write_lock.lock(account)
+    }
+
+    /// Registers a batch reading the account. Taken under the account lock, so
+    /// that a set which already waited for readers commits before the batch
+    /// starts reading.
+    pub fn acquire_read_snapshot(&self, account: AccountId) -> ReadSnapshotGuard {
+        let _account_lock = self.lock_account(account);
+        self.read_snapshots.acquire(account)
+    }
+
     #[inline(always)]
     pub fn try_lock_collection(
         &self,
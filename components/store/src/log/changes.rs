@@ -41,6 +41,7 @@ pub struct Changes {
     pub changes: Vec<Change>,
     pub from_change_id: ChangeId,
     pub to_change_id: ChangeId,
+    pub has_more_changes: bool,
 }
 
 #[derive(Debug)]
@@ -57,6 +58,7 @@ impl Default for Changes {
             changes: Vec::with_capacity(10),
             from_change_id: 0,
             to_change_id: 0,
+            has_more_changes: false,
         }
     }
 }
@@ -187,6 +189,7 @@ where
         account: AccountId,
         collection: Collection,
         query: Query,
+        max_changes: usize,
     ) -> crate::Result<Option<Changes>> {
         let mut changelog = Changes::default();
         let (is_inclusive, from_change_id, to_change_id) = match query {
@@ -197,6 +200,7 @@ where
                 (true, from_change_id, to_change_id)
             }
         };
+        let is_full_sync = matches!(query, Query::All);
         let key = LogKey::serialize_change(account, collection, from_change_id);
         let prefix = &key[0..LogKey::CHANGE_ID_POS];
         let mut is_first = true;
@@ -219,6 +223,21 @@ where
                 if to_change_id > 0 && change_id > to_change_id {
                     break;
                 }
+                // Changes up to a snapshot were compacted or truncated, they can
+                // only be returned to clients syncing from scratch.
+                if !is_full_sync
+                    && change_id > from_change_id
+                    && value.first() == Some(&batch::Change::SNAPSHOT)
+                {
+                    return Ok(None);
+                }
+
+                // Pages end between change ids, clients resume from the last one returned
+                if max_changes > 0 && changelog.changes.len() >= max_changes {
+                    changelog.has_more_changes = true;
+                    break;
+                }
+
                 if is_first {
                     changelog.from_change_id = change_id;
                     is_first = false;
@@ -22,6 +22,7 @@
 */
 
 use super::changes::ChangeId;
+use super::metrics::CompactionMetrics;
 use super::raft::LogIndex;
 use crate::core::bitmap::Bitmap;
 use crate::log::entry::Entry;
@@ -31,10 +32,11 @@ use crate::serialize::leb128::{Leb128Iterator, Leb128Vec};
 use crate::serialize::{DeserializeBigEndian, StoreDeserialize};
 use crate::write::batch;
 use crate::{
-    AccountId, Collection, ColumnFamily, Direction, JMAPStore, Store, StoreError, WriteOperation,
+    AccountId, Collection, ColumnFamily, Direction, DocumentId, JMAPStore, Store, StoreError,
+    WriteOperation,
 };
 use ahash::AHashMap;
-use roaring::RoaringTreemap;
+use roaring::{RoaringBitmap, RoaringTreemap};
 use tracing::debug;
 
 impl<T> JMAPStore<T>
@@ -74,6 +76,10 @@ where
         let mut inserted_ids = RoaringTreemap::new();
         let mut write_batch = Vec::new();
         let mut has_changes = false;
+        let mut metrics = CompactionMetrics {
+            up_to,
+            ..Default::default()
+        };
 
         for (key, value) in self.db.iterator(
             ColumnFamily::Logs,
@@ -107,8 +113,12 @@ where
                         current_account_id,
                         current_collection,
                         up_to,
+                        &mut metrics,
                     )?)?;
                     write_batch = Vec::new();
+                } else {
+                    // The collection had nothing to compact, discard its inserts
+                    inserted_ids.clear();
                 }
                 current_account_id = account_id;
                 current_collection = collection;
@@ -146,6 +156,7 @@ where
                 current_account_id,
                 current_collection,
                 up_to,
+                &mut metrics,
             )?)?;
             write_batch = Vec::new();
         }
@@ -196,6 +207,7 @@ where
 
                 if raft_id.index != up_to {
                     write_batch.push(WriteOperation::delete(ColumnFamily::Logs, key.to_vec()));
+                    metrics.entries_deleted += 1;
                 } else {
                     last_term = raft_id.term;
                 }
@@ -231,6 +243,7 @@ where
                 bytes.push_leb128(account_id);
             }
         }
+        metrics.snapshot_size += bytes.len() as u64;
         write_batch.push(WriteOperation::set(
             ColumnFamily::Logs,
             LogKey::serialize_raft(&RaftId::new(last_term, up_to)),
@@ -238,9 +251,189 @@ where
         ));
         self.db.write(write_batch)?;
 
+        debug!(
+            "Compacted log up to id {}: {} entries deleted, {} bytes in snapshots.",
+            up_to, metrics.entries_deleted, metrics.snapshot_size
+        );
+        self.log_metrics.record_compaction(metrics);
+
+        Ok(())
+    }
+
+    /// Deletes the changes logged for an account's collection before `before`,
+    /// folding them into a snapshot. Clients whose state predates the snapshot
+    /// get no changes back and have to resynchronize. Changes still referenced
+    /// by the raft log are kept.
+    pub fn truncate_changelog(
+        &self,
+        account_id: AccountId,
+        collection: Collection,
+        before: ChangeId,
+    ) -> crate::Result<()> {
+        let before = match self.get_next_raft_id(RaftId::new(0, 0))? {
+            Some(raft_id) if raft_id.index < before => {
+                debug!(
+                    "Changelog truncation for [{}/{:?}] limited to id {} by the raft log.",
+                    account_id, collection, raft_id.index
+                );
+                raft_id.index
+            }
+            _ => before,
+        };
+
+        let key = LogKey::serialize_change(account_id, collection, 0);
+        let prefix = &key[0..LogKey::CHANGE_ID_POS];
+        let mut inserted_ids = RoaringTreemap::new();
+        let mut write_batch = Vec::new();
+        let mut last_change_id = 0;
+
+        for (key, value) in self
+            .db
+            .iterator(ColumnFamily::Logs, &key, Direction::Forward)?
+        {
+            if !key.starts_with(prefix) {
+                break;
+            }
+            let change_id = LogKey::deserialize_change_id(&key).ok_or_else(|| {
+                StoreError::InternalError(format!(
+                    "Failed to deserialize changelog key for [{}/{:?}]: [{:?}]",
+                    account_id, collection, key
+                ))
+            })?;
+            if change_id >= before {
+                break;
+            }
+
+            deserialize_inserts(&mut inserted_ids, &value).ok_or_else(|| {
+                StoreError::InternalError(format!(
+                    "Failed to deserialize changelog value for [{}/{:?}]: [{:?}]",
+                    account_id, collection, key
+                ))
+            })?;
+            write_batch.push(WriteOperation::delete(ColumnFamily::Logs, key.to_vec()));
+            last_change_id = change_id;
+        }
+
+        // A single entry is already the oldest one retained
+        if write_batch.len() < 2 {
+            return Ok(());
+        }
+
+        // The last entry is replaced by the snapshot
+        write_batch.pop();
+        let mut metrics = CompactionMetrics {
+            up_to: last_change_id,
+            ..Default::default()
+        };
+        self.db.write(serialize_snapshot(
+            write_batch,
+            &mut inserted_ids,
+            account_id,
+            collection,
+            last_change_id,
+            &mut metrics,
+        )?)?;
+
+        debug!(
+            "Truncated changelog for [{}/{:?}] up to id {}: {} entries deleted.",
+            account_id, collection, last_change_id, metrics.entries_deleted
+        );
+
         Ok(())
     }
 
+    /// Replays the changelog of an account's collection, starting from its
+    /// snapshot, and returns the ids of the documents it reports as live.
+    pub fn get_changelog_document_ids(
+        &self,
+        account_id: AccountId,
+        collection: Collection,
+    ) -> crate::Result<RoaringBitmap> {
+        let key = LogKey::serialize_change(account_id, collection, 0);
+        let prefix = &key[0..LogKey::CHANGE_ID_POS];
+        let mut inserted_ids = RoaringTreemap::new();
+
+        for (key, value) in self
+            .db
+            .iterator(ColumnFamily::Logs, &key, Direction::Forward)?
+        {
+            if !key.starts_with(prefix) {
+                break;
+            }
+            deserialize_inserts(&mut inserted_ids, &value).ok_or_else(|| {
+                StoreError::InternalError(format!(
+                    "Failed to deserialize changelog value for [{}/{:?}]: [{:?}]",
+                    account_id, collection, key
+                ))
+            })?;
+        }
+
+        // Mail ids carry their thread id in the upper 32 bits
+        Ok(inserted_ids.iter().map(|id| id as DocumentId).collect())
+    }
+
+    /// Checks that the documents reported as live by the changelog of an
+    /// account's collection are exactly the ones stored.
+    pub fn verify_changelog(
+        &self,
+        account_id: AccountId,
+        collection: Collection,
+    ) -> crate::Result<()> {
+        let changelog_ids = self.get_changelog_document_ids(account_id, collection)?;
+        let document_ids = self
+            .get_document_ids(account_id, collection)?
+            .unwrap_or_default();
+
+        if changelog_ids != document_ids {
+            Err(StoreError::InternalError(format!(
+                "Changelog for [{}/{:?}] diverges from its documents: missing {:?}, unexpected {:?}.",
+                account_id,
+                collection,
+                &document_ids - &changelog_ids,
+                &changelog_ids - &document_ids
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Verifies the changelog of every account and collection, returning the
+    /// ones that diverge from their documents. Threads are skipped as they are
+    /// only tracked by the changelog.
+    pub fn verify_changelogs(&self) -> crate::Result<Vec<(AccountId, Collection)>> {
+        let mut collections = Vec::new();
+        for (key, _) in self.db.iterator(
+            ColumnFamily::Logs,
+            &[LogKey::CHANGE_KEY_PREFIX],
+            Direction::Forward,
+        )? {
+            if !key.starts_with(&[LogKey::CHANGE_KEY_PREFIX]) {
+                break;
+            }
+            if let (Some(account_id), Some(collection)) = (
+                (&key[..]).deserialize_be_u32(LogKey::ACCOUNT_POS),
+                key.get(LogKey::COLLECTION_POS),
+            ) {
+                let collection = Collection::from(*collection);
+                if collection != Collection::Thread
+                    && collections.last() != Some(&(account_id, collection))
+                {
+                    collections.push((account_id, collection));
+                }
+            }
+        }
+
+        let mut diverged = Vec::new();
+        for (account_id, collection) in collections {
+            if let Err(err) = self.verify_changelog(account_id, collection) {
+                debug!("{}", err);
+                diverged.push((account_id, collection));
+            }
+        }
+
+        Ok(diverged)
+    }
+
     /*pub fn compact_bitmaps(&self) -> crate::Result<()> {
         // Not currently used.
         for (key, value) in self
@@ -264,7 +457,11 @@ fn serialize_snapshot(
     current_account_id: AccountId,
     current_collection: Collection,
     last_change_id: ChangeId,
+    metrics: &mut CompactionMetrics,
 ) -> crate::Result<Vec<WriteOperation>> {
+    // Every operation queued so far deletes a changelog entry
+    metrics.entries_deleted += write_batch.len() as u64;
+
     let mut bytes = Vec::with_capacity(1 + inserted_ids.serialized_size());
     bytes.push(batch::Change::SNAPSHOT);
     inserted_ids.serialize_into(&mut bytes).map_err(|err| {
@@ -273,6 +470,7 @@ fn serialize_snapshot(
             current_account_id, current_collection, err
         ))
     })?;
+    metrics.snapshot_size += bytes.len() as u64;
     write_batch.push(WriteOperation::set(
         ColumnFamily::Logs,
         LogKey::serialize_change(current_account_id, current_collection, last_change_id),
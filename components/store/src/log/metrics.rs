@@ -0,0 +1,109 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use ahash::AHashMap;
+use parking_lot::Mutex;
+
+use super::changes::ChangeId;
+use crate::core::bitmap::Bitmap;
+use crate::{Collection, ColumnFamily, Direction, JMAPStore, Store};
+
+#[derive(Debug, Default)]
+pub struct LogMetrics {
+    changes_written: [AtomicU64; Collection::None as usize],
+    last_compaction: Mutex<Option<CompactionMetrics>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompactionMetrics {
+    pub up_to: ChangeId,
+    pub entries_deleted: u64,
+    pub snapshot_size: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LogStats {
+    pub changes_written: AHashMap<Collection, u64>,
+    pub logs_size: u64,
+    pub last_compaction: Option<CompactionMetrics>,
+}
+
+impl LogMetrics {
+    pub fn record_changes(&self, collections: &Bitmap<Collection>) {
+        let mut collections = collections.clone();
+        while let Some(collection) = collections.pop() {
+            if let Some(counter) = self.changes_written.get(collection as usize) {
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn record_compaction(&self, metrics: CompactionMetrics) {
+        *self.last_compaction.lock() = metrics.into();
+    }
+
+    pub fn total_changes(&self) -> u64 {
+        self.changes_written
+            .iter()
+            .map(|counter| counter.load(Ordering::Relaxed))
+            .sum()
+    }
+}
+
+impl<T> JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    /// Returns the number of changelog entries written per collection since
+    /// startup, the current size of the Logs column family and the results
+    /// of the last log compaction.
+    pub fn log_metrics(&self) -> crate::Result<LogStats> {
+        let mut logs_size = 0;
+        for (key, value) in self
+            .db
+            .iterator(ColumnFamily::Logs, &[], Direction::Forward)?
+        {
+            logs_size += (key.len() + value.len()) as u64;
+        }
+
+        Ok(LogStats {
+            changes_written: self
+                .log_metrics
+                .changes_written
+                .iter()
+                .enumerate()
+                .filter_map(|(collection, counter)| {
+                    let count = counter.load(Ordering::Relaxed);
+                    if count > 0 {
+                        (Collection::from(collection as u8), count).into()
+                    } else {
+                        None
+                    }
+                })
+                .collect(),
+            logs_size,
+            last_compaction: self.log_metrics.last_compaction.lock().clone(),
+        })
+    }
+}
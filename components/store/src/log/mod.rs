@@ -24,4 +24,6 @@
 pub mod changes;
 pub mod compact;
 pub mod entry;
+pub mod metrics;
 pub mod raft;
+pub mod scheduler;
@@ -0,0 +1,118 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+use tracing::debug;
+
+use crate::{JMAPStore, Store};
+
+const MAX_BACKOFF: u32 = 5;
+
+#[derive(Debug)]
+pub struct CompactionScheduler {
+    is_running: AtomicBool,
+    changes_at_last_run: AtomicU64,
+    last_run: Mutex<Instant>,
+    backoff: AtomicU32,
+}
+
+impl Default for CompactionScheduler {
+    fn default() -> Self {
+        Self {
+            is_running: false.into(),
+            changes_at_last_run: 0.into(),
+            last_run: Mutex::new(Instant::now()),
+            backoff: 0.into(),
+        }
+    }
+}
+
+impl<T> JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    /// Compacts the log once `log-compact-threshold` changes were written since
+    /// the last scheduled run, or after `log-compact-interval` seconds if there
+    /// were any changes at all. Concurrent calls return immediately, and both
+    /// limits are doubled for every run during which new changes kept arriving.
+    /// Returns `true` if a compaction was attempted.
+    pub fn compact_log_scheduled(&self, max_changes: u64) -> crate::Result<bool> {
+        let scheduler = &self.log_scheduler;
+        if scheduler
+            .is_running
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            debug!("Log compaction already in progress.");
+            return Ok(false);
+        }
+
+        let result = self.compact_log_if_due(max_changes);
+        scheduler.is_running.store(false, Ordering::Release);
+        result
+    }
+
+    fn compact_log_if_due(&self, max_changes: u64) -> crate::Result<bool> {
+        let scheduler = &self.log_scheduler;
+        let changes = self.log_metrics.total_changes();
+        let pending = changes.saturating_sub(scheduler.changes_at_last_run.load(Ordering::Acquire));
+        if pending == 0 {
+            return Ok(false);
+        }
+
+        let backoff = 1 << scheduler.backoff.load(Ordering::Acquire);
+        if pending < self.config.log_compact_threshold.saturating_mul(backoff)
+            && scheduler.last_run.lock().elapsed()
+                < Duration::from_secs(self.config.log_compact_interval.saturating_mul(backoff))
+        {
+            return Ok(false);
+        }
+
+        debug!(
+            "Running scheduled log compaction, {} changes since last run.",
+            pending
+        );
+        self.compact_log(max_changes)?;
+
+        // Back off while the store keeps receiving writes during compaction
+        if self.log_metrics.total_changes() > changes {
+            let backoff = scheduler.backoff.load(Ordering::Acquire);
+            if backoff < MAX_BACKOFF {
+                scheduler.backoff.store(backoff + 1, Ordering::Release);
+            }
+        } else {
+            scheduler.backoff.store(0, Ordering::Release);
+        }
+        scheduler
+            .changes_at_last_run
+            .store(changes, Ordering::Release);
+        *scheduler.last_run.lock() = Instant::now();
+
+        Ok(true)
+    }
+}
@@ -270,4 +270,38 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn search_snippets_cjk() {
+        let text = concat!(
+            "孫子曰：兵者，國之大事，死生之地，存亡之道，不可不察也。",
+            "故經之以五事，校之以計，而索其情：一曰道，二曰天，三曰地，四曰將，五曰法。",
+            "孫子兵法，兵者詭道也。故能而示之不能，用而示之不用，近而示之遠，遠而示之近。",
+        );
+
+        // Index and search using the same language-aware tokenizer
+        let mut builder = TermIndexBuilder::new();
+        let mut terms = Vec::new();
+        for token in Tokenizer::new(text, Language::Mandarin, 40) {
+            terms.push(builder.add_token(token));
+        }
+        builder.add_terms(0, 0, terms);
+        let term_index = TermIndex::deserialize(&builder.serialize().unwrap()[..]).unwrap();
+
+        let match_terms = Tokenizer::new("兵法", Language::Mandarin, 40)
+            .map(|token| term_index.get_match_term(token.word.as_ref(), None))
+            .collect::<Vec<_>>();
+        let term_groups = term_index
+            .match_terms(&match_terms, None, false, true, true)
+            .unwrap()
+            .unwrap();
+        assert_eq!(term_groups.len(), 1);
+
+        let snippet = generate_snippet(&term_groups[0].terms, text).unwrap();
+        assert!(snippet.len() <= 255, "{}", snippet);
+        assert!(snippet.contains("孫子<mark>兵法</mark>，"), "{}", snippet);
+        for marked in snippet.split("<mark>").skip(1) {
+            assert_eq!(marked.split_once("</mark>").unwrap().0, "兵法");
+        }
+    }
 }
@@ -0,0 +1,144 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{cell::RefCell, sync::Arc};
+
+use ahash::AHashMap;
+use parking_lot::{Condvar, Mutex};
+use roaring::RoaringBitmap;
+
+use crate::{core::collection::Collection, log::changes::ChangeId, AccountId};
+
+thread_local! {
+    static READ_CACHE: RefCell<Option<Arc<ReadCache>>> = RefCell::new(None);
+}
+
+/// Caches the document id bitmaps of the accounts accessed by a batch of
+/// read-only method calls, so that they are fetched from the database only
+/// once per request. Entries are keyed by the last change id of their
+/// collection, a bitmap is fetched again once other threads write to it.
+#[derive(Debug, Default)]
+pub struct ReadCache {
+    document_ids:
+        Mutex<AHashMap<(AccountId, Collection), (Option<ChangeId>, Option<RoaringBitmap>)>>,
+}
+
+pub struct ReadCacheGuard {
+    prev_cache: Option<Arc<ReadCache>>,
+}
+
+impl ReadCache {
+    /// Makes this cache available to store reads on the current thread
+    /// until the returned guard is dropped.
+    pub fn enter(self: &Arc<Self>) -> ReadCacheGuard {
+        ReadCacheGuard {
+            prev_cache: READ_CACHE.with(|cache| cache.borrow_mut().replace(self.clone())),
+        }
+    }
+
+    pub(crate) fn document_ids<C, F>(
+        account_id: AccountId,
+        collection: Collection,
+        change_id: C,
+        fetch: F,
+    ) -> crate::Result<Option<RoaringBitmap>>
+    where
+        C: FnOnce() -> crate::Result<Option<ChangeId>>,
+        F: FnOnce() -> crate::Result<Option<RoaringBitmap>>,
+    {
+        if let Some(cache) = READ_CACHE.with(|cache| cache.borrow().clone()) {
+            // The change id is read first, a bitmap fetched afterwards is at
+            // least as recent as the change it is stored under.
+            let change_id = change_id()?;
+            if let Some((cached_change_id, document_ids)) =
+                cache.document_ids.lock().get(&(account_id, collection))
+            {
+                if *cached_change_id == change_id {
+                    return Ok(document_ids.clone());
+                }
+            }
+            let document_ids = fetch()?;
+            cache
+                .document_ids
+                .lock()
+                .insert((account_id, collection), (change_id, document_ids.clone()));
+            Ok(document_ids)
+        } else {
+            fetch()
+        }
+    }
+}
+
+impl Drop for ReadCacheGuard {
+    fn drop(&mut self) {
+        let prev_cache = self.prev_cache.take();
+        READ_CACHE.with(|cache| *cache.borrow_mut() = prev_cache);
+    }
+}
+
+/// Tracks the accounts that are being read by batches which expect the results
+/// of one method call to still exist when a later call reads them back, such
+/// as an Email/query followed by an Email/get. Writers wait for these batches
+/// to finish before modifying the account.
+#[derive(Debug, Default)]
+pub struct ReadSnapshots {
+    readers: Mutex<AHashMap<AccountId, usize>>,
+    released: Condvar,
+}
+
+pub struct ReadSnapshotGuard {
+    snapshots: Arc<ReadSnapshots>,
+    account_id: AccountId,
+}
+
+impl ReadSnapshots {
+    pub fn acquire(self: &Arc<Self>, account_id: AccountId) -> ReadSnapshotGuard {
+        *self.readers.lock().entry(account_id).or_insert(0) += 1;
+        ReadSnapshotGuard {
+            snapshots: self.clone(),
+            account_id,
+        }
+    }
+
+    /// Waits until no batches are reading the account. Called with the account
+    /// lock held, which keeps new batches from starting in the meantime.
+    pub fn wait(&self, account_id: AccountId) {
+        let mut readers = self.readers.lock();
+        while readers.contains_key(&account_id) {
+            self.released.wait(&mut readers);
+        }
+    }
+}
+
+impl Drop for ReadSnapshotGuard {
+    fn drop(&mut self) {
+        let mut readers = self.snapshots.readers.lock();
+        if let Some(count) = readers.get_mut(&self.account_id) {
+            *count -= 1;
+            if *count == 0 {
+                readers.remove(&self.account_id);
+                self.snapshots.released.notify_all();
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    cell::RefCell,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::core::error::StoreError;
+
+thread_local! {
+    static DEADLINE: RefCell<Option<Arc<Deadline>>> = RefCell::new(None);
+}
+
+/// Limits how long the store spends serving a request. Long running reads
+/// check the deadline of the current thread and return early once it has
+/// expired or the request was cancelled, for example because the client
+/// disconnected.
+#[derive(Debug, Default)]
+pub struct Deadline {
+    expires: Option<Instant>,
+    cancelled: AtomicBool,
+}
+
+pub struct DeadlineGuard {
+    prev_deadline: Option<Arc<Deadline>>,
+}
+
+pub struct CancelOnDrop {
+    deadline: Arc<Deadline>,
+}
+
+impl Deadline {
+    pub fn new(timeout: Option<Duration>) -> Arc<Self> {
+        Arc::new(Deadline {
+            expires: timeout.map(|timeout| Instant::now() + timeout),
+            cancelled: false.into(),
+        })
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+            || self
+                .expires
+                .map_or(false, |expires| Instant::now() >= expires)
+    }
+
+    /// Makes this deadline apply to store reads on the current thread
+    /// until the returned guard is dropped.
+    pub fn enter(self: &Arc<Self>) -> DeadlineGuard {
+        DeadlineGuard {
+            prev_deadline: DEADLINE.with(|deadline| deadline.borrow_mut().replace(self.clone())),
+        }
+    }
+
+    /// Returns a guard that cancels the deadline when dropped, which happens
+    /// when the future handling the request is dropped on client disconnect.
+    pub fn cancel_on_drop(self: &Arc<Self>) -> CancelOnDrop {
+        CancelOnDrop {
+            deadline: self.clone(),
+        }
+    }
+
+    /// Returns true if the deadline of the current thread has expired.
+    pub fn is_current_expired() -> bool {
+        DEADLINE.with(|deadline| {
+            deadline
+                .borrow()
+                .as_ref()
+                .map_or(false, |deadline| deadline.is_expired())
+        })
+    }
+
+    /// Fails with `StoreError::Cancelled` if the deadline of the current
+    /// thread has expired.
+    pub fn check() -> crate::Result<()> {
+        if !Deadline::is_current_expired() {
+            Ok(())
+        } else {
+            Err(StoreError::Cancelled)
+        }
+    }
+}
+
+impl Drop for DeadlineGuard {
+    fn drop(&mut self) {
+        let prev_deadline = self.prev_deadline.take();
+        DEADLINE.with(|deadline| *deadline.borrow_mut() = prev_deadline);
+    }
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.deadline.cancel();
+    }
+}
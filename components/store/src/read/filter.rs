@@ -183,6 +183,16 @@ impl Text {
             text,
         }
     }
+
+    /// Language used to split the text into terms. Unknown languages fall back
+    /// to the default language, just like the indexer does.
+    pub fn tokenizer_language(&self, default_language: Language) -> Language {
+        if self.language != Language::Unknown {
+            self.language
+        } else {
+            default_language
+        }
+    }
 }
 
 impl Query {
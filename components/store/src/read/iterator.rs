@@ -30,7 +30,7 @@ use crate::{
     DocumentId, FieldId, JMAPId, JMAPStore, Store,
 };
 
-use super::comparator::Comparator;
+use super::{comparator::Comparator, deadline::Deadline, query::QueryStats};
 
 pub struct StoreIterator<'x, T, U>
 where
@@ -41,6 +41,8 @@ where
     iterators: Vec<IndexIterator<'x, T>>,
     filter_map: Option<U>,
     current: usize,
+    check_deadline: bool,
+    pub stats: Option<QueryStats>,
 }
 
 struct DocumentSetIndex {
@@ -164,6 +166,8 @@ where
             iterators,
             filter_map: None,
             current: 0,
+            check_deadline: false,
+            stats: None,
         }
     }
 
@@ -172,6 +176,14 @@ where
         self
     }
 
+    /// Stops the iteration once the deadline of the current thread expires.
+    /// Callers have to check the deadline afterwards to tell whether the
+    /// results are complete.
+    pub fn with_deadline(mut self) -> Self {
+        self.check_deadline = true;
+        self
+    }
+
     pub fn len(&self) -> usize {
         self.iterators[0].remaining.len() as usize
     }
@@ -202,6 +214,11 @@ where
 
     #[allow(clippy::while_let_on_iterator)]
     fn next(&mut self) -> Option<Self::Item> {
+        // Stop early once the request deadline has expired
+        if self.check_deadline && Deadline::is_current_expired() {
+            return None;
+        }
+
         'outer: loop {
             let mut doc_id;
 
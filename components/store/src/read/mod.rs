@@ -25,7 +25,9 @@ use crate::{DocumentId, JMAPId};
 
 pub mod acl;
 pub mod bitmap;
+pub mod cache;
 pub mod comparator;
+pub mod deadline;
 pub mod filter;
 pub mod get;
 pub mod iterator;
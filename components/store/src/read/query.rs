@@ -25,19 +25,31 @@ use crate::{
     core::{collection::Collection, document::MAX_TOKEN_LENGTH, error::StoreError},
     nlp::{stemmer::Stemmer, tokenizers::Tokenizer, Language},
     serialize::key::{BitmapKey, IndexKey},
-    AccountId, DocumentId, JMAPId, JMAPStore, Store,
+    AccountId, ColumnFamily, Direction, DocumentId, FieldId, JMAPId, JMAPStore, LongInteger, Store,
 };
 
 use ahash::AHashSet;
 use roaring::RoaringBitmap;
-use std::vec::IntoIter;
+use std::{
+    time::{Duration, Instant},
+    vec::IntoIter,
+};
 
 use super::{
     comparator::Comparator,
+    deadline::Deadline,
     filter::{Filter, FilterOperator, LogicalOperator, Query},
     iterator::StoreIterator,
 };
 
+#[derive(Debug, Default, Clone)]
+pub struct QueryStats {
+    pub conditions: Vec<u64>,
+    pub filter_time: Duration,
+    pub sort_time: Duration,
+    pub total_time: Duration,
+}
+
 struct State {
     op: LogicalOperator,
     it: IntoIter<Filter>,
@@ -59,6 +71,8 @@ where
     where
         U: FnMut(DocumentId) -> crate::Result<Option<JMAPId>>,
     {
+        Deadline::check()?;
+        let started = Instant::now();
         let document_ids = self
             .get_document_ids(account_id, collection)?
             .unwrap_or_else(RoaringBitmap::new);
@@ -98,9 +112,15 @@ where
         };
 
         let mut stack = Vec::new();
+        let mut stats = if self.config.query_stats {
+            QueryStats::default().into()
+        } else {
+            None
+        };
 
         'outer: loop {
             while let Some(cond) = state.it.next() {
+                Deadline::check()?;
                 match cond {
                     Filter::Condition(filter_cond) => {
                         match filter_cond.value {
@@ -144,22 +164,26 @@ where
 
                                     // Retrieve the Term Index for each candidate and match the exact phrase
                                     if let Some(candidates) = self.get_bitmaps_intersection(
-                                        Tokenizer::new(&text.text, text.language, MAX_TOKEN_LENGTH)
-                                            .into_iter()
-                                            .filter_map(|token| {
-                                                let word = token.word.into_owned();
-                                                let r = if !phrase.contains(&word) {
-                                                    BitmapKey::serialize_term(
-                                                        account_id, collection, field, &word, true,
-                                                    )
-                                                    .into()
-                                                } else {
-                                                    None
-                                                };
-                                                phrase.push(word);
-                                                r
-                                            })
-                                            .collect(),
+                                        Tokenizer::new(
+                                            &text.text,
+                                            text.tokenizer_language(self.config.default_language),
+                                            MAX_TOKEN_LENGTH,
+                                        )
+                                        .into_iter()
+                                        .filter_map(|token| {
+                                            let word = token.word.into_owned();
+                                            let r = if !phrase.contains(&word) {
+                                                BitmapKey::serialize_term(
+                                                    account_id, collection, field, &word, true,
+                                                )
+                                                .into()
+                                            } else {
+                                                None
+                                            };
+                                            phrase.push(word);
+                                            r
+                                        })
+                                        .collect(),
                                     )? {
                                         let mut results = RoaringBitmap::new();
                                         for document_id in candidates.iter() {
@@ -206,11 +230,8 @@ where
                                     let mut text_bitmap = None;
 
                                     // Default language for stemming
-                                    let language = if text.language != Language::Unknown {
-                                        text.language
-                                    } else {
-                                        self.config.default_language
-                                    };
+                                    let language =
+                                        text.tokenizer_language(self.config.default_language);
 
                                     for token in
                                         Stemmer::new(&text.text, language, MAX_TOKEN_LENGTH)
@@ -348,8 +369,22 @@ where
                     Filter::None => (),
                 }
 
-                if state.op == LogicalOperator::And && state.bm.as_ref().unwrap().is_empty() {
-                    break;
+                // Record the candidate set size after each condition
+                if let Some(stats) = &mut stats {
+                    stats
+                        .conditions
+                        .push(state.bm.as_ref().map_or(0, |bm| bm.len()));
+                }
+
+                match (state.op, state.bm.as_ref()) {
+                    (LogicalOperator::And, Some(bm)) if bm.is_empty() => break,
+                    (LogicalOperator::Or, Some(bm))
+                        if bm.len() >= document_ids.len() && document_ids.is_subset(bm) =>
+                    {
+                        // Every document already matches, skip the remaining conditions
+                        break;
+                    }
+                    _ => (),
                 }
             }
             if let Some(mut prev_state) = stack.pop() {
@@ -362,13 +397,78 @@ where
             }
         }
 
-        Ok(StoreIterator::new(
+        let mut results = StoreIterator::new(
             self,
             state.bm.unwrap_or_else(RoaringBitmap::new),
             document_ids,
             account_id,
             collection,
             sort,
-        ))
+        );
+        if let Some(mut stats) = stats {
+            stats.filter_time = started.elapsed();
+            results.stats = stats.into();
+        }
+
+        Ok(results)
+    }
+
+    /// Walks the index of a `LongInteger` field from its highest value down,
+    /// returning at most `limit` documents whose value is strictly greater than
+    /// `min_value`. Documents sharing the same value are returned in descending
+    /// id order, matching a descending `query_store` sort on the same field.
+    pub fn query_index_newer_than(
+        &self,
+        account_id: AccountId,
+        collection: Collection,
+        field: FieldId,
+        min_value: LongInteger,
+        limit: usize,
+    ) -> crate::Result<Vec<DocumentId>> {
+        let mut results = Vec::new();
+        let document_ids =
+            if let Some(document_ids) = self.get_document_ids(account_id, collection)? {
+                document_ids
+            } else {
+                return Ok(results);
+            };
+
+        let prefix = IndexKey::serialize_field(account_id, collection as u8, field);
+        let start_key = if field < FieldId::MAX {
+            IndexKey::serialize_field(account_id, collection as u8, field + 1)
+        } else {
+            IndexKey::serialize_field(account_id + 1, collection as u8, field)
+        };
+        let value_range = prefix.len()..prefix.len() + std::mem::size_of::<LongInteger>();
+
+        for (key, _) in self
+            .db
+            .iterator(ColumnFamily::Indexes, &start_key, Direction::Backward)?
+        {
+            if !key.starts_with(&prefix) || results.len() == limit {
+                break;
+            } else if Deadline::is_current_expired() {
+                return Err(StoreError::Cancelled);
+            } else if key.len() != value_range.end + std::mem::size_of::<DocumentId>() {
+                continue;
+            }
+
+            let value = LongInteger::from_be_bytes(
+                key[value_range.clone()]
+                    .try_into()
+                    .map_err(|_| StoreError::DataCorruption("Invalid index key".to_string()))?,
+            );
+            if value <= min_value {
+                break;
+            }
+
+            let document_id = IndexKey::deserialize_document_id(&key)
+                .ok_or_else(|| StoreError::DataCorruption("Invalid index key".to_string()))?;
+            if document_ids.contains(document_id) {
+                results.push(document_id);
+            }
+        }
+
+        Ok(results)
     }
 }
@@ -62,6 +62,7 @@ pub const INTERNAL_KEY_PREFIX: u8 = 0;
 
 pub const FOLLOWER_COMMIT_INDEX_KEY: &[u8; 2] = &[INTERNAL_KEY_PREFIX, 1];
 pub const LEADER_COMMIT_INDEX_KEY: &[u8; 2] = &[INTERNAL_KEY_PREFIX, 2];
+pub const BACKFILLED_FIELDS_KEY: &[u8; 2] = &[INTERNAL_KEY_PREFIX, 3];
 
 pub struct ValueKey {}
 pub struct BitmapKey {}
@@ -109,6 +110,16 @@ impl ValueKey {
         bytes
     }
 
+    /// Usage counters are stored under the `None` collection, which is not
+    /// used by any document.
+    pub fn serialize_usage(account: AccountId, counter: u8) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(std::mem::size_of::<AccountId>() + 2);
+        bytes.push_leb128(account);
+        bytes.push(Collection::None.into());
+        bytes.push(counter);
+        bytes
+    }
+
     pub fn serialize_acl(
         grant_account: AccountId,
         to_account: AccountId,
@@ -21,13 +21,15 @@
  * for more details.
 */
 
+use ahash::AHashSet;
 use roaring::RoaringBitmap;
 
-use crate::blob::BLOB_HASH_LEN;
-use crate::serialize::key::BitmapKey;
+use crate::blob::usage::USAGE_SHARED_BLOB_BYTES;
+use crate::blob::{BlobId, BLOB_HASH_LEN};
+use crate::serialize::key::{BitmapKey, ValueKey};
 use crate::serialize::leb128::{Leb128Iterator, Leb128Reader};
-use crate::serialize::DeserializeBigEndian;
-use crate::{ColumnFamily, Direction, JMAPStore, Store};
+use crate::serialize::{DeserializeBigEndian, StoreDeserialize, StoreSerialize};
+use crate::{AccountId, ColumnFamily, Direction, JMAPStore, Store};
 
 use super::operation::WriteOperation;
 
@@ -88,26 +90,52 @@ where
             }
         }
 
-        // Delete linked blobs
+        // Delete linked blobs, blobs that remain linked by a single account
+        // are no longer shared.
+        let usage_lock = self.usage_lock.lock();
+        let mut blob_key: Option<Box<[u8]>> = None;
+        let mut linked_by = AHashSet::new();
         for (key, _) in self
             .db
             .iterator(ColumnFamily::Blobs, &[], Direction::Forward)?
         {
-            if let Some((account_id, _)) =
-                key.get(BLOB_HASH_LEN + 1..).and_then(|b| b.read_leb128())
-            {
+            if key.len() <= BLOB_HASH_LEN {
+                continue;
+            }
+            let (hash_key, link) = key.split_at(BLOB_HASH_LEN + 1);
+            if blob_key.as_deref() != Some(hash_key) {
+                if let Some(blob_key) = blob_key.take() {
+                    self.unshare_blob(&mut batch, account_ids, &blob_key, &linked_by)?;
+                }
+                blob_key = Some(hash_key.into());
+                linked_by.clear();
+            }
+
+            if let Some((account_id, bytes_read)) = link.read_leb128::<AccountId>() {
+                // Ephemeral links only contain the account id
+                if link.len() > bytes_read {
+                    linked_by.insert(account_id);
+                }
                 if account_ids.contains(account_id) {
                     batch.push(WriteOperation::Delete {
                         cf: ColumnFamily::Blobs,
                         key: key.to_vec(),
                     });
-                    if batch.len() == DELETE_BATCH_SIZE {
+                    if batch.len() >= DELETE_BATCH_SIZE {
                         self.db.write(batch)?;
                         batch = Vec::with_capacity(64);
                     }
                 }
             }
         }
+        if let Some(blob_key) = blob_key {
+            self.unshare_blob(&mut batch, account_ids, &blob_key, &linked_by)?;
+        }
+        if !batch.is_empty() {
+            self.db.write(batch)?;
+            batch = Vec::with_capacity(64);
+        }
+        drop(usage_lock);
 
         // Delete bitmaps
         for (key, _) in self
@@ -133,4 +161,28 @@ where
 
         Ok(())
     }
+
+    fn unshare_blob(
+        &self,
+        batch: &mut Vec<WriteOperation>,
+        account_ids: &RoaringBitmap,
+        blob_key: &[u8],
+        linked_by: &AHashSet<AccountId>,
+    ) -> crate::Result<()> {
+        let mut remaining = linked_by
+            .iter()
+            .filter(|account_id| !account_ids.contains(**account_id));
+        if let (Some(account_id), None) = (remaining.next(), remaining.next()) {
+            if linked_by.len() > 1 {
+                if let Some(blob_id) = BlobId::deserialize(blob_key) {
+                    batch.push(WriteOperation::merge(
+                        ColumnFamily::Values,
+                        ValueKey::serialize_usage(*account_id, USAGE_SHARED_BLOB_BYTES),
+                        (-(self.blob_size(&blob_id)? as i64)).serialize().unwrap(),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
 }
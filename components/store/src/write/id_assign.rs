@@ -27,7 +27,8 @@ use parking_lot::Mutex;
 use roaring::RoaringBitmap;
 
 use crate::{
-    serialize::key::BitmapKey, AccountId, Collection, DocumentId, JMAPStore, Store, StoreError,
+    read::cache::ReadCache, serialize::key::BitmapKey, AccountId, Collection, DocumentId,
+    JMAPStore, Store, StoreError,
 };
 
 #[derive(Clone, Hash, PartialEq, Eq)]
@@ -85,6 +86,22 @@ impl IdAssigner {
             id
         }
     }
+
+    pub fn reserve_document_id(&mut self, document_id: DocumentId) {
+        if document_id >= self.next_id {
+            if document_id > self.next_id {
+                self.freed_ids
+                    .get_or_insert_with(RoaringBitmap::new)
+                    .insert_range(self.next_id..document_id);
+            }
+            self.next_id = document_id + 1;
+        } else if let Some(freed_ids) = &mut self.freed_ids {
+            freed_ids.remove(document_id);
+            if freed_ids.is_empty() {
+                self.freed_ids = None;
+            }
+        }
+    }
 }
 
 impl<T> JMAPStore<T>
@@ -116,12 +133,51 @@ where
             .assign_document_id())
     }
 
+    /// Takes back the id of a deleted document, such as a destroyed message that
+    /// is being restored. Returns false if the id is in use by another document.
+    pub fn reserve_document_id(
+        &self,
+        account_id: AccountId,
+        collection: Collection,
+        document_id: DocumentId,
+    ) -> crate::Result<bool> {
+        let id_assigner = self.get_id_assigner(account_id, collection)?;
+        let mut id_assigner = id_assigner.lock();
+        if self
+            .get_document_ids(account_id, collection)?
+            .map_or(false, |document_ids| document_ids.contains(document_id))
+        {
+            Ok(false)
+        } else {
+            id_assigner.reserve_document_id(document_id);
+            Ok(true)
+        }
+    }
+
     pub fn get_document_ids(
         &self,
         account_id: AccountId,
         collection: Collection,
     ) -> crate::Result<Option<RoaringBitmap>> {
-        self.get_bitmap(&BitmapKey::serialize_document_ids(account_id, collection))
+        ReadCache::document_ids(
+            account_id,
+            collection,
+            || self.get_last_change_id(account_id, collection),
+            || self.get_bitmap(&BitmapKey::serialize_document_ids(account_id, collection)),
+        )
+    }
+
+    /// Iterates over the ids of all documents in a collection. The ids are read
+    /// once, so documents inserted or deleted during the iteration are not reflected.
+    pub fn iter_documents(
+        &self,
+        account_id: AccountId,
+        collection: Collection,
+    ) -> crate::Result<impl Iterator<Item = DocumentId>> {
+        Ok(self
+            .get_document_ids(account_id, collection)?
+            .unwrap_or_default()
+            .into_iter())
     }
 }
 
@@ -24,7 +24,7 @@
 use ahash::AHashMap;
 
 use crate::{
-    blob::BlobId,
+    blob::{usage::BlobLinkChange, BlobId},
     core::{
         bitmap::Bitmap, collection::Collection, document::MAX_TOKEN_LENGTH, error::StoreError,
         tag::Tag,
@@ -68,16 +68,27 @@ where
             .load(std::sync::atomic::Ordering::Relaxed);
 
         // Prepare linked batch
+        let mut linked_changes = Vec::new();
+        let mut blob_links = Vec::new();
         for sub_batch in batch.linked_batch.drain(..) {
-            self.prepare_batch(&mut ops, sub_batch, tombstone_deletions)?;
+            if let Some(changes) =
+                self.prepare_batch(&mut ops, &mut blob_links, sub_batch, tombstone_deletions)?
+            {
+                linked_changes.push(changes);
+            }
         }
 
         // Prepare main batch
-        let changes = self.prepare_batch(&mut ops, batch, tombstone_deletions)?;
+        let changes = self.prepare_batch(&mut ops, &mut blob_links, batch, tombstone_deletions)?;
 
-        // Submit write batch
+        // Update usage counters and submit write batch
+        let _usage_lock = self.update_blob_usage(&mut ops, blob_links)?;
         self.db.write(ops)?;
 
+        for changes in linked_changes.iter().chain(changes.iter()) {
+            self.log_metrics.record_changes(&changes.collections);
+        }
+
         Ok(changes)
     }
 
@@ -85,17 +96,24 @@ where
         let mut ops = Vec::with_capacity(batch.documents.len());
 
         // Prepare batch
-        let changes = self.prepare_batch(&mut ops, batch, false)?;
+        let mut blob_links = Vec::new();
+        let changes = self.prepare_batch(&mut ops, &mut blob_links, batch, false)?;
 
-        // Submit write batch
+        // Update usage counters and submit write batch
+        let _usage_lock = self.update_blob_usage(&mut ops, blob_links)?;
         self.db.write(ops)?;
 
+        if let Some(changes) = &changes {
+            self.log_metrics.record_changes(&changes.collections);
+        }
+
         Ok(changes)
     }
 
     fn prepare_batch(
         &self,
         ops: &mut Vec<WriteOperation>,
+        blob_links: &mut Vec<BlobLinkChange>,
         batch: WriteBatch,
         tombstone_deletions: bool,
     ) -> crate::Result<Option<Changes>> {
@@ -151,6 +169,65 @@ where
                 }
             };
 
+            // Add/remove terms from existing term index, before any new terms are
+            // added so that a document's text can be replaced in a single update.
+            if let Some((term_index_id, options)) = document.term_index {
+                let token_index =
+                    TokenIndex::deserialize(&self.blob_get(&term_index_id)?.ok_or_else(|| {
+                        StoreError::NotFound("Term Index blob not found.".to_string())
+                    })?)
+                    .ok_or_else(|| {
+                        StoreError::InternalError("Failed to deserialize Term Index.".to_string())
+                    })?;
+                let is_clear = options.is_clear();
+                for term in token_index.terms {
+                    for (term_ids, is_exact) in
+                        [(term.exact_terms, true), (term.stemmed_terms, false)]
+                    {
+                        for term_id in term_ids {
+                            bitmap_list
+                                .entry(BitmapKey::serialize_term(
+                                    batch.account_id,
+                                    document.collection,
+                                    term.field_id,
+                                    token_index.tokens.get(term_id as usize).ok_or_else(|| {
+                                        StoreError::InternalError(
+                                            "Corrupted term index.".to_string(),
+                                        )
+                                    })?,
+                                    is_exact,
+                                ))
+                                .or_insert_with(AHashMap::default)
+                                .insert(document.document_id, !is_clear);
+                        }
+                    }
+                }
+
+                let term_index_key = ValueKey::serialize_term_index(
+                    batch.account_id,
+                    document.collection,
+                    document.document_id,
+                );
+                ops.push(if !is_clear {
+                    WriteOperation::set(
+                        ColumnFamily::Values,
+                        term_index_key,
+                        term_index_id.serialize().ok_or_else(|| {
+                            StoreError::InternalError(
+                                "Failed to serialize Term Index blobId.".to_string(),
+                            )
+                        })?,
+                    )
+                } else {
+                    WriteOperation::Delete {
+                        cf: ColumnFamily::Values,
+                        key: term_index_key,
+                    }
+                });
+
+                document.blobs.push((term_index_id, options));
+            }
+
             // Process text fields
             if !document.text_fields.is_empty() {
                 // Detect language for unknown fields
@@ -315,64 +392,6 @@ where
                 }
             }
 
-            // Add/remove terms from existing term index
-            if let Some((term_index_id, options)) = document.term_index {
-                let token_index =
-                    TokenIndex::deserialize(&self.blob_get(&term_index_id)?.ok_or_else(|| {
-                        StoreError::NotFound("Term Index blob not found.".to_string())
-                    })?)
-                    .ok_or_else(|| {
-                        StoreError::InternalError("Failed to deserialize Term Index.".to_string())
-                    })?;
-                let is_clear = options.is_clear();
-                for term in token_index.terms {
-                    for (term_ids, is_exact) in
-                        [(term.exact_terms, true), (term.stemmed_terms, false)]
-                    {
-                        for term_id in term_ids {
-                            bitmap_list
-                                .entry(BitmapKey::serialize_term(
-                                    batch.account_id,
-                                    document.collection,
-                                    term.field_id,
-                                    token_index.tokens.get(term_id as usize).ok_or_else(|| {
-                                        StoreError::InternalError(
-                                            "Corrupted term index.".to_string(),
-                                        )
-                                    })?,
-                                    is_exact,
-                                ))
-                                .or_insert_with(AHashMap::default)
-                                .insert(document.document_id, !is_clear);
-                        }
-                    }
-                }
-
-                let term_index_key = ValueKey::serialize_term_index(
-                    batch.account_id,
-                    document.collection,
-                    document.document_id,
-                );
-                ops.push(if !is_clear {
-                    WriteOperation::set(
-                        ColumnFamily::Values,
-                        term_index_key,
-                        term_index_id.serialize().ok_or_else(|| {
-                            StoreError::InternalError(
-                                "Failed to serialize Term Index blobId.".to_string(),
-                            )
-                        })?,
-                    )
-                } else {
-                    WriteOperation::Delete {
-                        cf: ColumnFamily::Values,
-                        key: term_index_key,
-                    }
-                });
-
-                document.blobs.push((term_index_id, options));
-            }
-
             // Process numeric values
             for field in document.number_fields {
                 if field.is_stored() {
@@ -448,9 +467,15 @@ where
                     document.document_id,
                 );
                 ops.push(if is_set {
-                    WriteOperation::set(ColumnFamily::Blobs, key, vec![])
+                    WriteOperation::set(ColumnFamily::Blobs, key.clone(), vec![])
                 } else {
-                    WriteOperation::delete(ColumnFamily::Blobs, key)
+                    WriteOperation::delete(ColumnFamily::Blobs, key.clone())
+                });
+                blob_links.push(BlobLinkChange {
+                    account_id: batch.account_id,
+                    blob_id: id,
+                    key,
+                    is_set,
                 });
             }
 
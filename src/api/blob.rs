@@ -28,6 +28,8 @@ use crate::JMAPServer;
 use actix_web::http::header::ContentType;
 use actix_web::HttpRequest;
 use actix_web::{http::StatusCode, web, HttpResponse};
+use async_stream::stream;
+use futures_util::StreamExt;
 use jmap::error::set::SetError;
 use jmap::request::blob::{CopyBlobRequest, CopyBlobResponse};
 use jmap::request::ACLEnforce;
@@ -37,13 +39,19 @@ use jmap::SUPERUSER_ID;
 use jmap_mail::mail::get::{BlobResult, JMAPGetMail};
 use jmap_mail::mail::sharing::JMAPShareMail;
 use jmap_sharing::principal::account::JMAPAccountStore;
-use reqwest::header::CONTENT_TYPE;
+use reqwest::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use std::io::Read;
 use store::blob::BlobId;
 use store::core::acl::ACL;
 use store::core::collection::Collection;
 use store::core::vec_map::VecMap;
 use store::JMAPStore;
-use store::{tracing::error, Store};
+use store::{
+    tracing::{debug, error},
+    Store,
+};
+
+const DOWNLOAD_CHUNK_SIZE: usize = 64 * 1024;
 
 #[derive(serde::Deserialize)]
 pub struct Params {
@@ -66,7 +74,7 @@ where
     let store = core.store.clone();
     match core
         .spawn_worker(move || {
-            store.mail_blob_get(
+            store.mail_blob_get_reader(
                 account_id,
                 &store.get_acl_token(session.account_id())?,
                 &blob_id,
@@ -74,7 +82,7 @@ where
         })
         .await
     {
-        Ok(BlobResult::Blob(bytes)) => Ok(HttpResponse::build(StatusCode::OK)
+        Ok(BlobResult::Blob(mut reader)) => Ok(HttpResponse::build(StatusCode::OK)
             .insert_header((
                 "Content-Type",
                 params
@@ -90,7 +98,35 @@ where
                 ),
             ))
             .insert_header(("Cache-Control", "private, immutable, max-age=31536000"))
-            .body(bytes)),
+            .streaming::<_, std::io::Error>(stream! {
+                // Read the blob in chunks so that memory usage does not depend
+                // on the size of the download
+                loop {
+                    match core
+                        .spawn_worker(move || {
+                            let mut chunk = vec![0; DOWNLOAD_CHUNK_SIZE];
+                            let bytes_read = reader.read(&mut chunk)?;
+                            chunk.truncate(bytes_read);
+                            Ok((reader, chunk))
+                        })
+                        .await
+                    {
+                        Ok((next_reader, chunk)) if !chunk.is_empty() => {
+                            reader = next_reader;
+                            yield Ok(web::Bytes::from(chunk));
+                        }
+                        Ok(_) => break,
+                        Err(err) => {
+                            error!("Blob download failed: {:?}", err);
+                            yield Err(std::io::Error::new(
+                                std::io::ErrorKind::Other,
+                                err.to_string(),
+                            ));
+                            break;
+                        }
+                    }
+                }
+            })),
         Ok(BlobResult::NotFound) => Err(RequestError::not_found()),
         Ok(BlobResult::Unauthorized) => Err(RequestError::forbidden()),
         Err(err) => {
@@ -114,7 +150,7 @@ struct UploadResponse {
 pub async fn handle_jmap_upload<T>(
     path: web::Path<(JMAPId,)>,
     request: HttpRequest,
-    bytes: web::Bytes,
+    mut payload: web::Payload,
     core: web::Data<JMAPServer<T>>,
     session: Session,
 ) -> Result<HttpResponse, RequestError>
@@ -125,10 +161,16 @@ where
     let account_id = id.get_document_id();
 
     // Rate limit uploads
-    let _upload_req = if session.account_id() != SUPERUSER_ID {
+    let limiter = if session.account_id() != SUPERUSER_ID {
         core.rate_limiters
             .get(&RemoteAddress::AccountId(session.account_id()))
             .unwrap()
+            .into()
+    } else {
+        None
+    };
+    let _upload_req = if let Some(limiter) = &limiter {
+        limiter
             .is_upload_allowed(core.store.config.max_concurrent_uploads)
             .ok_or_else(|| RequestError::limit(RequestLimitError::Concurrent))?
             .into()
@@ -136,6 +178,46 @@ where
         None
     };
 
+    // Reject uploads that announce a size over the limit or the account's byte budget
+    let max_size = core.store.config.max_size_upload;
+    let content_length = request
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.parse::<usize>().ok());
+    if let Some(content_length) = content_length {
+        if content_length > max_size {
+            return Err(RequestError::limit(RequestLimitError::SizeUpload));
+        } else if limiter.as_ref().map_or(false, |limiter| {
+            !limiter.is_upload_size_allowed(content_length)
+        }) {
+            return Err(RequestError::too_many_requests());
+        }
+    }
+
+    // Enforce the upload limits as the chunks arrive, charging to the budget
+    // any bytes that were not announced in advance
+    let mut bytes = web::BytesMut::new();
+    let mut bytes_charged = content_length.unwrap_or(0);
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|err| {
+            debug!("Failed to read upload payload: {}", err);
+            RequestError::invalid_parameters()
+        })?;
+        if bytes.len() + chunk.len() > max_size {
+            return Err(RequestError::limit(RequestLimitError::SizeUpload));
+        }
+        bytes.extend_from_slice(&chunk);
+        if bytes.len() > bytes_charged {
+            if let Some(limiter) = &limiter {
+                if !limiter.is_upload_size_allowed(bytes.len() - bytes_charged) {
+                    return Err(RequestError::too_many_requests());
+                }
+            }
+            bytes_charged = bytes.len();
+        }
+    }
+
     #[cfg(test)]
     {
         // Used for concurrent upload tests
@@ -144,10 +226,6 @@ where
         }
     }
 
-    if bytes.len() > core.store.config.max_size_upload {
-        return Err(RequestError::limit(RequestLimitError::Size));
-    }
-
     let store = core.store.clone();
     let size = bytes.len();
     match core
@@ -52,7 +52,13 @@ use jmap_sharing::principal::{
     account::JMAPAccountStore, get::JMAPGetPrincipal, query::JMAPPrincipalQuery,
     set::JMAPSetPrincipal,
 };
-use store::{core::collection::Collection, tracing::error, AccountId, Store};
+use std::{collections::BTreeSet, sync::Arc, time::Duration};
+use store::{
+    core::collection::Collection,
+    read::{cache::ReadCache, deadline::Deadline},
+    tracing::error,
+    AccountId, Store,
+};
 
 pub async fn handle_method_calls<T>(
     request: Request,
@@ -67,8 +73,70 @@ where
         session.state(),
         request.created_ids.unwrap_or_default(),
         request.method_calls.len(),
+    )
+    .with_result_ref_limits(
+        core.store.config.max_result_ref_depth,
+        core.store.config.max_result_refs_in_request,
     );
 
+    // Share document ids and states across the calls of read-only batches,
+    // such as the ones issued by clients during their initial sync.
+    let read_cache = if request.method_calls.len() > 1
+        && request
+            .method_calls
+            .iter()
+            .all(|call| call.method.is_read_only())
+    {
+        Some(Arc::new(ReadCache::default()))
+    } else {
+        None
+    };
+
+    // Messages returned by Email/query have to be still there when they are
+    // fetched by a later Email/get, so writes to the accounts read by the batch
+    // are held off until it is done. Accounts are visited in ascending order
+    // and only the ones the session has access to are held.
+    let mut snapshot_accounts = BTreeSet::new();
+    if read_cache.is_some() {
+        for call in &request.method_calls {
+            if let method::Request::QueryEmail(query) = &call.method {
+                if request.method_calls.iter().any(|call| {
+                    matches!(&call.method, method::Request::GetEmail(get)
+                                if get.account_id == query.account_id)
+                }) {
+                    snapshot_accounts.insert(query.account_id.get_document_id());
+                }
+            }
+        }
+    }
+    let _read_snapshots = if !snapshot_accounts.is_empty() {
+        let store = core.store.clone();
+        let account_id = session.account_id();
+        core.spawn_worker(move || {
+            let acl = store.get_acl_token(account_id)?;
+            Ok(snapshot_accounts
+                .into_iter()
+                .filter(|account_id| acl.has_access(*account_id, Collection::Mail))
+                .map(|account_id| store.acquire_read_snapshot(account_id))
+                .collect::<Vec<_>>())
+        })
+        .await
+        .unwrap_or_else(|err| {
+            error!("Failed to acquire read snapshots: {}", err);
+            Vec::new()
+        })
+    } else {
+        Vec::new()
+    };
+
+    // Abort queries that are still running when the request times out, or when
+    // this future is dropped because the client disconnected.
+    let deadline = Deadline::new(
+        Some(Duration::from_millis(core.store.config.request_timeout))
+            .filter(|timeout| !timeout.is_zero()),
+    );
+    let _cancel_on_drop = deadline.cancel_on_drop();
+
     for call in request.method_calls.into_iter() {
         let call_id = call.id;
         let mut call_method = call.method;
@@ -80,14 +148,28 @@ where
                 break;
             }
 
+            // Writes are not accepted while leadership is being handed over.
+            if !call_method.is_read_only() && core.is_transferring_leadership() {
+                response.push_error(call_id, MethodError::ServerUnavailable);
+                break;
+            }
+
             // Prepare request
-            if let Err(err) = call_method.prepare_request(&response) {
+            if let Err(err) = call_method.prepare_request(&mut response) {
                 response.push_error(call_id, err);
                 break;
             }
 
             // Execute request
-            match handle_method_call(call_method, &core, session.account_id()).await {
+            match handle_method_call(
+                call_method,
+                &core,
+                session.account_id(),
+                read_cache.clone(),
+                deadline.clone(),
+            )
+            .await
+            {
                 Ok(mut method_response) => {
                     let next_call_method = match method_response.changes() {
                         method::Changes::Item {
@@ -205,12 +287,18 @@ pub async fn handle_method_call<T>(
     call: method::Request,
     core: &web::Data<JMAPServer<T>>,
     account_id: AccountId,
+    read_cache: Option<Arc<ReadCache>>,
+    deadline: Arc<Deadline>,
 ) -> jmap::Result<method::Response>
 where
     T: for<'x> Store<'x> + 'static,
 {
     let store = core.store.clone();
     core.spawn_jmap_request(move || {
+        let _read_cache = read_cache.as_ref().map(|cache| cache.enter());
+        // Only queries are cut short, other methods read and write documents that
+        // have to be processed in full.
+        let _deadline = call.is_query().then(|| deadline.enter());
         Ok(match call {
             method::Request::CopyBlob(mut request) => {
                 request.acl = store
@@ -235,9 +235,25 @@ impl Request {
         }
     }
 
-    pub fn prepare_request(&mut self, response: &response::Response) -> jmap::Result<()> {
+    pub fn is_query(&self) -> bool {
+        matches!(
+            self,
+            Request::QueryMailbox(_)
+                | Request::QueryEmail(_)
+                | Request::QueryEmailSubmission(_)
+                | Request::QueryPrincipal(_)
+        )
+    }
+
+    pub fn prepare_request(&mut self, response: &mut response::Response) -> jmap::Result<()> {
         // Create JSON Pointer evaluation function
         let mut eval_result_ref = |rr: &ResultReference| -> Option<Vec<u64>> {
+            // Deep paths and long chains of references are not evaluated
+            if rr.path.depth() > response.result_ref_max_depth || response.result_refs_left == 0 {
+                return None;
+            }
+            response.result_refs_left -= 1;
+
             for r in &response.method_responses {
                 if r.id == rr.result_of {
                     match (&rr.name, &r.method) {
@@ -67,6 +67,8 @@ impl Default for StateChangeResponse {
 pub enum RequestLimitError {
     #[serde(rename(serialize = "maxSizeRequest"))]
     Size,
+    #[serde(rename(serialize = "maxSizeUpload"))]
+    SizeUpload,
     #[serde(rename(serialize = "maxCallsInRequest"))]
     CallsIn,
     #[serde(rename(serialize = "maxConcurrentRequests"))]
@@ -178,6 +180,10 @@ impl RequestError {
                     "The request is larger than the server ",
                     "is willing to process."
                 ),
+                RequestLimitError::SizeUpload => concat!(
+                    "The upload is larger than the server ",
+                    "is willing to accept."
+                ),
                 RequestLimitError::CallsIn => concat!(
                     "The request exceeds the maximum number ",
                     "of calls in a single request."
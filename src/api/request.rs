@@ -21,9 +21,14 @@
  * for more details.
 */
 
+use std::sync::Arc;
+
 use actix_web::{
-    http::{header::ContentType, StatusCode},
-    web, HttpResponse, ResponseError,
+    http::{
+        header::{ContentEncoding, ContentType},
+        StatusCode,
+    },
+    web, HttpRequest, HttpResponse, ResponseError,
 };
 use jmap::types::jmap::JMAPId;
 use store::{ahash::AHashMap, tracing::debug, Store};
@@ -47,27 +52,57 @@ pub struct Request {
     pub created_ids: Option<AHashMap<String, JMAPId>>,
 }
 
+// Response to a request sent with an Idempotency-Key header, which is
+// replayed when a client retries the same request after losing the response.
+pub struct IdempotentResponse {
+    pub request: web::Bytes,
+    pub response: Vec<u8>,
+}
+
 pub async fn handle_jmap_request<T>(
-    request: web::Bytes,
+    req: HttpRequest,
+    body: web::Bytes,
     core: web::Data<JMAPServer<T>>,
     session: Session,
 ) -> Result<HttpResponse, RequestError>
 where
     T: for<'x> Store<'x> + 'static,
 {
-    if request.len() < core.store.config.max_size_request {
-        match serde_json::from_slice::<Request>(&request) {
+    if body.len() < core.store.config.max_size_request {
+        // Replay the original response of retried requests
+        let idempotency_key = req
+            .headers()
+            .get("Idempotency-Key")
+            .and_then(|key| key.to_str().ok())
+            .map(|key| (session.account_id(), key.to_string()));
+        if let Some(idempotency_key) = &idempotency_key {
+            if let Some(cached) = core.idempotent_requests.get(idempotency_key) {
+                if cached.request == body {
+                    debug!(
+                        "Replaying response for idempotency key '{}'.",
+                        idempotency_key.1
+                    );
+                    return Ok(json_response(&core, cached.response.clone()));
+                }
+            }
+        }
+
+        match serde_json::from_slice::<Request>(&body) {
             Ok(request) => {
                 if request.method_calls.len() < core.store.config.max_calls_in_request {
                     // Make sure this node is still the leader
                     if !core.is_leader() {
-                        // Redirect requests if at least one method requires write access
-                        // or if this node is behind on the log.
+                        // Redirect requests if at least one method requires write access,
+                        // if this node is behind on the log or, when linearizable reads
+                        // are enabled, if it could not apply every change committed by
+                        // the leader before the request.
                         let do_redirect = !core.is_up_to_date()
                             || request
                                 .method_calls
                                 .iter()
-                                .any(|r| !r.method.is_read_only());
+                                .any(|r| !r.method.is_read_only())
+                            || (core.store.config.raft_linearizable_reads
+                                && core.read_index().await.is_none());
 
                         if do_redirect {
                             if let Some(leader_hostname) = core
@@ -90,11 +125,44 @@ where
                         }
                     }
 
-                    let result = handle_method_calls(request, core, session).await;
+                    let response = if let Some(idempotency_key) = idempotency_key {
+                        // The entry is created before executing the request, concurrent
+                        // retries with the same key wait for it instead of running twice.
+                        let mut request = Some(request);
+                        let cached = core
+                            .idempotent_requests
+                            .get_with(idempotency_key, async {
+                                let result = handle_method_calls(
+                                    request.take().unwrap(),
+                                    core.clone(),
+                                    session.clone(),
+                                )
+                                .await;
+                                Arc::new(IdempotentResponse {
+                                    request: body.clone(),
+                                    response: serde_json::to_vec(&result).unwrap_or_default(),
+                                })
+                            })
+                            .await;
+
+                        match request {
+                            Some(request) if cached.request != body => {
+                                // The key was reused for a different request
+                                serde_json::to_vec(
+                                    &handle_method_calls(request, core.clone(), session).await,
+                                )
+                                .unwrap_or_default()
+                            }
+                            _ => cached.response.clone(),
+                        }
+                    } else {
+                        serde_json::to_vec(
+                            &handle_method_calls(request, core.clone(), session).await,
+                        )
+                        .unwrap_or_default()
+                    };
 
-                    Ok(HttpResponse::build(StatusCode::OK)
-                        .insert_header(ContentType::json())
-                        .json(result))
+                    Ok(json_response(&core, response))
                 } else {
                     Err(RequestError::limit(RequestLimitError::CallsIn))
                 }
@@ -109,3 +177,18 @@ where
         Err(RequestError::limit(RequestLimitError::Size))
     }
 }
+
+fn json_response<T>(core: &JMAPServer<T>, response: Vec<u8>) -> HttpResponse
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut builder = HttpResponse::build(StatusCode::OK);
+    builder.insert_header(ContentType::json());
+
+    // Small responses are not worth compressing
+    if response.len() < core.store.config.response_compression_threshold {
+        builder.insert_header(ContentEncoding::Identity);
+    }
+
+    builder.body(response)
+}
@@ -42,6 +42,12 @@ pub struct Response {
     #[serde(rename(deserialize = "createdIds"))]
     #[serde(skip_serializing_if = "ahash_is_empty")]
     pub created_ids: AHashMap<String, JMAPId>,
+
+    #[serde(skip)]
+    pub result_ref_max_depth: usize,
+
+    #[serde(skip)]
+    pub result_refs_left: usize,
 }
 
 impl Response {
@@ -50,9 +56,17 @@ impl Response {
             session_state,
             created_ids,
             method_responses: Vec::with_capacity(capacity),
+            result_ref_max_depth: usize::MAX,
+            result_refs_left: usize::MAX,
         }
     }
 
+    pub fn with_result_ref_limits(mut self, max_depth: usize, max_refs: usize) -> Self {
+        self.result_ref_max_depth = max_depth;
+        self.result_refs_left = max_refs;
+        self
+    }
+
     pub fn push_response(&mut self, id: String, method: method::Response) {
         self.method_responses.push(method::Call { id, method });
     }
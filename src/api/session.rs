@@ -28,7 +28,12 @@ use actix_web::{
     http::{header::ContentType, StatusCode},
     web, HttpResponse,
 };
-use jmap::{principal::schema::Type, request::ACLEnforce, types::jmap::JMAPId, URI};
+use jmap::{
+    principal::schema::Type,
+    request::{query::SUPPORTED_COLLATIONS, ACLEnforce},
+    types::jmap::JMAPId,
+    URI,
+};
 use jmap_mail::mail::sharing::JMAPShareMail;
 use jmap_sharing::principal::account::JMAPAccountStore;
 use store::{
@@ -267,11 +272,10 @@ impl CoreCapabilities {
             max_calls_in_request: config.max_calls_in_request,
             max_objects_in_get: config.max_objects_in_get,
             max_objects_in_set: config.max_objects_in_set,
-            collation_algorithms: vec![
-                "i;ascii-numeric".to_string(),
-                "i;ascii-casemap".to_string(),
-                "i;unicode-casemap".to_string(),
-            ],
+            collation_algorithms: SUPPORTED_COLLATIONS
+                .iter()
+                .map(|collation| collation.to_string())
+                .collect(),
         }
     }
 }
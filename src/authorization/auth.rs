@@ -96,7 +96,10 @@ where
                     || request_path.starts_with("/jmap/ws")
                     || request_path.starts_with("/jmap/eventsource")
                     || request_path.starts_with("/auth")
-                    || request_path.starts_with("/.well-known/oauth-authorization-server");
+                    || request_path.starts_with("/.well-known/oauth-authorization-server")
+                    || (request_path.starts_with("/jmap/download")
+                        && core.store.config.raft_linearizable_reads
+                        && core.read_index().await.is_none());
 
                 // Redirect requests to /jmap are evaluated after parsing
                 if do_redirect {
@@ -63,6 +63,7 @@ pub enum LimiterType {
     Authenticated {
         concurrent_request: ConcurrencyLimiter,
         concurrent_uploads: ConcurrencyLimiter,
+        upload_limiter: RateLimiter,
     },
 }
 
@@ -85,8 +86,13 @@ impl RateLimiter {
         }
     }
 
-    // Token bucket rate limiter
     pub fn is_allowed(&self) -> bool {
+        self.is_allowed_n(1)
+    }
+
+    // Token bucket rate limiter
+    pub fn is_allowed_n(&self, tokens: u64) -> bool {
+        let tokens = tokens as f64;
         let mut limiter = self.limiter.lock();
         let elapsed = limiter.0.elapsed().as_secs_f64();
         limiter.0 = Instant::now();
@@ -94,8 +100,8 @@ impl RateLimiter {
         if limiter.1 > self.max_requests {
             limiter.1 = self.max_requests;
         }
-        if limiter.1 >= 1.0 {
-            limiter.1 -= 1.0;
+        if limiter.1 >= tokens {
+            limiter.1 -= tokens;
             true
         } else {
             false
@@ -141,12 +147,18 @@ impl Limiter {
         }
     }
 
-    pub fn new_authenticated(max_requests: u64, max_interval: u64) -> Self {
+    pub fn new_authenticated(
+        max_requests: u64,
+        max_interval: u64,
+        max_upload_bytes: u64,
+        max_upload_interval: u64,
+    ) -> Self {
         Limiter {
             request_limiter: RateLimiter::new(max_requests, max_interval),
             ltype: LimiterType::Authenticated {
                 concurrent_request: ConcurrencyLimiter::new(0),
                 concurrent_uploads: ConcurrencyLimiter::new(0),
+                upload_limiter: RateLimiter::new(max_upload_bytes, max_upload_interval),
             },
         }
     }
@@ -179,6 +191,15 @@ impl Limiter {
             _ => None,
         }
     }
+
+    pub fn is_upload_size_allowed(&self, size: usize) -> bool {
+        match &self.ltype {
+            LimiterType::Authenticated { upload_limiter, .. } => {
+                upload_limiter.is_allowed_n(size as u64)
+            }
+            _ => false,
+        }
+    }
 }
 
 impl<T> JMAPServer<T>
@@ -196,6 +217,8 @@ where
                     Arc::new(Limiter::new_authenticated(
                         self.store.config.rate_limit_authenticated.0,
                         self.store.config.rate_limit_authenticated.1,
+                        self.store.config.rate_limit_upload.0,
+                        self.store.config.rate_limit_upload.1,
                     ))
                 })
                 .await;
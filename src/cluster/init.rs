@@ -64,6 +64,7 @@ pub fn init_cluster(settings: &EnvSettings) -> Option<(ClusterIpc, ClusterInit)>
             ClusterIpc {
                 tx: main_tx.clone(),
                 state: RAFT_LOG_BEHIND.into(),
+                transfer_pending: false.into(),
                 commit_index_rx,
                 leader_hostname: None.into(),
             },
@@ -221,6 +222,9 @@ pub async fn start_cluster<T>(
                         break;
                     }
                     last_ping = Instant::now();
+
+                    // Abort leadership transfers past their deadline
+                    cluster.try_transfer_leadership().await;
                     ping_interval
                 } else {
                     ping_interval - time_since_last_ping
@@ -332,6 +336,7 @@ where
             uncommitted_index: last_log.index,
             last_log,
             state: crate::cluster::raft::State::init(),
+            leadership_transfer: None,
             core,
             peers: vec![],
             last_peer_pinged: u32::MAX as usize,
@@ -384,6 +384,30 @@ where
 
                         follower_last_index = match_log.index;
                         if !match_log.is_none() {
+                            // Entries needed by the follower might have been compacted
+                            // away, in which case the snapshot is installed instead.
+                            match core.get_next_raft_id(RaftId::new(0, 0)).await {
+                                Ok(Some(first_log)) if first_log.index > match_log.index => {
+                                    debug!(
+                                        concat!(
+                                            "[{}] Peer {} last log {:?} precedes the ",
+                                            "compaction point {:?}, installing snapshot."
+                                        ),
+                                        local_name, peer_name, match_log, first_log
+                                    );
+                                    follower_last_index = LogIndex::MAX;
+                                    state = State::Merge {
+                                        matched_log: RaftId::none(),
+                                    };
+                                    continue;
+                                }
+                                Ok(_) => (),
+                                Err(err) => {
+                                    error!("Error getting first raft id: {:?}", err);
+                                    break;
+                                }
+                            }
+
                             let local_match = match core.get_next_raft_id(match_log).await {
                                 Ok(Some(local_match)) => local_match,
                                 Ok(None) => {
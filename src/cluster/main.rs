@@ -89,6 +89,12 @@ where
                 rpc::Request::Command { command } => {
                     self.handle_command(command, response_tx).await;
                 }
+                rpc::Request::Heartbeat { term } => {
+                    self.handle_heartbeat(peer_id, response_tx, term);
+                }
+                rpc::Request::TimeoutNow { term } => {
+                    self.handle_timeout_now(peer_id, response_tx, term).await;
+                }
                 _ => response_tx
                     .send(rpc::Response::None)
                     .unwrap_or_else(|_| error!("Oneshot response channel closed.")),
@@ -121,6 +127,11 @@ where
             Event::UpdateLastLog { last_log } => {
                 self.last_log = last_log;
                 self.core.update_raft_index(last_log.index);
+
+                // Publish the applied index so that followers can serve reads.
+                if let Err(err) = self.commit_index_tx.send(last_log.index) {
+                    error!("Failed to send commit index: {:?}", err);
+                }
             }
             Event::AdvanceUncommittedIndex { uncommitted_index } => {
                 if uncommitted_index > self.uncommitted_index
@@ -142,6 +153,12 @@ where
             } => {
                 self.send_command(command, response_tx).await;
             }
+            Event::ReadIndex { response_tx } => {
+                self.read_index(response_tx);
+            }
+            Event::TransferLeadership { peer_id } => {
+                self.transfer_leadership(peer_id).await;
+            }
             Event::Shutdown => return Ok(false),
 
             #[cfg(test)]
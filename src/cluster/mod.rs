@@ -27,7 +27,11 @@ use crate::JMAPServer;
 use actix_web::web;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use std::{net::SocketAddr, sync::atomic::AtomicU8, time::Instant};
+use std::{
+    net::SocketAddr,
+    sync::atomic::{AtomicBool, AtomicU8},
+    time::Instant,
+};
 use store::log::raft::{LogIndex, RaftId, TermId};
 use store::{
     bincode,
@@ -94,6 +98,7 @@ where
     pub last_log: RaftId,
     pub uncommitted_index: LogIndex,
     pub state: raft::State,
+    pub leadership_transfer: Option<(PeerId, Instant)>,
 }
 
 pub struct Config {
@@ -127,6 +132,12 @@ pub enum Event {
         command: Command,
         response_tx: oneshot::Sender<CommandResponse>,
     },
+    ReadIndex {
+        response_tx: oneshot::Sender<Option<LogIndex>>,
+    },
+    TransferLeadership {
+        peer_id: PeerId,
+    },
     StepDown {
         term: TermId,
     },
@@ -183,6 +194,7 @@ pub struct Peer {
 pub struct ClusterIpc {
     pub tx: mpsc::Sender<Event>,
     pub state: AtomicU8,
+    pub transfer_pending: AtomicBool,
     pub leader_hostname: store::parking_lot::Mutex<Option<String>>,
     pub commit_index_rx: watch::Receiver<LogIndex>,
 }
@@ -81,6 +81,10 @@ where
                 self.last_log.index, indexes
             );
         }
+
+        // Complete a pending leadership transfer once the target has caught up
+        self.try_transfer_leadership().await;
+
         Ok(true)
     }
 }
@@ -93,6 +93,9 @@ where
 
     pub async fn step_down(&mut self, term: TermId) {
         self.reset_votes();
+        if self.leadership_transfer.is_some() {
+            self.set_leadership_transfer(None);
+        }
         self.core.set_follower(None).await;
         self.term = term;
         self.state = State::Wait {
@@ -26,7 +26,7 @@ use super::{State, RAFT_LOG_BEHIND, RAFT_LOG_LEADER, RAFT_LOG_UPDATED};
 use crate::services::{email_delivery, state_change};
 use crate::JMAPServer;
 use std::sync::atomic::Ordering;
-use store::tracing::debug;
+use store::tracing::{debug, error};
 use store::Store;
 use tokio::sync::mpsc;
 
@@ -58,6 +58,9 @@ where
             tx: tx.clone(),
         };
         self.reset_votes();
+        if let Err(err) = self.commit_index_tx.send(self.last_log.index) {
+            error!("Failed to send commit index: {:?}", err);
+        }
         self.core
             .set_follower(self.get_peer(peer_id).unwrap().hostname.clone().into())
             .await;
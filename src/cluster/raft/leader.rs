@@ -128,6 +128,8 @@ where
         }
         self.store.recipients.invalidate_all();
         self.store.shared_documents.invalidate_all();
+        self.store.mailbox_counters.invalidate_all();
+        self.store.mailbox_counters_validated.invalidate_all();
 
         // Set leader status
         self.store
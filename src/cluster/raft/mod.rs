@@ -26,6 +26,8 @@ pub mod election;
 pub mod follower;
 pub mod leader;
 pub mod log;
+pub mod read_index;
+pub mod transfer;
 pub mod vote;
 
 use self::election::{ELECTION_TIMEOUT_RAND_FROM, ELECTION_TIMEOUT_RAND_TO};
@@ -0,0 +1,198 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use super::{rpc, Cluster, PeerId, State};
+use crate::cluster::rpc::command::{Command, CommandResponse};
+use crate::cluster::rpc::{Request, Response};
+use crate::JMAPServer;
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::time::{Duration, Instant};
+use store::log::raft::{LogIndex, TermId};
+use store::tracing::{debug, error};
+use store::Store;
+use tokio::sync::oneshot;
+use tokio::time;
+
+impl<T> Cluster<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    pub fn read_index(&self, response_tx: oneshot::Sender<Option<LogIndex>>) {
+        match &self.state {
+            State::Leader { .. } => {
+                // Record the commit index before confirming leadership, any
+                // write acknowledged so far is guaranteed to be included.
+                let commit_index = *self.commit_index_tx.borrow();
+                let term = self.term;
+                let main_tx = self.tx.clone();
+                let peer_txs = self
+                    .peers
+                    .iter()
+                    .filter(|p| p.is_in_shard(self.shard_id))
+                    .map(|p| p.tx.clone())
+                    .collect::<Vec<_>>();
+                let quorum = ((peer_txs.len() as f64 + 1.0) / 2.0).floor() as usize;
+
+                tokio::spawn(async move {
+                    let mut responses = peer_txs
+                        .iter()
+                        .map(|peer_tx| Request::Heartbeat { term }.send(peer_tx))
+                        .collect::<FuturesUnordered<_>>();
+                    let mut acks = 0;
+
+                    while acks < quorum {
+                        match responses.next().await {
+                            Some(Some(Response::Pong)) => {
+                                acks += 1;
+                            }
+                            Some(Some(Response::StepDown { term: peer_term }))
+                                if peer_term > term =>
+                            {
+                                main_tx
+                                    .send(crate::cluster::Event::StepDown { term: peer_term })
+                                    .await
+                                    .ok();
+                                break;
+                            }
+                            Some(_) => (),
+                            None => break,
+                        }
+                    }
+
+                    let result = if acks >= quorum {
+                        Some(commit_index)
+                    } else {
+                        debug!(
+                            "Failed to confirm leadership for term {}, {}/{} peers replied.",
+                            term, acks, quorum
+                        );
+                        None
+                    };
+
+                    response_tx
+                        .send(result)
+                        .unwrap_or_else(|_| error!("Oneshot response channel closed."));
+                });
+            }
+            State::Follower { .. } if self.leader_peer().is_some() => {
+                let peer_tx = self.leader_peer().unwrap().tx.clone();
+                tokio::spawn(async move {
+                    let result = match (Request::Command {
+                        command: Command::ReadIndex,
+                    })
+                    .send(&peer_tx)
+                    .await
+                    {
+                        Some(Response::Command {
+                            response: CommandResponse::ReadIndex { index },
+                        }) => Some(index),
+                        err => {
+                            debug!("Leader failed to provide a read index: {:?}.", err);
+                            None
+                        }
+                    };
+
+                    response_tx
+                        .send(result)
+                        .unwrap_or_else(|_| error!("Oneshot response channel closed."));
+                });
+            }
+            _ => {
+                response_tx
+                    .send(None)
+                    .unwrap_or_else(|_| error!("Oneshot response channel closed."));
+            }
+        }
+    }
+
+    pub fn handle_heartbeat(
+        &self,
+        peer_id: PeerId,
+        response_tx: oneshot::Sender<rpc::Response>,
+        term: TermId,
+    ) {
+        response_tx
+            .send(
+                if term >= self.term && self.is_following_peer(peer_id).is_some() {
+                    rpc::Response::Pong
+                } else {
+                    rpc::Response::StepDown { term: self.term }
+                },
+            )
+            .unwrap_or_else(|_| error!("Oneshot response channel closed."));
+    }
+}
+
+impl<T> JMAPServer<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    pub async fn read_index(&self) -> Option<LogIndex> {
+        let cluster = self.cluster.as_ref()?;
+        let (tx, rx) = oneshot::channel();
+        if cluster
+            .tx
+            .send(crate::cluster::Event::ReadIndex { response_tx: tx })
+            .await
+            .is_err()
+        {
+            error!("Failed to send read index request to cluster.");
+            return None;
+        }
+        let read_index = rx.await.ok()??;
+
+        // The leader's store already contains every committed entry.
+        if read_index == LogIndex::MAX || self.is_leader() {
+            return read_index.into();
+        }
+
+        // Wait until this node has applied the leader's commit index.
+        let commit_timeout = self.store.config.raft_commit_timeout;
+        let mut commit_index_rx = cluster.commit_index_rx.clone();
+        let wait_start = Instant::now();
+        loop {
+            let applied_index = *commit_index_rx.borrow();
+            if applied_index != LogIndex::MAX && applied_index >= read_index {
+                return read_index.into();
+            }
+
+            let wait_elapsed = wait_start.elapsed().as_millis() as u64;
+            if wait_elapsed >= commit_timeout
+                || !matches!(
+                    time::timeout(
+                        Duration::from_millis(commit_timeout - wait_elapsed),
+                        commit_index_rx.changed(),
+                    )
+                    .await,
+                    Ok(Ok(()))
+                )
+            {
+                error!(
+                    "Failed to apply read index {}, timeout after {} ms.",
+                    read_index, commit_timeout
+                );
+                return None;
+            }
+        }
+    }
+}
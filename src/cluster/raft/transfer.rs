@@ -0,0 +1,172 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use super::{rpc, Cluster, PeerId};
+use crate::cluster::rpc::Request;
+use crate::JMAPServer;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+use store::log::raft::TermId;
+use store::tracing::{debug, error, info};
+use store::Store;
+use tokio::sync::oneshot;
+
+impl<T> Cluster<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    pub async fn transfer_leadership(&mut self, target: PeerId) {
+        if !self.is_leading() {
+            debug!(
+                "[{}] Ignoring leadership transfer request, not leading.",
+                self.addr
+            );
+            return;
+        } else if !self
+            .get_peer(target)
+            .map_or(false, |peer| peer.is_in_shard(self.shard_id))
+        {
+            error!(
+                "[{}] Cannot transfer leadership to unknown peer {}.",
+                self.addr, target
+            );
+            return;
+        }
+
+        self.set_leadership_transfer(Some((
+            target,
+            Instant::now() + Duration::from_millis(self.core.store.config.raft_commit_timeout),
+        )));
+        if !self.try_transfer_leadership().await {
+            // Bring the target's log up to date before handing over.
+            self.send_append_entries();
+        }
+    }
+
+    pub async fn try_transfer_leadership(&mut self) -> bool {
+        let (peer_id, transfer_due) = match self.leadership_transfer {
+            Some(transfer) => transfer,
+            None => return false,
+        };
+
+        // The deadline is checked first, a target that catches up late must
+        // not take over once proposals are accepted again.
+        if transfer_due < Instant::now() {
+            self.set_leadership_transfer(None);
+            info!(
+                "[{}] Aborting leadership transfer to peer {}, log not up to date after {} ms.",
+                self.addr, peer_id, self.core.store.config.raft_commit_timeout
+            );
+            return false;
+        }
+
+        let is_up_to_date = match self.get_peer(peer_id) {
+            Some(peer) if self.is_leading() => peer.commit_index == self.last_log.index,
+            _ => {
+                self.set_leadership_transfer(None);
+                return false;
+            }
+        };
+
+        if is_up_to_date {
+            self.set_leadership_transfer(None);
+            debug!(
+                "[{}] Transferring leadership to peer {} for term {}.",
+                self.addr,
+                self.get_peer(peer_id).unwrap(),
+                self.term
+            );
+            self.get_peer(peer_id)
+                .unwrap()
+                .dispatch_request(Request::TimeoutNow { term: self.term })
+                .await;
+            self.step_down(self.term).await;
+            true
+        } else {
+            false
+        }
+    }
+
+    // New proposals are rejected while a transfer is pending, so the target's
+    // log can catch up with the leader's.
+    pub fn set_leadership_transfer(&mut self, leadership_transfer: Option<(PeerId, Instant)>) {
+        self.core
+            .cluster
+            .as_ref()
+            .unwrap()
+            .transfer_pending
+            .store(leadership_transfer.is_some(), Ordering::Relaxed);
+        self.leadership_transfer = leadership_transfer;
+    }
+
+    pub async fn handle_timeout_now(
+        &mut self,
+        peer_id: PeerId,
+        response_tx: oneshot::Sender<rpc::Response>,
+        term: TermId,
+    ) {
+        if self.term == term && self.is_following_peer(peer_id).is_some() {
+            response_tx
+                .send(rpc::Response::Pong)
+                .unwrap_or_else(|_| error!("Oneshot response channel closed."));
+
+            // The leader has confirmed that this node's log is up to date,
+            // start an election right away.
+            self.run_for_election(true).await;
+            for peer in &self.peers {
+                if peer.is_in_shard(self.shard_id) && !peer.is_offline() {
+                    peer.vote_for_me(self.term, self.last_log.index, self.last_log.term)
+                        .await;
+                }
+            }
+        } else {
+            response_tx
+                .send(rpc::Response::StepDown { term: self.term })
+                .unwrap_or_else(|_| error!("Oneshot response channel closed."));
+        }
+    }
+}
+
+impl<T> JMAPServer<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    pub fn is_transferring_leadership(&self) -> bool {
+        self.cluster.as_ref().map_or(false, |cluster| {
+            cluster.transfer_pending.load(Ordering::Relaxed)
+        })
+    }
+
+    pub async fn transfer_leadership(&self, peer_id: PeerId) {
+        if let Some(cluster) = &self.cluster {
+            if cluster
+                .tx
+                .send(crate::cluster::Event::TransferLeadership { peer_id })
+                .await
+                .is_err()
+            {
+                error!("Failed to send leadership transfer event to cluster.");
+            }
+        }
+    }
+}
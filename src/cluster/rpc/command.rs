@@ -25,6 +25,7 @@ use jmap_sharing::principal::account::JMAPAccountStore;
 use serde::{Deserialize, Serialize};
 use store::{
     ahash::{AHashMap, AHashSet},
+    log::raft::LogIndex,
     tracing::error,
     AccountId, RecipientType, Store,
 };
@@ -48,6 +49,7 @@ pub enum Command {
         rcpt_to: AHashSet<AccountId>,
         raw_message: Vec<u8>,
     },
+    ReadIndex,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -58,6 +60,9 @@ pub enum CommandResponse {
     IngestMessage {
         result: Result<AHashMap<AccountId, DeliveryStatus>, String>,
     },
+    ReadIndex {
+        index: LogIndex,
+    },
     Error {
         message: String,
     },
@@ -104,7 +109,22 @@ where
         command: Command,
         response_tx: oneshot::Sender<super::Response>,
     ) {
-        if self.is_leading() {
+        if matches!(command, Command::ReadIndex) && self.is_leading() {
+            let (tx, rx) = oneshot::channel();
+            self.read_index(tx);
+            tokio::spawn(async move {
+                let response = match rx.await {
+                    Ok(Some(index)) => CommandResponse::ReadIndex { index },
+                    _ => CommandResponse::Error {
+                        message: "Failed to confirm leadership.".to_string(),
+                    },
+                };
+
+                response_tx
+                    .send(super::Response::Command { response })
+                    .unwrap_or_else(|_| error!("Oneshot response channel closed."));
+            });
+        } else if self.is_leading() {
             let core = self.core.clone();
             tokio::spawn(async move {
                 let response = match command {
@@ -129,6 +149,9 @@ where
                     } => CommandResponse::IngestMessage {
                         result: core.mail_ingest(mail_from, rcpt_to, raw_message).await,
                     },
+                    Command::ReadIndex => CommandResponse::Error {
+                        message: "Failed to confirm leadership.".to_string(),
+                    },
                 };
 
                 response_tx
@@ -61,6 +61,12 @@ pub enum Request {
     Command {
         command: Command,
     },
+    Heartbeat {
+        term: TermId,
+    },
+    TimeoutNow {
+        term: TermId,
+    },
     Ping,
     None,
 }
@@ -25,7 +25,7 @@ use std::sync::Arc;
 
 use authorization::{auth::RemoteAddress, rate_limit::Limiter};
 use cluster::ClusterIpc;
-use store::{moka::future::Cache, JMAPStore};
+use store::{moka::future::Cache, AccountId, JMAPStore};
 use tokio::sync::{mpsc, watch};
 
 pub mod api;
@@ -57,6 +57,7 @@ pub struct JMAPServer<T> {
 
     pub sessions: Cache<String, authorization::Session>,
     pub rate_limiters: Cache<RemoteAddress, Arc<Limiter>>,
+    pub idempotent_requests: Cache<(AccountId, String), Arc<api::request::IdempotentResponse>>,
 
     #[cfg(test)]
     pub is_offline: std::sync::atomic::AtomicBool,
@@ -214,6 +214,11 @@ where
         rcpt_to: AHashSet<AccountId>,
         raw_message: Vec<u8>,
     ) -> Result<AHashMap<AccountId, DeliveryStatus>, String> {
+        // Messages are not accepted while leadership is being handed over
+        if self.is_transferring_leadership() {
+            return Err("450 4.3.2 Temporary cluster failure.\r\n".to_string());
+        }
+
         // Ingest message
         let store = self.store.clone();
         let (change_id, status) = match self
@@ -41,7 +41,7 @@ use store::{
     core::{collection::Collection, document::Document},
     moka::future::Cache,
     rand::{distributions::Alphanumeric, thread_rng, Rng},
-    tracing::info,
+    tracing::{info, warn},
     write::batch::WriteBatch,
     JMAPStore, Store,
 };
@@ -49,7 +49,7 @@ use store::{
 use crate::{
     api::{
         blob::{handle_jmap_download, handle_jmap_upload},
-        request::handle_jmap_request,
+        request::{handle_jmap_request, IdempotentResponse},
         session::{handle_jmap_session, Session},
     },
     authorization::{
@@ -100,31 +100,12 @@ where
         .map_or(true, |ids| !ids.contains(SUPERUSER_ID))
     {
         #[cfg(not(test))]
-        {
-            let mut batch = WriteBatch::new(SUPERUSER_ID);
-
-            let account_id = store
-                .assign_document_id(SUPERUSER_ID, Collection::Principal)
-                .failed_to("generate account id.");
-            if account_id != SUPERUSER_ID as u32 {
-                super::failed_to(&format!(
-                    "generate account id, expected id {} but got {}.",
-                    SUPERUSER_ID, account_id
-                ));
-            }
-            let mut document = Document::new(Collection::Principal, account_id);
-            TinyORM::<Principal>::new_account(
-                "admin",
-                &settings
-                    .get("set-admin-password")
-                    .unwrap_or_else(|| "changeme".to_string()),
-                "Administrator",
-            )
-            .insert(&mut document)
-            .unwrap();
-            batch.insert_document(document);
-            store.write(batch).failed_to("write to database");
-        }
+        create_admin_account(
+            &store,
+            &settings
+                .get("set-admin-password")
+                .unwrap_or_else(|| "changeme".to_string()),
+        );
     } else if let Some(secret) = settings.get("set-admin-password") {
         // Reset admin password
         let mut batch = WriteBatch::new(SUPERUSER_ID);
@@ -142,6 +123,16 @@ where
         std::process::exit(0);
     }
 
+    // Check that the changelog agrees with the stored documents
+    if settings.parse("verify-changelog").unwrap_or(false) {
+        for (account_id, collection) in store.verify_changelogs().failed_to("verify changelog") {
+            warn!(
+                "Changelog for account {} diverges from its {:?} documents.",
+                account_id, collection
+            );
+        }
+    }
+
     let (email_tx, email_rx) = init_email_delivery();
     let (housekeeper_tx, housekeeper_rx) = init_housekeeper();
     let (change_tx, change_rx) = init_state_manager();
@@ -180,6 +171,16 @@ where
         ));
     }
 
+    // Responses replayed for retried requests, bounded by their size
+    let idempotent_requests = Cache::builder()
+        .initial_capacity(128)
+        .max_capacity(store.config.idempotency_cache_size)
+        .weigher(|_, response: &Arc<IdempotentResponse>| {
+            (response.request.len() + response.response.len()).min(u32::MAX as usize) as u32
+        })
+        .time_to_live(Duration::from_secs(store.config.idempotency_grace_period))
+        .build();
+
     let server = web::Data::new(JMAPServer {
         store,
         worker_pool: rayon::ThreadPoolBuilder::new()
@@ -204,6 +205,7 @@ where
             .time_to_idle(ONE_HOUR_EXPIRY)
             .build(),
         oauth_codes: Cache::builder().time_to_live(ONE_HOUR_EXPIRY).build(),
+        idempotent_requests,
         oauth,
         cluster,
         base_session,
@@ -226,6 +228,32 @@ where
     server
 }
 
+/// Creates the administrator account, which has to be the first principal of the
+/// database so it is assigned the superuser id.
+pub fn create_admin_account<T>(store: &JMAPStore<T>, password: &str)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut batch = WriteBatch::new(SUPERUSER_ID);
+
+    let account_id = store
+        .assign_document_id(SUPERUSER_ID, Collection::Principal)
+        .failed_to("generate account id.");
+    if account_id != SUPERUSER_ID as u32 {
+        super::failed_to(&format!(
+            "generate account id, expected id {} but got {}.",
+            SUPERUSER_ID, account_id
+        ));
+    }
+    let mut document = Document::new(Collection::Principal, account_id);
+    TinyORM::<Principal>::new_account("admin", password, "Administrator")
+        .insert(&mut document)
+        .unwrap();
+    batch.log_insert(Collection::Principal, account_id);
+    batch.insert_document(document);
+    store.write(batch).failed_to("write to database");
+}
+
 pub async fn build_jmap_server<T>(
     jmap_server: web::Data<JMAPServer<T>>,
     settings: EnvSettings,
@@ -282,7 +310,14 @@ where
             )))
             .app_data(jmap_server.clone())
             .route("/.well-known/jmap", web::get().to(handle_jmap_session::<T>))
-            .route("/jmap", web::post().to(handle_jmap_request::<T>))
+            .service(
+                web::resource("/jmap")
+                    .wrap(middleware::Condition::new(
+                        jmap_server.store.config.response_compression,
+                        middleware::Compress::default(),
+                    ))
+                    .route(web::post().to(handle_jmap_request::<T>)),
+            )
             .route(
                 "/jmap/upload/{accountId}",
                 web::post().to(handle_jmap_upload::<T>),
@@ -31,6 +31,7 @@ use crate::JMAPServer;
 use actix::{Actor, ActorContext, AsyncContext, Handler, Message, StreamHandler};
 use actix_web::{web, HttpRequest, HttpResponse};
 use actix_web_actors::ws::{self, WsResponseBuilder};
+use jmap::jmap_store::changes::JMAPChanges;
 use jmap::types::jmap::JMAPId;
 use jmap::types::state::JMAPState;
 use jmap::types::type_state::TypeState;
@@ -39,9 +40,13 @@ use std::time::{Duration, Instant};
 use store::ahash::AHashMap;
 use store::core::ahash_is_empty;
 use store::core::bitmap::Bitmap;
+use store::core::collection::Collection;
 use store::core::vec_map::VecMap;
+use store::log::changes::{ChangeId, Query};
+use store::serialize::base32::{Base32Reader, Base32Writer};
+use store::serialize::leb128::{Leb128Iterator, Leb128Writer};
 use store::tracing::log::debug;
-use store::Store;
+use store::{AccountId, JMAPStore, Store};
 
 #[derive(Debug, serde::Deserialize)]
 struct WebSocketRequest {
@@ -138,8 +143,28 @@ pub struct WebSocketStateChange {
     #[serde(rename = "pushState")]
     #[serde(skip_serializing_if = "Option::is_none")]
     push_state: Option<String>,
+    #[serde(rename = "cannotCalculateChanges")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    cannot_calculate_changes: Vec<TypeState>,
 }
 
+/// Last state delivered to the client for each data type, sent as the
+/// `pushState` of every StateChange and accepted on `WebSocketPushEnable`
+/// to resume notifications after a reconnect.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct WebSocketPushState {
+    pub states: VecMap<TypeState, JMAPState>,
+}
+
+const PUSH_STATE_TYPES: [(TypeState, Collection); 5] = [
+    (TypeState::Email, Collection::Mail),
+    (TypeState::EmailSubmission, Collection::EmailSubmission),
+    (TypeState::Mailbox, Collection::Mailbox),
+    (TypeState::Thread, Collection::Thread),
+    (TypeState::Identity, Collection::Identity),
+];
+
 #[derive(Debug, Message, serde::Serialize)]
 #[rtype(result = "()")]
 pub struct WebSocketRequestError {
@@ -303,6 +328,10 @@ where
                                 }
                             }
                             WebSocketMessage::PushEnable(request) => {
+                                if let Some(state_handle) = self.state_handle.take() {
+                                    ctx.cancel_future(state_handle);
+                                }
+
                                 let core = self.core.clone();
                                 let account_id = self.session.account_id();
                                 let throttle_ms = core.store.config.ws_throttle;
@@ -315,10 +344,14 @@ where
                                 } else {
                                     Bitmap::all()
                                 };
+                                let resume_state = request
+                                    .push_state
+                                    .as_deref()
+                                    .and_then(WebSocketPushState::parse);
 
                                 self.state_handle = Some(ctx.add_stream(async_stream::stream! {
                                     let mut change_rx = if let Some(change_rx) = core
-                                        .subscribe_state_manager(account_id, account_id, types)
+                                        .subscribe_state_manager(account_id, account_id, types.clone())
                                         .await
                                     {
                                         change_rx
@@ -326,8 +359,28 @@ where
                                         return;
                                     };
 
+                                    // Deliver any changes missed since the client's last push state
+                                    let store = core.store.clone();
+                                    let (mut push_state, response) = match core
+                                        .spawn_worker(move || {
+                                            resume_push_state(&store, account_id, &types, resume_state)
+                                        })
+                                        .await
+                                    {
+                                        Ok(result) => result,
+                                        Err(err) => {
+                                            debug!("Failed to resume push state: {}", err);
+                                            return;
+                                        }
+                                    };
+
                                     let mut last_message =
                                         Instant::now() - Duration::from_millis(throttle_ms);
+                                    if !response.changed.is_empty() {
+                                        last_message = Instant::now();
+                                        yield response;
+                                    }
+
                                     let mut timeout = Duration::from_millis(LONG_SLUMBER_MS);
                                     let mut response = WebSocketStateChange::new(None);
 
@@ -336,6 +389,7 @@ where
                                         {
                                             Ok(Some(state_change)) => {
                                                 for (type_state, change_id) in state_change.types {
+                                                    push_state.states.set(type_state, change_id.into());
                                                     response
                                                         .changed
                                                         .get_mut_or_insert(state_change.account_id.into())
@@ -353,6 +407,7 @@ where
                                             let elapsed = last_message.elapsed().as_millis() as u64;
                                             if elapsed >= throttle_ms {
                                                 last_message = Instant::now();
+                                                response.push_state = push_state.to_string().into();
                                                 yield response;
 
                                                 response = WebSocketStateChange::new(None);
@@ -445,6 +500,110 @@ impl WebSocketStateChange {
             type_: WebSocketStateChangeType::StateChange,
             changed: VecMap::new(),
             push_state,
+            cannot_calculate_changes: Vec::new(),
         }
     }
 }
+
+impl WebSocketPushState {
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut it = Base32Reader::new(value.as_bytes());
+        let mut states = VecMap::new();
+
+        while let Some(type_state) = it.next_leb128::<u64>() {
+            if type_state >= TypeState::None as u64 {
+                return None;
+            }
+            let state = match it.next_leb128::<ChangeId>()? {
+                0 => JMAPState::Initial,
+                change_id => JMAPState::Exact(change_id - 1),
+            };
+            states.append(TypeState::from(type_state), state);
+        }
+
+        Some(WebSocketPushState { states })
+    }
+}
+
+impl std::fmt::Display for WebSocketPushState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut writer = Base32Writer::with_capacity(self.states.len() * 4);
+
+        for (type_state, state) in self.states.iter() {
+            writer.write_leb128(*type_state as u64).unwrap();
+            writer
+                .write_leb128(match state {
+                    JMAPState::Initial => 0,
+                    _ => state.get_change_id().saturating_add(1),
+                })
+                .unwrap();
+        }
+
+        f.write_str(&writer.finalize())
+    }
+}
+
+/// Builds the push state for a newly enabled subscription and, when the client
+/// presents the push state it last received, a StateChange listing the types
+/// modified since then. Types whose changes can no longer be calculated are
+/// also listed under `cannotCalculateChanges`.
+fn resume_push_state<T>(
+    store: &JMAPStore<T>,
+    account_id: AccountId,
+    types: &Bitmap<TypeState>,
+    resume_state: Option<WebSocketPushState>,
+) -> store::Result<(WebSocketPushState, WebSocketStateChange)>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut push_state = WebSocketPushState::default();
+    let mut response = WebSocketStateChange::new(None);
+
+    for (type_state, collection) in PUSH_STATE_TYPES {
+        if !types.contains(type_state) {
+            continue;
+        }
+        let current_state = store.get_state(account_id, collection)?;
+
+        if let Some(last_state) = resume_state
+            .as_ref()
+            .and_then(|resume_state| resume_state.states.get(&type_state))
+        {
+            if *last_state != current_state {
+                let can_calculate = match (last_state, &current_state) {
+                    (JMAPState::Initial, _) => true,
+                    (JMAPState::Exact(last_id), JMAPState::Exact(current_id))
+                        if last_id < current_id =>
+                    {
+                        // The last delivered change has to still be in the changelog
+                        store
+                            .get_changes(
+                                account_id,
+                                collection,
+                                Query::SinceInclusive(*last_id),
+                                1,
+                            )?
+                            .map_or(false, |changes| changes.from_change_id == *last_id)
+                    }
+                    _ => false,
+                };
+
+                if !can_calculate {
+                    response.cannot_calculate_changes.push(type_state);
+                }
+                response
+                    .changed
+                    .get_mut_or_insert(account_id.into())
+                    .set(type_state, current_state.clone());
+            }
+        }
+
+        push_state.states.append(type_state, current_state);
+    }
+
+    if !response.changed.is_empty() {
+        response.push_state = push_state.to_string().into();
+    }
+
+    Ok((push_state, response))
+}
@@ -24,6 +24,7 @@
 use std::time::{Duration, SystemTime};
 
 use actix_web::web;
+use jmap_mail::mail::{reindex::JMAPMailReindex, tombstone::JMAPMailTombstone};
 use jmap_sharing::principal::set::JMAPSetPrincipal;
 use store::{
     chrono::{self, Datelike, TimeZone},
@@ -56,6 +57,9 @@ const TASK_PURGE_ACCOUNTS: usize = 0;
 const TASK_PURGE_BLOBS: usize = 1;
 const TASK_SNAPSHOT_LOG: usize = 2;
 const TASK_COMPACT_DB: usize = 3;
+const TASK_COMPACT_LOG: usize = 4;
+
+const COMPACT_LOG_CHECK_INTERVAL: Duration = Duration::from_secs(60);
 
 pub fn spawn_housekeeper<T>(
     core: web::Data<JMAPServer<T>>,
@@ -86,6 +90,20 @@ pub fn spawn_housekeeper<T>(
     );
     let max_log_entries: u64 = settings.parse("max-changelog-entries").unwrap_or(10000);
 
+    // Index fields added since existing messages were imported
+    {
+        let store = core.store.clone();
+        let core = core.clone();
+        tokio::spawn(async move {
+            if let Err(err) = core
+                .spawn_worker(move || store.mail_reindex_pending())
+                .await
+            {
+                error!("Error while indexing existing messages: {}", err);
+            }
+        });
+    }
+
     tokio::spawn(async move {
         debug!("Housekeeper task started.");
         loop {
@@ -94,8 +112,9 @@ pub fn spawn_housekeeper<T>(
                 purge_blobs_at.time_to_next(),
                 snapshot_log_at.time_to_next(),
                 compact_db_at.time_to_next(),
+                COMPACT_LOG_CHECK_INTERVAL,
             ];
-            let mut tasks_to_run = [false, false, false, false];
+            let mut tasks_to_run = [false, false, false, false, false];
             let start_time = SystemTime::now()
                 .duration_since(SystemTime::UNIX_EPOCH)
                 .map(|d| d.as_secs())
@@ -147,8 +166,15 @@ pub fn spawn_housekeeper<T>(
                             core.spawn_worker(move || store.principal_purge()).await
                         }
                         TASK_PURGE_BLOBS => {
-                            info!("Purging removed and expired blobs.");
-                            core.spawn_worker(move || store.purge_blobs()).await
+                            info!("Purging destroyed messages, removed and expired blobs.");
+                            core.spawn_worker(move || {
+                                store.mail_purge_tombstones()?;
+                                store.purge_blobs()?;
+
+                                // Remove the files left behind by interrupted blob writes
+                                store.blob_store.purge_temp_files().map(|_| ())
+                            })
+                            .await
                         }
                         TASK_SNAPSHOT_LOG => {
                             info!("Compacting changes and Raft logs.");
@@ -160,6 +186,12 @@ pub fn spawn_housekeeper<T>(
                             core.spawn_worker(move || store.db.compact(ColumnFamily::Bitmaps))
                                 .await
                         }
+                        TASK_COMPACT_LOG => {
+                            core.spawn_worker(move || {
+                                store.compact_log_scheduled(max_log_entries).map(|_| ())
+                            })
+                            .await
+                        }
                         _ => unreachable!(),
                     };
 
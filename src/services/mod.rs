@@ -25,6 +25,7 @@ pub mod email_delivery;
 pub mod housekeeper;
 pub mod push_subscription;
 pub mod push_subscription_ece;
+pub mod push_subscription_vapid;
 pub mod state_change;
 
 pub const LONG_SLUMBER_MS: u64 = 60 * 60 * 24 * 1000;
@@ -21,24 +21,42 @@
  * for more details.
 */
 
-use super::{push_subscription_ece::ece_encrypt, state_change::StateChange, LONG_SLUMBER_MS};
-use crate::{api::StateChangeResponse, cluster::IPC_CHANNEL_BUFFER, JMAPServer};
+use super::{
+    push_subscription_ece::ece_encrypt,
+    push_subscription_vapid::VapidKey,
+    state_change::{self, StateChange},
+    LONG_SLUMBER_MS,
+};
+use crate::{
+    api::StateChangeResponse, cluster::IPC_CHANNEL_BUFFER, server::UnwrapFailure, JMAPServer,
+};
 use jmap::{
     base64,
+    error::method::MethodError,
     orm::serialize::JMAPOrm,
-    push_subscription::schema::{self, Property, Value},
+    push_subscription::{
+        schema::{self, Property, Value},
+        set::JMAPSetPushSubscription,
+    },
     types::{jmap::JMAPId, type_state::TypeState},
 };
-use reqwest::header::{CONTENT_ENCODING, CONTENT_TYPE};
+use reqwest::{
+    header::{AUTHORIZATION, CONTENT_ENCODING, CONTENT_TYPE},
+    StatusCode,
+};
 use std::{
     collections::hash_map::Entry,
+    sync::Arc,
     time::{Duration, Instant, SystemTime},
 };
 use store::{
     ahash::{AHashMap, AHashSet},
     config::env_settings::EnvSettings,
-    core::{bitmap::Bitmap, collection::Collection, error::StoreError},
+    core::{
+        bitmap::Bitmap, collection::Collection, document::Document, error::StoreError, JMAPIdPrefix,
+    },
     tracing::debug,
+    write::batch::WriteBatch,
     AccountId, DocumentId, Store,
 };
 use tokio::{sync::mpsc, time};
@@ -84,6 +102,7 @@ pub enum Event {
     DeliveryFailure {
         id: store::JMAPId,
         state_changes: Vec<StateChange>,
+        is_gone: bool,
     },
     Reset,
 }
@@ -112,21 +131,47 @@ pub struct PushServer {
     url: String,
     keys: Option<EncriptionKeys>,
     num_attempts: u32,
+    num_gone: u32,
     last_request: Instant,
     state_changes: Vec<StateChange>,
     in_flight: bool,
 }
 
-pub fn spawn_push_manager(settings: &EnvSettings) -> mpsc::Sender<Event> {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DeliveryStatus {
+    Success,
+    Failure,
+    Gone,
+}
+
+pub fn spawn_push_manager(
+    settings: &EnvSettings,
+    state_tx: mpsc::Sender<state_change::Event>,
+) -> mpsc::Sender<Event> {
     let (push_tx_, mut push_rx) = mpsc::channel::<Event>(IPC_CHANNEL_BUFFER);
     let push_tx = push_tx_.clone();
 
     let push_attempt_interval: u64 = settings.parse("push-attempt-interval").unwrap_or(60 * 1000);
+    let push_attempt_interval_max: u64 = settings
+        .parse("push-attempt-interval-max")
+        .unwrap_or(60 * 60 * 1000);
     let push_attempts_max: u32 = settings.parse("push-attempts-max").unwrap_or(3);
     let push_retry_interval: u64 = settings.parse("push-retry-interval").unwrap_or(1000);
     let push_timeout: u64 = settings.parse("push-timeout").unwrap_or(10 * 1000);
     let push_verify_timeout: u64 = settings.parse("push-verify-timeout").unwrap_or(60 * 1000);
     let push_throttle: u64 = settings.parse("push-throttle").unwrap_or(1000);
+    let push_gone_max: u32 = settings.parse("push-gone-max").unwrap_or(3);
+    let push_verify_expiry = Duration::from_millis(
+        settings
+            .parse("push-verify-expiry")
+            .unwrap_or(60 * 60 * 1000),
+    );
+    let push_vapid = settings.get("push-vapid-key").map(|private_key| {
+        Arc::new(
+            VapidKey::new(&private_key, settings.get("push-vapid-subject"))
+                .failed_to("parse push-vapid-key"),
+        )
+    });
 
     tokio::spawn(async move {
         let mut subscriptions = AHashMap::default();
@@ -134,6 +179,7 @@ pub fn spawn_push_manager(settings: &EnvSettings) -> mpsc::Sender<Event> {
         let mut last_retry = Instant::now();
         let mut retry_timeout = Duration::from_millis(LONG_SLUMBER_MS);
         let mut retry_ids = AHashSet::default();
+        let mut pending_verifications: AHashMap<store::JMAPId, Instant> = AHashMap::default();
 
         loop {
             match time::timeout(retry_timeout, push_rx.recv()).await {
@@ -168,6 +214,7 @@ pub fn spawn_push_manager(settings: &EnvSettings) -> mpsc::Sender<Event> {
                                         })
                                         .unwrap_or(true)
                                     {
+                                        let vapid = push_vapid.clone();
                                         tokio::spawn(async move {
                                             http_request(
                                                 url,
@@ -181,12 +228,16 @@ pub fn spawn_push_manager(settings: &EnvSettings) -> mpsc::Sender<Event> {
                                                     code
                                                 ),
                                                 keys,
+                                                vapid,
                                                 push_timeout,
                                             )
                                             .await;
                                         });
 
                                         last_verify.insert(account_id, current_time);
+                                        pending_verifications
+                                            .entry(store::JMAPId::from_parts(account_id, id))
+                                            .or_insert_with(Instant::now);
                                     } else {
                                         debug!(
                                             concat!(
@@ -199,11 +250,13 @@ pub fn spawn_push_manager(settings: &EnvSettings) -> mpsc::Sender<Event> {
                                     }
                                 }
                                 PushUpdate::Register { id, url, keys } => {
+                                    pending_verifications.remove(&id);
                                     if let Entry::Vacant(entry) = subscriptions.entry(id) {
                                         entry.insert(PushServer {
                                             url,
                                             keys,
                                             num_attempts: 0,
+                                            num_gone: 0,
                                             last_request: Instant::now()
                                                 - Duration::from_millis(push_throttle + 1),
                                             state_changes: Vec::new(),
@@ -212,6 +265,7 @@ pub fn spawn_push_manager(settings: &EnvSettings) -> mpsc::Sender<Event> {
                                     }
                                 }
                                 PushUpdate::Unregister { id } => {
+                                    pending_verifications.remove(&id);
                                     subscriptions.remove(&id);
                                 }
                             }
@@ -229,9 +283,18 @@ pub fn spawn_push_manager(settings: &EnvSettings) -> mpsc::Sender<Event> {
                                         && last_request > push_throttle)
                                         || ((1..push_attempts_max)
                                             .contains(&subscription.num_attempts)
-                                            && last_request > push_attempt_interval))
+                                            && last_request
+                                                > subscription.backoff(
+                                                    push_attempt_interval,
+                                                    push_attempt_interval_max,
+                                                )))
                                 {
-                                    subscription.send(id, push_tx.clone(), push_timeout);
+                                    subscription.send(
+                                        id,
+                                        push_tx.clone(),
+                                        push_vapid.clone(),
+                                        push_timeout,
+                                    );
                                     retry_ids.remove(&id);
                                 } else {
                                     retry_ids.insert(id);
@@ -243,21 +306,49 @@ pub fn spawn_push_manager(settings: &EnvSettings) -> mpsc::Sender<Event> {
                     }
                     Event::Reset => {
                         subscriptions.clear();
+                        pending_verifications.clear();
                     }
                     Event::DeliverySuccess { id } => {
                         if let Some(subscription) = subscriptions.get_mut(&id) {
                             subscription.num_attempts = 0;
+                            subscription.num_gone = 0;
                             subscription.in_flight = false;
                             retry_ids.remove(&id);
                         }
                     }
-                    Event::DeliveryFailure { id, state_changes } => {
+                    Event::DeliveryFailure {
+                        id,
+                        state_changes,
+                        is_gone,
+                    } => {
                         if let Some(subscription) = subscriptions.get_mut(&id) {
-                            subscription.last_request = Instant::now();
-                            subscription.num_attempts += 1;
-                            subscription.state_changes.extend(state_changes);
-                            subscription.in_flight = false;
-                            retry_ids.insert(id);
+                            subscription.num_gone = if is_gone {
+                                subscription.num_gone + 1
+                            } else {
+                                0
+                            };
+
+                            if subscription.num_gone < push_gone_max {
+                                subscription.last_request = Instant::now();
+                                subscription.num_attempts += 1;
+                                subscription.state_changes.extend(state_changes);
+                                subscription.in_flight = false;
+                                retry_ids.insert(id);
+                            } else {
+                                // The endpoint no longer exists, destroy the subscription.
+                                debug!(
+                                    "Destroying push subscription {}: URL {} is gone.",
+                                    id, subscription.url
+                                );
+                                subscriptions.remove(&id);
+                                retry_ids.remove(&id);
+                                if let Err(err) = state_tx
+                                    .send(state_change::Event::DestroySubscription { id })
+                                    .await
+                                {
+                                    debug!("Error sending push subscription destroy: {}", err);
+                                }
+                            }
                         }
                     }
                 },
@@ -270,7 +361,18 @@ pub fn spawn_push_manager(settings: &EnvSettings) -> mpsc::Sender<Event> {
             retry_timeout = if !retry_ids.is_empty() {
                 let last_retry_elapsed = last_retry.elapsed().as_millis() as u64;
 
-                if last_retry_elapsed >= push_retry_interval {
+                // Throttled notifications are flushed as soon as their window closes,
+                // without waiting for the next retry interval.
+                let throttle_due = retry_ids.iter().any(|id| {
+                    subscriptions.get(id).map_or(false, |subscription| {
+                        !subscription.in_flight
+                            && subscription.num_attempts == 0
+                            && subscription.last_request.elapsed().as_millis() as u64
+                                >= push_throttle
+                    })
+                });
+
+                if last_retry_elapsed >= push_retry_interval || throttle_due {
                     let mut remove_ids = Vec::with_capacity(retry_ids.len());
 
                     for retry_id in &retry_ids {
@@ -282,10 +384,19 @@ pub fn spawn_push_manager(settings: &EnvSettings) -> mpsc::Sender<Event> {
                                 && ((subscription.num_attempts == 0
                                     && last_request >= push_throttle)
                                     || (subscription.num_attempts > 0
-                                        && last_request >= push_attempt_interval))
+                                        && last_request
+                                            >= subscription.backoff(
+                                                push_attempt_interval,
+                                                push_attempt_interval_max,
+                                            )))
                             {
                                 if subscription.num_attempts < push_attempts_max {
-                                    subscription.send(*retry_id, push_tx.clone(), push_timeout);
+                                    subscription.send(
+                                        *retry_id,
+                                        push_tx.clone(),
+                                        push_vapid.clone(),
+                                        push_timeout,
+                                    );
                                 } else {
                                     debug!(
                                         concat!(
@@ -320,6 +431,49 @@ pub fn spawn_push_manager(settings: &EnvSettings) -> mpsc::Sender<Event> {
             } else {
                 Duration::from_millis(LONG_SLUMBER_MS)
             };
+
+            // Wake up when the earliest throttle window closes
+            if let Some(next_flush) = retry_ids
+                .iter()
+                .filter_map(|id| subscriptions.get(id))
+                .filter(|subscription| !subscription.in_flight && subscription.num_attempts == 0)
+                .map(|subscription| {
+                    Duration::from_millis(push_throttle)
+                        .saturating_sub(subscription.last_request.elapsed())
+                })
+                .min()
+            {
+                retry_timeout = retry_timeout.min(next_flush);
+            }
+
+            // Reap subscriptions that were not verified in time
+            if !pending_verifications.is_empty() {
+                let mut reap_ids = Vec::new();
+                pending_verifications.retain(|id, sent_at| {
+                    if sent_at.elapsed() >= push_verify_expiry {
+                        reap_ids.push(*id);
+                        false
+                    } else {
+                        true
+                    }
+                });
+                for id in reap_ids {
+                    debug!("Reaping unverified push subscription {}.", id);
+                    if let Err(err) = state_tx
+                        .send(state_change::Event::ReapSubscription { id })
+                        .await
+                    {
+                        debug!("Error sending push subscription reap: {}", err);
+                    }
+                }
+                if let Some(next_reap) = pending_verifications
+                    .values()
+                    .map(|sent_at| push_verify_expiry.saturating_sub(sent_at.elapsed()))
+                    .min()
+                {
+                    retry_timeout = retry_timeout.min(next_reap);
+                }
+            }
         }
     });
 
@@ -327,7 +481,20 @@ pub fn spawn_push_manager(settings: &EnvSettings) -> mpsc::Sender<Event> {
 }
 
 impl PushServer {
-    fn send(&mut self, id: store::JMAPId, push_tx: mpsc::Sender<Event>, push_timeout: u64) {
+    /// Time to wait after a failed delivery, doubled on each further failure.
+    fn backoff(&self, attempt_interval: u64, attempt_interval_max: u64) -> u64 {
+        attempt_interval
+            .saturating_mul(1u64 << self.num_attempts.saturating_sub(1).min(32))
+            .min(attempt_interval_max)
+    }
+
+    fn send(
+        &mut self,
+        id: store::JMAPId,
+        push_tx: mpsc::Sender<Event>,
+        vapid: Option<Arc<VapidKey>>,
+        push_timeout: u64,
+    ) {
         let url = self.url.clone();
         let keys = self.keys.clone();
         let state_changes = std::mem::take(&mut self.state_changes);
@@ -348,17 +515,21 @@ impl PushServer {
 
             push_tx
                 .send(
-                    if http_request(
+                    match http_request(
                         url,
                         serde_json::to_string(&response).unwrap(),
                         keys,
+                        vapid,
                         push_timeout,
                     )
                     .await
                     {
-                        Event::DeliverySuccess { id }
-                    } else {
-                        Event::DeliveryFailure { id, state_changes }
+                        DeliveryStatus::Success => Event::DeliverySuccess { id },
+                        status => Event::DeliveryFailure {
+                            id,
+                            state_changes,
+                            is_gone: status == DeliveryStatus::Gone,
+                        },
                     },
                 )
                 .await
@@ -369,10 +540,11 @@ impl PushServer {
 
 async fn http_request(
     url: String,
-    mut body: String,
+    body: String,
     keys: Option<EncriptionKeys>,
+    vapid: Option<Arc<VapidKey>>,
     push_timeout: u64,
-) -> bool {
+) -> DeliveryStatus {
     let client_builder = reqwest::Client::builder().timeout(Duration::from_millis(push_timeout));
 
     #[cfg(test)]
@@ -385,27 +557,47 @@ async fn http_request(
         .header(CONTENT_TYPE, "application/json")
         .header("TTL", "86400");
 
-    if let Some(keys) = keys {
-        match ece_encrypt(&keys.p256dh, &keys.auth, body.as_bytes())
-            .map(|b| base64::encode_config(b, base64::URL_SAFE))
-        {
-            Ok(body_) => {
-                body = body_;
+    if let Some(vapid) = vapid {
+        match vapid.authorization(&url) {
+            Ok(authorization) => {
+                client = client.header(AUTHORIZATION, authorization);
+            }
+            Err(err) => {
+                debug!("Failed to sign push request to {}: {}", url, err);
+                return DeliveryStatus::Success;
+            }
+        }
+    }
+
+    // Encrypted payloads are sent as binary aes128gcm records (RFC 8291).
+    let body = if let Some(keys) = keys {
+        match ece_encrypt(&keys.p256dh, &keys.auth, body.as_bytes()) {
+            Ok(body) => {
                 client = client.header(CONTENT_ENCODING, "aes128gcm");
+                body
             }
             Err(err) => {
                 // Do not reattempt if encryption fails.
                 debug!("Failed to encrypt push subscription to {}: {}", url, err);
-                return true;
+                return DeliveryStatus::Success;
             }
         }
-    }
+    } else {
+        body.into_bytes()
+    };
 
     match client.body(body).send().await {
-        Ok(response) => response.status().is_success(),
+        Ok(response) => match response.status() {
+            status if status.is_success() => DeliveryStatus::Success,
+            StatusCode::NOT_FOUND | StatusCode::GONE => DeliveryStatus::Gone,
+            status => {
+                debug!("HTTP post to {} failed with status {}", url, status);
+                DeliveryStatus::Failure
+            }
+        },
         Err(err) => {
             debug!("HTTP post to {} failed with: {}", url, err);
-            false
+            DeliveryStatus::Failure
         }
     }
 }
@@ -524,4 +716,53 @@ where
         })
         .await
     }
+
+    pub async fn reap_push_subscription(&self, id: store::JMAPId) -> jmap::Result<()> {
+        let account_id = id.get_prefix_id();
+        let document_id = id.get_document_id();
+        let store = self.store.clone();
+
+        // The subscription might have been verified or destroyed in the meantime
+        let is_unverified = self
+            .spawn_jmap_request(move || {
+                Ok(store
+                    .get_orm::<schema::PushSubscription>(account_id, document_id)?
+                    .map_or(false, |subscription| {
+                        subscription.get(&Property::VerificationCode)
+                            != subscription.get(&Property::VerificationCode_)
+                    }))
+            })
+            .await?;
+
+        if is_unverified {
+            self.destroy_push_subscription(id).await
+        } else {
+            Ok(())
+        }
+    }
+
+    pub async fn destroy_push_subscription(&self, id: store::JMAPId) -> jmap::Result<()> {
+        let account_id = id.get_prefix_id();
+        let document_id = id.get_document_id();
+        let store = self.store.clone();
+
+        let change_id = self
+            .spawn_jmap_request(move || {
+                let mut batch = WriteBatch::new(account_id);
+                let mut document = Document::new(Collection::PushSubscription, document_id);
+                store.push_subscription_delete(account_id, &mut document)?;
+                batch.delete_document(document);
+                batch.log_delete(Collection::PushSubscription, document_id);
+                Ok(store.write(batch)?.map(|changes| changes.change_id))
+            })
+            .await?;
+
+        if let Some(change_id) = change_id {
+            if self.is_in_cluster() && !self.commit_index(change_id).await {
+                return Err(MethodError::ServerPartialFail);
+            }
+        }
+
+        self.update_push_subscriptions(account_id).await
+    }
 }
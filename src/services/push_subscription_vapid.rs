@@ -0,0 +1,98 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::SystemTime;
+
+use jmap::base64;
+use p256::{
+    ecdsa::{signature::Signer, Signature, SigningKey},
+    elliptic_curve::sec1::ToEncodedPoint,
+    SecretKey,
+};
+
+// Push services reject tokens valid for longer than 24 hours (RFC 8292, section 2).
+const VAPID_TOKEN_EXPIRY: u64 = 12 * 60 * 60;
+const VAPID_TOKEN_HEADER: &str = "{\"typ\":\"JWT\",\"alg\":\"ES256\"}";
+
+pub struct VapidKey {
+    signing_key: SigningKey,
+    public_key: String,
+    subject: Option<String>,
+}
+
+impl VapidKey {
+    /// Loads a P-256 private key encoded as base64url, the format produced by
+    /// most Web Push key generators.
+    pub fn new(private_key: &str, subject: Option<String>) -> Result<Self, String> {
+        let secret_key = base64::decode_config(
+            private_key.trim().trim_end_matches('='),
+            base64::URL_SAFE_NO_PAD,
+        )
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| SecretKey::from_be_bytes(&bytes).map_err(|e| e.to_string()))?;
+
+        Ok(VapidKey {
+            public_key: base64::encode_config(
+                secret_key.public_key().to_encoded_point(false).as_bytes(),
+                base64::URL_SAFE_NO_PAD,
+            ),
+            signing_key: SigningKey::from(&secret_key),
+            subject,
+        })
+    }
+
+    /// Returns the value of the Authorization header for a push request to `url`.
+    pub fn authorization(&self, url: &str) -> Result<String, String> {
+        let audience = reqwest::Url::parse(url)
+            .map_err(|e| e.to_string())?
+            .origin()
+            .ascii_serialization();
+        let expires = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+            + VAPID_TOKEN_EXPIRY;
+
+        let mut claims = serde_json::json!({
+            "aud": audience,
+            "exp": expires,
+        });
+        if let Some(subject) = &self.subject {
+            claims["sub"] = subject.clone().into();
+        }
+
+        let token = format!(
+            "{}.{}",
+            base64::encode_config(VAPID_TOKEN_HEADER, base64::URL_SAFE_NO_PAD),
+            base64::encode_config(claims.to_string(), base64::URL_SAFE_NO_PAD)
+        );
+        let signature: Signature = self.signing_key.sign(token.as_bytes());
+
+        Ok(format!(
+            "vapid t={}.{}, k={}",
+            token,
+            base64::encode_config(signature.as_ref(), base64::URL_SAFE_NO_PAD),
+            self.public_key
+        ))
+    }
+}
@@ -60,6 +60,12 @@ pub enum Event {
         account_id: AccountId,
         subscriptions: Vec<UpdateSubscription>,
     },
+    DestroySubscription {
+        id: JMAPId,
+    },
+    ReapSubscription {
+        id: JMAPId,
+    },
 }
 
 #[derive(Clone, Debug)]
@@ -110,7 +116,7 @@ pub fn spawn_state_manager<T>(
 ) where
     T: for<'x> Store<'x> + 'static,
 {
-    let push_tx = spawn_push_manager(settings);
+    let push_tx = spawn_push_manager(settings, core.state_change.clone());
 
     tokio::spawn(async move {
         let mut subscribers: AHashMap<AccountId, AHashMap<DocumentId, Subscriber>> =
@@ -385,6 +391,22 @@ pub fn spawn_state_manager<T>(
                         }
                     }
                 }
+                Event::DestroySubscription { id } if started => {
+                    let core = core.clone();
+                    tokio::spawn(async move {
+                        if let Err(err) = core.destroy_push_subscription(id).await {
+                            error!("Failed to destroy push subscription {}: {}", id, err);
+                        }
+                    });
+                }
+                Event::ReapSubscription { id } if started => {
+                    let core = core.clone();
+                    tokio::spawn(async move {
+                        if let Err(err) = core.reap_push_subscription(id).await {
+                            error!("Failed to reap push subscription {}: {}", id, err);
+                        }
+                    });
+                }
                 _ => {
                     debug!("Ignoring state event {:?}", event);
                 }
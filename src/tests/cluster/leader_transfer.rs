@@ -0,0 +1,101 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::{Duration, Instant};
+
+use store::Store;
+use tokio::time::sleep;
+
+use crate::{
+    cluster::PeerId,
+    tests::cluster::utils::{
+        assert_cluster_updated, assert_leader_elected, find_online_follower, shutdown_all, Clients,
+        Cluster,
+    },
+};
+
+pub async fn test<T>()
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Testing Raft leadership transfer...");
+    let mut cluster = Cluster::<T>::new("st_cluster_leader_transfer", 3, true).await;
+    let peers = cluster.start_cluster().await;
+    let leader = assert_leader_elected(&peers).await;
+    assert_cluster_updated(&peers).await;
+
+    // Hand over leadership to one of the followers
+    let target = &peers[find_online_follower(&peers)];
+    let target_id = target.get_key::<PeerId>("peer_id").await.unwrap().unwrap();
+    let transfer_start = Instant::now();
+    leader.transfer_leadership(target_id).await;
+
+    // The target must win the election before a regular election
+    // timeout would have elapsed.
+    while !target.is_leader() {
+        assert!(
+            transfer_start.elapsed() < Duration::from_millis(1000),
+            "Leadership was not transferred in time."
+        );
+        sleep(Duration::from_millis(10)).await;
+    }
+    assert!(!leader.is_leader());
+    assert_cluster_updated(&peers).await;
+
+    // Writes are refused while the target catches up and accepted again once
+    // the transfer is aborted at its deadline.
+    let leader = assert_leader_elected(&peers).await;
+    let leader_num = peers.iter().position(|peer| peer.is_leader()).unwrap();
+    let target_num = find_online_follower(&peers);
+    let target_id = peers[target_num]
+        .get_key::<PeerId>("peer_id")
+        .await
+        .unwrap()
+        .unwrap();
+    let clients = Clients::new(3).await;
+    let client = &clients.clients[leader_num];
+    peers[target_num].set_offline(true, false).await;
+    client.domain_create("example.com").await.unwrap();
+    leader.transfer_leadership(target_id).await;
+    sleep(Duration::from_millis(100)).await;
+    assert!(leader.is_transferring_leadership());
+    assert!(client.domain_create("example.org").await.is_err());
+    let transfer_start = Instant::now();
+    while leader.is_transferring_leadership() {
+        assert!(
+            transfer_start.elapsed()
+                < Duration::from_millis(leader.store.config.raft_commit_timeout + 1000),
+            "Leadership transfer was not aborted in time."
+        );
+        sleep(Duration::from_millis(10)).await;
+    }
+    assert!(leader.is_leader());
+    client.domain_create("example.org").await.unwrap();
+    peers[target_num].set_offline(false, false).await;
+    assert_cluster_updated(&peers).await;
+
+    // Stop cluster
+    cluster.stop_cluster().await;
+    shutdown_all(peers).await;
+    cluster.cleanup();
+}
@@ -0,0 +1,84 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use futures::future::join_all;
+use store::Store;
+
+use crate::tests::cluster::utils::{
+    assert_cluster_updated, assert_leader_elected, assert_mirrored_stores, find_online_follower,
+    shutdown_all, Clients, Cluster,
+};
+
+pub async fn test<T>()
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Testing Raft snapshot installation...");
+    let mut cluster = Cluster::<T>::new("st_cluster_log_snapshot", 3, true).await;
+    let peers = cluster.start_cluster().await;
+    assert_leader_elected(&peers).await;
+    let clients = Clients::new(3).await;
+    let client = &clients.clients[0];
+
+    // Replicate some changes to all peers
+    client.domain_create("example.com").await.unwrap();
+    client
+        .individual_create("jdoe@example.com", "12345", "John Doe")
+        .await
+        .unwrap();
+    assert_cluster_updated(&peers).await;
+
+    // Take a follower offline and keep writing while it is away
+    let follower = &peers[find_online_follower(&peers)];
+    follower.set_offline(true, true).await;
+    let leader = assert_leader_elected(&peers).await;
+    client
+        .individual_create("jane@example.com", "abcde", "Jane Doe")
+        .await
+        .unwrap();
+    client
+        .individual_create("bill@example.com", "xyz", "Bill Foobar")
+        .await
+        .unwrap();
+    assert_cluster_updated(&peers).await;
+
+    // Compact the log of the online peers past the offline follower's position
+    let last_log = leader.get_last_log().await.unwrap().unwrap();
+    join_all(peers.iter().filter(|peer| !peer.is_offline()).map(|peer| {
+        let store = peer.store.clone();
+        tokio::task::spawn_blocking(move || store.compact_log_up_to(last_log.index).unwrap())
+    }))
+    .await;
+
+    // The follower can no longer be sent the missing entries and has to
+    // converge from the snapshot instead
+    follower.set_offline(false, true).await;
+    assert_leader_elected(&peers).await;
+    assert_cluster_updated(&peers).await;
+    assert_mirrored_stores(peers.clone(), false).await;
+
+    // Stop cluster
+    cluster.stop_cluster().await;
+    shutdown_all(peers).await;
+    cluster.cleanup();
+}
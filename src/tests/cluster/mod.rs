@@ -26,8 +26,11 @@ use store_rocksdb::RocksDB;
 pub mod crud;
 pub mod election;
 pub mod fuzz;
+pub mod leader_transfer;
 pub mod log_conflict;
+pub mod log_snapshot;
 pub mod mail_thread_merge;
+pub mod read_index;
 pub mod utils;
 
 #[actix_web::test]
@@ -44,6 +47,9 @@ async fn cluster_tests() {
     crud::test::<RocksDB>().await;
     mail_thread_merge::test::<RocksDB>().await;
     log_conflict::test::<RocksDB>().await;
+    log_snapshot::test::<RocksDB>().await;
+    read_index::test::<RocksDB>().await;
+    leader_transfer::test::<RocksDB>().await;
 }
 
 #[actix_web::test]
@@ -0,0 +1,109 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap_sharing::principal::account::JMAPAccountStore;
+use store::Store;
+
+use crate::tests::cluster::utils::{
+    activate_all_peers, assert_cluster_updated, assert_leader_elected, find_online_follower,
+    shutdown_all, Clients, Cluster,
+};
+
+pub async fn test<T>()
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Testing linearizable follower reads...");
+    let mut cluster = Cluster::<T>::new("st_cluster_read_index", 3, true).await;
+    let peers = cluster.start_cluster().await;
+    let leader = assert_leader_elected(&peers).await;
+    assert_cluster_updated(&peers).await;
+
+    // Write to the leader, then read from a follower without waiting for replication.
+    let clients = Arc::new(Clients::new(3).await);
+    let client = &clients.clients[0];
+    client.domain_create("example.com").await.unwrap();
+    let account_id = client
+        .individual_create("jdoe@example.com", "12345", "John Doe")
+        .await
+        .unwrap()
+        .take_id();
+    let follower = &peers[find_online_follower(&peers)];
+    let read_index = follower.read_index().await.unwrap();
+    assert!(read_index >= leader.read_index().await.unwrap());
+    assert!(follower
+        .store
+        .find_individual("jdoe@example.com")
+        .unwrap()
+        .is_some());
+
+    // JMAP requests served by a follower see the writes acknowledged by the leader.
+    let leader_num = peers.iter().position(|peer| peer.is_leader()).unwrap();
+    let follower_num = find_online_follower(&peers);
+    let mut request = clients.clients[leader_num].build();
+    let create_id = request
+        .set_mailbox()
+        .account_id(&account_id)
+        .create()
+        .name("Read index")
+        .create_id()
+        .unwrap();
+    let mailbox_id = request
+        .send_set_mailbox()
+        .await
+        .unwrap()
+        .created(&create_id)
+        .unwrap()
+        .take_id();
+    let mut request = clients.clients[follower_num].build();
+    request
+        .get_mailbox()
+        .account_id(&account_id)
+        .ids([mailbox_id.as_str()]);
+    assert_eq!(
+        request.send_get_mailbox().await.unwrap().take_list().len(),
+        1
+    );
+
+    // A leader that can no longer reach a quorum must not return a read index.
+    for peer in peers.iter() {
+        if !peer.is_leader() {
+            peer.set_offline(true, false).await;
+        }
+    }
+    assert!(leader.read_index().await.is_none());
+
+    // Reads are served again once the cluster recovers.
+    activate_all_peers(&peers).await;
+    assert_leader_elected(&peers).await;
+    assert_cluster_updated(&peers).await;
+    let follower = &peers[find_online_follower(&peers)];
+    assert!(follower.read_index().await.is_some());
+
+    // Stop cluster
+    cluster.stop_cluster().await;
+    shutdown_all(peers).await;
+    cluster.cleanup();
+}
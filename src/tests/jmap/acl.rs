@@ -231,6 +231,40 @@ where
         .unwrap()
         .is_none());
 
+    // A message in both Inbox and Trash should not reveal the Trash folder
+    let jane_inbox_email_id = email_ids.get("jane").unwrap().first().unwrap();
+    jane_client
+        .email_set_mailboxes(jane_inbox_email_id, [&inbox_id, &trash_id])
+        .await
+        .unwrap();
+    assert_eq!(
+        john_client
+            .set_default_account_id(&jane_id)
+            .email_get(jane_inbox_email_id, [Property::MailboxIds].into())
+            .await
+            .unwrap()
+            .unwrap()
+            .mailbox_ids(),
+        [inbox_id.as_str()]
+    );
+    let mut mailbox_ids = jane_client
+        .email_get(jane_inbox_email_id, [Property::MailboxIds].into())
+        .await
+        .unwrap()
+        .unwrap()
+        .mailbox_ids()
+        .into_iter()
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>();
+    mailbox_ids.sort_unstable();
+    let mut expected_ids = vec![inbox_id.to_string(), trash_id.to_string()];
+    expected_ids.sort_unstable();
+    assert_eq!(mailbox_ids, expected_ids);
+    jane_client
+        .email_set_mailboxes(jane_inbox_email_id, [&inbox_id])
+        .await
+        .unwrap();
+
     // John should only be able to copy blobs he has access to
     let blob_id = jane_client
         .email_get(
@@ -286,6 +320,56 @@ where
         vec![ACL::ReadItems]
     );
 
+    // Only shared mailboxes are listed, and hidden parents are not disclosed
+    let jane_child_id = jane_client
+        .set_default_account_id(&jane_id)
+        .mailbox_create("Jane's folder", Some(&trash_id), Role::None)
+        .await
+        .unwrap()
+        .take_id();
+    jane_client
+        .mailbox_update_acl(
+            &jane_child_id,
+            "jdoe@example.com",
+            [ACL::Read, ACL::ReadItems],
+        )
+        .await
+        .unwrap();
+    let mut request = john_client.set_default_account_id(&jane_id).build();
+    request
+        .get_mailbox()
+        .properties([mailbox::Property::Id, mailbox::Property::ParentId]);
+    let mut mailboxes = request
+        .send_get_mailbox()
+        .await
+        .unwrap()
+        .take_list()
+        .into_iter()
+        .map(|mailbox| {
+            (
+                mailbox.id().unwrap().to_string(),
+                mailbox.parent_id().map(|id| id.to_string()),
+            )
+        })
+        .collect::<Vec<_>>();
+    mailboxes.sort_unstable();
+    let mut expected_mailboxes = vec![(inbox_id.to_string(), None), (jane_child_id.clone(), None)];
+    expected_mailboxes.sort_unstable();
+    assert_eq!(mailboxes, expected_mailboxes);
+    assert_eq!(
+        jane_client
+            .mailbox_get(&jane_child_id, [mailbox::Property::ParentId].into())
+            .await
+            .unwrap()
+            .unwrap()
+            .parent_id(),
+        Some(trash_id.as_str())
+    );
+    jane_client
+        .mailbox_destroy(&jane_child_id, true)
+        .await
+        .unwrap();
+
     // Try to add items using import and copy
     let blob_id = john_client
         .set_default_account_id(&john_id)
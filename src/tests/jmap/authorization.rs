@@ -21,7 +21,14 @@
  * for more details.
 */
 
-use std::{fmt::Debug, sync::Arc, time::Duration};
+use std::{
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use actix_web::web;
 use jmap::{types::jmap::JMAPId, SUPERUSER_ID};
@@ -35,8 +42,16 @@ use jmap_client::{
 };
 use jmap_sharing::principal::set::JMAPSetPrincipal;
 use store::Store;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
 
-use crate::{tests::store::utils::StoreCompareWith, JMAPServer};
+use crate::{
+    authorization::{auth::RemoteAddress, rate_limit::Limiter},
+    tests::store::utils::StoreCompareWith,
+    JMAPServer,
+};
 
 pub async fn test<T>(server: web::Data<JMAPServer<T>>, admin_client: &mut Client)
 where
@@ -136,6 +151,18 @@ where
         .await
         .is_err());
 
+    // Chunked uploads should be aborted as soon as the limit is exceeded
+    let (response, bytes_sent) = upload_chunked(
+        server.base_session.base_url(),
+        &account_id,
+        "amRvZUBleGFtcGxlLmNvbToxMjM0NQ==",
+        4 * 50000000,
+    )
+    .await;
+    assert!(response.starts_with("HTTP/1.1 400"), "{}", response);
+    assert!(response.contains("maxSizeUpload"), "{}", response);
+    assert!(bytes_sent < 4 * 50000000);
+
     // Users should not be allowed to create, read, modify or delete principals
     assert_forbidden(
         client
@@ -219,6 +246,52 @@ where
         }))
     ));
 
+    // Uploads should be throttled once the account's byte budget is spent
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    server
+        .rate_limiters
+        .insert(
+            RemoteAddress::AccountId(JMAPId::parse(&account_id).unwrap().get_document_id()),
+            Arc::new(Limiter::new_authenticated(1000, 60, 1000, 86400)),
+        )
+        .await;
+    for _ in 0..3 {
+        client.upload(None, vec![b'A'; 300], None).await.unwrap();
+    }
+    assert!(matches!(
+        client.upload(None, vec![b'A'; 300], None).await,
+        Err(jmap_client::Error::Problem(ProblemDetails {
+            status: Some(429),
+            ..
+        }))
+    ));
+    client.upload(None, vec![b'A'; 100], None).await.unwrap();
+    assert!(matches!(
+        client.upload(None, vec![b'A'; 1], None).await,
+        Err(jmap_client::Error::Problem(ProblemDetails {
+            status: Some(429),
+            ..
+        }))
+    ));
+
+    // Chunked uploads should be throttled as soon as the budget is spent
+    server
+        .rate_limiters
+        .insert(
+            RemoteAddress::AccountId(JMAPId::parse(&account_id).unwrap().get_document_id()),
+            Arc::new(Limiter::new_authenticated(1000, 60, 2 * 1024 * 1024, 86400)),
+        )
+        .await;
+    let (response, bytes_sent) = upload_chunked(
+        server.base_session.base_url(),
+        &account_id,
+        "amRvZUBleGFtcGxlLmNvbToxMjM0NQ==",
+        40000000,
+    )
+    .await;
+    assert!(response.starts_with("HTTP/1.1 429"), "{}", response);
+    assert!(bytes_sent < 40000000);
+
     // Destroy test accounts
     admin_client
         .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
@@ -243,3 +316,62 @@ pub fn assert_forbidden<T: Debug>(result: Result<T, jmap_client::Error>) {
         panic!("Expected forbidden, got {:?}", result);
     }
 }
+
+async fn upload_chunked(
+    base_url: &str,
+    account_id: &str,
+    credentials: &str,
+    size: usize,
+) -> (String, usize) {
+    let host = base_url
+        .trim_start_matches("http://")
+        .trim_end_matches('/')
+        .to_string();
+    let (mut reader, mut writer) = TcpStream::connect(&host).await.unwrap().into_split();
+    writer
+        .write_all(
+            format!(
+                concat!(
+                    "POST /jmap/upload/{}/ HTTP/1.1\r\n",
+                    "Host: {}\r\n",
+                    "Authorization: Basic {}\r\n",
+                    "Content-Type: application/octet-stream\r\n",
+                    "Transfer-Encoding: chunked\r\n\r\n"
+                ),
+                account_id, host, credentials
+            )
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+
+    // Keep streaming chunks until the server stops accepting them
+    let bytes_sent = Arc::new(AtomicUsize::new(0));
+    let bytes_sent_ = bytes_sent.clone();
+    tokio::spawn(async move {
+        let chunk = vec![b'A'; 1024 * 1024];
+        while bytes_sent_.load(Ordering::Relaxed) < size {
+            if writer
+                .write_all(format!("{:x}\r\n", chunk.len()).as_bytes())
+                .await
+                .is_err()
+                || writer.write_all(&chunk).await.is_err()
+                || writer.write_all(b"\r\n").await.is_err()
+            {
+                return;
+            }
+            bytes_sent_.fetch_add(chunk.len(), Ordering::Relaxed);
+        }
+        writer.write_all(b"0\r\n\r\n").await.ok();
+    });
+
+    let mut response = vec![0u8; 4096];
+    let bytes_read = tokio::time::timeout(Duration::from_secs(5), reader.read(&mut response))
+        .await
+        .unwrap()
+        .unwrap();
+    (
+        String::from_utf8_lossy(&response[..bytes_read]).into_owned(),
+        bytes_sent.load(Ordering::Relaxed),
+    )
+}
@@ -0,0 +1,88 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{io::Read, time::Duration};
+
+use actix_web::web;
+use flate2::read::GzDecoder;
+use reqwest::header;
+use store::Store;
+
+use crate::JMAPServer;
+
+pub async fn test<T>(server: web::Data<JMAPServer<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running response compression tests...");
+    let api_url = server.base_session.api_url().to_string();
+
+    // Large responses are compressed when the client accepts it
+    let payload = "Lorem ipsum dolor sit amet. ".repeat(2000);
+    let (encoding, body) = echo(&api_url, &payload).await;
+    assert_eq!(encoding.as_deref(), Some("gzip"));
+    let mut decoded = Vec::new();
+    GzDecoder::new(&body[..]).read_to_end(&mut decoded).unwrap();
+    assert!(decoded.len() > body.len());
+    let response = serde_json::from_slice::<serde_json::Value>(&decoded).unwrap();
+    assert_eq!(
+        response["methodResponses"][0][1]["payload"].as_str(),
+        Some(payload.as_str())
+    );
+
+    // Small responses are sent as is
+    let (encoding, body) = echo(&api_url, "small").await;
+    assert_ne!(encoding.as_deref(), Some("gzip"));
+    let response = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+    assert_eq!(
+        response["methodResponses"][0][1]["payload"].as_str(),
+        Some("small")
+    );
+}
+
+async fn echo(url: &str, payload: &str) -> (Option<String>, Vec<u8>) {
+    let response = reqwest::Client::builder()
+        .timeout(Duration::from_millis(1000))
+        .danger_accept_invalid_certs(true)
+        .build()
+        .unwrap_or_default()
+        .post(url)
+        .bearer_auth("DO_NOT_ATTEMPT_THIS_AT_HOME")
+        .header(header::ACCEPT_ENCODING, "gzip")
+        .body(
+            serde_json::json!({
+                "using": ["urn:ietf:params:jmap:core"],
+                "methodCalls": [["Core/echo", {"payload": payload}, "0"]]
+            })
+            .to_string(),
+        )
+        .send()
+        .await
+        .unwrap();
+    let encoding = response
+        .headers()
+        .get(header::CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string());
+    (encoding, response.bytes().await.unwrap().to_vec())
+}
@@ -0,0 +1,156 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use actix_web::web;
+use jmap::types::jmap::JMAPId;
+use jmap_client::{client::Client, mailbox};
+use store::Store;
+
+use crate::JMAPServer;
+
+pub async fn test<T>(server: web::Data<JMAPServer<T>>, client: &mut Client)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running idempotent retry tests...");
+    let account_id = JMAPId::new(1).to_string();
+    let api_url = server.base_session.api_url().to_string();
+
+    // Obtain the current Mailbox state
+    let response = post(
+        &api_url,
+        None,
+        format!(
+            concat!(
+                "{{\"using\": [\"urn:ietf:params:jmap:core\", \"urn:ietf:params:jmap:mail\"], ",
+                "\"methodCalls\": [[\"Mailbox/get\", {{\"accountId\": \"{}\", \"ids\": []}}, \"0\"]]}}"
+            ),
+            account_id
+        ),
+    )
+    .await;
+    let state = serde_json::from_slice::<serde_json::Value>(&response).unwrap()["methodResponses"]
+        [0][1]["state"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    // Create a mailbox, the first response is lost and the request is retried
+    let request = format!(
+        concat!(
+            "{{\"using\": [\"urn:ietf:params:jmap:core\", \"urn:ietf:params:jmap:mail\"], ",
+            "\"methodCalls\": [[\"Mailbox/set\", {{\"accountId\": \"{}\", \"ifInState\": \"{}\", ",
+            "\"create\": {{\"c1\": {{\"name\": \"Retried\"}}}}}}, \"0\"]]}}"
+        ),
+        account_id, state
+    );
+    let original_response = post(&api_url, "retry-1".into(), request.clone()).await;
+    let original = serde_json::from_slice::<serde_json::Value>(&original_response).unwrap();
+    let mailbox_id = original["methodResponses"][0][1]["created"]["c1"]["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    // Retrying with the same key returns the original response instead of a state mismatch
+    assert_eq!(
+        post(&api_url, "retry-1".into(), request.clone()).await,
+        original_response
+    );
+
+    // Without the key, or with a different key, the stale state is rejected
+    for key in [None, Some("retry-2")] {
+        let response = serde_json::from_slice::<serde_json::Value>(
+            &post(&api_url, key, request.clone()).await,
+        )
+        .unwrap();
+        assert_eq!(
+            response["methodResponses"][0][1]["type"].as_str(),
+            Some("stateMismatch"),
+            "{}",
+            response
+        );
+    }
+
+    // Only one mailbox was created
+    assert_eq!(
+        client
+            .mailbox_query(
+                mailbox::query::Filter::name("Retried").into(),
+                [mailbox::query::Comparator::name()].into()
+            )
+            .await
+            .unwrap()
+            .ids(),
+        [mailbox_id.as_str()]
+    );
+    client.mailbox_destroy(&mailbox_id, true).await.unwrap();
+
+    // Concurrent requests with the same key are executed once
+    let request = format!(
+        concat!(
+            "{{\"using\": [\"urn:ietf:params:jmap:core\", \"urn:ietf:params:jmap:mail\"], ",
+            "\"methodCalls\": [[\"Mailbox/set\", {{\"accountId\": \"{}\", ",
+            "\"create\": {{\"c1\": {{\"name\": \"Concurrent\"}}}}}}, \"0\"]]}}"
+        ),
+        account_id
+    );
+    let (response_1, response_2) = tokio::join!(
+        post(&api_url, "retry-3".into(), request.clone()),
+        post(&api_url, "retry-3".into(), request.clone())
+    );
+    assert_eq!(response_1, response_2);
+    let mailbox_ids = client
+        .mailbox_query(
+            mailbox::query::Filter::name("Concurrent").into(),
+            [mailbox::query::Comparator::name()].into(),
+        )
+        .await
+        .unwrap()
+        .take_ids();
+    assert_eq!(mailbox_ids.len(), 1, "{:?}", mailbox_ids);
+    client.mailbox_destroy(&mailbox_ids[0], true).await.unwrap();
+}
+
+async fn post(url: &str, idempotency_key: Option<&str>, body: String) -> Vec<u8> {
+    let mut request = reqwest::Client::builder()
+        .timeout(Duration::from_millis(1000))
+        .danger_accept_invalid_certs(true)
+        .build()
+        .unwrap_or_default()
+        .post(url)
+        .bearer_auth("DO_NOT_ATTEMPT_THIS_AT_HOME")
+        .body(body);
+    if let Some(idempotency_key) = idempotency_key {
+        request = request.header("Idempotency-Key", idempotency_key);
+    }
+    request
+        .send()
+        .await
+        .unwrap()
+        .bytes()
+        .await
+        .unwrap()
+        .to_vec()
+}
@@ -40,9 +40,13 @@ use super::store::utils::{destroy_temp_dir, init_settings};
 
 pub mod acl;
 pub mod authorization;
+pub mod compression;
 pub mod event_source;
+pub mod idempotency;
 pub mod oauth;
+pub mod push_retry;
 pub mod push_subscription;
+pub mod push_vapid;
 pub mod references;
 pub mod stress_test;
 pub mod websocket;
@@ -126,7 +130,7 @@ where
         .rate_limiters
         .insert(
             RemoteAddress::AccountId(SUPERUSER_ID),
-            Arc::new(Limiter::new_authenticated(1000, 1000)),
+            Arc::new(Limiter::new_authenticated(1000, 1000, 1000000000, 1000)),
         )
         .await;
 }
@@ -141,8 +145,22 @@ async fn jmap_core_tests() {
     acl::test(server.clone(), &mut client).await;
     authorization::test(server.clone(), &mut client).await;
     event_source::test(server.clone(), &mut client).await;
+    idempotency::test(server.clone(), &mut client).await;
+    compression::test(server.clone()).await;
     push_subscription::test(server.clone(), &mut client).await;
     websocket::test(server.clone(), &mut client).await;
 
     destroy_temp_dir(&temp_dir);
 }
+
+#[actix_web::test]
+#[ignore]
+async fn jmap_push_retry_tests() {
+    push_retry::test().await;
+}
+
+#[actix_web::test]
+#[ignore]
+async fn jmap_push_vapid_tests() {
+    push_vapid::test().await;
+}
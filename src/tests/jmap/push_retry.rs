@@ -0,0 +1,237 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    sync::atomic::{AtomicU32, Ordering},
+    time::{Duration, Instant},
+};
+
+use actix_web::{web, App, HttpResponse, HttpServer};
+use jmap::types::type_state::TypeState;
+use store::{ahash::AHashMap, config::env_settings::EnvSettings, core::JMAPIdPrefix, JMAPId};
+use tokio::sync::mpsc;
+
+use crate::services::{
+    push_subscription::{spawn_push_manager, Event, PushUpdate},
+    state_change::{self, StateChange},
+};
+
+pub async fn test() {
+    println!("Running Push retry tests...");
+
+    // Start mock push server
+    let (event_tx, mut event_rx) = mpsc::channel::<Instant>(100);
+    let push_server = web::Data::new(PushServer {
+        tx: event_tx,
+        num_failures: 2.into(),
+    });
+    let data = push_server.clone();
+    actix_web::rt::spawn(async move {
+        HttpServer::new(move || {
+            App::new()
+                .app_data(data.clone())
+                .route("/retry", web::post().to(handle_retry))
+                .route("/gone", web::post().to(handle_gone))
+        })
+        .bind("127.0.0.1:9001")?
+        .run()
+        .await
+    });
+
+    let settings = EnvSettings {
+        args: AHashMap::from_iter(
+            [
+                ("push-attempt-interval", "200"),
+                ("push-attempt-interval-max", "1000"),
+                ("push-attempts-max", "5"),
+                ("push-retry-interval", "50"),
+                ("push-throttle", "100"),
+                ("push-gone-max", "2"),
+                ("push-verify-expiry", "500"),
+            ]
+            .map(|(k, v)| (k.to_string(), v.to_string())),
+        ),
+    };
+    let (state_tx, mut state_rx) = mpsc::channel::<state_change::Event>(100);
+    let push_tx = spawn_push_manager(&settings, state_tx);
+
+    // Register subscriptions
+    let retry_id = JMAPId::from_parts(1, 0);
+    let gone_id = JMAPId::from_parts(1, 1);
+    push_tx
+        .send(Event::Update {
+            updates: vec![
+                PushUpdate::Register {
+                    id: retry_id,
+                    url: "http://127.0.0.1:9001/retry".to_string(),
+                    keys: None,
+                },
+                PushUpdate::Register {
+                    id: gone_id,
+                    url: "http://127.0.0.1:9001/gone".to_string(),
+                    keys: None,
+                },
+            ],
+        })
+        .await
+        .unwrap();
+
+    // Two failed deliveries are retried with an increasing delay
+    push(&push_tx, retry_id, 1).await;
+    let first_attempt = expect_request(&mut event_rx).await;
+    let second_attempt = expect_request(&mut event_rx).await;
+    let third_attempt = expect_request(&mut event_rx).await;
+    let first_delay = second_attempt - first_attempt;
+    let second_delay = third_attempt - second_attempt;
+    assert!(
+        first_delay >= Duration::from_millis(200),
+        "{:?}",
+        first_delay
+    );
+    assert!(
+        second_delay >= Duration::from_millis(400),
+        "{:?}",
+        second_delay
+    );
+    assert!(second_delay > first_delay);
+
+    // The third attempt succeeded, nothing else should be sent
+    expect_nothing(&mut event_rx).await;
+
+    // A successful delivery resets the backoff
+    push_server.num_failures.store(1, Ordering::Relaxed);
+    push(&push_tx, retry_id, 2).await;
+    let first_attempt = expect_request(&mut event_rx).await;
+    let second_attempt = expect_request(&mut event_rx).await;
+    let delay = second_attempt - first_attempt;
+    assert!(
+        delay >= Duration::from_millis(200) && delay < Duration::from_millis(400),
+        "{:?}",
+        delay
+    );
+    expect_nothing(&mut event_rx).await;
+
+    // Subscriptions pointing to a gone endpoint are destroyed
+    push(&push_tx, gone_id, 3).await;
+    expect_request(&mut event_rx).await;
+    expect_request(&mut event_rx).await;
+    match tokio::time::timeout(Duration::from_millis(1500), state_rx.recv()).await {
+        Ok(Some(state_change::Event::DestroySubscription { id })) => assert_eq!(id, gone_id),
+        result => panic!("Expected DestroySubscription event, got {:?}", result),
+    }
+
+    // Further changes are not delivered to the destroyed subscription
+    push(&push_tx, gone_id, 4).await;
+    expect_nothing(&mut event_rx).await;
+
+    // Subscriptions that are not verified in time are reaped
+    let unverified_id = JMAPId::from_parts(2, 0);
+    let verified_id = JMAPId::from_parts(3, 0);
+    push_tx
+        .send(Event::Update {
+            updates: [unverified_id, verified_id]
+                .into_iter()
+                .map(|id| PushUpdate::Verify {
+                    id: id.get_document_id(),
+                    account_id: id.get_prefix_id(),
+                    url: "http://127.0.0.1:9001/retry".to_string(),
+                    code: "1234".to_string(),
+                    keys: None,
+                })
+                .collect(),
+        })
+        .await
+        .unwrap();
+    expect_request(&mut event_rx).await;
+    expect_request(&mut event_rx).await;
+    push_tx
+        .send(Event::Update {
+            updates: vec![PushUpdate::Register {
+                id: verified_id,
+                url: "http://127.0.0.1:9001/retry".to_string(),
+                keys: None,
+            }],
+        })
+        .await
+        .unwrap();
+    match tokio::time::timeout(Duration::from_millis(1500), state_rx.recv()).await {
+        Ok(Some(state_change::Event::ReapSubscription { id })) => assert_eq!(id, unverified_id),
+        result => panic!("Expected ReapSubscription event, got {:?}", result),
+    }
+    match tokio::time::timeout(Duration::from_millis(1000), state_rx.recv()).await {
+        Err(_) => (),
+        result => panic!("Expected no further events, got {:?}", result),
+    }
+}
+
+struct PushServer {
+    tx: mpsc::Sender<Instant>,
+    num_failures: AtomicU32,
+}
+
+async fn handle_retry(data: web::Data<PushServer>) -> HttpResponse {
+    data.tx.send(Instant::now()).await.unwrap();
+
+    if data
+        .num_failures
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+        .is_ok()
+    {
+        HttpResponse::ServiceUnavailable().finish()
+    } else {
+        HttpResponse::Ok().finish()
+    }
+}
+
+async fn handle_gone(data: web::Data<PushServer>) -> HttpResponse {
+    data.tx.send(Instant::now()).await.unwrap();
+    HttpResponse::Gone().finish()
+}
+
+async fn push(push_tx: &mpsc::Sender<Event>, id: JMAPId, change_id: u64) {
+    push_tx
+        .send(Event::Push {
+            ids: vec![id],
+            state_change: StateChange::new(1, vec![(TypeState::Mailbox, change_id)]),
+        })
+        .await
+        .unwrap();
+}
+
+async fn expect_request(event_rx: &mut mpsc::Receiver<Instant>) -> Instant {
+    match tokio::time::timeout(Duration::from_millis(1500), event_rx.recv()).await {
+        Ok(Some(instant)) => instant,
+        result => {
+            panic!("Timeout waiting for push: {:?}", result);
+        }
+    }
+}
+
+async fn expect_nothing(event_rx: &mut mpsc::Receiver<Instant>) {
+    match tokio::time::timeout(Duration::from_millis(1000), event_rx.recv()).await {
+        Err(_) => {}
+        message => {
+            panic!("Received a message when expecting nothing: {:?}", message);
+        }
+    }
+}
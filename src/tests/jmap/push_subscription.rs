@@ -29,11 +29,12 @@ use std::{
 
 use actix_web::{middleware, web, App, HttpRequest, HttpResponse, HttpServer};
 use ece::EcKeyComponents;
-use jmap::{
-    base64,
-    types::{jmap::JMAPId, type_state::TypeState},
+use jmap::types::{jmap::JMAPId, type_state::TypeState};
+use jmap_client::{
+    client::Client,
+    mailbox::Role,
+    push_subscription::{self, Keys},
 };
-use jmap_client::{client::Client, mailbox::Role, push_subscription::Keys};
 use reqwest::header::CONTENT_ENCODING;
 use store::{ahash::AHashSet, Store};
 use tokio::sync::mpsc;
@@ -98,20 +99,31 @@ where
     let verification = expect_push(&mut event_rx).await.unwrap_verification();
     assert_eq!(verification.push_subscription_id, push_id);
 
-    // Update verification code
-    client
-        .push_subscription_verify(&push_id, verification.verification_code)
+    // Wrong verification codes are rejected and nothing is delivered
+    // to an unverified subscription
+    assert!(client
+        .push_subscription_verify(&push_id, "wrong_code")
         .await
-        .unwrap();
-
-    // Create a mailbox and expect a state change
+        .is_err());
     let mailbox_id = client
         .set_default_account_id(JMAPId::new(1).to_string())
         .mailbox_create("PushSubscription Test", None::<String>, Role::None)
         .await
         .unwrap()
         .take_id();
+    expect_nothing(&mut event_rx).await;
+
+    // Update verification code
+    client
+        .push_subscription_verify(&push_id, verification.verification_code)
+        .await
+        .unwrap();
 
+    // Update the mailbox and expect a state change
+    client
+        .mailbox_update_sort_order(&mailbox_id, 1)
+        .await
+        .unwrap();
     assert_state(&mut event_rx, &[TypeState::Mailbox]).await;
 
     // Receive states just for the requested types
@@ -152,6 +164,31 @@ where
     let verification = expect_push(&mut event_rx).await.unwrap_verification();
     assert_eq!(verification.push_subscription_id, push_id);
 
+    // Encryption keys should not be returned
+    let mut request = client.build();
+    request.get_push_subscription().ids([&push_id]).properties([
+        push_subscription::Property::Id,
+        push_subscription::Property::DeviceClientId,
+        push_subscription::Property::Url,
+        push_subscription::Property::Keys,
+        push_subscription::Property::Types,
+        push_subscription::Property::Expires,
+    ]);
+    let push_subscription = request
+        .send_get_push_subscription()
+        .await
+        .unwrap()
+        .take_list()
+        .pop()
+        .unwrap();
+    assert_eq!(push_subscription.id().unwrap(), push_id);
+    assert_eq!(push_subscription.device_client_id().unwrap(), "123");
+    assert_eq!(
+        push_subscription.url().unwrap(),
+        "https://127.0.0.1:9000/push?skip_checks=true"
+    );
+    assert!(push_subscription.keys().is_none());
+
     // Update verification code
     client
         .push_subscription_verify(&push_id, verification.verification_code)
@@ -253,12 +290,7 @@ async fn handle_push(
         .map_or(false, |encoding| encoding.to_str().unwrap() == "aes128gcm");
 
     let message = serde_json::from_slice::<PushMessage>(&if is_encrypted {
-        ece::decrypt(
-            &data.keypair,
-            &data.auth_secret,
-            &base64::decode_config(payload, base64::URL_SAFE).unwrap(),
-        )
-        .unwrap()
+        ece::decrypt(&data.keypair, &data.auth_secret, &payload).unwrap()
     } else {
         payload.to_vec()
     })
@@ -0,0 +1,227 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::{Duration, SystemTime};
+
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use ece::EcKeyComponents;
+use jmap::{
+    base64,
+    types::{jmap::JMAPId, state::JMAPState, type_state::TypeState},
+};
+use p256::{
+    ecdsa::{signature::Verifier, Signature, VerifyingKey},
+    elliptic_curve::rand_core::OsRng,
+    SecretKey,
+};
+use reqwest::header::{AUTHORIZATION, CONTENT_ENCODING};
+use store::{ahash::AHashMap, config::env_settings::EnvSettings, core::JMAPIdPrefix};
+use tokio::sync::mpsc;
+
+use crate::{
+    api::StateChangeResponse,
+    services::{
+        push_subscription::{spawn_push_manager, EncriptionKeys, Event, PushUpdate},
+        state_change::{self, StateChange},
+    },
+};
+
+pub async fn test() {
+    println!("Running VAPID push tests...");
+
+    // Create subscription keys
+    let (keypair, auth_secret) = ece::generate_keypair_and_auth_secret().unwrap();
+    let keys = EncriptionKeys {
+        p256dh: keypair.pub_as_raw().unwrap(),
+        auth: auth_secret.to_vec(),
+    };
+
+    // Start mock push server
+    let (event_tx, mut event_rx) = mpsc::channel::<StateChangeResponse>(100);
+    let data = web::Data::new(PushServer {
+        keypair: keypair.raw_components().unwrap(),
+        auth_secret: auth_secret.to_vec(),
+        tx: event_tx,
+    });
+    actix_web::rt::spawn(async move {
+        HttpServer::new(move || {
+            App::new()
+                .app_data(data.clone())
+                .route("/push", web::post().to(handle_push))
+                .route("/gone", web::post().to(handle_gone))
+        })
+        .bind("127.0.0.1:9002")?
+        .run()
+        .await
+    });
+
+    // Start push manager with a VAPID key
+    let vapid_key = SecretKey::random(&mut OsRng);
+    let settings = EnvSettings {
+        args: AHashMap::from_iter(
+            [
+                (
+                    "push-vapid-key".to_string(),
+                    base64::encode_config(vapid_key.to_be_bytes(), base64::URL_SAFE_NO_PAD),
+                ),
+                (
+                    "push-vapid-subject".to_string(),
+                    "mailto:admin@example.org".to_string(),
+                ),
+                ("push-throttle".to_string(), "100".to_string()),
+                ("push-gone-max".to_string(), "1".to_string()),
+            ]
+            .into_iter(),
+        ),
+    };
+    let (state_tx, mut state_rx) = mpsc::channel::<state_change::Event>(100);
+    let push_tx = spawn_push_manager(&settings, state_tx);
+
+    let push_id = store::JMAPId::from_parts(1, 0);
+    let gone_id = store::JMAPId::from_parts(1, 1);
+    push_tx
+        .send(Event::Update {
+            updates: vec![
+                PushUpdate::Register {
+                    id: push_id,
+                    url: "http://127.0.0.1:9002/push".to_string(),
+                    keys: keys.clone().into(),
+                },
+                PushUpdate::Register {
+                    id: gone_id,
+                    url: "http://127.0.0.1:9002/gone".to_string(),
+                    keys: keys.into(),
+                },
+            ],
+        })
+        .await
+        .unwrap();
+
+    // The signed and encrypted payload decrypts to the expected StateChange
+    push_tx
+        .send(Event::Push {
+            ids: vec![push_id],
+            state_change: StateChange::new(1, vec![(TypeState::Email, 7)]),
+        })
+        .await
+        .unwrap();
+    let state_change =
+        match tokio::time::timeout(Duration::from_millis(1500), event_rx.recv()).await {
+            Ok(Some(state_change)) => state_change,
+            result => panic!("Timeout waiting for push: {:?}", result),
+        };
+    let changed = state_change.changed.get(&JMAPId::new(1)).unwrap();
+    assert_eq!(changed.len(), 1);
+    assert_eq!(changed.get(&TypeState::Email), Some(&JMAPState::from(7)));
+
+    // A 410 response destroys the subscription
+    push_tx
+        .send(Event::Push {
+            ids: vec![gone_id],
+            state_change: StateChange::new(1, vec![(TypeState::Email, 8)]),
+        })
+        .await
+        .unwrap();
+    match tokio::time::timeout(Duration::from_millis(1500), state_rx.recv()).await {
+        Ok(Some(state_change::Event::DestroySubscription { id })) => assert_eq!(id, gone_id),
+        result => panic!("Expected DestroySubscription event, got {:?}", result),
+    }
+}
+
+struct PushServer {
+    keypair: EcKeyComponents,
+    auth_secret: Vec<u8>,
+    tx: mpsc::Sender<StateChangeResponse>,
+}
+
+async fn handle_push(
+    payload: web::Bytes,
+    request: HttpRequest,
+    data: web::Data<PushServer>,
+) -> HttpResponse {
+    assert_vapid(&request, "http://127.0.0.1:9002");
+    assert_eq!(
+        request.headers().get(CONTENT_ENCODING).unwrap(),
+        "aes128gcm"
+    );
+
+    let state_change = serde_json::from_slice::<StateChangeResponse>(
+        &ece::decrypt(&data.keypair, &data.auth_secret, &payload).unwrap(),
+    )
+    .unwrap();
+    data.tx.send(state_change).await.unwrap();
+
+    HttpResponse::Created().finish()
+}
+
+async fn handle_gone(request: HttpRequest) -> HttpResponse {
+    assert_vapid(&request, "http://127.0.0.1:9002");
+    HttpResponse::Gone().finish()
+}
+
+fn assert_vapid(request: &HttpRequest, audience: &str) {
+    let authorization = request
+        .headers()
+        .get(AUTHORIZATION)
+        .unwrap()
+        .to_str()
+        .unwrap();
+    let (token, public_key) = authorization
+        .strip_prefix("vapid t=")
+        .and_then(|params| params.split_once(", k="))
+        .unwrap();
+
+    // Verify the ES256 signature using the advertised public key
+    let (signed_data, signature) = token.rsplit_once('.').unwrap();
+    let public_key = VerifyingKey::from_sec1_bytes(
+        &base64::decode_config(public_key, base64::URL_SAFE_NO_PAD).unwrap(),
+    )
+    .unwrap();
+    let signature = Signature::try_from(
+        base64::decode_config(signature, base64::URL_SAFE_NO_PAD)
+            .unwrap()
+            .as_slice(),
+    )
+    .unwrap();
+    public_key
+        .verify(signed_data.as_bytes(), &signature)
+        .unwrap();
+
+    // Validate claims
+    let claims: serde_json::Value = serde_json::from_slice(
+        &base64::decode_config(
+            signed_data.split_once('.').unwrap().1,
+            base64::URL_SAFE_NO_PAD,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let expires = claims["exp"].as_u64().unwrap();
+    assert_eq!(claims["aud"], audience);
+    assert_eq!(claims["sub"], "mailto:admin@example.org");
+    assert!(expires > now && expires <= now + 86400, "{}", expires);
+}
@@ -21,7 +21,12 @@
  * for more details.
 */
 
-use jmap::{error::method::MethodError, jmap_store::get::GetObject, types::jmap::JMAPId};
+use jmap::{
+    error::method::MethodError,
+    jmap_store::get::GetObject,
+    request::query::QueryResponse,
+    types::{jmap::JMAPId, state::JMAPState},
+};
 use jmap_mail::mailbox::schema::Property;
 use store::ahash::AHashMap;
 
@@ -159,14 +164,14 @@ fn map_sort_created_ids() {
     )
     .unwrap();
 
-    let response = Response::new(
+    let mut response = Response::new(
         1234,
         request.created_ids.unwrap_or_default(),
         request.method_calls.len(),
     );
 
     for (test_num, mut call) in request.method_calls.into_iter().enumerate() {
-        match call.method.prepare_request(&response) {
+        match call.method.prepare_request(&mut response) {
             Ok(_) => assert!(
                 (0..3).contains(&test_num),
                 "Unexpected invocation {}",
@@ -306,7 +311,7 @@ fn map_sort_created_ids() {
 
     let mut invocations = request.method_calls.into_iter();
     let mut call = invocations.next().unwrap();
-    call.method.prepare_request(&response).unwrap();
+    call.method.prepare_request(&mut response).unwrap();
 
     if let method::Request::SetMailbox(request) = call.method {
         let create = request
@@ -327,7 +332,7 @@ fn map_sort_created_ids() {
     response.created_ids.insert("c".to_string(), JMAPId::new(7));
 
     let mut call = invocations.next().unwrap();
-    call.method.prepare_request(&response).unwrap();
+    call.method.prepare_request(&mut response).unwrap();
 
     if let method::Request::SetMailbox(request) = call.method {
         let create = request
@@ -343,3 +348,113 @@ fn map_sort_created_ids() {
         panic!("Expected Mailbox Set Request");
     }
 }
+
+#[test]
+fn result_reference_limits() {
+    let request = serde_json::from_slice::<Request>(
+        br##"{
+            "using": [
+                "urn:ietf:params:jmap:core",
+                "urn:ietf:params:jmap:mail"
+            ],
+            "methodCalls": [
+                [
+                    "Mailbox/get",
+                    {
+                        "accountId": "b",
+                        "#ids": {
+                            "resultOf": "q",
+                            "name": "Mailbox/query",
+                            "path": "/ids"
+                        }
+                    },
+                    "shallow"
+                ],
+                [
+                    "Mailbox/get",
+                    {
+                        "accountId": "b",
+                        "#ids": {
+                            "resultOf": "q",
+                            "name": "Mailbox/query",
+                            "path": "/ids/*/a/b/c/d/e/f/g/h"
+                        }
+                    },
+                    "deep"
+                ],
+                [
+                    "Mailbox/get",
+                    {
+                        "accountId": "b",
+                        "#ids": {
+                            "resultOf": "q",
+                            "name": "Mailbox/query",
+                            "path": "/ids/*"
+                        }
+                    },
+                    "last"
+                ],
+                [
+                    "Mailbox/get",
+                    {
+                        "accountId": "b",
+                        "#ids": {
+                            "resultOf": "q",
+                            "name": "Mailbox/query",
+                            "path": "/ids"
+                        }
+                    },
+                    "exceeded"
+                ]
+            ]
+        }"##,
+    )
+    .unwrap();
+
+    let mut response = Response::new(
+        1234,
+        request.created_ids.unwrap_or_default(),
+        request.method_calls.len(),
+    )
+    .with_result_ref_limits(3, 2);
+    response.push_response(
+        "q".to_string(),
+        method::Response::QueryMailbox(QueryResponse {
+            account_id: JMAPId::new(1),
+            query_state: JMAPState::Initial,
+            can_calculate_changes: true,
+            position: 0,
+            ids: vec![JMAPId::new(1), JMAPId::new(2)],
+            total: None,
+            limit: None,
+            next_cursor: None,
+            is_immutable: false,
+        }),
+    );
+
+    for mut call in request.method_calls {
+        let result = call.method.prepare_request(&mut response);
+        match call.id.as_str() {
+            "shallow" | "last" => {
+                result.unwrap();
+                if let method::Request::GetMailbox(request) = call.method {
+                    assert_eq!(
+                        request.ids.unwrap().unwrap_value().unwrap(),
+                        vec![JMAPId::new(1), JMAPId::new(2)]
+                    );
+                } else {
+                    panic!("Expected Mailbox Get Request");
+                }
+            }
+            "deep" | "exceeded" => {
+                assert!(
+                    matches!(result, Err(MethodError::InvalidResultReference(_))),
+                    "{}: {:?}",
+                    call.id,
+                    result
+                );
+            }
+            _ => unreachable!(),
+        }
+    }
+}
@@ -25,7 +25,10 @@ use std::time::Duration;
 
 use actix_web::web;
 use futures::StreamExt;
-use jmap::types::jmap::JMAPId;
+use jmap::{
+    jmap_store::changes::JMAPChanges,
+    types::{jmap::JMAPId, state::JMAPState, type_state::TypeState as StoreTypeState},
+};
 use jmap_client::{
     client::Client,
     client_ws::WebSocketMessage,
@@ -35,10 +38,12 @@ use jmap_client::{
     },
     TypeState,
 };
-use store::{ahash::AHashSet, Store};
+use store::{ahash::AHashSet, core::collection::Collection, Store};
 use tokio::sync::mpsc;
 
-use crate::{tests::store::utils::StoreCompareWith, JMAPServer};
+use crate::{
+    server::websocket::WebSocketPushState, tests::store::utils::StoreCompareWith, JMAPServer,
+};
 
 pub async fn test<T>(server: web::Data<JMAPServer<T>>, client: &mut Client)
 where
@@ -101,6 +106,68 @@ where
     assert_state(&mut stream_rx, &[TypeState::Mailbox]).await;
     expect_nothing(&mut stream_rx).await;
 
+    // A burst of changes is coalesced and the last notification carries the latest state
+    for num in 0..20 {
+        client
+            .mailbox_update_sort_order(&mailbox_id, num)
+            .await
+            .unwrap();
+    }
+    let states = collect_mailbox_states(&mut stream_rx).await;
+    assert!(
+        !states.is_empty() && states.len() <= 3,
+        "Expected coalesced notifications, got {:?}",
+        states
+    );
+    assert_eq!(
+        states.last().unwrap(),
+        &server
+            .store
+            .get_state(1, Collection::Mailbox)
+            .unwrap()
+            .to_string()
+    );
+
+    // Simulate a dropped connection and make changes while disconnected
+    let push_state = current_push_state(&server);
+    client.disable_push_ws().await.unwrap();
+    client
+        .mailbox_update_sort_order(&mailbox_id, 10)
+        .await
+        .unwrap();
+    expect_nothing(&mut stream_rx).await;
+
+    // Resuming from the last push state delivers the missed changes
+    client
+        .enable_push_ws(None::<Vec<_>>, Some(push_state.to_string()))
+        .await
+        .unwrap();
+    assert_state(&mut stream_rx, &[TypeState::Mailbox]).await;
+    expect_nothing(&mut stream_rx).await;
+
+    // Resuming from an up to date push state delivers nothing
+    client.disable_push_ws().await.unwrap();
+    client
+        .enable_push_ws(
+            None::<Vec<_>>,
+            Some(current_push_state(&server).to_string()),
+        )
+        .await
+        .unwrap();
+    expect_nothing(&mut stream_rx).await;
+
+    // Unknown states are reported as changed so the client can resync
+    let mut push_state = current_push_state(&server);
+    push_state
+        .states
+        .set(StoreTypeState::Mailbox, JMAPState::Exact(u32::MAX as u64));
+    client.disable_push_ws().await.unwrap();
+    client
+        .enable_push_ws(None::<Vec<_>>, Some(push_state.to_string()))
+        .await
+        .unwrap();
+    assert_state(&mut stream_rx, &[TypeState::Mailbox]).await;
+
     // Disable push notifications
     client.disable_push_ws().await.unwrap();
 
@@ -121,6 +188,25 @@ where
     server.store.assert_is_empty();
 }
 
+fn current_push_state<T>(server: &web::Data<JMAPServer<T>>) -> WebSocketPushState
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut push_state = WebSocketPushState::default();
+    for (type_state, collection) in [
+        (StoreTypeState::Email, Collection::Mail),
+        (StoreTypeState::EmailSubmission, Collection::EmailSubmission),
+        (StoreTypeState::Mailbox, Collection::Mailbox),
+        (StoreTypeState::Thread, Collection::Thread),
+        (StoreTypeState::Identity, Collection::Identity),
+    ] {
+        push_state
+            .states
+            .append(type_state, server.store.get_state(1, collection).unwrap());
+    }
+    push_state
+}
+
 async fn expect_response(
     stream_rx: &mut mpsc::Receiver<WebSocketMessage>,
 ) -> Response<TaggedMethodResponse> {
@@ -156,6 +242,27 @@ async fn assert_state(stream_rx: &mut mpsc::Receiver<WebSocketMessage>, state: &
     }
 }
 
+async fn collect_mailbox_states(stream_rx: &mut mpsc::Receiver<WebSocketMessage>) -> Vec<String> {
+    let mut states = Vec::new();
+    while let Ok(Some(message)) =
+        tokio::time::timeout(Duration::from_millis(1000), stream_rx.recv()).await
+    {
+        match message {
+            WebSocketMessage::StateChange(changes) => {
+                states.extend(
+                    changes
+                        .changes(&JMAPId::new(1).to_string())
+                        .unwrap()
+                        .filter(|x| x.0 == &TypeState::Mailbox)
+                        .map(|x| x.1.to_string()),
+                );
+            }
+            _ => panic!("Expected state change, got: {:?}", message),
+        }
+    }
+    states
+}
+
 async fn expect_nothing(stream_rx: &mut mpsc::Receiver<WebSocketMessage>) {
     match tokio::time::timeout(Duration::from_millis(1000), stream_rx.recv()).await {
         Err(_) => {}
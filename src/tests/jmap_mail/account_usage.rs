@@ -0,0 +1,165 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    jmap_store::Object,
+    request::{set::SetRequest, MaybeResultReference},
+    types::jmap::JMAPId,
+};
+use jmap_mail::mail::{
+    import::JMAPMailImport,
+    set::{JMAPSetMail, SetArguments},
+};
+use store::{
+    blob::{usage::AccountUsage, BlobId},
+    core::acl::ACLToken,
+    AccountId, DocumentId, JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running account storage usage tests...");
+
+    // Create two accounts with a mailbox each
+    let mut mailbox_ids = Vec::new();
+    for account_id in [1, 2] {
+        let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+        mailbox_ids.push(mailbox_id);
+        assert_eq!(db.account_usage(account_id).unwrap().blob_bytes, 0);
+    }
+
+    let message = concat!(
+        "From: sender@example.com\r\n",
+        "Subject: Usage\r\n\r\n",
+        "A message body that takes some room in the blob store.\r\n"
+    )
+    .as_bytes()
+    .to_vec();
+    let blob_id = BlobId::new_external(&message);
+    db.blob_store(&blob_id, message.clone()).unwrap();
+
+    // Importing a message accounts for its blobs and index entries
+    let first_id = import_message(&db, 1, mailbox_ids[0], &blob_id, &message, 1000);
+    let usage = db.account_usage(1).unwrap();
+    assert!(usage.blob_bytes >= message.len() as u64, "{:?}", usage);
+    assert!(usage.index_bytes > 0, "{:?}", usage);
+    assert_eq!(usage.shared_blob_bytes, 0);
+
+    // A second copy of the same message links the same blobs, which are counted once
+    let second_id = import_message(&db, 1, mailbox_ids[0], &blob_id, &message, 1000);
+    let usage_copy = db.account_usage(1).unwrap();
+    assert_eq!(usage_copy.blob_bytes, usage.blob_bytes);
+    assert_eq!(usage_copy.shared_blob_bytes, 0);
+    assert!(usage_copy.index_bytes > usage.index_bytes);
+
+    // Blobs also linked by another account are reported as shared by both
+    import_message(&db, 2, mailbox_ids[1], &blob_id, &message, 2000);
+    let usage_shared = db.account_usage(1).unwrap();
+    let other_usage = db.account_usage(2).unwrap();
+    assert_eq!(usage_shared.blob_bytes, usage.blob_bytes);
+    assert!(
+        usage_shared.shared_blob_bytes >= message.len() as u64,
+        "{:?}",
+        usage_shared
+    );
+    assert!(usage_shared.shared_blob_bytes < usage_shared.blob_bytes);
+    assert_eq!(
+        other_usage.shared_blob_bytes,
+        usage_shared.shared_blob_bytes
+    );
+
+    // Destroying the messages releases their blobs
+    destroy_messages(&db, 1, vec![first_id]);
+    assert_eq!(
+        db.account_usage(1).unwrap().blob_bytes,
+        usage.blob_bytes,
+        "the second copy still links every blob"
+    );
+    destroy_messages(&db, 1, vec![second_id]);
+    let usage_empty = db.account_usage(1).unwrap();
+    assert_eq!(
+        usage_empty,
+        AccountUsage {
+            blob_bytes: 0,
+            shared_blob_bytes: 0,
+            index_bytes: usage_empty.index_bytes,
+        }
+    );
+    assert_eq!(db.account_usage(2).unwrap().shared_blob_bytes, 0);
+    assert_eq!(
+        db.account_usage(2).unwrap().blob_bytes,
+        other_usage.blob_bytes
+    );
+}
+
+fn import_message<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    mailbox_id: DocumentId,
+    blob_id: &BlobId,
+    message: &[u8],
+    received_at: i64,
+) -> JMAPId
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    *db.mail_import_item(
+        account_id,
+        blob_id.clone(),
+        message,
+        vec![mailbox_id],
+        vec![],
+        received_at.into(),
+    )
+    .unwrap()
+    .id()
+    .unwrap()
+}
+
+fn destroy_messages<T>(db: &JMAPStore<T>, account_id: AccountId, ids: Vec<JMAPId>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let response = db
+        .mail_set(SetRequest {
+            acl: Arc::new(ACLToken {
+                member_of: vec![account_id],
+                access_to: vec![],
+            })
+            .into(),
+            account_id: JMAPId::new(account_id as u64),
+            if_in_state: None,
+            create: None,
+            update: None,
+            destroy: MaybeResultReference::Value(ids.clone()).into(),
+            arguments: SetArguments::default(),
+        })
+        .unwrap();
+    assert_eq!(response.destroyed, ids);
+}
@@ -0,0 +1,177 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    jmap_store::Object,
+    request::{set::SetRequest, MaybeResultReference},
+    types::jmap::JMAPId,
+    SUPERUSER_ID,
+};
+use jmap_mail::{
+    mail::{
+        import::JMAPMailImport,
+        set::{JMAPSetMail, SetArguments},
+    },
+    mailbox::schema::Mailbox,
+};
+use store::{
+    blob::BlobId,
+    core::{acl::ACLToken, collection::Collection, document::Document},
+    write::batch::WriteBatch,
+    AccountId, DocumentId, JMAPStore, Store,
+};
+
+use crate::{server::http::create_admin_account, tests::jmap_mail::create_mailbox};
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running changelog compaction verification tests...");
+
+    // The administrator account is logged like any other principal
+    create_admin_account(&db, "secret");
+    assert_eq!(db.verify_changelogs().unwrap(), vec![]);
+
+    // Create two accounts with a mailbox each
+    let mut batch = WriteBatch::new(SUPERUSER_ID);
+    for account_id in [1, 2] {
+        batch.log_insert(Collection::Principal, account_id);
+        batch.insert_document(Document::new(Collection::Principal, account_id));
+    }
+    db.write(batch).unwrap();
+    let mailbox_id = create_mailbox(&db, 1, "Inbox", "inbox");
+    let other_mailbox_id = create_mailbox(&db, 2, "Inbox", "inbox");
+
+    // Import ten messages and destroy half of them
+    let ids = (0..10)
+        .map(|num| import_message(&db, 1, mailbox_id, num))
+        .collect::<Vec<_>>();
+    let destroy_ids = ids.iter().step_by(2).cloned().collect::<Vec<_>>();
+    let response = db
+        .mail_set(SetRequest {
+            acl: Arc::new(ACLToken {
+                member_of: vec![1],
+                access_to: vec![],
+            })
+            .into(),
+            account_id: JMAPId::new(1),
+            if_in_state: None,
+            create: None,
+            update: None,
+            destroy: MaybeResultReference::Value(destroy_ids.clone()).into(),
+            arguments: SetArguments::default(),
+        })
+        .unwrap();
+    assert_eq!(response.destroyed, destroy_ids);
+    assert_changelog_matches(&db);
+
+    // Compact up to the last destroyed message
+    let up_to = db.get_last_change_id(1, Collection::Mail).unwrap().unwrap();
+    db.compact_log_up_to(up_to).unwrap();
+
+    // The snapshots hold exactly the live documents
+    assert_changelog_matches(&db);
+    assert_eq!(
+        db.get_changelog_document_ids(1, Collection::Mail).unwrap(),
+        ids.iter()
+            .skip(1)
+            .step_by(2)
+            .map(|id| id.get_document_id())
+            .collect()
+    );
+    assert_eq!(
+        db.get_changelog_document_ids(2, Collection::Mailbox)
+            .unwrap(),
+        [other_mailbox_id].into_iter().collect()
+    );
+
+    // Changes after the compaction are replayed on top of the snapshot
+    let id = import_message(&db, 1, mailbox_id, 11);
+    assert!(db
+        .get_changelog_document_ids(1, Collection::Mail)
+        .unwrap()
+        .contains(id.get_document_id()));
+    assert_changelog_matches(&db);
+
+    // The first message of the second account is the last change compacted,
+    // so its collection has no older entries to fold into a snapshot and its
+    // inserts must not leak into the snapshot of the next collection
+    import_message(&db, 2, other_mailbox_id, 12);
+    let up_to = db.get_last_change_id(2, Collection::Mail).unwrap().unwrap();
+    db.compact_log_up_to(up_to).unwrap();
+    assert_changelog_matches(&db);
+    assert_eq!(
+        db.get_changelog_document_ids(2, Collection::Mailbox)
+            .unwrap(),
+        [other_mailbox_id].into_iter().collect()
+    );
+}
+
+fn assert_changelog_matches<T>(db: &JMAPStore<T>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    for account_id in [1, 2] {
+        for collection in [Collection::Mail, Collection::Mailbox] {
+            db.verify_changelog(account_id, collection).unwrap();
+        }
+    }
+    assert_eq!(db.verify_changelogs().unwrap(), vec![]);
+}
+
+fn import_message<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    mailbox_id: DocumentId,
+    num: usize,
+) -> JMAPId
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let message = format!(
+        concat!(
+            "From: sender@example.com\r\n",
+            "Message-ID: <compact-{}@example.com>\r\n",
+            "Subject: Message {}\r\n\r\n",
+            "Body of message {}.\r\n"
+        ),
+        num, num, num
+    )
+    .into_bytes();
+    let blob_id = BlobId::new_external(&message);
+    db.blob_store(&blob_id, message.clone()).unwrap();
+    *db.mail_import_item(
+        account_id,
+        blob_id,
+        &message,
+        vec![mailbox_id],
+        vec![],
+        None,
+    )
+    .unwrap()
+    .id()
+    .unwrap()
+}
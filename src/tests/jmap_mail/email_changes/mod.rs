@@ -21,6 +21,8 @@
  * for more details.
 */
 
+pub mod compaction;
+
 use actix_web::web;
 
 use jmap::types::jmap::JMAPId;
@@ -287,6 +289,40 @@ where
     );
     assert_eq!(changes.updated(), Vec::<String>::new());
     assert_eq!(changes.destroyed(), Vec::<String>::new());
+
+    // Messages created and destroyed within the queried range should not be reported
+    let state = changes.new_state().to_string();
+    for actions in [
+        vec![LogAction::Insert(13)],
+        vec![LogAction::Update(13), LogAction::Insert(14)],
+        vec![LogAction::UpdateChild(14)],
+        vec![LogAction::Delete(13), LogAction::Delete(14)],
+    ] {
+        let mut documents = WriteBatch::new(1);
+        for action in actions {
+            match action {
+                LogAction::Insert(id) => documents.log_insert(Collection::Mail, id),
+                LogAction::Update(id) => documents.log_update(Collection::Mail, id),
+                LogAction::Delete(id) => documents.log_delete(Collection::Mail, id),
+                LogAction::UpdateChild(id) => documents.log_child_update(Collection::Mail, id),
+                LogAction::Move(old_id, new_id) => {
+                    documents.log_move(Collection::Mail, old_id, new_id)
+                }
+            }
+        }
+        server.store.write(documents).unwrap();
+    }
+    for max_changes in [0, 1] {
+        let changes = client
+            .email_changes(state.clone(), max_changes.into())
+            .await
+            .unwrap();
+        assert_eq!(changes.created(), Vec::<String>::new());
+        assert_eq!(changes.updated(), Vec::<String>::new());
+        assert_eq!(changes.destroyed(), Vec::<String>::new());
+        assert!(!changes.has_more_changes());
+        assert_ne!(changes.new_state(), state);
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
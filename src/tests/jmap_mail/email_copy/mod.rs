@@ -21,6 +21,8 @@
  * for more details.
 */
 
+pub mod state;
+
 use actix_web::web;
 use jmap::types::jmap::JMAPId;
 use jmap_client::{client::Client, mailbox::Role};
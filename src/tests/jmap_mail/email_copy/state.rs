@@ -0,0 +1,167 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    error::{method::MethodError, set::SetErrorType},
+    jmap_store::{changes::JMAPChanges, Object},
+    orm::serialize::JMAPOrm,
+    request::{copy::CopyRequest, MaybeIdReference},
+    types::jmap::JMAPId,
+};
+use jmap_mail::mail::{
+    copy::JMAPCopyMail,
+    import::JMAPMailImport,
+    schema::{Email, Keyword, Property},
+};
+use store::{
+    blob::BlobId,
+    core::{acl::ACLToken, collection::Collection, tag::Tag},
+    AccountId, DocumentId, JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email/copy state tests...");
+
+    // Create two accounts with one mailbox each
+    let from_mailbox_id = create_account_mailbox(&db, 1, "Inbox", "inbox");
+    let to_mailbox_id = create_account_mailbox(&db, 2, "Inbox", "inbox");
+
+    let email_id = import_message(&db, from_mailbox_id, "First message");
+    let stale_state = db.get_state(1, Collection::Mail).unwrap();
+    import_message(&db, from_mailbox_id, "Second message");
+    let current_state = db.get_state(1, Collection::Mail).unwrap();
+    assert_ne!(stale_state, current_state);
+
+    // A stale ifFromInState must be rejected without copying anything
+    assert!(matches!(
+        db.mail_copy(copy_request(
+            email_id,
+            to_mailbox_id,
+            &serde_json::to_string(&stale_state).unwrap()
+        )),
+        Err(MethodError::StateMismatch)
+    ));
+    assert!(db
+        .get_document_ids(2, Collection::Mail)
+        .unwrap()
+        .unwrap_or_default()
+        .is_empty());
+
+    // Copy with the current state, missing source ids are reported as notFound
+    let missing_id = JMAPId::from_parts(0, 1000);
+    let mut request = copy_request(
+        email_id,
+        to_mailbox_id,
+        &serde_json::to_string(&current_state).unwrap(),
+    );
+    let item = request.create.values().next().unwrap().clone();
+    request
+        .create
+        .append(MaybeIdReference::Value(missing_id), item);
+    let response = db.mail_copy(request).unwrap();
+    assert_eq!(response.created.len(), 1, "{:?}", response);
+    assert!(matches!(
+        response.not_created.get(&missing_id).unwrap().type_,
+        SetErrorType::NotFound
+    ));
+
+    // Mailboxes and keywords are taken from the request
+    let copy = db
+        .get_orm::<Email>(
+            2,
+            response
+                .created
+                .get(&email_id)
+                .unwrap()
+                .id()
+                .unwrap()
+                .get_document_id(),
+        )
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        copy.get_tags(&Property::MailboxIds)
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>(),
+        vec![Tag::Id(to_mailbox_id)]
+    );
+    assert_eq!(
+        copy.get_tags(&Property::Keywords)
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>(),
+        vec![Tag::Static(Keyword::FLAGGED)]
+    );
+}
+
+fn copy_request(email_id: JMAPId, mailbox_id: DocumentId, state: &str) -> CopyRequest<Email> {
+    let mut request: CopyRequest<Email> = serde_json::from_str(&format!(
+        concat!(
+            "{{\"fromAccountId\": \"{}\", \"ifFromInState\": {}, \"accountId\": \"{}\", ",
+            "\"create\": {{\"{}\": {{\"mailboxIds\": {{\"{}\": true}}, ",
+            "\"keywords\": {{\"$flagged\": true}}}}}}}}"
+        ),
+        JMAPId::new(1),
+        state,
+        JMAPId::new(2),
+        email_id,
+        JMAPId::from(mailbox_id)
+    ))
+    .unwrap();
+    request.acl = acl(&[1, 2]).into();
+    request
+}
+
+fn import_message<T>(db: &JMAPStore<T>, mailbox_id: DocumentId, subject: &str) -> JMAPId
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let message = format!(
+        "From: sender@example.com\r\nSubject: {}\r\n\r\nCopy me.\r\n",
+        subject
+    )
+    .into_bytes();
+    let blob_id = BlobId::new_external(&message);
+    db.blob_store(&blob_id, message.clone()).unwrap();
+    *db.mail_import_item(1, blob_id, &message, vec![mailbox_id], vec![], None)
+        .unwrap()
+        .id()
+        .unwrap()
+}
+
+fn acl(member_of: &[AccountId]) -> Arc<ACLToken> {
+    Arc::new(ACLToken {
+        member_of: member_of.to_vec(),
+        access_to: vec![],
+    })
+}
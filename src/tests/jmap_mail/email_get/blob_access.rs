@@ -0,0 +1,106 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use std::sync::Arc;
+
+use jmap::{types::blob::JMAPBlob, SUPERUSER_ID};
+use jmap_mail::mail::{
+    get::{BlobResult, JMAPGetMail},
+    import::JMAPMailImport,
+};
+use store::{
+    blob::BlobId,
+    core::{acl::ACLToken, collection::Collection, document::Document},
+    write::batch::WriteBatch,
+    AccountId, JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::create_mailbox;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running blob access tests...");
+    let owner_id: AccountId = 1;
+    let other_id: AccountId = 2;
+
+    // Create accounts
+    let mut batch = WriteBatch::new(SUPERUSER_ID);
+    batch.insert_document(Document::new(Collection::Principal, owner_id));
+    batch.insert_document(Document::new(Collection::Principal, other_id));
+    db.write(batch).unwrap();
+
+    // Create an Inbox for the owner
+    let inbox_id = create_mailbox(&db, owner_id, "Inbox", "inbox");
+
+    // Import a message into the owner's account
+    let message = concat!(
+        "From: john@example.com\r\n",
+        "Subject: Private\r\n\r\n",
+        "This message is only readable by its owner.\r\n"
+    )
+    .as_bytes()
+    .to_vec();
+    let blob_id = BlobId::new_external(&message);
+    db.blob_store(&blob_id, message.clone()).unwrap();
+    db.mail_import_item(
+        owner_id,
+        blob_id.clone(),
+        &message,
+        vec![inbox_id],
+        vec![],
+        None,
+    )
+    .unwrap();
+
+    // The owner can download the blob
+    let blob = JMAPBlob::new(blob_id);
+    match db
+        .mail_blob_get(owner_id, &acl_token(owner_id), &blob)
+        .unwrap()
+    {
+        BlobResult::Blob(bytes) => assert_eq!(bytes, message),
+        _ => panic!("Expected blob to be returned to its owner."),
+    }
+
+    // Other accounts requesting the blob through their own account get not found
+    assert!(matches!(
+        db.mail_blob_get(other_id, &acl_token(other_id), &blob)
+            .unwrap(),
+        BlobResult::NotFound
+    ));
+
+    // Requests through the owner's account without access are rejected
+    assert!(matches!(
+        db.mail_blob_get(owner_id, &acl_token(other_id), &blob)
+            .unwrap(),
+        BlobResult::Unauthorized
+    ));
+}
+
+fn acl_token(account_id: AccountId) -> Arc<ACLToken> {
+    Arc::new(ACLToken {
+        member_of: vec![account_id],
+        access_to: vec![],
+    })
+}
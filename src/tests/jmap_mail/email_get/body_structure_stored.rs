@@ -0,0 +1,186 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    jmap_store::Object,
+    request::{get::GetRequest, MaybeResultReference},
+    types::{blob::JMAPBlob, jmap::JMAPId},
+};
+use jmap_mail::mail::{
+    get::{GetArguments, JMAPGetMail},
+    import::JMAPMailImport,
+    parse::{EmailParseRequest, JMAPMailParse},
+    schema::{BodyProperty, Email, Property},
+};
+use store::{
+    blob::{BlobId, BlobStore},
+    core::acl::ACLToken,
+    AccountId, JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+const BODY_PROPERTIES: [BodyProperty; 10] = [
+    BodyProperty::PartId,
+    BodyProperty::Size,
+    BodyProperty::Name,
+    BodyProperty::Type,
+    BodyProperty::Charset,
+    BodyProperty::Disposition,
+    BodyProperty::Cid,
+    BodyProperty::Language,
+    BodyProperty::Location,
+    BodyProperty::Subparts,
+];
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email/get stored bodyStructure tests...");
+    let account_id = 1;
+
+    // Create account and mailbox
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+
+    // Import a message with nested multiparts, an inline image and an attachment
+    let message = concat!(
+        "From: sender@example.com\r\n",
+        "To: jdoe@example.com\r\n",
+        "Subject: Nested parts\r\n",
+        "Content-Type: multipart/mixed; boundary=\"outer\"\r\n\r\n",
+        "--outer\r\n",
+        "Content-Type: multipart/alternative; boundary=\"inner\"\r\n\r\n",
+        "--inner\r\n",
+        "Content-Type: text/plain; charset=utf-8\r\n\r\n",
+        "Hello there.\r\n",
+        "--inner\r\n",
+        "Content-Type: multipart/related; boundary=\"related\"\r\n\r\n",
+        "--related\r\n",
+        "Content-Type: text/html; charset=utf-8\r\n",
+        "Content-Language: en\r\n\r\n",
+        "<p>Hello <img src=\"cid:logo@example.com\"> there.</p>\r\n",
+        "--related\r\n",
+        "Content-Type: image/png\r\n",
+        "Content-ID: <logo@example.com>\r\n",
+        "Content-Location: logo.png\r\n",
+        "Content-Transfer-Encoding: base64\r\n\r\n",
+        "iVBORw0KGgo=\r\n",
+        "--related--\r\n",
+        "--inner--\r\n",
+        "--outer\r\n",
+        "Content-Type: application/pdf\r\n",
+        "Content-Disposition: attachment; filename=\"report.pdf\"\r\n",
+        "Content-Transfer-Encoding: base64\r\n\r\n",
+        "JVBERi0xLjQK\r\n",
+        "--outer--\r\n"
+    )
+    .as_bytes()
+    .to_vec();
+    let blob_id = BlobId::new_external(&message);
+    db.blob_store(&blob_id, message.clone()).unwrap();
+    let id = *db
+        .mail_import_item(
+            account_id,
+            blob_id.clone(),
+            &message,
+            vec![mailbox_id],
+            vec![],
+            None,
+        )
+        .unwrap()
+        .id()
+        .unwrap();
+
+    // The stored structure has to match a fresh parse of the message
+    let parsed = serde_json::to_value(
+        db.mail_parse(parse_request(account_id, &JMAPBlob::from(&blob_id)))
+            .unwrap(),
+    )
+    .unwrap()["parsed"][JMAPBlob::from(&blob_id).to_string()]["bodyStructure"]
+        .clone();
+    assert!(parsed.is_object(), "{}", parsed);
+    assert_eq!(get_body_structure(&db, account_id, id).unwrap(), parsed);
+
+    // Once the raw message is gone, bodyStructure can still be obtained from the
+    // stored structure while anything that needs the message contents fails
+    assert!(db.blob_store.delete(&blob_id).unwrap());
+    for _ in 0..3 {
+        assert_eq!(get_body_structure(&db, account_id, id).unwrap(), parsed);
+    }
+    assert!(db
+        .mail_get(GetRequest {
+            acl: acl(account_id).into(),
+            account_id: JMAPId::new(account_id as u64),
+            ids: MaybeResultReference::Value(vec![id]).into(),
+            properties: MaybeResultReference::Value(vec![Property::BodyValues]).into(),
+            arguments: GetArguments {
+                fetch_all_body_values: Some(true),
+                ..Default::default()
+            },
+        })
+        .is_err());
+}
+
+fn get_body_structure<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    id: JMAPId,
+) -> jmap::Result<serde_json::Value>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut response = db.mail_get(GetRequest {
+        acl: acl(account_id).into(),
+        account_id: JMAPId::new(account_id as u64),
+        ids: MaybeResultReference::Value(vec![id]).into(),
+        properties: MaybeResultReference::Value(vec![Property::BodyStructure]).into(),
+        arguments: GetArguments {
+            body_properties: Some(BODY_PROPERTIES.to_vec()),
+            ..Default::default()
+        },
+    })?;
+    let email: Email = response.list.pop().unwrap();
+    Ok(serde_json::to_value(&email).unwrap()["bodyStructure"].clone())
+}
+
+fn parse_request(account_id: AccountId, blob_id: &JMAPBlob) -> EmailParseRequest {
+    let mut request: EmailParseRequest = serde_json::from_value(serde_json::json!({
+        "accountId": JMAPId::new(account_id as u64).to_string(),
+        "blobIds": [blob_id.to_string()],
+        "properties": ["bodyStructure"],
+        "bodyProperties": BODY_PROPERTIES,
+    }))
+    .unwrap();
+    request.acl = acl(account_id).into();
+    request
+}
+
+fn acl(account_id: AccountId) -> Arc<ACLToken> {
+    Arc::new(ACLToken {
+        member_of: vec![account_id],
+        access_to: vec![],
+    })
+}
@@ -0,0 +1,160 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    jmap_store::Object,
+    request::{get::GetRequest, set::SetRequest, MaybeResultReference},
+    types::jmap::JMAPId,
+};
+use jmap_mail::mail::{
+    get::{GetArguments, JMAPGetMail},
+    import::JMAPMailImport,
+    schema::{Email, Property},
+    set::{JMAPSetMail, SetArguments},
+    MessageField,
+};
+use store::{
+    blob::BlobId,
+    core::{acl::ACLToken, collection::Collection, vec_map::VecMap, JMAPIdPrefix},
+    serialize::key::BlobKey,
+    AccountId, ColumnFamily, JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+const MESSAGE: &[u8] =
+    b"From: john@example.com\r\nTo: jane@example.com\r\nSubject: Cached\r\n\r\nHello.\r\n";
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email/get cache tests...");
+    let account_id = 1;
+
+    // Create account and mailbox
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+
+    let blob_id = BlobId::new_external(MESSAGE);
+    db.blob_store(&blob_id, MESSAGE.to_vec()).unwrap();
+    let id = *db
+        .mail_import_item(
+            account_id,
+            blob_id,
+            MESSAGE,
+            vec![mailbox_id],
+            vec![],
+            Some(10000),
+        )
+        .unwrap()
+        .id()
+        .unwrap();
+
+    // The first get assembles the email and caches it
+    let email = get(&db, account_id, id);
+    assert_eq!(email["subject"], "Cached");
+    assert_eq!(email["keywords"], serde_json::json!({}));
+
+    // Repeated gets are served without reading the metadata blob
+    let metadata_id = db
+        .get_document_value::<BlobId>(
+            account_id,
+            Collection::Mail,
+            id.get_document_id(),
+            MessageField::Metadata.into(),
+        )
+        .unwrap()
+        .unwrap();
+    let metadata = db.blob_get(&metadata_id).unwrap().unwrap();
+    let metadata_key = BlobKey::serialize(&metadata_id);
+    db.db.delete(ColumnFamily::Blobs, &metadata_key).unwrap();
+    assert!(db.blob_get(&metadata_id).unwrap().is_none());
+    assert_eq!(get(&db, account_id, id), email);
+
+    // A different set of properties is not served from the same entry
+    assert!(db
+        .mail_get(GetRequest {
+            acl: acl(account_id).into(),
+            account_id: JMAPId::new(account_id as u64),
+            ids: MaybeResultReference::Value(vec![id]).into(),
+            properties: MaybeResultReference::Value(vec![Property::Subject]).into(),
+            arguments: GetArguments::default(),
+        })
+        .is_err());
+    db.db
+        .set(ColumnFamily::Blobs, &metadata_key, &metadata)
+        .unwrap();
+
+    // Updating the email bumps the Email state and invalidates the entry
+    let mut update = VecMap::new();
+    update.append(
+        id,
+        serde_json::from_value::<Email>(serde_json::json!({"keywords/$seen": true})).unwrap(),
+    );
+    let response = db
+        .mail_set(SetRequest {
+            acl: acl(account_id).into(),
+            account_id: JMAPId::new(account_id as u64),
+            if_in_state: None,
+            create: None,
+            update: update.into(),
+            destroy: None,
+            arguments: SetArguments::default(),
+        })
+        .unwrap();
+    assert!(
+        response.not_updated.is_empty(),
+        "{:?}",
+        response.not_updated
+    );
+    assert_eq!(
+        get(&db, account_id, id)["keywords"],
+        serde_json::json!({"$seen": true})
+    );
+}
+
+fn acl(account_id: AccountId) -> Arc<ACLToken> {
+    Arc::new(ACLToken {
+        member_of: vec![account_id],
+        access_to: vec![],
+    })
+}
+
+fn get<T>(db: &JMAPStore<T>, account_id: AccountId, id: JMAPId) -> serde_json::Value
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut response = db
+        .mail_get(GetRequest {
+            acl: acl(account_id).into(),
+            account_id: JMAPId::new(account_id as u64),
+            ids: MaybeResultReference::Value(vec![id]).into(),
+            properties: MaybeResultReference::Value(vec![Property::Subject, Property::Keywords])
+                .into(),
+            arguments: GetArguments::default(),
+        })
+        .unwrap();
+    serde_json::to_value(&response.list.pop().unwrap()).unwrap()
+}
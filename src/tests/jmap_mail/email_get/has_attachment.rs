@@ -0,0 +1,160 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    jmap_store::Object,
+    request::{get::GetRequest, query::QueryRequest, MaybeResultReference},
+    types::jmap::JMAPId,
+};
+use jmap_mail::mail::{
+    get::JMAPGetMail,
+    import::JMAPMailImport,
+    query::JMAPMailQuery,
+    schema::{Email, Property, Value},
+};
+use store::{blob::BlobId, core::acl::ACLToken, AccountId, JMAPStore, Store};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email hasAttachment tests...");
+    let account_id = 1;
+
+    // Create account and mailbox
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+
+    // Images displayed inline within the body are not considered attachments
+    let mut ids = Vec::new();
+    for (num, message) in [
+        concat!(
+            "From: sender@example.com\r\n",
+            "Subject: Plain text\r\n\r\n",
+            "No attachments here.\r\n"
+        ),
+        concat!(
+            "From: sender@example.com\r\n",
+            "Subject: Inline image\r\n",
+            "Content-Type: multipart/mixed; boundary=\"boundary\"\r\n\r\n",
+            "--boundary\r\n",
+            "Content-Type: text/plain\r\n\r\n",
+            "Look at this picture:\r\n",
+            "--boundary\r\n",
+            "Content-Type: image/png\r\n",
+            "Content-Disposition: inline\r\n",
+            "Content-Transfer-Encoding: base64\r\n\r\n",
+            "iVBORw0KGgo=\r\n",
+            "--boundary--\r\n"
+        ),
+        concat!(
+            "From: sender@example.com\r\n",
+            "Subject: Attachment\r\n",
+            "Content-Type: multipart/mixed; boundary=\"boundary\"\r\n\r\n",
+            "--boundary\r\n",
+            "Content-Type: text/plain\r\n\r\n",
+            "The report is attached.\r\n",
+            "--boundary\r\n",
+            "Content-Type: application/pdf\r\n",
+            "Content-Disposition: attachment; filename=\"report.pdf\"\r\n",
+            "Content-Transfer-Encoding: base64\r\n\r\n",
+            "JVBERi0xLjQK\r\n",
+            "--boundary--\r\n"
+        ),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let message = message.as_bytes().to_vec();
+        let blob_id = BlobId::new_external(&message);
+        db.blob_store(&blob_id, message.clone()).unwrap();
+        ids.push(
+            *db.mail_import_item(
+                account_id,
+                blob_id,
+                &message,
+                vec![mailbox_id],
+                vec![],
+                Some(num as i64 * 60),
+            )
+            .unwrap()
+            .id()
+            .unwrap(),
+        );
+    }
+
+    // Email/get returns the value computed during import
+    let response = db
+        .mail_get(GetRequest {
+            acl: acl(account_id).into(),
+            account_id: JMAPId::new(account_id as u64),
+            ids: MaybeResultReference::Value(ids.clone()).into(),
+            properties: MaybeResultReference::Value(vec![Property::HasAttachment]).into(),
+            arguments: Default::default(),
+        })
+        .unwrap();
+    assert_eq!(
+        response
+            .list
+            .iter()
+            .map(
+                |email| match email.properties.get(&Property::HasAttachment) {
+                    Some(Value::Bool { value }) => *value,
+                    other => panic!("Unexpected hasAttachment {:?}", other),
+                }
+            )
+            .collect::<Vec<_>>(),
+        vec![false, false, true]
+    );
+
+    // Email/query can filter on it
+    for (has_attachment, expected_ids) in [(true, vec![ids[2]]), (false, vec![ids[0], ids[1]])] {
+        let mut request: QueryRequest<Email> = serde_json::from_str(&format!(
+            concat!(
+                "{{\"accountId\": \"{}\", ",
+                "\"filter\": {{\"hasAttachment\": {}}}, ",
+                "\"sort\": [{{\"property\": \"receivedAt\", \"isAscending\": true}}]}}"
+            ),
+            JMAPId::new(account_id as u64),
+            has_attachment
+        ))
+        .unwrap();
+        request.acl = acl(account_id).into();
+        assert_eq!(
+            db.mail_query(request).unwrap().ids,
+            expected_ids,
+            "{}",
+            has_attachment
+        );
+    }
+}
+
+fn acl(account_id: AccountId) -> Arc<ACLToken> {
+    Arc::new(ACLToken {
+        member_of: vec![account_id],
+        access_to: vec![],
+    })
+}
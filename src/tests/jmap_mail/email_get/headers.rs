@@ -0,0 +1,156 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    request::{get::GetRequest, MaybeResultReference},
+    types::jmap::JMAPId,
+};
+use jmap_mail::mail::{
+    get::JMAPGetMail,
+    import::JMAPMailImport,
+    schema::{Email, Property, Value},
+};
+use store::{blob::BlobId, core::acl::ACLToken, JMAPStore, Store};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email/get non UTF-8 header tests...");
+    let account_id = 1;
+
+    // Create account and mailbox
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+
+    // Import a message with a Latin-1 encoded-word and a header containing raw bytes
+    let mut message = Vec::new();
+    message.extend_from_slice(b"From: john@example.com\r\n");
+    message.extend_from_slice(b"To: jane@example.com\r\n");
+    message.extend_from_slice(b"Subject: =?ISO-8859-1?Q?Caf=E9_cr=E8me?=\r\n");
+    message.extend_from_slice(b"X-Raw-Bytes: caf\xe9 \xff\xfe ok\r\n");
+    message.extend_from_slice(b"\r\nHello world.\r\n");
+    let blob_id = BlobId::new_external(&message);
+    db.blob_store(&blob_id, message.clone()).unwrap();
+    let id = *db
+        .mail_import_item(
+            account_id,
+            blob_id,
+            &message,
+            vec![mailbox_id],
+            vec![],
+            None,
+        )
+        .unwrap()
+        .id()
+        .unwrap();
+
+    let mut response = db
+        .mail_get(GetRequest {
+            acl: Arc::new(ACLToken {
+                member_of: vec![account_id],
+                access_to: vec![],
+            })
+            .into(),
+            account_id: JMAPId::new(account_id as u64),
+            ids: MaybeResultReference::Value(vec![id]).into(),
+            properties: MaybeResultReference::Value(vec![
+                Property::Subject,
+                Property::parse("header:X-Raw-Bytes"),
+                Property::parse("header:X-Raw-Bytes:asText"),
+                Property::Headers,
+            ])
+            .into(),
+            arguments: Default::default(),
+        })
+        .unwrap();
+    assert!(response.not_found.is_empty());
+    let email: Email = response.list.pop().unwrap();
+
+    // Encoded-words are decoded using their declared charset
+    match email.properties.get(&Property::Subject) {
+        Some(Value::Text { value }) => assert_eq!(value, "Café crème"),
+        other => panic!("Unexpected subject value {:?}", other),
+    }
+
+    // Undecodable bytes are replaced rather than failing the request
+    for property in [
+        Property::parse("header:X-Raw-Bytes"),
+        Property::parse("header:X-Raw-Bytes:asText"),
+    ] {
+        match email.properties.get(&property) {
+            Some(Value::Text { value }) => {
+                assert!(value.contains('\u{FFFD}'), "{:?}", value);
+                assert!(value.trim().ends_with("ok"), "{:?}", value);
+            }
+            other => panic!("Unexpected {:?} value {:?}", property, other),
+        }
+    }
+    match email.properties.get(&Property::Headers) {
+        Some(Value::Headers { value }) => {
+            let header = value
+                .iter()
+                .find(|h| h.name.eq_ignore_ascii_case("X-Raw-Bytes"))
+                .unwrap();
+            assert!(header.value.contains('\u{FFFD}'), "{:?}", header.value);
+        }
+        other => panic!("Unexpected headers value {:?}", other),
+    }
+
+    // Headers are returned along with properties that do not need the raw message
+    for property in [Property::BodyStructure, Property::Preview] {
+        let mut response = db
+            .mail_get(GetRequest {
+                acl: Arc::new(ACLToken {
+                    member_of: vec![account_id],
+                    access_to: vec![],
+                })
+                .into(),
+                account_id: JMAPId::new(account_id as u64),
+                ids: MaybeResultReference::Value(vec![id]).into(),
+                properties: MaybeResultReference::Value(vec![Property::Headers, property.clone()])
+                    .into(),
+                arguments: Default::default(),
+            })
+            .unwrap();
+        let email: Email = response.list.pop().unwrap();
+        match email.properties.get(&Property::Headers) {
+            Some(Value::Headers { value }) => {
+                assert!(
+                    value.iter().any(|h| h.name.eq_ignore_ascii_case("Subject")),
+                    "{:?}",
+                    value
+                );
+            }
+            other => panic!("Unexpected headers value {:?}", other),
+        }
+        assert!(
+            email.properties.get(&property).is_some(),
+            "{:?}",
+            email.properties
+        );
+    }
+}
@@ -0,0 +1,103 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::jmap_store::Object;
+use jmap_mail::mail::{import::JMAPMailImport, list::JMAPMailList, schema::Keyword};
+use store::{
+    blob::BlobId,
+    core::{collection::Collection, tag::Tag},
+    JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email listing tests...");
+    let account_id = 1;
+
+    // Create account and mailbox
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+
+    // An empty account has nothing to list
+    assert_eq!(
+        db.iter_documents(account_id, Collection::Mail)
+            .unwrap()
+            .count(),
+        0
+    );
+
+    // Import messages
+    let mut ids = Vec::new();
+    for num in 0..10 {
+        let message = format!(
+            "From: john@example.com\r\nSubject: Message {}\r\n\r\nAudit me.\r\n",
+            num
+        )
+        .into_bytes();
+        let blob_id = BlobId::new_external(&message);
+        db.blob_store(&blob_id, message.clone()).unwrap();
+        ids.push(
+            *db.mail_import_item(
+                account_id,
+                blob_id,
+                &message,
+                vec![mailbox_id],
+                vec![Tag::Static(Keyword::SEEN)],
+                Some(1000 + num),
+            )
+            .unwrap()
+            .id()
+            .unwrap(),
+        );
+    }
+
+    // Enumerate all messages and fetch their metadata
+    let document_ids = db
+        .get_document_ids(account_id, Collection::Mail)
+        .unwrap()
+        .unwrap();
+    let mut total = 0;
+    for document_id in db.iter_documents(account_id, Collection::Mail).unwrap() {
+        assert!(document_ids.contains(document_id));
+        let summary = db.mail_summary(account_id, document_id).unwrap().unwrap();
+        assert!(ids.contains(&summary.id), "{:?}", summary);
+        assert_eq!(summary.mailbox_ids, vec![mailbox_id]);
+        assert_eq!(summary.keywords, vec![Tag::Static(Keyword::SEEN)]);
+        assert!((1000..1010).contains(&summary.received_at));
+        assert!(summary.size > 0);
+        total += 1;
+    }
+    assert_eq!(total, document_ids.len());
+    assert_eq!(total, ids.len() as u64);
+
+    // Unknown ids have no metadata
+    assert!(db
+        .mail_summary(account_id, ids.len() as u32 + 100)
+        .unwrap()
+        .is_none());
+}
@@ -21,6 +21,16 @@
  * for more details.
 */
 
+pub mod blob_access;
+pub mod body_structure_stored;
+pub mod cache;
+pub mod has_attachment;
+pub mod headers;
+pub mod list;
+pub mod preview;
+pub mod preview_html;
+pub mod received_at_date;
+
 use std::{fs, path::PathBuf};
 
 use actix_web::web;
@@ -189,11 +199,64 @@ where
         }
     }
 
+    // Threading headers are returned as arrays in header order, or null when absent
+    for (raw_message, message_id, in_reply_to, references) in [
+        (
+            concat!(
+                "Message-ID: <reply@example.com>\r\n",
+                "In-Reply-To: <parent@example.com>\r\n",
+                "References: <root@example.com>\r\n",
+                "  <parent@example.com>\r\n",
+                "Subject: Re: Threading\r\n\r\nReply.\r\n"
+            ),
+            Some(vec!["reply@example.com"]),
+            Some(vec!["parent@example.com"]),
+            Some(vec!["root@example.com", "parent@example.com"]),
+        ),
+        (
+            "Subject: No threading headers\r\n\r\nHi.\r\n",
+            None,
+            None,
+            None,
+        ),
+    ] {
+        let email_id = client
+            .email_import(
+                raw_message.as_bytes().to_vec(),
+                [mailbox_id.clone()],
+                None::<Vec<String>>,
+                None,
+            )
+            .await
+            .unwrap()
+            .take_id();
+        let email = client
+            .email_get(
+                &email_id,
+                [
+                    email::Property::MessageId,
+                    email::Property::InReplyTo,
+                    email::Property::References,
+                ]
+                .into(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(ids_to_vec(email.message_id()), message_id);
+        assert_eq!(ids_to_vec(email.in_reply_to()), in_reply_to);
+        assert_eq!(ids_to_vec(email.references()), references);
+    }
+
     client.mailbox_destroy(&mailbox_id, true).await.unwrap();
 
     server.store.assert_is_empty();
 }
 
+fn ids_to_vec(ids: Option<&[String]>) -> Option<Vec<&str>> {
+    ids.map(|ids| ids.iter().map(|id| id.as_str()).collect())
+}
+
 pub fn all_headers() -> Vec<email::Property> {
     let mut properties = Vec::new();
 
@@ -0,0 +1,124 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    request::{get::GetRequest, MaybeResultReference},
+    types::jmap::JMAPId,
+};
+use jmap_mail::mail::{
+    get::JMAPGetMail,
+    import::JMAPMailImport,
+    schema::{Email, Property, Value},
+};
+use store::{blob::BlobId, core::acl::ACLToken, AccountId, JMAPStore, Store};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+const MESSAGE: &[u8] = concat!(
+    "From: john@example.com\r\n",
+    "To: jane@example.com\r\n",
+    "Subject: Preview\r\n",
+    "Content-Type: text/plain; charset=utf-8\r\n",
+    "\r\n",
+    "Ünïcödé façade résumé naïve coöperate 日本語のテキスト\r\n"
+)
+.as_bytes();
+
+pub fn test<T>(db: Arc<JMAPStore<T>>) -> JMAPId
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email preview length tests...");
+    let account_id = 1;
+    let preview_length = db.config.mail_preview_length;
+    assert!(preview_length < 30);
+
+    // Create account and mailbox
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+
+    // Import message
+    let blob_id = BlobId::new_external(MESSAGE);
+    db.blob_store(&blob_id, MESSAGE.to_vec()).unwrap();
+    let id = *db
+        .mail_import_item(account_id, blob_id, MESSAGE, vec![mailbox_id], vec![], None)
+        .unwrap()
+        .id()
+        .unwrap();
+
+    // Fetch the preview and make sure it was truncated at a character boundary
+    let preview = get_preview(&db, account_id, id);
+    assert!(!preview.is_empty());
+    assert!(
+        preview.chars().count() <= preview_length,
+        "{:?} exceeds {} characters",
+        preview,
+        preview_length
+    );
+    assert!(preview.starts_with("Ünïcödé"), "{:?}", preview);
+
+    id
+}
+
+pub fn test_length_change<T>(db: Arc<JMAPStore<T>>, id: JMAPId)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email preview length change tests...");
+    let preview_length = db.config.mail_preview_length;
+
+    // Previews stored for a different length are generated again
+    let preview = get_preview(&db, 1, id);
+    assert!(
+        preview.chars().count() <= preview_length,
+        "{:?} exceeds {} characters",
+        preview,
+        preview_length
+    );
+    assert!(preview.starts_with("Ünï"), "{:?}", preview);
+}
+
+fn get_preview<T>(db: &JMAPStore<T>, account_id: AccountId, id: JMAPId) -> String
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut response = db
+        .mail_get(GetRequest {
+            acl: Arc::new(ACLToken {
+                member_of: vec![account_id],
+                access_to: vec![],
+            })
+            .into(),
+            account_id: JMAPId::new(account_id as u64),
+            ids: MaybeResultReference::Value(vec![id]).into(),
+            properties: MaybeResultReference::Value(vec![Property::Preview]).into(),
+            arguments: Default::default(),
+        })
+        .unwrap();
+    let email: Email = response.list.pop().unwrap();
+    match email.properties.get(&Property::Preview) {
+        Some(Value::Text { value }) => value.clone(),
+        other => panic!("Unexpected preview value {:?}", other),
+    }
+}
@@ -0,0 +1,166 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    request::{get::GetRequest, MaybeResultReference},
+    types::jmap::JMAPId,
+};
+use jmap_mail::mail::{
+    get::JMAPGetMail,
+    import::JMAPMailImport,
+    schema::{Email, Property, Value},
+    MessageField,
+};
+use store::{
+    blob::BlobId,
+    core::{acl::ACLToken, collection::Collection, document::Document},
+    write::{
+        batch::WriteBatch,
+        options::{IndexOptions, Options},
+    },
+    AccountId, JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email preview generation tests...");
+    let account_id = 1;
+
+    // Create account and mailbox
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+
+    for (message, expected_preview) in [
+        // HTML-only newsletter, tags are removed
+        (
+            concat!(
+                "From: news@example.com\r\n",
+                "Subject: Weekly newsletter\r\n",
+                "Content-Type: text/html; charset=utf-8\r\n\r\n",
+                "<html><body>\r\n",
+                "<h1>This week's <b>news</b></h1>\r\n",
+                "<p>Read <a href=\"https://example.com\">all about it</a>.</p>\r\n",
+                "</body></html>\r\n"
+            ),
+            Some(["This week's", "news", "Read", "all about it"].as_slice()),
+        ),
+        // The text/plain alternative is preferred over the HTML one
+        (
+            concat!(
+                "From: news@example.com\r\n",
+                "Subject: Alternative\r\n",
+                "Content-Type: multipart/alternative; boundary=\"boundary\"\r\n\r\n",
+                "--boundary\r\n",
+                "Content-Type: text/plain\r\n\r\n",
+                "Plain text version\r\n",
+                "--boundary\r\n",
+                "Content-Type: text/html\r\n\r\n",
+                "<p>HTML version</p>\r\n",
+                "--boundary--\r\n"
+            ),
+            Some(["Plain text version"].as_slice()),
+        ),
+        // No textual body
+        (
+            concat!(
+                "From: news@example.com\r\n",
+                "Subject: Image only\r\n",
+                "Content-Type: image/png\r\n",
+                "Content-Transfer-Encoding: base64\r\n\r\n",
+                "iVBORw0KGgo=\r\n"
+            ),
+            None,
+        ),
+    ] {
+        let message = message.as_bytes().to_vec();
+        let blob_id = BlobId::new_external(&message);
+        db.blob_store(&blob_id, message.clone()).unwrap();
+        let id = *db
+            .mail_import_item(
+                account_id,
+                blob_id,
+                &message,
+                vec![mailbox_id],
+                vec![],
+                None,
+            )
+            .unwrap()
+            .id()
+            .unwrap();
+
+        let preview = get_preview(&db, account_id, id);
+        match (&preview, expected_preview) {
+            (Some(value), Some(expected_words)) => {
+                assert!(!value.contains(['<', '>']), "{:?}", value);
+                assert!(!value.contains("HTML"), "{:?}", value);
+                for word in expected_words {
+                    assert!(value.contains(word), "{:?} not in {:?}", word, value);
+                }
+            }
+            (None, None) => (),
+            (other, _) => panic!("Unexpected preview value {:?}", other),
+        }
+
+        // Messages stored without a preview have it computed on demand
+        let mut batch = WriteBatch::new(account_id);
+        let mut document = Document::new(Collection::Mail, id.get_document_id());
+        document.binary(
+            MessageField::Preview,
+            Vec::new(),
+            IndexOptions::new().clear(),
+        );
+        batch.update_document(document);
+        db.write(batch).unwrap();
+        assert_eq!(get_preview(&db, account_id, id), preview);
+    }
+}
+
+fn get_preview<T>(db: &JMAPStore<T>, account_id: AccountId, id: JMAPId) -> Option<String>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut response = db
+        .mail_get(GetRequest {
+            acl: Arc::new(ACLToken {
+                member_of: vec![account_id],
+                access_to: vec![],
+            })
+            .into(),
+            account_id: JMAPId::new(account_id as u64),
+            ids: MaybeResultReference::Value(vec![id]).into(),
+            properties: MaybeResultReference::Value(vec![Property::Preview]).into(),
+            arguments: Default::default(),
+        })
+        .unwrap();
+    let email: Email = response.list.pop().unwrap();
+    match email.properties.get(&Property::Preview) {
+        Some(Value::Text { value }) => Some(value.clone()),
+        None | Some(Value::Null) => None,
+        other => panic!("Unexpected preview value {:?}", other),
+    }
+}
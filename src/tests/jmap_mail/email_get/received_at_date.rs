@@ -0,0 +1,254 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{sync::Arc, time::SystemTime};
+
+use jmap::{
+    jmap_store::Object,
+    request::{get::GetRequest, MaybeResultReference},
+    types::{blob::JMAPBlob, date::JMAPDate, jmap::JMAPId},
+};
+use jmap_mail::{
+    mail::{
+        get::JMAPGetMail,
+        import::{EmailImportRequest, JMAPMailImport},
+        schema::{Email, Property, Value},
+    },
+    thread::get::JMAPGetThread,
+};
+use store::{blob::BlobId, core::acl::ACLToken, AccountId, DocumentId, JMAPStore, Store};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email/import receivedAt from Date tests...");
+    let account_id = 1;
+
+    // Create account and mailbox
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+
+    // Import a thread in reverse order, with client supplied times that don't
+    // match the order in which its messages were sent
+    let mut thread_ids = Vec::new();
+    for (message, received_at) in [
+        (
+            concat!(
+                "Message-ID: <c@example.com>\r\nReferences: <a@example.com> <b@example.com>\r\n",
+                "Date: Mon, 1 Jan 2024 12:00:00 +0000\r\nSubject: Re: Plans\r\n\r\nThird\r\n"
+            ),
+            "2024-01-05T00:00:00Z",
+        ),
+        (
+            concat!(
+                "Message-ID: <a@example.com>\r\n",
+                "Date: Mon, 1 Jan 2024 10:00:00 +0000\r\nSubject: Plans\r\n\r\nFirst\r\n"
+            ),
+            "2024-01-07T00:00:00Z",
+        ),
+        (
+            concat!(
+                "Message-ID: <b@example.com>\r\nReferences: <a@example.com>\r\n",
+                "Date: Mon, 1 Jan 2024 11:00:00 +0000\r\nSubject: Re: Plans\r\n\r\nSecond\r\n"
+            ),
+            "2024-01-06T00:00:00Z",
+        ),
+    ] {
+        thread_ids.push(
+            import_message(
+                &db,
+                account_id,
+                mailbox_id,
+                message,
+                Some(received_at),
+                true,
+            )
+            .unwrap(),
+        );
+    }
+
+    // The thread is ordered by the Date of each message
+    let thread_id = get_thread_id(&db, account_id, thread_ids[0]);
+    let email_ids = db
+        .thread_get(GetRequest {
+            acl: acl(account_id).into(),
+            account_id: JMAPId::new(account_id as u64),
+            ids: MaybeResultReference::Value(vec![thread_id]).into(),
+            properties: None,
+            arguments: Default::default(),
+        })
+        .unwrap()
+        .list
+        .pop()
+        .unwrap()
+        .email_ids;
+    assert_eq!(email_ids, vec![thread_ids[1], thread_ids[2], thread_ids[0]]);
+    assert_eq!(
+        email_ids
+            .iter()
+            .map(|id| get_received_at(&db, account_id, *id))
+            .collect::<Vec<_>>(),
+        [
+            "2024-01-01T10:00:00Z",
+            "2024-01-01T11:00:00Z",
+            "2024-01-01T12:00:00Z"
+        ]
+        .iter()
+        .map(|date| JMAPDate::parse(date).unwrap().timestamp())
+        .collect::<Vec<_>>()
+    );
+
+    // Implausible dates fall back to the supplied receivedAt, then to the import time
+    let future_message = concat!(
+        "Message-ID: <future@example.com>\r\n",
+        "Date: Fri, 1 Jan 2100 10:00:00 +0000\r\nSubject: From the future\r\n\r\nHi\r\n"
+    );
+    let id = import_message(
+        &db,
+        account_id,
+        mailbox_id,
+        future_message,
+        Some("2024-02-01T00:00:00Z"),
+        true,
+    )
+    .unwrap();
+    assert_eq!(
+        get_received_at(&db, account_id, id),
+        JMAPDate::parse("2024-02-01T00:00:00Z").unwrap().timestamp()
+    );
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let id = import_message(
+        &db,
+        account_id,
+        mailbox_id,
+        &future_message.replace("future@", "future2@"),
+        None,
+        true,
+    )
+    .unwrap();
+    assert!((get_received_at(&db, account_id, id) - now).abs() < 60);
+
+    // The Date header is ignored unless requested
+    let id = import_message(
+        &db,
+        account_id,
+        mailbox_id,
+        concat!(
+            "Message-ID: <plain@example.com>\r\n",
+            "Date: Mon, 1 Jan 2024 10:00:00 +0000\r\nSubject: Plain\r\n\r\nHi\r\n"
+        ),
+        Some("2024-03-01T00:00:00Z"),
+        false,
+    )
+    .unwrap();
+    assert_eq!(
+        get_received_at(&db, account_id, id),
+        JMAPDate::parse("2024-03-01T00:00:00Z").unwrap().timestamp()
+    );
+}
+
+fn import_message<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    mailbox_id: DocumentId,
+    message: &str,
+    received_at: Option<&str>,
+    received_at_from_date: bool,
+) -> Option<JMAPId>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let message = message.as_bytes().to_vec();
+    let blob_id = BlobId::new_external(&message);
+    db.blob_store(&blob_id, message).unwrap();
+    db.blob_link_ephemeral(&blob_id, account_id).unwrap();
+
+    let mut request: EmailImportRequest = serde_json::from_value(serde_json::json!({
+        "accountId": JMAPId::new(account_id as u64).to_string(),
+        "emails": {
+            "m": {
+                "blobId": JMAPBlob::new(blob_id).to_string(),
+                "mailboxIds": { JMAPId::from(mailbox_id).to_string(): true },
+                "receivedAt": received_at,
+                "receivedAtFromDate": received_at_from_date,
+            }
+        }
+    }))
+    .unwrap();
+    request.acl = acl(account_id).into();
+
+    db.mail_import(request)
+        .unwrap()
+        .created
+        .and_then(|mut created| created.remove(&"m".to_string()))
+        .and_then(|email| email.id().copied())
+}
+
+fn get_email<T>(db: &JMAPStore<T>, account_id: AccountId, id: JMAPId, property: Property) -> Value
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut response = db
+        .mail_get(GetRequest {
+            acl: acl(account_id).into(),
+            account_id: JMAPId::new(account_id as u64),
+            ids: MaybeResultReference::Value(vec![id]).into(),
+            properties: MaybeResultReference::Value(vec![property.clone()]).into(),
+            arguments: Default::default(),
+        })
+        .unwrap();
+    let mut email: Email = response.list.pop().unwrap();
+    email.properties.remove(&property).unwrap()
+}
+
+fn get_received_at<T>(db: &JMAPStore<T>, account_id: AccountId, id: JMAPId) -> i64
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    match get_email(db, account_id, id, Property::ReceivedAt) {
+        Value::Date { value } => value.timestamp(),
+        other => panic!("Unexpected receivedAt {:?}", other),
+    }
+}
+
+fn get_thread_id<T>(db: &JMAPStore<T>, account_id: AccountId, id: JMAPId) -> JMAPId
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    match get_email(db, account_id, id, Property::ThreadId) {
+        Value::Id { value } => value,
+        other => panic!("Unexpected threadId {:?}", other),
+    }
+}
+
+fn acl(account_id: AccountId) -> Arc<ACLToken> {
+    Arc::new(ACLToken {
+        member_of: vec![account_id],
+        access_to: vec![],
+    })
+}
@@ -0,0 +1,226 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use std::sync::Arc;
+
+use jmap::{
+    jmap_store::Object, orm::serialize::JMAPOrm, request::query::QueryRequest, types::jmap::JMAPId,
+};
+use jmap_mail::mail::{
+    import::JMAPMailImport,
+    query::JMAPMailQuery,
+    schema::{Email, Keyword, Property},
+};
+use store::{blob::BlobId, core::acl::ACLToken, AccountId, DocumentId, JMAPStore, Store};
+
+use crate::tests::jmap_mail::{create_account_mailbox, create_mailbox};
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let dedup = db.config.import_dedup_by_message_id;
+    println!(
+        "Running duplicate Message-ID tests (dedup {})...",
+        if dedup { "enabled" } else { "disabled" }
+    );
+    let account_id = 1;
+
+    // Create account and mailboxes
+    let inbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+    let archive_id = create_mailbox(&db, account_id, "Archive", "archive");
+    let drafts_id = create_mailbox(&db, account_id, "Drafts", "drafts");
+
+    // Import two messages sharing the same Message-ID, both referencing
+    // themselves in References
+    let first = import_message(
+        &db,
+        account_id,
+        inbox_id,
+        concat!(
+            "From: list@example.com\r\n",
+            "Message-ID: <dup@example.com>\r\n",
+            "References: <dup@example.com>\r\n",
+            "Subject: Duplicate\r\n\r\n",
+            "First copy.\r\n"
+        ),
+    );
+    let second = import_message(
+        &db,
+        account_id,
+        archive_id,
+        concat!(
+            "From: list@example.com\r\n",
+            "Message-ID: <dup@example.com>\r\n",
+            "References: <dup@example.com> <dup@example.com>\r\n",
+            "Subject: Duplicate\r\n\r\n",
+            "Second copy.\r\n"
+        ),
+    );
+
+    // Replies to the duplicated Message-ID join the same thread
+    let reply = import_message(
+        &db,
+        account_id,
+        inbox_id,
+        concat!(
+            "From: jdoe@example.com\r\n",
+            "Message-ID: <reply@example.com>\r\n",
+            "In-Reply-To: <dup@example.com>\r\n",
+            "Subject: Re: Duplicate\r\n\r\n",
+            "Reply.\r\n"
+        ),
+    );
+    assert_eq!(first.get_prefix_id(), second.get_prefix_id());
+    assert_eq!(first.get_prefix_id(), reply.get_prefix_id());
+
+    let by_message_id = query(
+        &db,
+        account_id,
+        "{\"header\": [\"Message-Id\", \"dup@example.com\"]}",
+    );
+    let in_archive = query(
+        &db,
+        account_id,
+        &format!("{{\"inMailbox\": \"{}\"}}", JMAPId::from(archive_id)),
+    );
+
+    if dedup {
+        // The second copy collapses into the first one
+        assert_eq!(first, second);
+        assert_eq!(by_message_id, vec![first]);
+        assert_eq!(in_archive, vec![first]);
+
+        // Keywords requested for a duplicate are added to the existing message
+        let message = concat!(
+            "From: list@example.com\r\n",
+            "Message-ID: <dup@example.com>\r\n",
+            "Subject: Duplicate\r\n\r\n",
+            "Flagged copy.\r\n"
+        )
+        .as_bytes()
+        .to_vec();
+        let blob_id = BlobId::new_external(&message);
+        db.blob_store(&blob_id, message.clone()).unwrap();
+        let flagged = *db
+            .mail_import_item(
+                account_id,
+                blob_id,
+                &message,
+                vec![inbox_id],
+                vec![Keyword::parse("$flagged").tag],
+                None,
+            )
+            .unwrap()
+            .id()
+            .unwrap();
+        assert_eq!(flagged, first);
+        assert!(db
+            .get_orm::<Email>(account_id, first.get_document_id())
+            .unwrap()
+            .unwrap()
+            .get_tags(&Property::Keywords)
+            .unwrap()
+            .contains(&Keyword::parse("$flagged").tag));
+    } else {
+        // Both copies are kept
+        assert_ne!(first, second);
+        assert_eq!(by_message_id.len(), 2);
+        assert!(by_message_id.contains(&first) && by_message_id.contains(&second));
+        assert_eq!(in_archive, vec![second]);
+    }
+
+    // Autosaving a draft twice under the same Message-ID keeps both versions
+    let draft_v1 = import_message(
+        &db,
+        account_id,
+        drafts_id,
+        concat!(
+            "From: jdoe@example.com\r\n",
+            "Message-ID: <draft@example.com>\r\n",
+            "Subject: Draft\r\n\r\n",
+            "First version.\r\n"
+        ),
+    );
+    let draft_v2 = import_message(
+        &db,
+        account_id,
+        drafts_id,
+        concat!(
+            "From: jdoe@example.com\r\n",
+            "Message-ID: <draft@example.com>\r\n",
+            "Subject: Draft\r\n\r\n",
+            "Second version.\r\n"
+        ),
+    );
+    assert_ne!(draft_v1, draft_v2);
+    let in_drafts = query(
+        &db,
+        account_id,
+        &format!("{{\"inMailbox\": \"{}\"}}", JMAPId::from(drafts_id)),
+    );
+    assert_eq!(in_drafts.len(), 2);
+    assert!(in_drafts.contains(&draft_v1) && in_drafts.contains(&draft_v2));
+}
+
+fn import_message<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    mailbox_id: DocumentId,
+    message: &str,
+) -> JMAPId
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let message = message.as_bytes().to_vec();
+    let blob_id = BlobId::new_external(&message);
+    db.blob_store(&blob_id, message.clone()).unwrap();
+    *db.mail_import_item(
+        account_id,
+        blob_id,
+        &message,
+        vec![mailbox_id],
+        vec![],
+        None,
+    )
+    .unwrap()
+    .id()
+    .unwrap()
+}
+
+fn query<T>(db: &JMAPStore<T>, account_id: AccountId, filter: &str) -> Vec<JMAPId>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut request: QueryRequest<Email> = serde_json::from_str(&format!(
+        "{{\"accountId\": \"{}\", \"filter\": {}}}",
+        JMAPId::new(account_id as u64),
+        filter
+    ))
+    .unwrap();
+    request.acl = Arc::new(ACLToken {
+        member_of: vec![account_id],
+        access_to: vec![],
+    })
+    .into();
+    db.mail_query(request).unwrap().ids
+}
@@ -0,0 +1,143 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    error::set::SetErrorType,
+    jmap_store::Object,
+    orm::serialize::JMAPOrm,
+    types::{blob::JMAPBlob, jmap::JMAPId},
+};
+use jmap_mail::mail::{
+    import::{EmailImportRequest, JMAPMailImport, UNPARSED_KEYWORD},
+    schema::{Email, Keyword, Property},
+};
+use store::{blob::BlobId, core::acl::ACLToken, AccountId, DocumentId, JMAPStore, Store};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let store_unparsed = db.config.import_store_unparsed;
+    println!(
+        "Running Email/import unparsed message tests ({})...",
+        if store_unparsed { "lenient" } else { "strict" }
+    );
+    let account_id = 1;
+
+    // Create account and mailbox
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+
+    // Well-formed messages are imported in both modes, without the flag
+    let id = import_message(
+        &db,
+        account_id,
+        mailbox_id,
+        b"Subject: Hello\r\n\r\nWorld\r\n".to_vec(),
+    )
+    .unwrap();
+    assert!(!is_unparsed(&db, account_id, id));
+
+    // Blobs without any headers or content are rejected unless lenient
+    for garbage in [b"".to_vec(), b"\r\n\r\n \t\r\n".to_vec()] {
+        let result = import_message(&db, account_id, mailbox_id, garbage.clone());
+        if store_unparsed {
+            let id = result.unwrap();
+            assert!(is_unparsed(&db, account_id, id));
+
+            // The raw blob can still be retrieved
+            assert_eq!(
+                db.blob_get(&BlobId::new_external(&garbage))
+                    .unwrap()
+                    .unwrap(),
+                garbage
+            );
+        } else {
+            assert!(
+                matches!(result, Err(SetErrorType::InvalidEmail)),
+                "{:?}",
+                result
+            );
+        }
+    }
+}
+
+fn import_message<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    mailbox_id: DocumentId,
+    message: Vec<u8>,
+) -> Result<JMAPId, SetErrorType>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let blob_id = BlobId::new_external(&message);
+    db.blob_store(&blob_id, message).unwrap();
+    db.blob_link_ephemeral(&blob_id, account_id).unwrap();
+
+    let mut request: EmailImportRequest = serde_json::from_value(serde_json::json!({
+        "accountId": JMAPId::new(account_id as u64).to_string(),
+        "emails": {
+            "m": {
+                "blobId": JMAPBlob::new(blob_id).to_string(),
+                "mailboxIds": { JMAPId::from(mailbox_id).to_string(): true },
+            }
+        }
+    }))
+    .unwrap();
+    request.acl = Arc::new(ACLToken {
+        member_of: vec![account_id],
+        access_to: vec![],
+    })
+    .into();
+
+    let response = db.mail_import(request).unwrap();
+    if let Some(email) = response
+        .created
+        .and_then(|mut created| created.remove(&"m".to_string()))
+    {
+        Ok(*email.id().unwrap())
+    } else {
+        Err(response
+            .not_created
+            .and_then(|mut not_created| not_created.remove(&"m".to_string()))
+            .unwrap()
+            .type_)
+    }
+}
+
+fn is_unparsed<T>(db: &JMAPStore<T>, account_id: AccountId, id: JMAPId) -> bool
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    db.get_orm::<Email>(account_id, id.get_document_id())
+        .unwrap()
+        .unwrap()
+        .get_tags(&Property::Keywords)
+        .map_or(false, |tags| {
+            tags.contains(&Keyword::parse(UNPARSED_KEYWORD).tag)
+        })
+}
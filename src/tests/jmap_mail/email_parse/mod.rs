@@ -21,6 +21,9 @@
  * for more details.
 */
 
+pub mod duplicate_id;
+pub mod import_unparsed;
+
 use std::{fs, path::PathBuf};
 
 use actix_web::web;
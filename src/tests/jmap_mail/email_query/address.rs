@@ -0,0 +1,182 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use std::sync::Arc;
+
+use jmap::{jmap_store::Object, request::query::QueryRequest, types::jmap::JMAPId};
+use jmap_mail::mail::{
+    import::JMAPMailImport, query::JMAPMailQuery, reindex::JMAPMailReindex, schema::Email,
+    MessageData, MessageField,
+};
+use store::{
+    blob::BlobId,
+    core::{acl::ACLToken, collection::Collection, document::Document},
+    serialize::StoreDeserialize,
+    write::batch::WriteBatch,
+    AccountId, FieldId, JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email/query address match type tests...");
+    let account_id = 1;
+
+    // Create account and mailbox
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+
+    let mut ids = Vec::new();
+    for (num, from) in [
+        "\"John Smith\" <john.smith@example.com>",
+        "Jane Doe <jdoe@example.org>",
+        "Johanna Berg <berg@sample.net>",
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let message = format!(
+            concat!(
+                "From: {}\r\n",
+                "To: team@example.com\r\n",
+                "Subject: Message {}\r\n\r\n",
+                "This is message number {}.\r\n"
+            ),
+            from, num, num
+        )
+        .into_bytes();
+        let blob_id = BlobId::new_external(&message);
+        db.blob_store(&blob_id, message.clone()).unwrap();
+        ids.push(
+            *db.mail_import_item(
+                account_id,
+                blob_id,
+                &message,
+                vec![mailbox_id],
+                vec![],
+                Some(num as i64 * 60),
+            )
+            .unwrap()
+            .id()
+            .unwrap(),
+        );
+    }
+
+    let cases = vec![
+        ("from", "exact", "jo", vec![]),
+        ("from", "exact", "john", vec![ids[0]]),
+        ("from", "prefix", "jo", vec![ids[0], ids[2]]),
+        ("from", "prefix", "john", vec![ids[0]]),
+        ("from", "prefix", "exam", vec![ids[0], ids[1]]),
+        ("from", "prefix", "Sm", vec![ids[0]]),
+        ("from", "prefix", "jane d", vec![ids[1]]),
+        ("from", "prefix", "mith", vec![]),
+        ("from", "contains", "mith", vec![ids[0]]),
+        ("from", "contains", "ample", vec![ids[0], ids[2]]),
+        ("from", "contains", "hanna", vec![ids[2]]),
+        ("from", "contains", "doe", vec![ids[1]]),
+        ("to", "prefix", "tea", vec![ids[0], ids[1], ids[2]]),
+        ("to", "contains", "doe", vec![]),
+    ];
+    assert_matches(&db, account_id, cases.clone());
+
+    // Messages imported before address search was enabled get their grams once reindexed
+    let address_fields = [
+        FieldId::from(MessageField::AddressPrefix),
+        FieldId::from(MessageField::AddressTrigram),
+    ];
+    let mut batch = WriteBatch::new(account_id);
+    for id in &ids {
+        let document_id = id.get_document_id();
+        let mut document = Document::new(Collection::Mail, document_id);
+        db.get_document_value::<BlobId>(
+            account_id,
+            Collection::Mail,
+            document_id,
+            MessageField::Metadata.into(),
+        )
+        .unwrap()
+        .and_then(|blob_id| db.blob_get(&blob_id).unwrap())
+        .and_then(|bytes| MessageData::deserialize(&bytes))
+        .unwrap()
+        .build_index(&mut document, false, true)
+        .unwrap();
+        document
+            .tag_fields
+            .retain(|field| address_fields.contains(&field.field));
+        document.text_fields.clear();
+        document.number_fields.clear();
+        document.binary_fields.clear();
+        document.blobs.clear();
+        batch.update_document(document);
+    }
+    db.write(batch).unwrap();
+    assert_matches(
+        &db,
+        account_id,
+        vec![
+            ("from", "prefix", "jo", vec![]),
+            ("from", "contains", "mith", vec![]),
+        ],
+    );
+    db.mail_reindex_pending().unwrap();
+    assert_matches(&db, account_id, cases);
+}
+
+fn assert_matches<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    cases: Vec<(&str, &str, &str, Vec<JMAPId>)>,
+) where
+    T: for<'x> Store<'x> + 'static,
+{
+    for (filter, match_type, value, expected_ids) in cases {
+        let mut request: QueryRequest<Email> = serde_json::from_str(&format!(
+            concat!(
+                "{{\"accountId\": \"{}\", ",
+                "\"filter\": {{\"{}\": \"{}\"}}, ",
+                "\"matchType\": \"{}\", ",
+                "\"sort\": [{{\"property\": \"receivedAt\", \"isAscending\": true}}]}}"
+            ),
+            JMAPId::new(account_id as u64),
+            filter,
+            value,
+            match_type
+        ))
+        .unwrap();
+        request.acl = Arc::new(ACLToken {
+            member_of: vec![account_id],
+            access_to: vec![],
+        })
+        .into();
+        assert_eq!(
+            db.mail_query(request).unwrap().ids,
+            expected_ids,
+            "{} {} {}",
+            match_type,
+            filter,
+            value
+        );
+    }
+}
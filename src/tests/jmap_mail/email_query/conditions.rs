@@ -0,0 +1,139 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use std::sync::Arc;
+
+use jmap::{
+    error::method::MethodError, jmap_store::Object, request::query::QueryRequest,
+    types::jmap::JMAPId,
+};
+use jmap_mail::mail::{import::JMAPMailImport, query::JMAPMailQuery, schema::Email};
+use store::{blob::BlobId, core::acl::ACLToken, AccountId, JMAPStore, Store};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email/query filter condition limit tests...");
+    let account_id = 1;
+    let max_conditions = db.config.query_max_conditions;
+    assert!(max_conditions > 1);
+
+    // Create account and mailbox
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+
+    let message = b"From: sender@example.com\r\nSubject: Hello\r\n\r\nHello world.\r\n".to_vec();
+    let blob_id = BlobId::new_external(&message);
+    db.blob_store(&blob_id, message.clone()).unwrap();
+    let email_id = *db
+        .mail_import_item(
+            account_id,
+            blob_id,
+            &message,
+            vec![mailbox_id],
+            vec![],
+            None,
+        )
+        .unwrap()
+        .id()
+        .unwrap();
+
+    // Build an OR filter over one existing and many non-existing mailboxes
+    let mailbox_ids = (0..=max_conditions as u64)
+        .map(|id| {
+            JMAPId::new(if id == 0 {
+                mailbox_id as u64
+            } else {
+                id + 1000
+            })
+        })
+        .collect::<Vec<_>>();
+    let or_filter = |total: usize| {
+        format!(
+            "{{\"operator\": \"OR\", \"conditions\": [{}]}}",
+            mailbox_ids[..total]
+                .iter()
+                .map(|id| format!("{{\"inMailbox\": \"{}\"}}", id))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    };
+    let other_than_filter = |total: usize| {
+        format!(
+            "{{\"inMailboxOtherThan\": [{}]}}",
+            mailbox_ids[1..=total]
+                .iter()
+                .map(|id| format!("\"{}\"", id))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    };
+
+    // Filters at the limit are accepted
+    assert_eq!(
+        query(&db, account_id, &or_filter(max_conditions)).unwrap(),
+        vec![email_id]
+    );
+    assert_eq!(
+        query(&db, account_id, &other_than_filter(max_conditions)).unwrap(),
+        vec![email_id]
+    );
+
+    // Filters exceeding the limit are rejected
+    for filter in [
+        or_filter(max_conditions + 1),
+        format!(
+            "{{\"operator\": \"AND\", \"conditions\": [{}]}}",
+            or_filter(max_conditions + 1)
+        ),
+        other_than_filter(max_conditions + 1),
+    ] {
+        assert!(
+            matches!(
+                query(&db, account_id, &filter),
+                Err(MethodError::UnsupportedFilter(_))
+            ),
+            "{}",
+            filter
+        );
+    }
+}
+
+fn query<T>(db: &JMAPStore<T>, account_id: AccountId, filter: &str) -> jmap::Result<Vec<JMAPId>>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut request: QueryRequest<Email> = serde_json::from_str(&format!(
+        "{{\"accountId\": \"{}\", \"filter\": {}}}",
+        JMAPId::new(account_id as u64),
+        filter
+    ))
+    .unwrap();
+    request.acl = Arc::new(ACLToken {
+        member_of: vec![account_id],
+        access_to: vec![],
+    })
+    .into();
+    db.mail_query(request).map(|response| response.ids)
+}
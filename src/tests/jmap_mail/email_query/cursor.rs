@@ -0,0 +1,164 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use std::sync::Arc;
+
+use jmap::{
+    error::method::MethodError,
+    request::{
+        query::{QueryRequest, QueryResponse},
+        set::SetRequest,
+        MaybeResultReference,
+    },
+    types::{cursor::JMAPQueryCursor, jmap::JMAPId},
+};
+use jmap_mail::mail::{
+    import::JMAPMailImport,
+    query::JMAPMailQuery,
+    schema::Email,
+    set::{JMAPSetMail, SetArguments},
+};
+use store::{blob::BlobId, core::acl::ACLToken, AccountId, JMAPStore, Store};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+const TOTAL_MESSAGES: usize = 1000;
+const BATCH_SIZE: usize = 64;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email/query cursor tests...");
+    let account_id = 1;
+
+    // Create account and mailbox
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+
+    for num in 0..TOTAL_MESSAGES {
+        let message = format!(
+            "From: john@example.com\r\nSubject: Message {}\r\n\r\nHello.\r\n",
+            num
+        )
+        .into_bytes();
+        let blob_id = BlobId::new_external(&message);
+        db.blob_store(&blob_id, message.clone()).unwrap();
+        db.mail_import_item(
+            account_id,
+            blob_id,
+            &message,
+            vec![mailbox_id],
+            vec![],
+            Some(1_000_000_000 + num as i64),
+        )
+        .unwrap();
+    }
+
+    // Iterating with a cursor yields the same results as a single query,
+    // never returning more than one batch at a time
+    let expected_ids = query(&db, account_id, None, None).unwrap().ids;
+    assert_eq!(expected_ids.len(), TOTAL_MESSAGES);
+    let mut ids = Vec::with_capacity(TOTAL_MESSAGES);
+    let mut cursor = JMAPQueryCursor::default();
+    loop {
+        let response = query(&db, account_id, cursor.into(), BATCH_SIZE.into()).unwrap();
+        assert!(response.ids.len() <= BATCH_SIZE);
+        assert_eq!(response.position as usize, ids.len());
+        ids.extend(response.ids);
+        if let Some(next_cursor) = response.next_cursor {
+            cursor = next_cursor;
+        } else {
+            break;
+        }
+    }
+    assert_eq!(ids, expected_ids);
+
+    // Removing the last id returned does not skip or repeat any results
+    let response = query(
+        &db,
+        account_id,
+        JMAPQueryCursor::default().into(),
+        BATCH_SIZE.into(),
+    )
+    .unwrap();
+    let last_id = *response.ids.last().unwrap();
+    destroy(&db, account_id, last_id);
+    let response = query(&db, account_id, response.next_cursor, BATCH_SIZE.into()).unwrap();
+    assert_eq!(response.position as usize, BATCH_SIZE - 1);
+    assert_eq!(response.ids, expected_ids[BATCH_SIZE..BATCH_SIZE * 2]);
+
+    // Expired cursors are rejected
+    assert!(matches!(
+        query(
+            &db,
+            account_id,
+            JMAPQueryCursor::new(BATCH_SIZE, expected_ids[BATCH_SIZE - 1], 0).into(),
+            BATCH_SIZE.into(),
+        ),
+        Err(MethodError::InvalidArguments(_))
+    ));
+}
+
+fn query<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    cursor: Option<JMAPQueryCursor>,
+    limit: Option<usize>,
+) -> jmap::Result<QueryResponse>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut request: QueryRequest<Email> = serde_json::from_value(serde_json::json!({
+        "accountId": JMAPId::new(account_id as u64).to_string(),
+        "sort": [{"property": "receivedAt", "isAscending": true}],
+        "limit": limit,
+    }))
+    .unwrap();
+    request.acl = acl(account_id).into();
+    request.cursor = cursor;
+    db.mail_query(request)
+}
+
+fn destroy<T>(db: &JMAPStore<T>, account_id: AccountId, id: JMAPId)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let response = db
+        .mail_set(SetRequest {
+            acl: acl(account_id).into(),
+            account_id: JMAPId::new(account_id as u64),
+            if_in_state: None,
+            create: None,
+            update: None,
+            destroy: MaybeResultReference::Value(vec![id]).into(),
+            arguments: SetArguments::default(),
+        })
+        .unwrap();
+    assert_eq!(response.destroyed, vec![id]);
+}
+
+fn acl(account_id: AccountId) -> Arc<ACLToken> {
+    Arc::new(ACLToken {
+        member_of: vec![account_id],
+        access_to: vec![],
+    })
+}
@@ -0,0 +1,113 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use std::sync::Arc;
+
+use jmap::{jmap_store::Object, request::query::QueryRequest, types::jmap::JMAPId};
+use jmap_mail::mail::{import::JMAPMailImport, query::JMAPMailQuery, schema::Email};
+use store::{blob::BlobId, core::acl::ACLToken, JMAPStore, Store};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email/query default sort tests...");
+    let account_id = 1;
+
+    // Create account and mailbox
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+
+    // Import messages out of chronological order
+    let mut ids = Vec::new();
+    for received_at in [120i64, 0, 240, 60] {
+        let message = format!(
+            concat!(
+                "From: sender@example.com\r\n",
+                "To: jdoe@example.com\r\n",
+                "Subject: Received at {}\r\n\r\n",
+                "Hello world.\r\n"
+            ),
+            received_at
+        )
+        .into_bytes();
+        let blob_id = BlobId::new_external(&message);
+        db.blob_store(&blob_id, message.clone()).unwrap();
+        ids.push((
+            received_at,
+            *db.mail_import_item(
+                account_id,
+                blob_id,
+                &message,
+                vec![mailbox_id],
+                vec![],
+                Some(received_at),
+            )
+            .unwrap()
+            .id()
+            .unwrap(),
+        ));
+    }
+    ids.sort_unstable_by(|a, b| b.0.cmp(&a.0));
+    let expected_ids = ids.into_iter().map(|(_, id)| id).collect::<Vec<_>>();
+
+    // Queries without comparators, or with an empty list, return newest first
+    for sort in ["", ", \"sort\": []"] {
+        let mut request: QueryRequest<Email> = serde_json::from_str(&format!(
+            "{{\"accountId\": \"{}\"{}}}",
+            JMAPId::new(account_id as u64),
+            sort
+        ))
+        .unwrap();
+        request.acl = Arc::new(ACLToken {
+            member_of: vec![account_id],
+            access_to: vec![],
+        })
+        .into();
+        assert_eq!(
+            db.mail_query(request).unwrap().ids,
+            expected_ids,
+            "{:?}",
+            sort
+        );
+    }
+
+    // Explicit comparators take precedence over the default sort
+    let mut request: QueryRequest<Email> = serde_json::from_str(&format!(
+        concat!(
+            "{{\"accountId\": \"{}\", ",
+            "\"sort\": [{{\"property\": \"receivedAt\", \"isAscending\": true}}]}}"
+        ),
+        JMAPId::new(account_id as u64),
+    ))
+    .unwrap();
+    request.acl = Arc::new(ACLToken {
+        member_of: vec![account_id],
+        access_to: vec![],
+    })
+    .into();
+    assert_eq!(
+        db.mail_query(request).unwrap().ids,
+        expected_ids.into_iter().rev().collect::<Vec<_>>()
+    );
+}
@@ -21,6 +21,14 @@
  * for more details.
 */
 
+pub mod address;
+pub mod conditions;
+pub mod cursor;
+pub mod default_sort;
+pub mod received_after;
+pub mod snapshot;
+pub mod sort;
+
 use std::{collections::hash_map::Entry, time::Instant};
 
 use actix_web::web;
@@ -664,6 +672,22 @@ pub async fn query_options(client: &mut Client) {
             }
         }
     }
+
+    // Totals should only count one message per thread when collapsing
+    let mut totals = Vec::with_capacity(2);
+    for collapse_threads in [false, true] {
+        let mut request = client.build();
+        let query_request = request
+            .query_email()
+            .sort([email::query::Comparator::subject()])
+            .calculate_total(true);
+        query_request.arguments().collapse_threads(collapse_threads);
+        let response = request.send_query_email().await.unwrap();
+        totals.push((response.total().unwrap(), response.ids().len()));
+    }
+    assert_eq!(totals[0].0, totals[0].1);
+    assert_eq!(totals[1].0, totals[1].1);
+    assert!(totals[1].0 < totals[0].0, "{:?}", totals);
 }
 
 pub async fn create(client: &mut Client) {
@@ -0,0 +1,125 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    jmap_store::Object,
+    request::query::QueryRequest,
+    types::{date::JMAPDate, jmap::JMAPId},
+};
+use jmap_mail::mail::{import::JMAPMailImport, query::JMAPMailQuery, schema::Email};
+use store::{blob::BlobId, core::acl::ACLToken, JMAPStore, Store};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email/query receivedAt incremental fetch tests...");
+    let account_id = 1;
+
+    // Create account and mailbox
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+
+    // Import messages out of order, some of them sharing the same receivedAt
+    for (pos, received_at) in [300i64, 60, 180, 180, 0, 240, 60, 120, 300, 180]
+        .into_iter()
+        .enumerate()
+    {
+        let message = format!(
+            concat!(
+                "From: sender@example.com\r\n",
+                "To: jdoe@example.com\r\n",
+                "Subject: Message {} received at {}\r\n\r\n",
+                "Hello world.\r\n"
+            ),
+            pos, received_at
+        )
+        .into_bytes();
+        let blob_id = BlobId::new_external(&message);
+        db.blob_store(&blob_id, message.clone()).unwrap();
+        db.mail_import_item(
+            account_id,
+            blob_id,
+            &message,
+            vec![mailbox_id],
+            vec![],
+            Some(received_at),
+        )
+        .unwrap();
+    }
+
+    // The index walk must return the same results as the filtered and
+    // sorted query, which is forced by wrapping the condition in an AND.
+    for received_after in [0i64, 59, 60, 180, 299, 300] {
+        let after = JMAPDate::from_timestamp(received_after).to_string();
+        for limit in ["", ", \"limit\": 1", ", \"limit\": 3", ", \"limit\": 20"] {
+            let fast_path = query(
+                &db,
+                account_id,
+                &format!("{{\"after\": \"{}\"}}", after),
+                limit,
+            );
+            let full_path = query(
+                &db,
+                account_id,
+                &format!(
+                    "{{\"operator\": \"AND\", \"conditions\": [{{\"after\": \"{}\"}}]}}",
+                    after
+                ),
+                limit,
+            );
+            assert_eq!(fast_path, full_path, "after {}{}", received_after, limit);
+        }
+    }
+}
+
+fn query<T>(
+    db: &JMAPStore<T>,
+    account_id: u32,
+    filter: &str,
+    limit: &str,
+) -> (Vec<JMAPId>, Option<usize>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut request: QueryRequest<Email> = serde_json::from_str(&format!(
+        concat!(
+            "{{\"accountId\": \"{}\", \"filter\": {}, ",
+            "\"sort\": [{{\"property\": \"receivedAt\", \"isAscending\": false}}]{}}}"
+        ),
+        JMAPId::new(account_id as u64),
+        filter,
+        limit
+    ))
+    .unwrap();
+    request.acl = Arc::new(ACLToken {
+        member_of: vec![account_id],
+        access_to: vec![],
+    })
+    .into();
+    let response = db.mail_query(request).unwrap();
+    (response.ids, response.limit)
+}
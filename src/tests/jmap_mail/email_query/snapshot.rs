@@ -0,0 +1,152 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use std::time::Duration;
+
+use actix_web::web;
+use jmap::types::jmap::JMAPId;
+use jmap_client::{
+    client::{Client, Credentials},
+    email::{self, query::Filter},
+    mailbox::Role,
+};
+use store::Store;
+
+use crate::JMAPServer;
+
+pub async fn test<T>(server: web::Data<JMAPServer<T>>, client: &mut Client)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email Query snapshot tests...");
+
+    let mailbox_id = client
+        .set_default_account_id(JMAPId::new(1).to_string())
+        .mailbox_create("JMAP Snapshot", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+
+    let mut email_ids = Vec::new();
+    for num in 0..50 {
+        email_ids.push(
+            client
+                .email_import(
+                    format!(
+                        "From: test@test.com\r\nSubject: test {}\r\n\r\ntest {}\r\n",
+                        num, num
+                    )
+                    .into_bytes(),
+                    [&mailbox_id],
+                    None::<Vec<&str>>,
+                    None,
+                )
+                .await
+                .unwrap()
+                .take_id(),
+        );
+    }
+
+    // Destroy messages from a second connection while the first one
+    // keeps running Email/query + Email/get batches.
+    let mut mutator = Client::new()
+        .credentials(Credentials::bearer("DO_NOT_ATTEMPT_THIS_AT_HOME"))
+        .connect(server.base_session.base_url())
+        .await
+        .unwrap();
+    mutator.set_default_account_id(JMAPId::new(1));
+    let mutator = actix_web::rt::spawn(async move {
+        for email_id in email_ids {
+            mutator.email_destroy(&email_id).await.unwrap();
+        }
+    });
+
+    loop {
+        let mut request = client.build();
+        let query_result_ref = request
+            .query_email()
+            .filter(Filter::in_mailbox(&mailbox_id))
+            .result_reference();
+        request
+            .get_email()
+            .ids_ref(query_result_ref)
+            .properties([email::Property::Id]);
+        let mut response = request.send().await.unwrap().unwrap_method_responses();
+        let mut get_response = response.pop().unwrap().unwrap_get_email().unwrap();
+        let query_ids = response
+            .pop()
+            .unwrap()
+            .unwrap_query_email()
+            .unwrap()
+            .take_ids();
+
+        // Every id returned by the query has to be readable by the get call.
+        assert!(
+            get_response.not_found().is_empty(),
+            "{:?}",
+            get_response.not_found()
+        );
+        let get_ids = get_response
+            .take_list()
+            .into_iter()
+            .map(|email| email.id().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(query_ids, get_ids);
+
+        if query_ids.is_empty() {
+            break;
+        }
+    }
+
+    mutator.await.unwrap();
+
+    // Writes wait for the batches reading the account to finish
+    let snapshot = server.store.acquire_read_snapshot(1);
+    let mut mutator = Client::new()
+        .credentials(Credentials::bearer("DO_NOT_ATTEMPT_THIS_AT_HOME"))
+        .connect(server.base_session.base_url())
+        .await
+        .unwrap();
+    mutator.set_default_account_id(JMAPId::new(1));
+    let destroy_mailbox_id = mailbox_id.clone();
+    let mutator = actix_web::rt::spawn(async move {
+        mutator
+            .mailbox_destroy(&destroy_mailbox_id, true)
+            .await
+            .unwrap();
+    });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(client
+        .mailbox_get(&mailbox_id, None::<Vec<_>>)
+        .await
+        .unwrap()
+        .is_some());
+    drop(snapshot);
+
+    mutator.await.unwrap();
+    assert!(client
+        .mailbox_get(&mailbox_id, None::<Vec<_>>)
+        .await
+        .unwrap()
+        .is_none());
+    server.store.assert_is_empty();
+}
@@ -0,0 +1,219 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    error::method::MethodError, jmap_store::Object, request::query::QueryRequest,
+    types::jmap::JMAPId,
+};
+use jmap_mail::mail::{
+    import::JMAPMailImport, query::JMAPMailQuery, reindex::JMAPMailReindex, schema::Email,
+    MessageData, MessageField,
+};
+use store::{
+    blob::BlobId,
+    core::{acl::ACLToken, collection::Collection, document::Document},
+    serialize::StoreDeserialize,
+    write::batch::WriteBatch,
+    AccountId, DocumentId, FieldId, JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+const MESSAGES: [(&str, &str, &str); 5] = [
+    (
+        "\"bob Smith\" <zed@example.com>",
+        "Re: banana",
+        "Carol <carol@example.com>",
+    ),
+    (
+        "alice@example.com",
+        "apple",
+        "\"ALICE Walker\" <a.walker@example.com>",
+    ),
+    (
+        "Carol <carol@example.com>",
+        "Fwd: Cherry",
+        "bob@example.com",
+    ),
+    (
+        "\"ALICE Walker\" <a.walker@example.com>",
+        "Date",
+        "\"Dave\" <dave@example.com>",
+    ),
+    (
+        "Dave <dave@example.com>",
+        "RE: elderberry",
+        "Bob Smith <zed@example.com>",
+    ),
+];
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email/query sort by participants and subject tests...");
+    let account_id = 1;
+
+    // Create account and mailbox
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+
+    let mut ids = Vec::with_capacity(MESSAGES.len());
+    for (from, subject, to) in MESSAGES {
+        let message = format!(
+            "From: {}\r\nTo: {}\r\nSubject: {}\r\n\r\nHello.\r\n",
+            from, to, subject
+        )
+        .into_bytes();
+        let blob_id = BlobId::new_external(&message);
+        db.blob_store(&blob_id, message.clone()).unwrap();
+        ids.push(
+            *db.mail_import_item(
+                account_id,
+                blob_id,
+                &message,
+                vec![mailbox_id],
+                vec![],
+                None,
+            )
+            .unwrap()
+            .id()
+            .unwrap(),
+        );
+    }
+
+    // Participants sort by display name then address, subjects by their base
+    // subject, both ignoring case
+    for (property, expected) in [
+        ("from", [3, 1, 0, 2, 4]),
+        ("to", [1, 4, 2, 0, 3]),
+        ("subject", [1, 0, 2, 3, 4]),
+    ] {
+        let expected = expected.iter().map(|pos| ids[*pos]).collect::<Vec<_>>();
+        for collation in [None, Some("i;unicode-casemap")] {
+            assert_eq!(
+                query(&db, account_id, property, true, collation).unwrap(),
+                expected,
+                "{}",
+                property
+            );
+        }
+        assert_eq!(
+            query(&db, account_id, property, false, None).unwrap(),
+            expected.into_iter().rev().collect::<Vec<_>>(),
+            "{}",
+            property
+        );
+    }
+
+    // Sort keys are case-folded, numeric collations cannot be applied to them
+    assert!(matches!(
+        query(&db, account_id, "subject", true, Some("i;ascii-numeric")),
+        Err(MethodError::UnsupportedSort(_))
+    ));
+
+    // Messages imported before subjects had a sort key get one once reindexed
+    let subject_sort = [FieldId::from(MessageField::SubjectSort)];
+    let expected = [1, 0, 2, 3, 4]
+        .iter()
+        .map(|pos| ids[*pos])
+        .collect::<Vec<_>>();
+    let mut batch = WriteBatch::new(account_id);
+    for id in &ids {
+        let mut document = Document::new(Collection::Mail, id.get_document_id());
+        get_message_data(&db, account_id, id.get_document_id())
+            .build_index(&mut document, false, false)
+            .unwrap();
+        document
+            .text_fields
+            .retain(|field| subject_sort.contains(&field.field));
+        document.number_fields.clear();
+        document.binary_fields.clear();
+        document.tag_fields.clear();
+        document.blobs.clear();
+        batch.update_document(document);
+    }
+    db.write(batch).unwrap();
+    assert_ne!(
+        query(&db, account_id, "subject", true, None).unwrap(),
+        expected
+    );
+    db.mail_reindex_pending().unwrap();
+    assert_eq!(
+        query(&db, account_id, "subject", true, None).unwrap(),
+        expected
+    );
+}
+
+fn get_message_data<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    document_id: DocumentId,
+) -> MessageData
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    db.get_document_value::<BlobId>(
+        account_id,
+        Collection::Mail,
+        document_id,
+        MessageField::Metadata.into(),
+    )
+    .unwrap()
+    .and_then(|blob_id| db.blob_get(&blob_id).unwrap())
+    .and_then(|bytes| MessageData::deserialize(&bytes))
+    .unwrap()
+}
+
+fn query<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    property: &str,
+    is_ascending: bool,
+    collation: Option<&str>,
+) -> jmap::Result<Vec<JMAPId>>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut request: QueryRequest<Email> = serde_json::from_str(&format!(
+        concat!(
+            "{{\"accountId\": \"{}\", \"sort\": [{{\"property\": \"{}\", ",
+            "\"isAscending\": {}{}}}]}}"
+        ),
+        JMAPId::new(account_id as u64),
+        property,
+        is_ascending,
+        collation.map_or_else(String::new, |collation| format!(
+            ", \"collation\": \"{}\"",
+            collation
+        ))
+    ))
+    .unwrap();
+    request.acl = Arc::new(ACLToken {
+        member_of: vec![account_id],
+        access_to: vec![],
+    })
+    .into();
+    db.mail_query(request).map(|response| response.ids)
+}
@@ -0,0 +1,171 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use std::sync::Arc;
+
+use jmap::{
+    error::set::SetErrorType,
+    jmap_store::Object,
+    request::set::SetRequest,
+    types::{blob::JMAPBlob, jmap::JMAPId},
+};
+use jmap_mail::mail::{schema::Email, set::JMAPSetMail};
+use store::{
+    blob::BlobId,
+    core::{acl::ACLToken, vec_map::VecMap},
+    AccountId, DocumentId, JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running attachment size limit tests...");
+    let account_id = 1;
+    let max_size = db.config.mail_attachments_max_size;
+
+    // Create account and mailbox
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+
+    let small_blob = upload_blob(&db, account_id, vec![b'a'; max_size / 4]);
+    let large_blob = upload_blob(&db, account_id, vec![b'b'; (max_size / 4) * 3]);
+    let large_body = "c".repeat(max_size * 2);
+
+    // A large body with small attachments is accepted
+    assert!(create_email(
+        &db,
+        account_id,
+        mailbox_id,
+        &format!(
+            concat!(
+                "\"bodyValues\": {{\"1\": {{\"value\": \"{}\"}}}}, ",
+                "\"textBody\": [{{\"type\": \"text/plain\", \"partId\": \"1\"}}], ",
+                "\"attachments\": [{{\"type\": \"image/png\", \"blobId\": \"{}\"}}, ",
+                "{{\"type\": \"image/png\", \"blobId\": \"{}\"}}]"
+            ),
+            large_body, small_blob, small_blob
+        ),
+    )
+    .is_ok());
+
+    // Attachments exceeding the limit are rejected
+    assert!(matches!(
+        create_email(
+            &db,
+            account_id,
+            mailbox_id,
+            &format!(
+                concat!(
+                    "\"bodyValues\": {{\"1\": {{\"value\": \"Hello\"}}}}, ",
+                    "\"textBody\": [{{\"type\": \"text/plain\", \"partId\": \"1\"}}], ",
+                    "\"attachments\": [{{\"type\": \"image/png\", \"blobId\": \"{}\"}}, ",
+                    "{{\"type\": \"image/png\", \"blobId\": \"{}\"}}]"
+                ),
+                large_blob, small_blob
+            ),
+        ),
+        Err(SetErrorType::TooLarge)
+    ));
+
+    // Only parts with an attachment disposition count within a bodyStructure
+    let body_structure = |disposition: &str| {
+        format!(
+            concat!(
+                "\"bodyStructure\": {{\"type\": \"multipart/mixed\", \"subParts\": [",
+                "{{\"type\": \"image/png\", \"blobId\": \"{}\", \"disposition\": \"inline\"}}, ",
+                "{{\"type\": \"image/png\", \"blobId\": \"{}\", \"disposition\": \"{}\"}}]}}"
+            ),
+            large_blob, large_blob, disposition
+        )
+    };
+    assert!(create_email(&db, account_id, mailbox_id, &body_structure("inline")).is_ok());
+    assert!(create_email(&db, account_id, mailbox_id, &body_structure("attachment")).is_ok());
+    let body_structure = format!(
+        concat!(
+            "\"bodyStructure\": {{\"type\": \"multipart/mixed\", \"subParts\": [",
+            "{{\"type\": \"image/png\", \"blobId\": \"{}\", \"disposition\": \"attachment\"}}, ",
+            "{{\"type\": \"image/png\", \"blobId\": \"{}\", \"disposition\": \"attachment\"}}]}}"
+        ),
+        large_blob, large_blob
+    );
+    assert!(matches!(
+        create_email(&db, account_id, mailbox_id, &body_structure),
+        Err(SetErrorType::TooLarge)
+    ));
+}
+
+fn upload_blob<T>(db: &JMAPStore<T>, account_id: AccountId, bytes: Vec<u8>) -> JMAPBlob
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let blob_id = BlobId::new_external(&bytes);
+    db.blob_store(&blob_id, bytes).unwrap();
+    db.blob_link_ephemeral(&blob_id, account_id).unwrap();
+    JMAPBlob::new(blob_id)
+}
+
+fn create_email<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    mailbox_id: DocumentId,
+    properties: &str,
+) -> Result<JMAPId, SetErrorType>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let email: Email = serde_json::from_str(&format!(
+        "{{\"mailboxIds\": {{\"{}\": true}}, \"subject\": \"Attachments\", {}}}",
+        JMAPId::from(mailbox_id),
+        properties
+    ))
+    .unwrap();
+    let mut create = VecMap::new();
+    create.append("e1".to_string(), email);
+
+    let mut response = db
+        .mail_set(SetRequest {
+            acl: Arc::new(ACLToken {
+                member_of: vec![account_id],
+                access_to: vec![],
+            })
+            .into(),
+            account_id: JMAPId::new(account_id as u64),
+            if_in_state: None,
+            create: create.into(),
+            update: None,
+            destroy: None,
+            arguments: Default::default(),
+        })
+        .unwrap();
+
+    if let Some(email) = response.created.remove("e1") {
+        Ok(*email.id().unwrap())
+    } else {
+        Err(response
+            .not_created
+            .remove(&"e1".to_string())
+            .unwrap()
+            .type_)
+    }
+}
@@ -0,0 +1,131 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use std::sync::Arc;
+
+use jmap::{jmap_store::Object, request::query::QueryRequest, types::jmap::JMAPId};
+use jmap_mail::mail::{import::JMAPMailImport, query::JMAPMailQuery, schema::Email};
+use store::{blob::BlobId, core::acl::ACLToken, JMAPStore, Store};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email/query attachmentType tests...");
+    let account_id = 1;
+
+    // Create account and mailbox
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+
+    // Import messages with PDF and image attachments
+    let mut ids = Vec::new();
+    for (num, attachment_types) in [
+        vec!["application/pdf"],
+        vec!["image/png"],
+        vec!["Image/JPEG", "application/pdf"],
+        vec![],
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let message = build_message(num, &attachment_types);
+        let blob_id = BlobId::new_external(&message);
+        db.blob_store(&blob_id, message.clone()).unwrap();
+        ids.push(
+            *db.mail_import_item(
+                account_id,
+                blob_id,
+                &message,
+                vec![mailbox_id],
+                vec![],
+                Some(num as i64 * 60),
+            )
+            .unwrap()
+            .id()
+            .unwrap(),
+        );
+    }
+
+    for (attachment_type, expected_ids) in [
+        ("application/pdf", vec![ids[0], ids[2]]),
+        ("image/", vec![ids[1], ids[2]]),
+        ("image", vec![ids[1], ids[2]]),
+        ("image/png", vec![ids[1]]),
+        ("image/jpeg", vec![ids[2]]),
+        ("application/", vec![ids[0], ids[2]]),
+        ("video/", vec![]),
+        ("text/", vec![]),
+    ] {
+        let mut request: QueryRequest<Email> = serde_json::from_str(&format!(
+            concat!(
+                "{{\"accountId\": \"{}\", ",
+                "\"filter\": {{\"attachmentType\": \"{}\"}}, ",
+                "\"sort\": [{{\"property\": \"receivedAt\", \"isAscending\": true}}]}}"
+            ),
+            JMAPId::new(account_id as u64),
+            attachment_type
+        ))
+        .unwrap();
+        request.acl = Arc::new(ACLToken {
+            member_of: vec![account_id],
+            access_to: vec![],
+        })
+        .into();
+        assert_eq!(
+            db.mail_query(request).unwrap().ids,
+            expected_ids,
+            "{}",
+            attachment_type
+        );
+    }
+}
+
+fn build_message(num: usize, attachment_types: &[&str]) -> Vec<u8> {
+    let mut message = format!(
+        concat!(
+            "From: sender@example.com\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: Message {}\r\n",
+            "Content-Type: multipart/mixed; boundary=\"boundary\"\r\n\r\n",
+            "--boundary\r\n",
+            "Content-Type: text/plain\r\n\r\n",
+            "This is message number {}.\r\n"
+        ),
+        num, num
+    );
+    for (part_num, attachment_type) in attachment_types.iter().enumerate() {
+        message.push_str(&format!(
+            concat!(
+                "--boundary\r\n",
+                "Content-Type: {}\r\n",
+                "Content-Disposition: attachment; filename=\"file{}\"\r\n",
+                "Content-Transfer-Encoding: base64\r\n\r\n",
+                "SGVsbG8gd29ybGQ=\r\n"
+            ),
+            attachment_type, part_num
+        ));
+    }
+    message.push_str("--boundary--\r\n");
+    message.into_bytes()
+}
@@ -0,0 +1,155 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    error::set::{SetError, SetErrorType},
+    jmap_store::Object,
+    request::set::SetRequest,
+    types::jmap::JMAPId,
+};
+use jmap_mail::mail::{
+    schema::{Email, Property},
+    set::JMAPSetMail,
+};
+use store::{
+    core::{acl::ACLToken, vec_map::VecMap},
+    AccountId, DocumentId, JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running bodyStructure and body shortcut conflict tests...");
+    let account_id = 1;
+
+    // Create account and mailbox
+    let mailbox_id = create_account_mailbox(&db, account_id, "Drafts", "drafts");
+
+    let body_structure = concat!(
+        "\"bodyStructure\": {\"type\": \"multipart/mixed\", \"subParts\": [",
+        "{\"type\": \"text/plain\", \"partId\": \"2\"}]}"
+    );
+
+    // The body shortcuts alone are accepted
+    assert!(create_email(
+        &db,
+        account_id,
+        mailbox_id,
+        concat!(
+            "\"textBody\": [{\"type\": \"text/plain\", \"partId\": \"1\"}], ",
+            "\"htmlBody\": [{\"type\": \"text/html\", \"partId\": \"3\"}]"
+        ),
+    )
+    .is_ok());
+
+    // So is a bodyStructure alone
+    assert!(create_email(&db, account_id, mailbox_id, body_structure).is_ok());
+
+    // Combining bodyStructure with any of the shortcuts is rejected
+    for (shortcut, property) in [
+        (
+            "\"textBody\": [{\"type\": \"text/plain\", \"partId\": \"1\"}]",
+            Property::TextBody,
+        ),
+        (
+            "\"htmlBody\": [{\"type\": \"text/html\", \"partId\": \"3\"}]",
+            Property::HtmlBody,
+        ),
+        (
+            "\"attachments\": [{\"type\": \"text/plain\", \"partId\": \"1\"}]",
+            Property::Attachments,
+        ),
+    ] {
+        for properties in [
+            format!("{}, {}", body_structure, shortcut),
+            format!("{}, {}", shortcut, body_structure),
+        ] {
+            let error = create_email(&db, account_id, mailbox_id, &properties).unwrap_err();
+            assert!(
+                matches!(error.type_, SetErrorType::InvalidProperties),
+                "{:?}",
+                error
+            );
+            let error = serde_json::to_value(&error).unwrap();
+            let properties = error["properties"].as_array().unwrap();
+            for property in [property.clone(), Property::BodyStructure] {
+                assert!(
+                    properties.contains(&serde_json::Value::String(property.to_string())),
+                    "{:?}",
+                    error
+                );
+            }
+        }
+    }
+}
+
+fn create_email<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    mailbox_id: DocumentId,
+    properties: &str,
+) -> Result<JMAPId, SetError<Property>>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let email: Email = serde_json::from_str(&format!(
+        concat!(
+            "{{\"mailboxIds\": {{\"{}\": true}}, \"subject\": \"Conflicts\", ",
+            "\"bodyValues\": {{\"1\": {{\"value\": \"Hello\"}}, ",
+            "\"2\": {{\"value\": \"World\"}}, ",
+            "\"3\": {{\"value\": \"<p>Hello</p>\"}}}}, {}}}"
+        ),
+        JMAPId::from(mailbox_id),
+        properties
+    ))
+    .unwrap();
+    let mut create = VecMap::new();
+    create.append("e1".to_string(), email);
+
+    let mut response = db
+        .mail_set(SetRequest {
+            acl: Arc::new(ACLToken {
+                member_of: vec![account_id],
+                access_to: vec![],
+            })
+            .into(),
+            account_id: JMAPId::new(account_id as u64),
+            if_in_state: None,
+            create: create.into(),
+            update: None,
+            destroy: None,
+            arguments: Default::default(),
+        })
+        .unwrap();
+
+    if let Some(email) = response.created.remove("e1") {
+        Ok(*email.id().unwrap())
+    } else {
+        Err(response.not_created.remove(&"e1".to_string()).unwrap())
+    }
+}
@@ -0,0 +1,125 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use std::sync::Arc;
+
+use jmap::{
+    jmap_store::Object,
+    orm::serialize::JMAPOrm,
+    request::{set::SetRequest, MaybeResultReference},
+    types::jmap::JMAPId,
+    SUPERUSER_ID,
+};
+use jmap_mail::{
+    mail::{
+        import::JMAPMailImport,
+        set::{JMAPSetMail, SetArguments},
+    },
+    mailbox::schema::Mailbox,
+};
+use store::{
+    blob::BlobId,
+    core::{acl::ACLToken, collection::Collection, document::Document},
+    write::batch::WriteBatch,
+    JMAPStore, Store,
+};
+
+use crate::tests::{jmap_mail::create_account_mailbox, store::utils::StoreCompareWith};
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email/set destroy blob cleanup tests...");
+    let account_id = 1;
+
+    // Blobs are stored compressed, so their files differ from their contents
+    assert!(db.blob_store.compress);
+
+    // Create account and mailbox
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+
+    // Import messages sharing the same body, so their blobs are referenced more than once
+    let mut ids = Vec::new();
+    for num in 0..5 {
+        let message = format!(
+            concat!(
+                "From: sender@example.com\r\n",
+                "Subject: Message {}\r\n\r\n",
+                "The same body for every message.\r\n"
+            ),
+            num % 2
+        )
+        .into_bytes();
+        let blob_id = BlobId::new_external(&message);
+        db.blob_store(&blob_id, message.clone()).unwrap();
+        ids.push(
+            *db.mail_import_item(
+                account_id,
+                blob_id,
+                &message,
+                vec![mailbox_id],
+                vec![],
+                None,
+            )
+            .unwrap()
+            .id()
+            .unwrap(),
+        );
+    }
+
+    // Destroy all messages
+    let response = db
+        .mail_set(SetRequest {
+            acl: Arc::new(ACLToken {
+                member_of: vec![account_id],
+                access_to: vec![],
+            })
+            .into(),
+            account_id: JMAPId::new(account_id as u64),
+            if_in_state: None,
+            create: None,
+            update: None,
+            destroy: MaybeResultReference::Value(ids.clone()).into(),
+            arguments: SetArguments::default(),
+        })
+        .unwrap();
+    assert_eq!(response.destroyed, ids);
+
+    // Delete the mailbox and the account
+    let mut batch = WriteBatch::new(account_id);
+    let mut document = Document::new(Collection::Mailbox, mailbox_id);
+    db.get_orm::<Mailbox>(account_id, mailbox_id)
+        .unwrap()
+        .unwrap()
+        .delete(&mut document);
+    batch.delete_document(document);
+    batch.log_delete(Collection::Mailbox, mailbox_id);
+    db.write(batch).unwrap();
+    let mut batch = WriteBatch::new(SUPERUSER_ID);
+    batch.delete_document(Document::new(Collection::Principal, account_id));
+    db.write(batch).unwrap();
+
+    // No document should be left holding a reference to a blob
+    db.purge_blobs().unwrap();
+    db.assert_is_empty();
+}
@@ -0,0 +1,121 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use std::{
+    sync::{mpsc, Arc},
+    time::Duration,
+};
+
+use jmap::{
+    error::method::MethodError,
+    jmap_store::changes::JMAPChanges,
+    request::{
+        set::{SetRequest, SetResponse},
+        MaybeResultReference,
+    },
+    types::{jmap::JMAPId, state::JMAPState},
+};
+use jmap_mail::mail::{
+    schema::Email,
+    set::{JMAPSetMail, SetArguments},
+};
+use store::{
+    core::{acl::ACLToken, collection::Collection, vec_map::VecMap},
+    AccountId, JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running empty Email/set tests...");
+    let account_id = 1;
+
+    // Create account, mailbox and a message
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+
+    let mut create = VecMap::new();
+    create.append(
+        "m1".to_string(),
+        serde_json::from_str::<Email>(&format!(
+            r#"{{"mailboxIds": {{"{}": true}}, "subject": "Hello"}}"#,
+            JMAPId::from(mailbox_id)
+        ))
+        .unwrap(),
+    );
+    let mut request = empty_request(account_id, None);
+    request.create = create.into();
+    assert_eq!(db.mail_set(request).unwrap().created.len(), 1);
+    let state = db.get_state(account_id, Collection::Mail).unwrap();
+    assert_ne!(state, JMAPState::Initial);
+
+    // Empty requests report the current state as both old and new state
+    let response = db.mail_set(empty_request(account_id, None)).unwrap();
+    assert_eq!(response.old_state, Some(state.clone()));
+    assert_eq!(response.new_state, Some(state.clone()));
+    assert!(response.change_id.is_none());
+    assert_eq!(
+        db.mail_set(empty_request(account_id, state.clone().into()))
+            .unwrap()
+            .new_state,
+        Some(state.clone())
+    );
+
+    // ifInState is still enforced
+    assert!(matches!(
+        db.mail_set(empty_request(account_id, JMAPState::Initial.into())),
+        Err(MethodError::StateMismatch)
+    ));
+
+    // Empty requests do not wait for the account write lock
+    let lock = db.lock_account(account_id);
+    let (tx, rx) = mpsc::channel();
+    let db_ = db.clone();
+    std::thread::spawn(move || {
+        tx.send(db_.mail_set(empty_request(account_id, None)).unwrap())
+            .unwrap();
+    });
+    let response: SetResponse<Email> = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(response.new_state, Some(state.clone()));
+    drop(lock);
+
+    // Nothing was written
+    assert_eq!(db.get_state(account_id, Collection::Mail).unwrap(), state);
+}
+
+fn empty_request(account_id: AccountId, if_in_state: Option<JMAPState>) -> SetRequest<Email> {
+    SetRequest {
+        acl: Arc::new(ACLToken {
+            member_of: vec![account_id],
+            access_to: vec![],
+        })
+        .into(),
+        account_id: JMAPId::new(account_id as u64),
+        if_in_state,
+        create: VecMap::new().into(),
+        update: VecMap::new().into(),
+        destroy: MaybeResultReference::Value(Vec::new()).into(),
+        arguments: SetArguments::default(),
+    }
+}
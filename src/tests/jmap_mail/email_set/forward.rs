@@ -0,0 +1,161 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    error::set::SetErrorType,
+    request::set::SetRequest,
+    types::{blob::JMAPBlob, jmap::JMAPId},
+};
+use jmap_mail::mail::{
+    import::JMAPMailImport,
+    schema::{Email, Property, Value},
+    set::JMAPSetMail,
+};
+use store::{
+    blob::BlobId,
+    core::{acl::ACLToken, vec_map::VecMap},
+    AccountId, DocumentId, JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+const MESSAGE: &[u8] = concat!(
+    "From: john@example.com\r\n",
+    "To: jane@example.com\r\n",
+    "Subject: Original message\r\n",
+    "Content-Type: text/plain; charset=utf-8\r\n",
+    "\r\n",
+    "Ünïcödé text that should travel untouched.\r\n"
+)
+.as_bytes();
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email forward as attachment tests...");
+    let account_id = 1;
+
+    // Create account and mailbox
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+
+    // Import the message to forward
+    let blob_id = BlobId::new_external(MESSAGE);
+    db.blob_store(&blob_id, MESSAGE.to_vec()).unwrap();
+    db.mail_import_item(
+        account_id,
+        blob_id.clone(),
+        MESSAGE,
+        vec![mailbox_id],
+        vec![],
+        None,
+    )
+    .unwrap();
+
+    // Forward it as an attachment
+    let raw_message = String::from_utf8(
+        create_email(&db, account_id, mailbox_id, &JMAPBlob::new(blob_id)).unwrap(),
+    )
+    .unwrap();
+    assert!(
+        raw_message.contains("Content-Type: multipart/mixed"),
+        "{}",
+        raw_message
+    );
+    assert!(
+        raw_message.contains("Content-Type: message/rfc822"),
+        "{}",
+        raw_message
+    );
+    assert!(
+        raw_message.contains(std::str::from_utf8(MESSAGE).unwrap().trim_end()),
+        "{}",
+        raw_message
+    );
+
+    // Blobs that are not messages are rejected
+    let bytes = vec![0xffu8; 128];
+    let blob_id = BlobId::new_external(&bytes);
+    db.blob_store(&blob_id, bytes).unwrap();
+    db.blob_link_ephemeral(&blob_id, account_id).unwrap();
+    assert!(matches!(
+        create_email(&db, account_id, mailbox_id, &JMAPBlob::new(blob_id)),
+        Err(SetErrorType::InvalidProperties)
+    ));
+}
+
+fn create_email<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    mailbox_id: DocumentId,
+    blob_id: &JMAPBlob,
+) -> Result<Vec<u8>, SetErrorType>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let email: Email = serde_json::from_str(&format!(
+        concat!(
+            "{{\"mailboxIds\": {{\"{}\": true}}, \"subject\": \"Fwd: Original message\", ",
+            "\"bodyValues\": {{\"1\": {{\"value\": \"See attached.\"}}}}, ",
+            "\"textBody\": [{{\"type\": \"text/plain\", \"partId\": \"1\"}}], ",
+            "\"attachments\": [{{\"type\": \"message/rfc822\", \"blobId\": \"{}\", ",
+            "\"name\": \"original.eml\"}}]}}"
+        ),
+        JMAPId::from(mailbox_id),
+        blob_id
+    ))
+    .unwrap();
+    let mut create = VecMap::new();
+    create.append("e1".to_string(), email);
+
+    let mut response = db
+        .mail_set(SetRequest {
+            acl: Arc::new(ACLToken {
+                member_of: vec![account_id],
+                access_to: vec![],
+            })
+            .into(),
+            account_id: JMAPId::new(account_id as u64),
+            if_in_state: None,
+            create: create.into(),
+            update: None,
+            destroy: None,
+            arguments: Default::default(),
+        })
+        .unwrap();
+
+    if let Some(email) = response.created.remove("e1") {
+        match email.properties.get(&Property::BlobId) {
+            Some(Value::Blob { value }) => Ok(db.blob_get(&value.id).unwrap().unwrap()),
+            other => panic!("Unexpected blobId {:?}", other),
+        }
+    } else {
+        Err(response
+            .not_created
+            .remove(&"e1".to_string())
+            .unwrap()
+            .type_)
+    }
+}
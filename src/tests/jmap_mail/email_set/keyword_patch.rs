@@ -0,0 +1,182 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use store::ahash::AHashSet;
+
+use jmap::{
+    error::set::SetErrorType, jmap_store::Object, orm::serialize::JMAPOrm,
+    request::set::SetRequest, types::jmap::JMAPId,
+};
+use jmap_mail::mail::{
+    import::JMAPMailImport,
+    schema::{Email, Keyword, Property},
+    set::{JMAPSetMail, SetArguments},
+};
+use store::{
+    blob::BlobId,
+    core::{acl::ACLToken, tag::Tag, vec_map::VecMap},
+    JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+const MESSAGE: &[u8] =
+    b"From: john@example.com\r\nTo: jane@example.com\r\nSubject: Patch\r\n\r\nHello.\r\n";
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email keyword patch tests...");
+    let account_id = 1;
+
+    // Create account and mailbox
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+
+    // Import message
+    let blob_id = BlobId::new_external(MESSAGE);
+    db.blob_store(&blob_id, MESSAGE.to_vec()).unwrap();
+    let id = *db
+        .mail_import_item(
+            account_id,
+            blob_id,
+            MESSAGE,
+            vec![mailbox_id],
+            vec![Tag::Static(Keyword::SEEN)],
+            Some(10000),
+        )
+        .unwrap()
+        .id()
+        .unwrap();
+
+    // Patching individual keywords keeps the rest
+    let response = db
+        .mail_set(update_request(
+            account_id,
+            id,
+            r#"{"keywords/$flagged": true, "keywords/$draft": true}"#,
+        ))
+        .unwrap();
+    assert!(
+        response.not_updated.is_empty(),
+        "{:?}",
+        response.not_updated
+    );
+    assert_eq!(
+        get_keywords(&db, account_id, id),
+        keywords(&[Keyword::DRAFT, Keyword::FLAGGED, Keyword::SEEN])
+    );
+
+    // Removing a keyword with a null patch
+    let response = db
+        .mail_set(update_request(
+            account_id,
+            id,
+            r#"{"keywords/$draft": null}"#,
+        ))
+        .unwrap();
+    assert!(
+        response.not_updated.is_empty(),
+        "{:?}",
+        response.not_updated
+    );
+    assert_eq!(
+        get_keywords(&db, account_id, id),
+        keywords(&[Keyword::FLAGGED, Keyword::SEEN])
+    );
+
+    // Mixing a full keywords object with patches is rejected, in either order
+    for json in [
+        r#"{"keywords": {"$answered": true}, "keywords/$flagged": null}"#,
+        r#"{"keywords/$flagged": null, "keywords": {"$answered": true}}"#,
+    ] {
+        let response = db.mail_set(update_request(account_id, id, json)).unwrap();
+        assert!(
+            matches!(
+                response.not_updated.get(&id),
+                Some(err) if matches!(err.type_, SetErrorType::InvalidPatch)
+            ),
+            "{:?}",
+            response.not_updated
+        );
+        assert_eq!(
+            get_keywords(&db, account_id, id),
+            keywords(&[Keyword::FLAGGED, Keyword::SEEN])
+        );
+    }
+
+    // A full keywords object replaces all keywords
+    let response = db
+        .mail_set(update_request(
+            account_id,
+            id,
+            r#"{"keywords": {"$answered": true}}"#,
+        ))
+        .unwrap();
+    assert!(
+        response.not_updated.is_empty(),
+        "{:?}",
+        response.not_updated
+    );
+    assert_eq!(
+        get_keywords(&db, account_id, id),
+        keywords(&[Keyword::ANSWERED])
+    );
+}
+
+fn get_keywords<T>(db: &JMAPStore<T>, account_id: store::AccountId, id: JMAPId) -> AHashSet<Tag>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    db.get_orm::<Email>(account_id, id.get_document_id())
+        .unwrap()
+        .unwrap()
+        .get_tags(&Property::Keywords)
+        .cloned()
+        .unwrap_or_default()
+}
+
+fn keywords(keywords: &[u8]) -> AHashSet<Tag> {
+    keywords.iter().map(|k| Tag::Static(*k)).collect()
+}
+
+fn update_request(account_id: store::AccountId, id: JMAPId, json: &str) -> SetRequest<Email> {
+    let mut update = VecMap::new();
+    update.append(id, serde_json::from_str::<Email>(json).unwrap());
+
+    SetRequest {
+        acl: Arc::new(ACLToken {
+            member_of: vec![account_id],
+            access_to: vec![],
+        })
+        .into(),
+        account_id: JMAPId::new(account_id as u64),
+        if_in_state: None,
+        create: None,
+        update: update.into(),
+        destroy: None,
+        arguments: SetArguments::default(),
+    }
+}
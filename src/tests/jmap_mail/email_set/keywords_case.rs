@@ -0,0 +1,187 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    jmap_store::Object,
+    request::{get::GetRequest, set::SetRequest, MaybeResultReference},
+    types::jmap::JMAPId,
+};
+use jmap_mail::mail::{
+    get::{GetArguments, JMAPGetMail},
+    import::JMAPMailImport,
+    schema::{Email, Property},
+    set::{JMAPSetMail, SetArguments},
+};
+use store::{
+    blob::BlobId,
+    core::{acl::ACLToken, vec_map::VecMap},
+    AccountId, JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+const MESSAGE: &[u8] =
+    b"From: john@example.com\r\nTo: jane@example.com\r\nSubject: Keywords\r\n\r\nHello.\r\n";
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email keyword case tests...");
+    let account_id = 1;
+
+    // Create account and mailbox
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+
+    let blob_id = BlobId::new_external(MESSAGE);
+    db.blob_store(&blob_id, MESSAGE.to_vec()).unwrap();
+    let id = *db
+        .mail_import_item(
+            account_id,
+            blob_id,
+            MESSAGE,
+            vec![mailbox_id],
+            vec![],
+            Some(10000),
+        )
+        .unwrap()
+        .id()
+        .unwrap();
+
+    // Keywords are returned in the lowercase form they are stored in,
+    // system keywords included
+    update(
+        &db,
+        account_id,
+        id,
+        serde_json::json!({
+            "keywords": {
+                "$Seen": true,
+                "$FLAGGED": true,
+                "$MDNSent": true,
+                "$Custom": true,
+                "Project-X": true
+            }
+        }),
+    );
+    assert_eq!(
+        get_keywords(&db, account_id, id),
+        serde_json::json!({
+            "$seen": true,
+            "$flagged": true,
+            "$mdnsent": true,
+            "$custom": true,
+            "project-x": true
+        })
+    );
+
+    // Patches are matched against keywords regardless of their case
+    update(
+        &db,
+        account_id,
+        id,
+        serde_json::json!({
+            "keywords/$ANSWERED": true,
+            "keywords/Project-Y": true,
+            "keywords/PROJECT-X": null,
+            "keywords/$sEEN": null
+        }),
+    );
+    assert_eq!(
+        get_keywords(&db, account_id, id),
+        serde_json::json!({
+            "$flagged": true,
+            "$mdnsent": true,
+            "$custom": true,
+            "$answered": true,
+            "project-y": true
+        })
+    );
+
+    // Setting the same keywords with a different case is not a change
+    let keywords = get_keywords(&db, account_id, id);
+    update(
+        &db,
+        account_id,
+        id,
+        serde_json::json!({
+            "keywords": {
+                "$Flagged": true,
+                "$MdnSent": true,
+                "$CUSTOM": true,
+                "$Answered": true,
+                "PROJECT-Y": true
+            }
+        }),
+    );
+    assert_eq!(get_keywords(&db, account_id, id), keywords);
+}
+
+fn acl(account_id: AccountId) -> Arc<ACLToken> {
+    Arc::new(ACLToken {
+        member_of: vec![account_id],
+        access_to: vec![],
+    })
+}
+
+fn update<T>(db: &JMAPStore<T>, account_id: AccountId, id: JMAPId, patch: serde_json::Value)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut update = VecMap::new();
+    update.append(id, serde_json::from_value::<Email>(patch).unwrap());
+    let response = db
+        .mail_set(SetRequest {
+            acl: acl(account_id).into(),
+            account_id: JMAPId::new(account_id as u64),
+            if_in_state: None,
+            create: None,
+            update: update.into(),
+            destroy: None,
+            arguments: SetArguments::default(),
+        })
+        .unwrap();
+    assert!(
+        response.not_updated.is_empty(),
+        "{:?}",
+        response.not_updated
+    );
+}
+
+fn get_keywords<T>(db: &JMAPStore<T>, account_id: AccountId, id: JMAPId) -> serde_json::Value
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut response = db
+        .mail_get(GetRequest {
+            acl: acl(account_id).into(),
+            account_id: JMAPId::new(account_id as u64),
+            ids: MaybeResultReference::Value(vec![id]).into(),
+            properties: MaybeResultReference::Value(vec![Property::Keywords]).into(),
+            arguments: GetArguments::default(),
+        })
+        .unwrap();
+    serde_json::to_value(&response.list.pop().unwrap()).unwrap()["keywords"].clone()
+}
@@ -0,0 +1,155 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    jmap_store::Object,
+    request::{get::GetRequest, set::SetRequest, MaybeResultReference},
+    types::jmap::JMAPId,
+};
+use jmap_mail::{
+    mail::{
+        get::JMAPGetMail,
+        schema::{Email, Property, Value},
+        set::JMAPSetMail,
+    },
+    mail_parser::Message,
+};
+use store::{
+    core::{acl::ACLToken, vec_map::VecMap},
+    JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>, enforce_line_length: bool)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!(
+        "Running Email line length tests (enforce-line-length: {})...",
+        enforce_line_length
+    );
+    assert_eq!(db.config.enforce_line_length, enforce_line_length);
+    let account_id = 1;
+    let acl = Arc::new(ACLToken {
+        member_of: vec![account_id],
+        access_to: vec![],
+    });
+
+    // Create account and mailbox
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+
+    // Create a message with a single unbroken 2000 character line
+    let long_line = "a".repeat(2000);
+    let mut create = VecMap::new();
+    create.append(
+        "e1".to_string(),
+        serde_json::from_str::<Email>(&format!(
+            concat!(
+                "{{\"mailboxIds\": {{\"{}\": true}}, \"subject\": \"Long line\", ",
+                "\"bodyValues\": {{\"1\": {{\"value\": \"{}\"}}}}, ",
+                "\"textBody\": [{{\"type\": \"text/plain\", \"partId\": \"1\"}}]}}"
+            ),
+            JMAPId::from(mailbox_id),
+            long_line
+        ))
+        .unwrap(),
+    );
+    let mut response = db
+        .mail_set(SetRequest {
+            acl: acl.clone().into(),
+            account_id: JMAPId::new(account_id as u64),
+            if_in_state: None,
+            create: create.into(),
+            update: None,
+            destroy: None,
+            arguments: Default::default(),
+        })
+        .unwrap();
+    let email = response
+        .created
+        .remove("e1")
+        .unwrap_or_else(|| panic!("{:?}", response.not_created));
+    let raw_message = match email.properties.get(&Property::BlobId) {
+        Some(Value::Blob { value }) => db.blob_get(&value.id).unwrap().unwrap(),
+        other => panic!("Unexpected blobId {:?}", other),
+    };
+    let raw_message = String::from_utf8(raw_message).unwrap();
+
+    if enforce_line_length {
+        for line in raw_message.split("\r\n") {
+            assert!(line.len() <= 998, "{} octet line", line.len());
+        }
+        assert!(
+            raw_message.contains("Content-Transfer-Encoding: quoted-printable"),
+            "{}",
+            raw_message
+        );
+    } else {
+        assert!(raw_message.contains(&long_line), "{}", raw_message);
+    }
+
+    // The part is encoded only once and decodes back to the original text
+    assert!(
+        raw_message.matches("Content-Transfer-Encoding:").count() <= 1,
+        "{}",
+        raw_message
+    );
+    let message = Message::parse(raw_message.as_bytes()).unwrap();
+    assert_eq!(
+        message.get_text_body(0).unwrap().trim_end(),
+        long_line,
+        "{}",
+        raw_message
+    );
+
+    // The decoded text is unchanged
+    let mut response = db
+        .mail_get(GetRequest {
+            acl: acl.into(),
+            account_id: JMAPId::new(account_id as u64),
+            ids: MaybeResultReference::Value(vec![*email.id().unwrap()]).into(),
+            properties: MaybeResultReference::Value(vec![Property::Preview]).into(),
+            arguments: Default::default(),
+        })
+        .unwrap();
+    match response
+        .list
+        .pop()
+        .unwrap()
+        .properties
+        .get(&Property::Preview)
+    {
+        Some(Value::Text { value }) => {
+            assert!(!value.is_empty());
+            assert!(
+                value.trim_end_matches('.').chars().all(|ch| ch == 'a'),
+                "{:?}",
+                value
+            );
+        }
+        other => panic!("Unexpected preview {:?}", other),
+    }
+}
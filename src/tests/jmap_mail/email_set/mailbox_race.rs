@@ -0,0 +1,118 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{sync::Arc, time::Duration};
+
+use jmap::{
+    error::set::SetErrorType, orm::serialize::JMAPOrm, request::set::SetRequest,
+    types::jmap::JMAPId,
+};
+use jmap_mail::{
+    mail::{
+        schema::Email,
+        set::{JMAPSetMail, SetArguments},
+    },
+    mailbox::schema::Mailbox,
+};
+use store::{
+    core::{acl::ACLToken, collection::Collection, document::Document, vec_map::VecMap},
+    write::batch::WriteBatch,
+    JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email/set concurrent mailbox destroy tests...");
+    let account_id = 1;
+
+    // Create account and mailbox
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+
+    // Start creating a message while the mailbox is being destroyed
+    let lock = db.lock_account(account_id);
+    let create_handle = {
+        let db = db.clone();
+        std::thread::spawn(move || db.mail_set(create_request(account_id, mailbox_id)).unwrap())
+    };
+    std::thread::sleep(Duration::from_millis(200));
+    let mut batch = WriteBatch::new(account_id);
+    let mut document = Document::new(Collection::Mailbox, mailbox_id);
+    db.get_orm::<Mailbox>(account_id, mailbox_id)
+        .unwrap()
+        .unwrap()
+        .delete(&mut document);
+    batch.delete_document(document);
+    batch.log_delete(Collection::Mailbox, mailbox_id);
+    db.write(batch).unwrap();
+    drop(lock);
+
+    // The create has to fail and no message should be left behind
+    let response = create_handle.join().unwrap();
+    assert!(response.created.is_empty(), "{:?}", response.created);
+    assert!(
+        matches!(
+            response.not_created.get(&"m1".to_string()),
+            Some(err) if matches!(err.type_, SetErrorType::InvalidProperties)
+        ),
+        "{:?}",
+        response.not_created
+    );
+    assert!(db
+        .get_document_ids(account_id, Collection::Mail)
+        .unwrap()
+        .unwrap_or_default()
+        .is_empty());
+}
+
+fn create_request(
+    account_id: store::AccountId,
+    mailbox_id: store::DocumentId,
+) -> SetRequest<Email> {
+    let mut create = VecMap::new();
+    create.append(
+        "m1".to_string(),
+        serde_json::from_str::<Email>(&format!(
+            r#"{{"mailboxIds": {{"{}": true}}, "subject": "Orphan?"}}"#,
+            JMAPId::from(mailbox_id)
+        ))
+        .unwrap(),
+    );
+
+    SetRequest {
+        acl: Arc::new(ACLToken {
+            member_of: vec![account_id],
+            access_to: vec![],
+        })
+        .into(),
+        account_id: JMAPId::new(account_id as u64),
+        if_in_state: None,
+        create: create.into(),
+        update: None,
+        destroy: None,
+        arguments: SetArguments::default(),
+    }
+}
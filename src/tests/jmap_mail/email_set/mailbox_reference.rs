@@ -0,0 +1,176 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    error::set::SetErrorType,
+    jmap_store::Object,
+    request::{get::GetRequest, set::SetRequest, MaybeIdReference, MaybeResultReference},
+    types::jmap::JMAPId,
+};
+use jmap_mail::{
+    mail::{
+        get::JMAPGetMail,
+        import::JMAPMailImport,
+        schema::{Email, Property, Value},
+        set::JMAPSetMail,
+    },
+    mailbox::{
+        schema::{Mailbox, Property as MailboxProperty, Value as MailboxValue},
+        set::JMAPSetMailbox,
+    },
+};
+use store::{
+    blob::BlobId,
+    core::{acl::ACLToken, vec_map::VecMap},
+    AccountId, JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+const MESSAGE: &[u8] =
+    b"From: john@example.com\r\nTo: jane@example.com\r\nSubject: Hello\r\n\r\nHi there.\r\n";
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email/set mailbox creation reference tests...");
+    let account_id = 1;
+
+    // Create account, inbox and a message
+    let inbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+    let blob_id = BlobId::new_external(MESSAGE);
+    db.blob_store(&blob_id, MESSAGE.to_vec()).unwrap();
+    let message_id = *db
+        .mail_import_item(account_id, blob_id, MESSAGE, vec![inbox_id], vec![], None)
+        .unwrap()
+        .id()
+        .unwrap();
+
+    // Mailbox/set creates a mailbox, its creation id is added to the
+    // ids created within the request
+    let mut mailbox = Mailbox::default();
+    mailbox.properties.append(
+        MailboxProperty::Name,
+        MailboxValue::Text {
+            value: "Projects".to_string(),
+        },
+    );
+    let mut create = VecMap::new();
+    create.append("newMailbox".to_string(), mailbox);
+    let created_ids = db
+        .mailbox_set(SetRequest {
+            acl: acl(account_id).into(),
+            account_id: JMAPId::new(account_id as u64),
+            if_in_state: None,
+            create: create.into(),
+            update: None,
+            destroy: None,
+            arguments: Default::default(),
+        })
+        .unwrap()
+        .created_ids()
+        .unwrap();
+    let new_mailbox_id = *created_ids.get("newMailbox").unwrap();
+
+    // Email/set creates and updates messages referencing the new mailbox
+    let mut request: SetRequest<Email> = serde_json::from_str(&format!(
+        concat!(
+            "{{\"accountId\": \"{}\", ",
+            "\"create\": {{",
+            "\"e1\": {{\"mailboxIds\": {{\"#newMailbox\": true}}, \"subject\": \"Hi\"}}, ",
+            "\"e2\": {{\"mailboxIds\": {{\"#otherMailbox\": true}}, \"subject\": \"Hi\"}}",
+            "}}, ",
+            "\"update\": {{\"{}\": {{\"mailboxIds/#newMailbox\": true}}}}}}"
+        ),
+        JMAPId::new(account_id as u64),
+        message_id
+    ))
+    .unwrap();
+    request.acl = acl(account_id).into();
+    request.eval_references(|_| None, &created_ids).unwrap();
+
+    let mut response = db.mail_set(request).unwrap();
+    assert!(
+        matches!(
+            response.not_created.get("e2").map(|err| &err.type_),
+            Some(SetErrorType::InvalidProperties)
+        ),
+        "{:?}",
+        response.not_created
+    );
+    assert!(
+        response.not_updated.is_empty(),
+        "{:?}",
+        response.not_updated
+    );
+    let created_id = *response.created.remove("e1").unwrap().id().unwrap();
+
+    assert_eq!(
+        get_mailbox_ids(&db, account_id, created_id),
+        vec![new_mailbox_id]
+    );
+    let mut mailbox_ids = get_mailbox_ids(&db, account_id, message_id);
+    mailbox_ids.sort_unstable_by_key(u64::from);
+    assert_eq!(mailbox_ids, vec![JMAPId::from(inbox_id), new_mailbox_id]);
+}
+
+fn get_mailbox_ids<T>(db: &JMAPStore<T>, account_id: AccountId, id: JMAPId) -> Vec<JMAPId>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut response = db
+        .mail_get(GetRequest {
+            acl: acl(account_id).into(),
+            account_id: JMAPId::new(account_id as u64),
+            ids: MaybeResultReference::Value(vec![id]).into(),
+            properties: MaybeResultReference::Value(vec![Property::MailboxIds]).into(),
+            arguments: Default::default(),
+        })
+        .unwrap();
+    match response
+        .list
+        .pop()
+        .unwrap()
+        .properties
+        .get(&Property::MailboxIds)
+    {
+        Some(Value::MailboxIds { value, .. }) => value
+            .keys()
+            .map(|id| match id {
+                MaybeIdReference::Value(id) => *id,
+                MaybeIdReference::Reference(id) => panic!("Unexpected reference {}", id),
+            })
+            .collect(),
+        other => panic!("Unexpected mailboxIds {:?}", other),
+    }
+}
+
+fn acl(account_id: AccountId) -> Arc<ACLToken> {
+    Arc::new(ACLToken {
+        member_of: vec![account_id],
+        access_to: vec![],
+    })
+}
@@ -21,6 +21,28 @@
  * for more details.
 */
 
+pub mod attachment_limit;
+pub mod attachment_type;
+pub mod body_conflict;
+pub mod destroy_blobs;
+pub mod empty;
+pub mod forward;
+pub mod keyword_patch;
+pub mod keywords_case;
+pub mod line_length;
+pub mod mailbox_race;
+pub mod mailbox_reference;
+pub mod part_size;
+pub mod recipients;
+pub mod redact;
+pub mod reply_headers;
+pub mod restore;
+pub mod sender;
+pub mod serial;
+pub mod server_set;
+pub mod shared_access;
+pub mod trash;
+
 use std::{fs, path::PathBuf};
 
 use actix_web::web;
@@ -54,6 +76,7 @@ where
 
     create(client, &mailbox_id).await;
     update(client, &mailbox_id).await;
+    part_types(client, &mailbox_id).await;
 
     client.mailbox_destroy(&mailbox_id, true).await.unwrap();
 
@@ -256,6 +279,37 @@ async fn update(client: &mut Client, root_mailbox_id: &str) {
     )
     .await;
 
+    // Over-long keywords and keywords containing whitespace are rejected
+    for keyword in ["a".repeat(101), "two words".to_string()] {
+        let mut request = client.build();
+        request
+            .set_email()
+            .update(mailbox.id(0))
+            .keyword(&keyword, true);
+        assert!(
+            matches!(
+                request
+                    .send_set_email()
+                    .await
+                    .unwrap()
+                    .updated(mailbox.id(0)),
+                Err(Error::Set(SetError {
+                    type_: SetErrorType::InvalidProperties,
+                    ..
+                }))
+            ),
+            "{}",
+            keyword
+        );
+    }
+    assert_email_properties(
+        client,
+        mailbox.id(0),
+        &[&test_mailbox2_id],
+        &["test1", "test3"],
+    )
+    .await;
+
     // Orphan messages should not be permitted
     let mut request = client.build();
     request
@@ -344,3 +398,71 @@ pub async fn assert_email_properties(
     assert_eq!(mailbox_ids_, mailbox_ids);
     assert_eq!(keywords_, keywords);
 }
+
+async fn part_types(client: &mut Client, mailbox_id: &str) {
+    let blob_id = client
+        .upload(None, vec![0x89, b'P', b'N', b'G', 0xff, 0xfe, 0x00], None)
+        .await
+        .unwrap()
+        .take_blob_id();
+
+    for (json, is_valid) in [
+        // Binary type with a text value
+        (
+            concat!(
+                "{\"subject\": \"Mismatch\", ",
+                "\"bodyValues\": {\"1\": {\"value\": \"Not an image.\"}}, ",
+                "\"bodyStructure\": {\"type\": \"image/png\", \"partId\": \"1\"}}"
+            )
+            .to_string(),
+            false,
+        ),
+        // Text type pointing at a binary blob
+        (
+            format!(
+                concat!(
+                    "{{\"subject\": \"Mismatch\", ",
+                    "\"bodyStructure\": {{\"type\": \"text/plain\", \"blobId\": \"{}\"}}}}"
+                ),
+                blob_id
+            ),
+            false,
+        ),
+        // Matching types
+        (
+            format!(
+                concat!(
+                    "{{\"subject\": \"Match\", ",
+                    "\"bodyStructure\": {{\"type\": \"image/png\", \"blobId\": \"{}\"}}}}"
+                ),
+                blob_id
+            ),
+            true,
+        ),
+    ] {
+        let mut request = client.build();
+        let mut create_item = serde_json::from_str::<Email<Set>>(&json).unwrap();
+        create_item.mailbox_ids([mailbox_id]);
+        let create_id = request.set_email().create_item(create_item);
+        let result = request.send_set_email().await.unwrap().created(&create_id);
+
+        if is_valid {
+            client
+                .email_destroy(result.unwrap().id().unwrap())
+                .await
+                .unwrap();
+        } else {
+            assert!(
+                matches!(
+                    result,
+                    Err(Error::Set(SetError {
+                        type_: SetErrorType::InvalidProperties,
+                        ..
+                    }))
+                ),
+                "{}",
+                json
+            );
+        }
+    }
+}
@@ -0,0 +1,157 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    jmap_store::Object,
+    request::{get::GetRequest, MaybeResultReference},
+    types::jmap::JMAPId,
+};
+use jmap_mail::mail::{
+    get::{BlobResult, GetArguments, JMAPGetMail},
+    import::JMAPMailImport,
+    schema::{BodyProperty, Email, EmailBodyPart, Property, Value},
+};
+use store::{blob::BlobId, core::acl::ACLToken, AccountId, JMAPStore, Store};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+const ATTACHMENT: &str = "This attachment is base64 encoded, its size is the decoded length.";
+const ATTACHMENT_BASE64: &str =
+    "VGhpcyBhdHRhY2htZW50IGlzIGJhc2U2NCBlbmNvZGVkLCBpdHMgc2l6ZSBpcyB0aGUgZGVjb2RlZCBsZW5ndGgu";
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email/get bodyStructure size tests...");
+    let account_id = 1;
+
+    // Create account and mailbox
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+
+    // Import a message with a quoted-printable Latin-1 body and a base64 attachment
+    let message = format!(
+        concat!(
+            "From: sender@example.com\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: Part sizes\r\n",
+            "Content-Type: multipart/mixed; boundary=\"boundary\"\r\n\r\n",
+            "--boundary\r\n",
+            "Content-Type: text/plain; charset=iso-8859-1\r\n",
+            "Content-Transfer-Encoding: quoted-printable\r\n\r\n",
+            "Caf=E9 cr=E8me br=FBl=E9e\r\n",
+            "--boundary\r\n",
+            "Content-Type: text/plain\r\n",
+            "Content-Disposition: attachment; filename=\"notes.txt\"\r\n",
+            "Content-Transfer-Encoding: base64\r\n\r\n",
+            "{}\r\n",
+            "--boundary--\r\n"
+        ),
+        ATTACHMENT_BASE64
+    )
+    .into_bytes();
+    let blob_id = BlobId::new_external(&message);
+    db.blob_store(&blob_id, message.clone()).unwrap();
+    let id = *db
+        .mail_import_item(
+            account_id,
+            blob_id,
+            &message,
+            vec![mailbox_id],
+            vec![],
+            None,
+        )
+        .unwrap()
+        .id()
+        .unwrap();
+
+    let mut response = db
+        .mail_get(GetRequest {
+            acl: acl(account_id).into(),
+            account_id: JMAPId::new(account_id as u64),
+            ids: MaybeResultReference::Value(vec![id]).into(),
+            properties: MaybeResultReference::Value(vec![Property::BodyStructure]).into(),
+            arguments: GetArguments {
+                body_properties: Some(vec![
+                    BodyProperty::PartId,
+                    BodyProperty::BlobId,
+                    BodyProperty::Size,
+                    BodyProperty::Subparts,
+                ]),
+                ..Default::default()
+            },
+        })
+        .unwrap();
+    let email: Email = response.list.pop().unwrap();
+    let mut leaf_parts = Vec::new();
+    match email.properties.get(&Property::BodyStructure) {
+        Some(Value::BodyPart { value }) => add_leaf_parts(value, &mut leaf_parts),
+        other => panic!("Unexpected bodyStructure {:?}", other),
+    }
+    assert_eq!(leaf_parts.len(), 2, "{:?}", leaf_parts);
+
+    // Each part reports the number of octets obtained when downloading it
+    let mut sizes = Vec::new();
+    for part in leaf_parts {
+        let size = match part.properties.get(&BodyProperty::Size) {
+            Some(Value::Size { value }) => *value,
+            other => panic!("Unexpected size {:?}", other),
+        };
+        let blob = match part.properties.get(&BodyProperty::BlobId) {
+            Some(Value::Blob { value }) => value.clone(),
+            other => panic!("Unexpected blobId {:?}", other),
+        };
+        match db
+            .mail_blob_get(account_id, &acl(account_id), &blob)
+            .unwrap()
+        {
+            BlobResult::Blob(bytes) => assert_eq!(bytes.len(), size, "{:?}", part),
+            _ => panic!("Failed to download part {:?}", part),
+        }
+        sizes.push(size);
+    }
+
+    // Latin-1 characters are one octet each, the UTF-8 text would be longer
+    assert!(sizes[0] < "Café crème brûlée".len(), "{:?}", sizes);
+    assert_eq!(sizes[1], ATTACHMENT.len());
+    assert_ne!(sizes[1], ATTACHMENT_BASE64.len());
+}
+
+fn add_leaf_parts(part: &EmailBodyPart, leaf_parts: &mut Vec<EmailBodyPart>) {
+    if let Some(Value::BodyPartList { value }) = part.properties.get(&BodyProperty::Subparts) {
+        for subpart in value {
+            add_leaf_parts(subpart, leaf_parts);
+        }
+    } else {
+        leaf_parts.push(part.clone());
+    }
+}
+
+fn acl(account_id: AccountId) -> Arc<ACLToken> {
+    Arc::new(ACLToken {
+        member_of: vec![account_id],
+        access_to: vec![],
+    })
+}
@@ -0,0 +1,221 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    error::set::SetErrorType,
+    jmap_store::Object,
+    request::{get::GetRequest, set::SetRequest, MaybeResultReference},
+    types::jmap::JMAPId,
+};
+use jmap_mail::mail::{
+    get::JMAPGetMail,
+    schema::{Email, EmailAddress, Property, Value},
+    set::JMAPSetMail,
+};
+use store::{
+    core::{acl::ACLToken, vec_map::VecMap},
+    AccountId, JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::create_mailbox;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let dedup = db.config.mail_dedup_recipients;
+    println!(
+        "Running Email/set recipient tests ({})...",
+        if dedup { "dedup" } else { "passthrough" }
+    );
+    assert_eq!(db.config.mail_max_recipients, 10);
+    let account_id = 1;
+
+    // Create a mailbox
+    let mailbox_id = create_mailbox(&db, account_id, "Drafts", "drafts");
+
+    let addresses = (0..11)
+        .map(|n| format!("user{}@example.com", n))
+        .collect::<Vec<_>>();
+    let addresses = addresses.iter().map(|a| a.as_str()).collect::<Vec<_>>();
+
+    let mut create = VecMap::new();
+    // Exactly at the limit
+    create.append(
+        "at_limit".to_string(),
+        build_email(mailbox_id, &addresses[0..5], &addresses[5..10], &[]),
+    );
+    // One recipient over the limit
+    create.append(
+        "over_limit".to_string(),
+        build_email(
+            mailbox_id,
+            &addresses[0..6],
+            &addresses[6..10],
+            &addresses[10..],
+        ),
+    );
+    // Over the limit only when duplicates are counted
+    create.append(
+        "over_limit_duplicates".to_string(),
+        build_email(mailbox_id, &addresses[0..6], &addresses[0..6], &[]),
+    );
+    // Duplicates across fields, compared case-insensitively
+    create.append(
+        "duplicates".to_string(),
+        build_email(
+            mailbox_id,
+            &["jane@example.com", "john@example.com"],
+            &["JOHN@example.com", "bill@example.com"],
+            &["jane@example.com"],
+        ),
+    );
+
+    let mut response = db
+        .mail_set(SetRequest {
+            acl: acl(account_id).into(),
+            account_id: JMAPId::new(account_id as u64),
+            if_in_state: None,
+            create: create.into(),
+            update: None,
+            destroy: None,
+            arguments: Default::default(),
+        })
+        .unwrap();
+
+    assert!(
+        response.created.contains_key("at_limit"),
+        "{:?}",
+        response.not_created
+    );
+    assert!(matches!(
+        response.not_created.get("over_limit").map(|err| &err.type_),
+        Some(SetErrorType::TooLarge)
+    ));
+    if dedup {
+        assert!(
+            response.created.contains_key("over_limit_duplicates"),
+            "{:?}",
+            response.not_created
+        );
+    } else {
+        assert!(matches!(
+            response
+                .not_created
+                .get("over_limit_duplicates")
+                .map(|err| &err.type_),
+            Some(SetErrorType::TooLarge)
+        ));
+    }
+
+    let id = *response.created.remove("duplicates").unwrap().id().unwrap();
+    let recipients = get_recipients(&db, account_id, id);
+    if dedup {
+        assert_eq!(
+            recipients,
+            vec![
+                vec!["jane@example.com", "john@example.com"],
+                vec!["bill@example.com"],
+                vec![],
+            ]
+        );
+    } else {
+        assert_eq!(
+            recipients,
+            vec![
+                vec!["jane@example.com", "john@example.com"],
+                vec!["JOHN@example.com", "bill@example.com"],
+                vec!["jane@example.com"],
+            ]
+        );
+    }
+}
+
+fn build_email(mailbox_id: u32, to: &[&str], cc: &[&str], bcc: &[&str]) -> Email {
+    let mut email: Email = serde_json::from_str(&format!(
+        concat!(
+            "{{\"mailboxIds\": {{\"{}\": true}}, \"subject\": \"Hello\", ",
+            "\"from\": [{{\"email\": \"jdoe@example.com\"}}], ",
+            "\"bodyValues\": {{\"1\": {{\"value\": \"Hi there.\"}}}}, ",
+            "\"textBody\": [{{\"type\": \"text/plain\", \"partId\": \"1\"}}]}}"
+        ),
+        JMAPId::from(mailbox_id),
+    ))
+    .unwrap();
+    for (property, addresses) in [(Property::To, to), (Property::Cc, cc), (Property::Bcc, bcc)] {
+        if !addresses.is_empty() {
+            email.insert(
+                property,
+                Value::Addresses {
+                    value: addresses
+                        .iter()
+                        .map(|email| EmailAddress {
+                            name: None,
+                            email: email.to_string(),
+                        })
+                        .collect(),
+                },
+            );
+        }
+    }
+    email
+}
+
+fn get_recipients<T>(db: &JMAPStore<T>, account_id: AccountId, id: JMAPId) -> Vec<Vec<String>>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut response = db
+        .mail_get(GetRequest {
+            acl: acl(account_id).into(),
+            account_id: JMAPId::new(account_id as u64),
+            ids: MaybeResultReference::Value(vec![id]).into(),
+            properties: MaybeResultReference::Value(vec![
+                Property::To,
+                Property::Cc,
+                Property::Bcc,
+            ])
+            .into(),
+            arguments: Default::default(),
+        })
+        .unwrap();
+    let email: Email = response.list.pop().unwrap();
+
+    [Property::To, Property::Cc, Property::Bcc]
+        .iter()
+        .map(|property| match email.properties.get(property) {
+            Some(Value::Addresses { value }) => value.iter().map(|a| a.email.clone()).collect(),
+            Some(Value::Null) | None => vec![],
+            other => panic!("Unexpected {} value {:?}", property, other),
+        })
+        .collect()
+}
+
+fn acl(account_id: AccountId) -> Arc<ACLToken> {
+    Arc::new(ACLToken {
+        member_of: vec![account_id],
+        access_to: vec![],
+    })
+}
@@ -0,0 +1,203 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    jmap_store::Object,
+    request::{get::GetRequest, query::QueryRequest, MaybeResultReference},
+    types::{blob::JMAPBlob, jmap::JMAPId},
+};
+use jmap_mail::mail::{
+    get::JMAPGetMail,
+    import::JMAPMailImport,
+    query::JMAPMailQuery,
+    redact::JMAPMailRedact,
+    schema::{Email, Keyword, Property, Value},
+};
+use store::{
+    blob::BlobId,
+    core::{acl::ACLToken, tag::Tag},
+    AccountId, JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email redaction tests...");
+    let account_id = 1;
+
+    // Create account and mailbox
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+
+    // Import a message with a sensitive attachment
+    let message = build_message("Account number 12345, keep this confidential.");
+    let blob_id = BlobId::new_external(&message);
+    db.blob_store(&blob_id, message.clone()).unwrap();
+    let id = *db
+        .mail_import_item(
+            account_id,
+            blob_id,
+            &message,
+            vec![mailbox_id],
+            vec![Tag::Static(Keyword::SEEN)],
+            Some(1000),
+        )
+        .unwrap()
+        .id()
+        .unwrap();
+    let original = get_email(&db, account_id, id);
+    assert_eq!(query_text(&db, account_id, "confidential"), vec![id]);
+    assert_eq!(query_text(&db, account_id, "redacted"), vec![]);
+
+    // Replace the attachment
+    let redacted_message = build_message("This attachment has been redacted.");
+    let redacted = db
+        .mail_redact(account_id, id.get_document_id(), &redacted_message)
+        .unwrap();
+    assert_eq!(
+        redacted.properties.get(&Property::BlobId),
+        Some(&Value::Blob {
+            value: JMAPBlob::new(BlobId::new_external(&redacted_message))
+        })
+    );
+
+    // The id, thread, mailboxes, keywords and reception time are preserved,
+    // the blob and size are those of the redacted message
+    let updated = get_email(&db, account_id, id);
+    for property in [
+        Property::Id,
+        Property::ThreadId,
+        Property::MailboxIds,
+        Property::Keywords,
+        Property::ReceivedAt,
+        Property::Subject,
+    ] {
+        assert_eq!(
+            original.properties.get(&property),
+            updated.properties.get(&property),
+            "{}",
+            property
+        );
+    }
+    for property in [Property::BlobId, Property::Size] {
+        assert_ne!(
+            original.properties.get(&property),
+            updated.properties.get(&property),
+            "{}",
+            property
+        );
+    }
+    assert_eq!(
+        updated.properties.get(&Property::BlobId),
+        redacted.properties.get(&Property::BlobId)
+    );
+    assert_eq!(
+        updated.properties.get(&Property::Size),
+        Some(&Value::Size {
+            value: redacted_message.len()
+        })
+    );
+
+    // The full-text index reflects the redacted message only
+    assert_eq!(query_text(&db, account_id, "confidential"), vec![]);
+    assert_eq!(query_text(&db, account_id, "redacted"), vec![id]);
+    assert_eq!(query_text(&db, account_id, "quarterly"), vec![id]);
+
+    // Messages that do not exist can't be redacted
+    assert!(db
+        .mail_redact(account_id, id.get_document_id() + 1, &redacted_message)
+        .is_err());
+}
+
+fn build_message(attachment: &str) -> Vec<u8> {
+    format!(
+        concat!(
+            "From: sender@example.com\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: Quarterly report\r\n",
+            "Content-Type: multipart/mixed; boundary=\"boundary\"\r\n\r\n",
+            "--boundary\r\n",
+            "Content-Type: text/plain\r\n\r\n",
+            "The quarterly report is attached.\r\n",
+            "--boundary\r\n",
+            "Content-Type: text/plain\r\n",
+            "Content-Disposition: attachment; filename=\"report.txt\"\r\n\r\n",
+            "{}\r\n",
+            "--boundary--\r\n"
+        ),
+        attachment
+    )
+    .into_bytes()
+}
+
+fn get_email<T>(db: &JMAPStore<T>, account_id: AccountId, id: JMAPId) -> Email
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut response = db
+        .mail_get(GetRequest {
+            acl: acl(account_id).into(),
+            account_id: JMAPId::new(account_id as u64),
+            ids: MaybeResultReference::Value(vec![id]).into(),
+            properties: MaybeResultReference::Value(vec![
+                Property::Id,
+                Property::ThreadId,
+                Property::MailboxIds,
+                Property::Keywords,
+                Property::ReceivedAt,
+                Property::Subject,
+                Property::BlobId,
+                Property::Size,
+            ])
+            .into(),
+            arguments: Default::default(),
+        })
+        .unwrap();
+    assert!(response.not_found.is_empty());
+    response.list.pop().unwrap()
+}
+
+fn query_text<T>(db: &JMAPStore<T>, account_id: AccountId, text: &str) -> Vec<JMAPId>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut request: QueryRequest<Email> = serde_json::from_str(&format!(
+        "{{\"accountId\": \"{}\", \"filter\": {{\"text\": \"{}\"}}}}",
+        JMAPId::new(account_id as u64),
+        text
+    ))
+    .unwrap();
+    request.acl = acl(account_id).into();
+    db.mail_query(request).unwrap().ids
+}
+
+fn acl(account_id: AccountId) -> Arc<ACLToken> {
+    Arc::new(ACLToken {
+        member_of: vec![account_id],
+        access_to: vec![],
+    })
+}
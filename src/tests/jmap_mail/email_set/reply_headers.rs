@@ -0,0 +1,256 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    jmap_store::Object,
+    request::{get::GetRequest, set::SetRequest, MaybeResultReference},
+    types::jmap::JMAPId,
+};
+use jmap_mail::mail::{
+    get::JMAPGetMail,
+    import::JMAPMailImport,
+    schema::{Email, Property, Value},
+    set::{JMAPSetMail, SetArguments},
+};
+use store::{
+    blob::BlobId,
+    core::{acl::ACLToken, vec_map::VecMap},
+    AccountId, JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email/set reply headers tests...");
+    let account_id = 1;
+
+    // Create account and mailbox
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+
+    // Import the messages being replied to
+    let with_references = import(
+        &db,
+        account_id,
+        mailbox_id,
+        concat!(
+            "Message-ID: <parent@example.com>\r\n",
+            "In-Reply-To: <middle@example.com>\r\n",
+            "References: <root@example.com> <middle@example.com>\r\n",
+        ),
+    );
+    let with_in_reply_to = import(
+        &db,
+        account_id,
+        mailbox_id,
+        concat!(
+            "Message-ID: <parent2@example.com>\r\n",
+            "In-Reply-To: <root2@example.com>\r\n",
+        ),
+    );
+    let without_message_id = import(&db, account_id, mailbox_id, "");
+
+    // Replies are threaded from the original message
+    let mut replies = VecMap::new();
+    replies.append("r1".to_string(), reply(mailbox_id, None));
+    replies.append("r2".to_string(), reply(mailbox_id, None));
+    replies.append("r3".to_string(), reply(mailbox_id, None));
+    replies.append(
+        "r4".to_string(),
+        reply(mailbox_id, Some("custom@example.com")),
+    );
+    replies.append("r5".to_string(), reply(mailbox_id, None));
+    replies.append("r6".to_string(), reply(mailbox_id, None));
+    let mut in_reply_to_email_id = VecMap::new();
+    in_reply_to_email_id.append("r1".to_string(), with_references);
+    in_reply_to_email_id.append("r2".to_string(), with_in_reply_to);
+    in_reply_to_email_id.append("r3".to_string(), without_message_id);
+    in_reply_to_email_id.append("r4".to_string(), with_references);
+    in_reply_to_email_id.append("r5".to_string(), JMAPId::from_parts(0, 999));
+
+    let mut response = db
+        .mail_set(SetRequest {
+            acl: acl(account_id).into(),
+            account_id: JMAPId::new(account_id as u64),
+            if_in_state: None,
+            create: replies.into(),
+            update: None,
+            destroy: None,
+            arguments: SetArguments {
+                in_reply_to_email_id: in_reply_to_email_id.into(),
+                ..Default::default()
+            },
+        })
+        .unwrap();
+
+    assert!(
+        response.not_created.get("r5").is_some(),
+        "{:?}",
+        response.not_created
+    );
+    assert_eq!(response.created.len(), 5, "{:?}", response.not_created);
+
+    for (create_id, expected_in_reply_to, expected_references) in [
+        (
+            "r1",
+            vec!["parent@example.com"],
+            vec![
+                "root@example.com",
+                "middle@example.com",
+                "parent@example.com",
+            ],
+        ),
+        (
+            "r2",
+            vec!["parent2@example.com"],
+            vec!["root2@example.com", "parent2@example.com"],
+        ),
+        ("r3", vec![], vec![]),
+        (
+            "r4",
+            vec!["custom@example.com"],
+            vec![
+                "root@example.com",
+                "middle@example.com",
+                "parent@example.com",
+            ],
+        ),
+        ("r6", vec![], vec![]),
+    ] {
+        let id = match response
+            .created
+            .remove(create_id)
+            .unwrap()
+            .properties
+            .get(&Property::Id)
+        {
+            Some(Value::Id { value }) => *value,
+            other => panic!("Unexpected id {:?}", other),
+        };
+        assert_eq!(
+            get_threading_headers(&db, account_id, id),
+            (
+                expected_in_reply_to
+                    .into_iter()
+                    .map(String::from)
+                    .collect::<Vec<_>>(),
+                expected_references
+                    .into_iter()
+                    .map(String::from)
+                    .collect::<Vec<_>>()
+            ),
+            "{}",
+            create_id
+        );
+    }
+}
+
+fn import<T>(db: &JMAPStore<T>, account_id: AccountId, mailbox_id: u32, headers: &str) -> JMAPId
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let message = format!(
+        "{}From: john@example.com\r\nSubject: Hello\r\n\r\nHi there.\r\n",
+        headers
+    )
+    .into_bytes();
+    let blob_id = BlobId::new_external(&message);
+    db.blob_store(&blob_id, message.clone()).unwrap();
+    *db.mail_import_item(
+        account_id,
+        blob_id,
+        &message,
+        vec![mailbox_id],
+        vec![],
+        None,
+    )
+    .unwrap()
+    .id()
+    .unwrap()
+}
+
+fn reply(mailbox_id: u32, in_reply_to: Option<&str>) -> Email {
+    let mut email: Email = serde_json::from_str(&format!(
+        concat!(
+            "{{\"mailboxIds\": {{\"{}\": true}}, \"subject\": \"Re: Hello\", ",
+            "\"bodyValues\": {{\"1\": {{\"value\": \"Hi back.\"}}}}, ",
+            "\"textBody\": [{{\"type\": \"text/plain\", \"partId\": \"1\"}}]}}"
+        ),
+        JMAPId::from(mailbox_id),
+    ))
+    .unwrap();
+    if let Some(in_reply_to) = in_reply_to {
+        email.insert(
+            Property::InReplyTo,
+            Value::TextList {
+                value: vec![in_reply_to.to_string()],
+            },
+        );
+    }
+    email
+}
+
+fn get_threading_headers<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    id: JMAPId,
+) -> (Vec<String>, Vec<String>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut response = db
+        .mail_get(GetRequest {
+            acl: acl(account_id).into(),
+            account_id: JMAPId::new(account_id as u64),
+            ids: MaybeResultReference::Value(vec![id]).into(),
+            properties: MaybeResultReference::Value(vec![
+                Property::InReplyTo,
+                Property::References,
+            ])
+            .into(),
+            arguments: Default::default(),
+        })
+        .unwrap();
+    assert!(response.not_found.is_empty());
+    let email: Email = response.list.pop().unwrap();
+
+    let mut headers = [Property::InReplyTo, Property::References]
+        .into_iter()
+        .map(|property| match email.properties.get(&property) {
+            Some(Value::TextList { value }) => value.clone(),
+            Some(Value::Null) | None => vec![],
+            other => panic!("Unexpected {} value {:?}", property, other),
+        });
+    (headers.next().unwrap(), headers.next().unwrap())
+}
+
+fn acl(account_id: AccountId) -> Arc<ACLToken> {
+    Arc::new(ACLToken {
+        member_of: vec![account_id],
+        access_to: vec![],
+    })
+}
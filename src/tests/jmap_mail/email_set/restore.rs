@@ -0,0 +1,246 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{sync::Arc, time::Duration};
+
+use jmap::{
+    error::set::SetErrorType,
+    jmap_store::Object,
+    orm::serialize::JMAPOrm,
+    request::{set::SetRequest, MaybeResultReference},
+    types::jmap::JMAPId,
+};
+use jmap_mail::mail::{
+    import::JMAPMailImport,
+    schema::{Email, Keyword, Property},
+    set::{JMAPSetMail, SetArguments},
+    tombstone::{JMAPMailTombstone, TombstoneChanges},
+};
+use store::{
+    blob::BlobId,
+    core::{acl::ACLToken, collection::Collection, tag::Tag},
+    write::batch::WriteBatch,
+    JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+const MESSAGE: &[u8] = b"From: john@example.com\r\nTo: jane@example.com\r\nSubject: Undo\r\n\r\nPlease keep me around.\r\n";
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email restore tests...");
+    let account_id = 1;
+
+    // Create account and mailbox
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+
+    // Import message
+    let id = import_message(&db, account_id, mailbox_id);
+
+    // Destroy the message and restore it within the grace period
+    let response = db
+        .mail_set(set_request(account_id, vec![id], None))
+        .unwrap();
+    assert_eq!(response.destroyed, vec![id]);
+    assert!(!db
+        .get_document_ids(account_id, Collection::Mail)
+        .unwrap()
+        .unwrap_or_default()
+        .contains(id.get_document_id()));
+    assert_eq!(db.mail_tombstone_ids(account_id).unwrap().len(), 1);
+
+    let response = db
+        .mail_set(set_request(account_id, vec![], vec![id].into()))
+        .unwrap();
+    assert!(
+        response.not_updated.is_empty(),
+        "{:?}",
+        response.not_updated
+    );
+    assert!(response.created.is_empty());
+    let restored_id = *response
+        .updated
+        .get(&id)
+        .unwrap()
+        .as_ref()
+        .unwrap()
+        .id()
+        .unwrap();
+    assert_eq!(restored_id.get_document_id(), id.get_document_id());
+    let fields = db
+        .get_orm::<Email>(account_id, restored_id.get_document_id())
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        fields
+            .get_tags(&Property::MailboxIds)
+            .unwrap()
+            .iter()
+            .collect::<Vec<_>>(),
+        vec![&Tag::Id(mailbox_id)]
+    );
+    assert!(fields
+        .get_tags(&Property::Keywords)
+        .unwrap()
+        .contains(&Tag::Static(Keyword::SEEN)));
+    assert!(db.mail_tombstone_ids(account_id).unwrap().is_empty());
+
+    // Restoring twice should fail
+    let response = db
+        .mail_set(set_request(account_id, vec![], vec![id].into()))
+        .unwrap();
+    assert!(response.not_updated.get(&id).is_some());
+
+    // Destroy the message again and wait for the grace period to expire
+    let response = db
+        .mail_set(set_request(account_id, vec![restored_id], None))
+        .unwrap();
+    assert_eq!(response.destroyed, vec![restored_id]);
+
+    // A restore that fails keeps the tombstone
+    let blob_id = replace_blob_id(&db, restored_id, BlobId::new_external(b"missing"));
+    let response = db
+        .mail_set(set_request(account_id, vec![], vec![restored_id].into()))
+        .unwrap();
+    assert!(response.updated.is_empty());
+    assert!(
+        matches!(
+            response.not_updated.get(&restored_id),
+            Some(err) if matches!(err.type_, SetErrorType::BlobNotFound)
+        ),
+        "{:?}",
+        response.not_updated
+    );
+    assert_eq!(db.mail_tombstone_ids(account_id).unwrap().len(), 1);
+    replace_blob_id(&db, restored_id, blob_id);
+    std::thread::sleep(Duration::from_secs(db.config.mail_destroy_grace_period + 1));
+
+    let response = db
+        .mail_set(set_request(account_id, vec![], vec![restored_id].into()))
+        .unwrap();
+    assert!(response.updated.is_empty());
+    assert!(response.not_updated.get(&restored_id).is_some());
+
+    // Purge expired tombstones
+    assert_eq!(db.mail_tombstone_ids(account_id).unwrap().len(), 1);
+    db.mail_purge_tombstones().unwrap();
+    assert!(db.mail_tombstone_ids(account_id).unwrap().is_empty());
+    assert!(db
+        .get_document_ids(account_id, Collection::Mail)
+        .unwrap()
+        .unwrap_or_default()
+        .is_empty());
+
+    // Messages can not be restored once their id was reused by another message,
+    // freed ids are handed out again after the id assigner is reloaded.
+    db.id_assigner.invalidate_all();
+    let id = import_message(&db, account_id, mailbox_id);
+    let response = db
+        .mail_set(set_request(account_id, vec![id], None))
+        .unwrap();
+    assert_eq!(response.destroyed, vec![id]);
+    db.id_assigner.invalidate_all();
+    let reused_id = import_message(&db, account_id, mailbox_id);
+    assert_eq!(reused_id.get_document_id(), id.get_document_id());
+    let response = db
+        .mail_set(set_request(account_id, vec![], vec![id].into()))
+        .unwrap();
+    assert!(response.updated.is_empty());
+    assert!(
+        matches!(
+            response.not_updated.get(&id),
+            Some(err) if matches!(err.type_, SetErrorType::AlreadyExists)
+        ),
+        "{:?}",
+        response.not_updated
+    );
+    assert_eq!(db.mail_tombstone_ids(account_id).unwrap().len(), 1);
+}
+
+fn import_message<T>(
+    db: &JMAPStore<T>,
+    account_id: store::AccountId,
+    mailbox_id: store::DocumentId,
+) -> JMAPId
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let blob_id = BlobId::new_external(MESSAGE);
+    db.blob_store(&blob_id, MESSAGE.to_vec()).unwrap();
+    *db.mail_import_item(
+        account_id,
+        blob_id,
+        MESSAGE,
+        vec![mailbox_id],
+        vec![Tag::Static(Keyword::SEEN)],
+        Some(10000),
+    )
+    .unwrap()
+    .id()
+    .unwrap()
+}
+
+fn replace_blob_id<T>(db: &JMAPStore<T>, id: JMAPId, blob_id: BlobId) -> BlobId
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut changes = TombstoneChanges::new(1);
+    let tombstones = changes.get_mut(db, id.get_document_id()).unwrap();
+    let mut tombstone = tombstones.remove(id).unwrap();
+    let prev_blob_id = std::mem::replace(&mut tombstone.blob_id, blob_id);
+    tombstones.insert(tombstone);
+    let mut batch = WriteBatch::new(1);
+    db.mail_tombstones_write(&mut batch, changes).unwrap();
+    db.write(batch).unwrap();
+    prev_blob_id
+}
+
+fn set_request(
+    account_id: store::AccountId,
+    destroy: Vec<JMAPId>,
+    restore: Option<Vec<JMAPId>>,
+) -> SetRequest<Email> {
+    SetRequest {
+        acl: Arc::new(ACLToken {
+            member_of: vec![account_id],
+            access_to: vec![],
+        })
+        .into(),
+        account_id: JMAPId::new(account_id as u64),
+        if_in_state: None,
+        create: None,
+        update: None,
+        destroy: if !destroy.is_empty() {
+            MaybeResultReference::Value(destroy).into()
+        } else {
+            None
+        },
+        arguments: SetArguments {
+            restore,
+            ..Default::default()
+        },
+    }
+}
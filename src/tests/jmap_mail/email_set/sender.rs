@@ -0,0 +1,207 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    jmap_store::Object,
+    orm::TinyORM,
+    principal::schema::{self as principal, Principal},
+    request::{get::GetRequest, set::SetRequest, MaybeResultReference},
+    types::jmap::JMAPId,
+    SUPERUSER_ID,
+};
+use jmap_mail::mail::{
+    get::JMAPGetMail,
+    schema::{Email, EmailAddress, Property, Value},
+    set::JMAPSetMail,
+};
+use store::{
+    core::{acl::ACLToken, collection::Collection, document::Document, vec_map::VecMap},
+    write::batch::WriteBatch,
+    AccountId, JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::create_mailbox;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let normalize = db.config.mail_normalize_sender;
+    println!(
+        "Running Email/set sender tests ({})...",
+        if normalize {
+            "normalized"
+        } else {
+            "passthrough"
+        }
+    );
+    let account_id = 1;
+
+    // Create account and mailbox
+    let mut batch = WriteBatch::new(SUPERUSER_ID);
+    let mut document = Document::new(Collection::Principal, account_id);
+    let mut fields = TinyORM::<Principal>::new();
+    fields.set(
+        principal::Property::Email,
+        principal::Value::Text {
+            value: "jdoe@example.com".to_string(),
+        },
+    );
+    fields.insert(&mut document).unwrap();
+    batch.insert_document(document);
+    db.write(batch).unwrap();
+    let mailbox_id = create_mailbox(&db, account_id, "Drafts", "drafts");
+
+    let tests = [
+        // A single From does not need a Sender
+        ("single", vec!["jane@example.com"], vec![], vec![], vec![]),
+        // A Sender matching the only From is redundant
+        (
+            "redundant",
+            vec!["jane@example.com"],
+            vec!["JANE@example.com"],
+            vec![],
+            vec!["JANE@example.com"],
+        ),
+        // A Sender different from the From is kept
+        (
+            "different",
+            vec!["jane@example.com"],
+            vec!["secretary@example.com"],
+            vec!["secretary@example.com"],
+            vec!["secretary@example.com"],
+        ),
+        // Multiple From addresses require a Sender, the account's address is used
+        (
+            "multiple",
+            vec!["jane@example.com", "john@example.com"],
+            vec![],
+            vec!["jdoe@example.com"],
+            vec![],
+        ),
+        // Unless one was provided
+        (
+            "multiple_sender",
+            vec!["jane@example.com", "john@example.com"],
+            vec!["john@example.com"],
+            vec!["john@example.com"],
+            vec!["john@example.com"],
+        ),
+    ];
+
+    let mut create = VecMap::new();
+    for (create_id, from, sender, _, _) in &tests {
+        create.append(create_id.to_string(), build_email(mailbox_id, from, sender));
+    }
+    let mut response = db
+        .mail_set(SetRequest {
+            acl: acl(account_id).into(),
+            account_id: JMAPId::new(account_id as u64),
+            if_in_state: None,
+            create: create.into(),
+            update: None,
+            destroy: None,
+            arguments: Default::default(),
+        })
+        .unwrap();
+    assert_eq!(
+        response.created.len(),
+        tests.len(),
+        "{:?}",
+        response.not_created
+    );
+
+    for (create_id, _, _, expected_normalized, expected_passthrough) in tests {
+        let id = *response.created.remove(create_id).unwrap().id().unwrap();
+        assert_eq!(
+            get_sender(&db, account_id, id),
+            if normalize {
+                expected_normalized
+            } else {
+                expected_passthrough
+            },
+            "{}",
+            create_id
+        );
+    }
+}
+
+fn build_email(mailbox_id: u32, from: &[&str], sender: &[&str]) -> Email {
+    let mut email: Email = serde_json::from_str(&format!(
+        concat!(
+            "{{\"mailboxIds\": {{\"{}\": true}}, \"subject\": \"Hello\", ",
+            "\"bodyValues\": {{\"1\": {{\"value\": \"Hi there.\"}}}}, ",
+            "\"textBody\": [{{\"type\": \"text/plain\", \"partId\": \"1\"}}]}}"
+        ),
+        JMAPId::from(mailbox_id),
+    ))
+    .unwrap();
+    for (property, addresses) in [(Property::From, from), (Property::Sender, sender)] {
+        if !addresses.is_empty() {
+            email.insert(
+                property,
+                Value::Addresses {
+                    value: addresses
+                        .iter()
+                        .map(|email| EmailAddress {
+                            name: None,
+                            email: email.to_string(),
+                        })
+                        .collect(),
+                },
+            );
+        }
+    }
+    email
+}
+
+fn get_sender<T>(db: &JMAPStore<T>, account_id: AccountId, id: JMAPId) -> Vec<String>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut response = db
+        .mail_get(GetRequest {
+            acl: acl(account_id).into(),
+            account_id: JMAPId::new(account_id as u64),
+            ids: MaybeResultReference::Value(vec![id]).into(),
+            properties: MaybeResultReference::Value(vec![Property::Sender]).into(),
+            arguments: Default::default(),
+        })
+        .unwrap();
+    let email: Email = response.list.pop().unwrap();
+
+    match email.properties.get(&Property::Sender) {
+        Some(Value::Addresses { value }) => value.iter().map(|a| a.email.clone()).collect(),
+        Some(Value::Null) | None => vec![],
+        other => panic!("Unexpected sender value {:?}", other),
+    }
+}
+
+fn acl(account_id: AccountId) -> Arc<ACLToken> {
+    Arc::new(ACLToken {
+        member_of: vec![account_id],
+        access_to: vec![],
+    })
+}
@@ -0,0 +1,119 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use std::{sync::Arc, time::Duration};
+
+use jmap::{
+    jmap_store::changes::JMAPChanges,
+    request::set::{SetRequest, SetResponse},
+    types::jmap::JMAPId,
+};
+use jmap_mail::mail::{
+    schema::Email,
+    set::{JMAPSetMail, SetArguments},
+};
+use store::{
+    core::{acl::ACLToken, collection::Collection, vec_map::VecMap},
+    AccountId, DocumentId, JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email/set account serialization tests...");
+    let account_id = 1;
+
+    // Create account and mailbox
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+    let initial_state = db.get_state(account_id, Collection::Mail).unwrap();
+
+    // Queue two sets on the same account while its write lock is held
+    let lock = db.lock_account(account_id);
+    let handles = (0..2)
+        .map(|num| {
+            let db = db.clone();
+            std::thread::spawn(move || {
+                db.mail_set(create_request(account_id, mailbox_id, num))
+                    .unwrap()
+            })
+        })
+        .collect::<Vec<_>>();
+    std::thread::sleep(Duration::from_millis(200));
+    drop(lock);
+
+    let mut responses = handles
+        .into_iter()
+        .map(|handle| handle.join().unwrap())
+        .collect::<Vec<SetResponse<Email>>>();
+    for response in &responses {
+        assert_eq!(response.created.len(), 1, "{:?}", response.not_created);
+        assert_ne!(response.old_state, response.new_state);
+    }
+
+    // Both sets succeeded and the second one saw the state left by the first
+    if responses[0].old_state != Some(initial_state.clone()) {
+        responses.swap(0, 1);
+    }
+    assert_eq!(responses[0].old_state, Some(initial_state));
+    assert_eq!(responses[1].old_state, responses[0].new_state);
+    assert_eq!(
+        responses[1].new_state,
+        Some(db.get_state(account_id, Collection::Mail).unwrap())
+    );
+    assert_eq!(
+        db.get_document_ids(account_id, Collection::Mail)
+            .unwrap()
+            .unwrap_or_default()
+            .len(),
+        2
+    );
+}
+
+fn create_request(account_id: AccountId, mailbox_id: DocumentId, num: usize) -> SetRequest<Email> {
+    let mut create = VecMap::new();
+    create.append(
+        format!("m{}", num),
+        serde_json::from_str::<Email>(&format!(
+            r#"{{"mailboxIds": {{"{}": true}}, "subject": "Message {}"}}"#,
+            JMAPId::from(mailbox_id),
+            num
+        ))
+        .unwrap(),
+    );
+
+    SetRequest {
+        acl: Arc::new(ACLToken {
+            member_of: vec![account_id],
+            access_to: vec![],
+        })
+        .into(),
+        account_id: JMAPId::new(account_id as u64),
+        if_in_state: None,
+        create: create.into(),
+        update: None,
+        destroy: None,
+        arguments: SetArguments::default(),
+    }
+}
@@ -0,0 +1,170 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    jmap_store::Object,
+    request::{get::GetRequest, set::SetRequest, MaybeResultReference},
+    types::{date::JMAPDate, jmap::JMAPId},
+};
+use jmap_mail::mail::{
+    get::JMAPGetMail,
+    import::JMAPMailImport,
+    schema::{Email, Property, Value},
+    set::JMAPSetMail,
+};
+use store::{
+    blob::BlobId,
+    core::{acl::ACLToken, vec_map::VecMap},
+    AccountId, JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+const MESSAGE: &[u8] =
+    b"From: john@example.com\r\nTo: jane@example.com\r\nSubject: Hello\r\n\r\nHi there.\r\n";
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email/get server-set properties tests...");
+    let account_id = 1;
+
+    // Create account and mailbox
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+
+    // Messages created with Email/set are returned with their size and
+    // reception time, both of which are available right away on Email/get
+    for received_at in [None, Some(JMAPDate::from_timestamp(1000000))] {
+        let mut email: Email = serde_json::from_str(&format!(
+            concat!(
+                "{{\"mailboxIds\": {{\"{}\": true}}, \"subject\": \"Hello\", ",
+                "\"bodyValues\": {{\"1\": {{\"value\": \"Hi there.\"}}}}, ",
+                "\"textBody\": [{{\"type\": \"text/plain\", \"partId\": \"1\"}}]}}"
+            ),
+            JMAPId::from(mailbox_id),
+        ))
+        .unwrap();
+        if let Some(received_at) = &received_at {
+            email.insert(
+                Property::ReceivedAt,
+                Value::Date {
+                    value: received_at.clone(),
+                },
+            );
+        }
+        let mut create = VecMap::new();
+        create.append("e1".to_string(), email);
+
+        let created = db
+            .mail_set(SetRequest {
+                acl: acl(account_id).into(),
+                account_id: JMAPId::new(account_id as u64),
+                if_in_state: None,
+                create: create.into(),
+                update: None,
+                destroy: None,
+                arguments: Default::default(),
+            })
+            .unwrap()
+            .created
+            .remove("e1")
+            .unwrap();
+        let id = match created.properties.get(&Property::Id) {
+            Some(Value::Id { value }) => *value,
+            other => panic!("Unexpected id {:?}", other),
+        };
+        let (size, created_at) = match (
+            created.properties.get(&Property::Size),
+            created.properties.get(&Property::ReceivedAt),
+        ) {
+            (Some(Value::Size { value: size }), Some(Value::Date { value: created_at })) => {
+                (*size, created_at.clone())
+            }
+            other => panic!("Unexpected server-set properties {:?}", other),
+        };
+        assert!(size > 0);
+        if let Some(received_at) = received_at {
+            assert_eq!(created_at, received_at);
+        }
+
+        assert_eq!(get_size_and_date(&db, account_id, id), (size, created_at));
+    }
+
+    // Same for imported messages
+    let blob_id = BlobId::new_external(MESSAGE);
+    db.blob_store(&blob_id, MESSAGE.to_vec()).unwrap();
+    let id = *db
+        .mail_import_item(
+            account_id,
+            blob_id,
+            MESSAGE,
+            vec![mailbox_id],
+            vec![],
+            Some(2000000),
+        )
+        .unwrap()
+        .id()
+        .unwrap();
+    assert_eq!(
+        get_size_and_date(&db, account_id, id),
+        (MESSAGE.len(), JMAPDate::from_timestamp(2000000))
+    );
+}
+
+fn get_size_and_date<T>(db: &JMAPStore<T>, account_id: AccountId, id: JMAPId) -> (usize, JMAPDate)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut response = db
+        .mail_get(GetRequest {
+            acl: acl(account_id).into(),
+            account_id: JMAPId::new(account_id as u64),
+            ids: MaybeResultReference::Value(vec![id]).into(),
+            properties: MaybeResultReference::Value(vec![Property::Size, Property::ReceivedAt])
+                .into(),
+            arguments: Default::default(),
+        })
+        .unwrap();
+    assert!(response.not_found.is_empty());
+    let email: Email = response.list.pop().unwrap();
+
+    match (
+        email.properties.get(&Property::Size),
+        email.properties.get(&Property::ReceivedAt),
+    ) {
+        (Some(Value::Size { value: size }), Some(Value::Date { value: received_at })) => {
+            (*size, received_at.clone())
+        }
+        other => panic!("Unexpected server-set properties {:?}", other),
+    }
+}
+
+fn acl(account_id: AccountId) -> Arc<ACLToken> {
+    Arc::new(ACLToken {
+        member_of: vec![account_id],
+        access_to: vec![],
+    })
+}
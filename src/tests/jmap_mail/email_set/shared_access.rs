@@ -0,0 +1,171 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    jmap_store::Object,
+    orm::TinyORM,
+    request::{get::GetRequest, MaybeResultReference},
+    types::jmap::JMAPId,
+    SUPERUSER_ID,
+};
+use jmap_mail::{
+    mail::{
+        get::{GetArguments, JMAPGetMail},
+        import::JMAPMailImport,
+        schema::Property,
+        sharing::JMAPShareMail,
+    },
+    mailbox::{schema::Mailbox, CreateMailbox},
+};
+use store::{
+    blob::BlobId,
+    core::{
+        acl::{ACLToken, ACL},
+        collection::Collection,
+        document::Document,
+    },
+    write::batch::WriteBatch,
+    AccountId, DocumentId, JMAPStore, SharedBitmap, Store,
+};
+
+const GRANTEE_ID: AccountId = 2;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running inherited email ACL tests...");
+    let owner_id = 1;
+
+    // Create both accounts
+    let mut batch = WriteBatch::new(SUPERUSER_ID);
+    batch.insert_document(Document::new(Collection::Principal, owner_id));
+    batch.insert_document(Document::new(Collection::Principal, GRANTEE_ID));
+    db.write(batch).unwrap();
+
+    // The owner shares one mailbox for reading and another one for flagging only
+    let shared_id = create_mailbox(&db, owner_id, "Shared", vec![ACL::ReadItems]);
+    let private_id = create_mailbox(&db, owner_id, "Private", vec![]);
+    let flagged_id = create_mailbox(&db, owner_id, "Flagged", vec![ACL::ModifyItems]);
+
+    let shared_and_private = import_message(&db, owner_id, vec![shared_id, private_id], 0);
+    let private_only = import_message(&db, owner_id, vec![private_id], 1);
+    let shared_and_flagged = import_message(&db, owner_id, vec![shared_id, flagged_id], 2);
+
+    // Messages inherit the permissions of their mailboxes
+    let readable = db
+        .mail_shared_messages(owner_id, &[GRANTEE_ID], ACL::ReadItems)
+        .unwrap();
+    assert!(readable.has_access(shared_and_private.get_document_id()));
+    assert!(readable.has_access(shared_and_flagged.get_document_id()));
+    assert!(!readable.has_access(private_only.get_document_id()));
+
+    // Permissions from different mailboxes add up
+    let modifiable = db
+        .mail_shared_messages(owner_id, &[GRANTEE_ID], ACL::ModifyItems)
+        .unwrap();
+    assert!(modifiable.has_access(shared_and_flagged.get_document_id()));
+    assert!(!modifiable.has_access(shared_and_private.get_document_id()));
+    assert!(!modifiable.has_access(private_only.get_document_id()));
+
+    // The grantee can fetch messages in a shared mailbox, but only sees the shared mailbox
+    let response = db
+        .mail_get(GetRequest {
+            acl: Arc::new(ACLToken {
+                member_of: vec![GRANTEE_ID],
+                access_to: vec![(owner_id, vec![Collection::Mail, Collection::Mailbox].into())],
+            })
+            .into(),
+            account_id: JMAPId::new(owner_id as u64),
+            ids: MaybeResultReference::Value(vec![shared_and_private, private_only]).into(),
+            properties: MaybeResultReference::Value(vec![Property::Id, Property::MailboxIds])
+                .into(),
+            arguments: GetArguments::default(),
+        })
+        .unwrap();
+    assert_eq!(response.list.len(), 1);
+    let email = serde_json::to_value(&response.list[0]).unwrap();
+    assert_eq!(email["id"], shared_and_private.to_string());
+    assert_eq!(
+        email["mailboxIds"],
+        serde_json::json!({ (JMAPId::from(shared_id).to_string()): true })
+    );
+    assert_eq!(response.not_found, vec![private_only]);
+}
+
+fn create_mailbox<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    name: &str,
+    acls: Vec<ACL>,
+) -> DocumentId
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut batch = WriteBatch::new(account_id);
+    let mut document = Document::new(
+        Collection::Mailbox,
+        db.assign_document_id(account_id, Collection::Mailbox)
+            .unwrap(),
+    );
+    let mailbox_id = document.document_id;
+    let mut mailbox = TinyORM::<Mailbox>::new_mailbox(name, "");
+    if !acls.is_empty() {
+        mailbox.acl_update(GRANTEE_ID, acls);
+        mailbox.acl_finish();
+    }
+    mailbox.insert(&mut document).unwrap();
+    batch.log_insert(Collection::Mailbox, mailbox_id);
+    batch.insert_document(document);
+    db.write(batch).unwrap();
+    mailbox_id
+}
+
+fn import_message<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    mailbox_ids: Vec<DocumentId>,
+    num: usize,
+) -> JMAPId
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let message = format!(
+        concat!(
+            "From: sender@example.com\r\n",
+            "Message-ID: <shared-{}@example.com>\r\n",
+            "Subject: Shared message {}\r\n\r\n",
+            "Body of message {}.\r\n"
+        ),
+        num, num, num
+    )
+    .into_bytes();
+    let blob_id = BlobId::new_external(&message);
+    db.blob_store(&blob_id, message.clone()).unwrap();
+    *db.mail_import_item(account_id, blob_id, &message, mailbox_ids, vec![], None)
+        .unwrap()
+        .id()
+        .unwrap()
+}
@@ -0,0 +1,166 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    error::set::SetErrorType, jmap_store::Object, orm::serialize::JMAPOrm,
+    request::set::SetRequest, types::jmap::JMAPId, SUPERUSER_ID,
+};
+use jmap_mail::mail::{
+    import::JMAPMailImport,
+    schema::{Email, Property},
+    set::{JMAPSetMail, SetArguments},
+};
+use store::{
+    blob::BlobId,
+    core::{acl::ACLToken, collection::Collection, document::Document, tag::Tag, vec_map::VecMap},
+    write::batch::WriteBatch,
+    AccountId, DocumentId, JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::create_mailbox;
+
+const MESSAGE: &[u8] =
+    b"From: john@example.com\r\nTo: jane@example.com\r\nSubject: Trash me\r\n\r\nBye.\r\n";
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email move to Trash tests...");
+
+    // Create an account with Trash and one without it
+    let mut batch = WriteBatch::new(SUPERUSER_ID);
+    for account_id in [1, 2] {
+        batch.insert_document(Document::new(Collection::Principal, account_id));
+    }
+    db.write(batch).unwrap();
+    let inbox_id = create_mailbox(&db, 1, "Inbox", "inbox");
+    let archive_id = create_mailbox(&db, 1, "Archive", "archive");
+    let trash_id = create_mailbox(&db, 1, "Deleted Items", "trash");
+    let other_inbox_id = create_mailbox(&db, 2, "Inbox", "inbox");
+
+    // Messages in several mailboxes end up only in Trash
+    let id = import_message(&db, 1, vec![inbox_id, archive_id]);
+    let response = db
+        .mail_set(set_request(1, VecMap::new(), vec![id]))
+        .unwrap();
+    assert!(
+        response.not_updated.is_empty(),
+        "{:?}",
+        response.not_updated
+    );
+    assert!(response.updated.contains_key(&id));
+    assert_eq!(mailbox_tags(&db, 1, id), vec![Tag::Id(trash_id)]);
+
+    // Trashing a message that is already in Trash is a no-op
+    let response = db
+        .mail_set(set_request(1, VecMap::new(), vec![id]))
+        .unwrap();
+    assert!(response.updated.contains_key(&id));
+    assert_eq!(mailbox_tags(&db, 1, id), vec![Tag::Id(trash_id)]);
+
+    // A message cannot be patched and trashed in the same request
+    let id = import_message(&db, 1, vec![inbox_id]);
+    let response = db
+        .mail_set(set_request(
+            1,
+            VecMap::from_iter([(id, Email::default())]),
+            vec![id],
+        ))
+        .unwrap();
+    assert!(matches!(
+        response.not_updated.get(&id).map(|err| &err.type_),
+        Some(SetErrorType::InvalidPatch)
+    ));
+    assert_eq!(mailbox_tags(&db, 1, id), vec![Tag::Id(inbox_id)]);
+
+    // Trashing fails when the account has no Trash mailbox
+    let id = import_message(&db, 2, vec![other_inbox_id]);
+    let response = db
+        .mail_set(set_request(2, VecMap::new(), vec![id]))
+        .unwrap();
+    assert!(matches!(
+        response.not_updated.get(&id).map(|err| &err.type_),
+        Some(SetErrorType::InvalidProperties)
+    ));
+    assert_eq!(mailbox_tags(&db, 2, id), vec![Tag::Id(other_inbox_id)]);
+}
+
+fn import_message<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    mailbox_ids: Vec<DocumentId>,
+) -> JMAPId
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let blob_id = BlobId::new_external(MESSAGE);
+    db.blob_store(&blob_id, MESSAGE.to_vec()).unwrap();
+    *db.mail_import_item(account_id, blob_id, MESSAGE, mailbox_ids, vec![], None)
+        .unwrap()
+        .id()
+        .unwrap()
+}
+
+fn mailbox_tags<T>(db: &JMAPStore<T>, account_id: AccountId, id: JMAPId) -> Vec<Tag>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    db.get_orm::<Email>(account_id, id.get_document_id())
+        .unwrap()
+        .unwrap()
+        .get_tags(&Property::MailboxIds)
+        .unwrap()
+        .iter()
+        .cloned()
+        .collect()
+}
+
+fn set_request(
+    account_id: AccountId,
+    update: VecMap<JMAPId, Email>,
+    trash: Vec<JMAPId>,
+) -> SetRequest<Email> {
+    SetRequest {
+        acl: Arc::new(ACLToken {
+            member_of: vec![account_id],
+            access_to: vec![],
+        })
+        .into(),
+        account_id: JMAPId::new(account_id as u64),
+        if_in_state: None,
+        create: None,
+        update: if !update.is_empty() {
+            update.into()
+        } else {
+            None
+        },
+        destroy: None,
+        arguments: SetArguments {
+            trash: trash.into(),
+            ..Default::default()
+        },
+    }
+}
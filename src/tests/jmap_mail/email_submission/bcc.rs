@@ -0,0 +1,225 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    jmap_store::Object,
+    orm::TinyORM,
+    request::{query::QueryRequest, set::SetRequest, MaybeIdReference},
+    types::jmap::JMAPId,
+};
+use jmap_mail::{
+    email_submission::{
+        schema::EmailSubmission,
+        set::{JMAPSetEmailSubmission, SetArguments},
+    },
+    identity::schema::{Identity, Property as IdentityProperty, Value as IdentityValue},
+    mail::{
+        import::JMAPMailImport, query::JMAPMailQuery, schema::Email, set::JMAPSetMail, MessageData,
+        MessageField,
+    },
+    mail_parser::RfcHeader,
+};
+use store::{
+    blob::BlobId,
+    core::{acl::ACLToken, collection::Collection, document::Document, vec_map::VecMap},
+    serialize::StoreDeserialize,
+    write::batch::WriteBatch,
+    AccountId, DocumentId, JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::{create_account_mailbox, create_mailbox};
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let keep_bcc = db.config.mail_sent_keep_bcc;
+    println!(
+        "Running EmailSubmission Bcc tests (Bcc {})...",
+        if keep_bcc { "kept" } else { "stripped" }
+    );
+    let account_id = 1;
+
+    // Create account, mailboxes and identity
+    let drafts_id = create_account_mailbox(&db, account_id, "Drafts", "drafts");
+    let sent_id = create_mailbox(&db, account_id, "Sent", "sent");
+
+    let mut batch = WriteBatch::new(account_id);
+    let mut document = Document::new(
+        Collection::Identity,
+        db.assign_document_id(account_id, Collection::Identity)
+            .unwrap(),
+    );
+    let identity_id = document.document_id;
+    let mut identity = TinyORM::<Identity>::new();
+    identity.set(
+        IdentityProperty::Email,
+        IdentityValue::Text {
+            value: "jdoe@example.com".to_string(),
+        },
+    );
+    identity.insert(&mut document).unwrap();
+    batch.log_insert(Collection::Identity, identity_id);
+    batch.insert_document(document);
+    db.write(batch).unwrap();
+
+    // Import a draft with a (folded) Bcc header
+    let message = concat!(
+        "From: jdoe@example.com\r\n",
+        "To: jane@example.com\r\n",
+        "Bcc: bill@example.com,\r\n",
+        " boss@example.com\r\n",
+        "Subject: Quarterly report\r\n\r\n",
+        "Hi Jane,\r\n"
+    )
+    .as_bytes()
+    .to_vec();
+    let blob_id = BlobId::new_external(&message);
+    db.blob_store(&blob_id, message.clone()).unwrap();
+    let draft_id = *db
+        .mail_import_item(account_id, blob_id, &message, vec![drafts_id], vec![], None)
+        .unwrap()
+        .id()
+        .unwrap();
+
+    // Submit and move the message to the Sent mailbox
+    let acl = Arc::new(ACLToken {
+        member_of: vec![account_id],
+        access_to: vec![],
+    });
+    let mut create = VecMap::new();
+    create.append(
+        "s1".to_string(),
+        serde_json::from_str::<EmailSubmission>(&format!(
+            r#"{{"emailId": "{}", "identityId": "{}"}}"#,
+            draft_id,
+            JMAPId::from(identity_id)
+        ))
+        .unwrap(),
+    );
+    let mut on_success_update_email = VecMap::new();
+    on_success_update_email.append(
+        MaybeIdReference::Reference("s1".to_string()),
+        serde_json::from_str::<Email>(&format!(
+            r#"{{"mailboxIds": {{"{}": true}}}}"#,
+            JMAPId::from(sent_id)
+        ))
+        .unwrap(),
+    );
+    let mut response = db
+        .email_submission_set(SetRequest {
+            acl: acl.clone().into(),
+            account_id: JMAPId::new(account_id as u64),
+            if_in_state: None,
+            create: create.into(),
+            update: None,
+            destroy: None,
+            arguments: SetArguments {
+                on_success_update_email: on_success_update_email.into(),
+                ..Default::default()
+            },
+        })
+        .unwrap();
+    assert!(
+        response.created.contains_key("s1"),
+        "{:?}",
+        response.not_created
+    );
+    let response = db.mail_set(response.next_call().unwrap()).unwrap();
+    assert!(
+        response.not_updated.is_empty(),
+        "{:?}",
+        response.not_updated
+    );
+    assert!(
+        response.not_destroyed.is_empty(),
+        "{:?}",
+        response.not_destroyed
+    );
+
+    // Drafts is now empty and Sent holds a single message
+    assert_eq!(query_mailbox(&db, &acl, account_id, drafts_id), vec![]);
+    let sent = query_mailbox(&db, &acl, account_id, sent_id);
+    assert_eq!(sent.len(), 1);
+
+    let message_data = MessageData::deserialize(
+        &db.blob_get(
+            &db.get_document_value::<BlobId>(
+                account_id,
+                Collection::Mail,
+                sent[0].get_document_id(),
+                MessageField::Metadata.into(),
+            )
+            .unwrap()
+            .unwrap(),
+        )
+        .unwrap()
+        .unwrap(),
+    )
+    .unwrap();
+    let raw_message =
+        String::from_utf8(db.blob_get(&message_data.raw_message).unwrap().unwrap()).unwrap();
+
+    if keep_bcc {
+        // The submitted message itself is moved to Sent
+        assert_eq!(sent[0], draft_id);
+        assert!(message_data.headers.contains_key(&RfcHeader::Bcc));
+        assert!(raw_message.contains("boss@example.com"), "{}", raw_message);
+    } else {
+        // A copy without Bcc replaces the submitted message
+        assert_ne!(sent[0], draft_id);
+        assert!(!message_data.headers.contains_key(&RfcHeader::Bcc));
+        assert!(!raw_message.contains("example.com,"), "{}", raw_message);
+        assert!(!raw_message.contains("boss@example.com"), "{}", raw_message);
+        assert!(
+            raw_message.starts_with(concat!(
+                "From: jdoe@example.com\r\n",
+                "To: jane@example.com\r\n",
+                "Subject: Quarterly report\r\n\r\n",
+            )),
+            "{}",
+            raw_message
+        );
+    }
+}
+
+fn query_mailbox<T>(
+    db: &JMAPStore<T>,
+    acl: &Arc<ACLToken>,
+    account_id: AccountId,
+    mailbox_id: DocumentId,
+) -> Vec<JMAPId>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut request: QueryRequest<Email> = serde_json::from_str(&format!(
+        "{{\"accountId\": \"{}\", \"filter\": {{\"inMailbox\": \"{}\"}}}}",
+        JMAPId::new(account_id as u64),
+        JMAPId::from(mailbox_id)
+    ))
+    .unwrap();
+    request.acl = acl.clone().into();
+    db.mail_query(request).unwrap().ids
+}
@@ -0,0 +1,283 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    error::set::SetErrorType,
+    jmap_store::Object,
+    orm::TinyORM,
+    request::{set::SetRequest, MaybeIdReference, MaybeResultReference},
+    types::jmap::JMAPId,
+};
+use jmap_mail::{
+    email_submission::{
+        schema::EmailSubmission,
+        set::{JMAPSetEmailSubmission, SetArguments as SubmissionSetArguments},
+    },
+    identity::schema::{Identity, Property as IdentityProperty, Value as IdentityValue},
+    mail::{
+        import::JMAPMailImport,
+        set::{JMAPSetMail, SetArguments},
+    },
+};
+use store::{
+    blob::BlobId,
+    core::{acl::ACLToken, collection::Collection, document::Document, vec_map::VecMap},
+    write::batch::WriteBatch,
+    AccountId, DocumentId, JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email/set destroy with pending submissions tests...");
+    let account_id = 1;
+
+    // Create account, mailbox and identity
+    let mailbox_id = create_account_mailbox(&db, account_id, "Drafts", "drafts");
+
+    let mut batch = WriteBatch::new(account_id);
+    let mut document = Document::new(
+        Collection::Identity,
+        db.assign_document_id(account_id, Collection::Identity)
+            .unwrap(),
+    );
+    let identity_id = document.document_id;
+    let mut identity = TinyORM::<Identity>::new();
+    identity.set(
+        IdentityProperty::Email,
+        IdentityValue::Text {
+            value: "jdoe@example.com".to_string(),
+        },
+    );
+    identity.insert(&mut document).unwrap();
+    batch.log_insert(Collection::Identity, identity_id);
+    batch.insert_document(document);
+    db.write(batch).unwrap();
+
+    // Drafts with a pending submission can't be destroyed until the
+    // submission is canceled or final
+    for undo_status in ["canceled", "final"] {
+        let email_id = import_draft(&db, account_id, mailbox_id);
+        let submission_id = submit(&db, account_id, email_id, identity_id, "pending");
+        assert!(
+            matches!(
+                destroy_email(&db, account_id, email_id, false),
+                Err(SetErrorType::Forbidden)
+            ),
+            "{}",
+            undo_status
+        );
+
+        set_undo_status(&db, account_id, submission_id, undo_status);
+        destroy_email(&db, account_id, email_id, false).unwrap();
+    }
+
+    // Submissions that were already sent or canceled don't block destroys
+    for undo_status in ["canceled", "final"] {
+        let email_id = import_draft(&db, account_id, mailbox_id);
+        submit(&db, account_id, email_id, identity_id, undo_status);
+        destroy_email(&db, account_id, email_id, false).unwrap();
+    }
+
+    // Pending submissions for other messages are not taken into account
+    let email_id = import_draft(&db, account_id, mailbox_id);
+    let other_email_id = import_draft(&db, account_id, mailbox_id);
+    submit(&db, account_id, other_email_id, identity_id, "pending");
+    destroy_email(&db, account_id, email_id, false).unwrap();
+
+    // Destroys can be forced
+    destroy_email(&db, account_id, other_email_id, true).unwrap();
+
+    // Messages destroyed on a successful submission are always destroyed
+    let email_id = import_draft(&db, account_id, mailbox_id);
+    let mut create = VecMap::new();
+    create.append(
+        "s1".to_string(),
+        submission(email_id, identity_id, "pending"),
+    );
+    let mut response = db
+        .email_submission_set(SetRequest {
+            acl: acl(account_id).into(),
+            account_id: JMAPId::new(account_id as u64),
+            if_in_state: None,
+            create: create.into(),
+            update: None,
+            destroy: None,
+            arguments: SubmissionSetArguments {
+                on_success_destroy_email: vec![MaybeIdReference::Reference("s1".to_string())]
+                    .into(),
+                ..Default::default()
+            },
+        })
+        .unwrap();
+    assert!(response.created.contains_key("s1"));
+    let next_call = response.next_call().unwrap();
+    assert_eq!(next_call.arguments.force_destroy, Some(true));
+    assert_eq!(db.mail_set(next_call).unwrap().destroyed, vec![email_id],);
+}
+
+fn acl(account_id: AccountId) -> Arc<ACLToken> {
+    Arc::new(ACLToken {
+        member_of: vec![account_id],
+        access_to: vec![],
+    })
+}
+
+fn import_draft<T>(db: &JMAPStore<T>, account_id: AccountId, mailbox_id: DocumentId) -> JMAPId
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let message = concat!(
+        "From: jdoe@example.com\r\n",
+        "To: jane@example.com\r\n",
+        "Subject: Draft\r\n\r\n",
+        "Hello Jane,\r\n"
+    )
+    .as_bytes()
+    .to_vec();
+    let blob_id = BlobId::new_external(&message);
+    db.blob_store(&blob_id, message.clone()).unwrap();
+    *db.mail_import_item(
+        account_id,
+        blob_id,
+        &message,
+        vec![mailbox_id],
+        vec![],
+        None,
+    )
+    .unwrap()
+    .id()
+    .unwrap()
+}
+
+fn submission(email_id: JMAPId, identity_id: DocumentId, undo_status: &str) -> EmailSubmission {
+    serde_json::from_str::<EmailSubmission>(&format!(
+        r#"{{"emailId": "{}", "identityId": "{}", "undoStatus": "{}"}}"#,
+        email_id,
+        JMAPId::from(identity_id),
+        undo_status
+    ))
+    .unwrap()
+}
+
+fn submit<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    email_id: JMAPId,
+    identity_id: DocumentId,
+    undo_status: &str,
+) -> JMAPId
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut create = VecMap::new();
+    create.append(
+        "s1".to_string(),
+        submission(email_id, identity_id, undo_status),
+    );
+    let mut response = db
+        .email_submission_set(SetRequest {
+            acl: acl(account_id).into(),
+            account_id: JMAPId::new(account_id as u64),
+            if_in_state: None,
+            create: create.into(),
+            update: None,
+            destroy: None,
+            arguments: SubmissionSetArguments::default(),
+        })
+        .unwrap();
+    *response
+        .created
+        .remove("s1")
+        .unwrap_or_else(|| panic!("{:?}", response.not_created))
+        .id()
+        .unwrap()
+}
+
+fn set_undo_status<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    submission_id: JMAPId,
+    undo_status: &str,
+) where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut update = VecMap::new();
+    update.append(
+        submission_id,
+        serde_json::from_str::<EmailSubmission>(&format!(r#"{{"undoStatus": "{}"}}"#, undo_status))
+            .unwrap(),
+    );
+    let response = db
+        .email_submission_set(SetRequest {
+            acl: acl(account_id).into(),
+            account_id: JMAPId::new(account_id as u64),
+            if_in_state: None,
+            create: None,
+            update: update.into(),
+            destroy: None,
+            arguments: SubmissionSetArguments::default(),
+        })
+        .unwrap();
+    assert!(
+        response.updated.contains_key(&submission_id),
+        "{:?}",
+        response.not_updated
+    );
+}
+
+fn destroy_email<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    email_id: JMAPId,
+    force_destroy: bool,
+) -> Result<(), SetErrorType>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut response = db
+        .mail_set(SetRequest {
+            acl: acl(account_id).into(),
+            account_id: JMAPId::new(account_id as u64),
+            if_in_state: None,
+            create: None,
+            update: None,
+            destroy: MaybeResultReference::Value(vec![email_id]).into(),
+            arguments: SetArguments {
+                force_destroy: force_destroy.into(),
+                ..Default::default()
+            },
+        })
+        .unwrap();
+
+    if response.destroyed == vec![email_id] {
+        Ok(())
+    } else {
+        Err(response.not_destroyed.remove(&email_id).unwrap().type_)
+    }
+}
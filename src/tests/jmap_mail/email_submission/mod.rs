@@ -21,6 +21,10 @@
  * for more details.
 */
 
+pub mod bcc;
+pub mod destroy_email;
+pub mod signature;
+
 use std::{sync::Arc, time::Duration};
 
 use actix_web::web;
@@ -0,0 +1,221 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use std::sync::Arc;
+
+use jmap::{jmap_store::Object, orm::TinyORM, request::set::SetRequest, types::jmap::JMAPId};
+use jmap_mail::{
+    email_submission::{
+        schema::{EmailSubmission, Property},
+        set::{JMAPSetEmailSubmission, SetArguments},
+    },
+    identity::schema::{Identity, Property as IdentityProperty, Value as IdentityValue},
+    mail::import::JMAPMailImport,
+};
+use store::{
+    blob::BlobId,
+    core::{acl::ACLToken, collection::Collection, document::Document, vec_map::VecMap},
+    write::batch::WriteBatch,
+    AccountId, DocumentId, JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running EmailSubmission signature tests...");
+    let account_id = 1;
+
+    // Create account, mailbox and identity
+    let mailbox_id = create_account_mailbox(&db, account_id, "Drafts", "drafts");
+
+    let mut batch = WriteBatch::new(account_id);
+    let mut document = Document::new(
+        Collection::Identity,
+        db.assign_document_id(account_id, Collection::Identity)
+            .unwrap(),
+    );
+    let identity_id = document.document_id;
+    let mut identity = TinyORM::<Identity>::new();
+    for (property, value) in [
+        (IdentityProperty::Email, "jdoe@example.com"),
+        (IdentityProperty::TextSignature, "John Doe\nExample Corp"),
+        (IdentityProperty::HtmlSignature, "<b>John Doe</b>"),
+    ] {
+        identity.set(
+            property,
+            IdentityValue::Text {
+                value: value.to_string(),
+            },
+        );
+    }
+    identity.insert(&mut document).unwrap();
+    batch.log_insert(Collection::Identity, identity_id);
+    batch.insert_document(document);
+    db.write(batch).unwrap();
+
+    // The signature is appended once to both the text and HTML parts
+    let email_id = import_message(
+        &db,
+        account_id,
+        mailbox_id,
+        concat!(
+            "From: jdoe@example.com\r\n",
+            "To: jane@example.com\r\n",
+            "Subject: Signed\r\n",
+            "Content-Type: multipart/alternative; boundary=\"boundary\"\r\n\r\n",
+            "--boundary\r\n",
+            "Content-Type: text/plain; charset=utf-8\r\n\r\n",
+            "Hello Jane,\r\n",
+            "--boundary\r\n",
+            "Content-Type: text/html; charset=utf-8\r\n",
+            "Content-Transfer-Encoding: quoted-printable\r\n\r\n",
+            "<html><body><p>Hello Jane,</p></body></html>\r\n",
+            "--boundary--\r\n"
+        ),
+    );
+    let message = submit(&db, account_id, email_id, identity_id, true);
+    assert!(
+        message.contains("Hello Jane,\r\n-- \r\nJohn Doe\r\nExample Corp\r\n"),
+        "{}",
+        message
+    );
+    assert!(
+        message.contains("<p>Hello Jane,</p><div>-- <br>\r\n<b>John Doe</b></div>\r\n</body>"),
+        "{}",
+        message
+    );
+    assert_eq!(message.matches("-- \r\n").count(), 1, "{}", message);
+    assert_eq!(message.matches("-- <br>").count(), 1, "{}", message);
+
+    // Without appendSignature the message is sent as is
+    let message = submit(&db, account_id, email_id, identity_id, false);
+    assert!(!message.contains("John Doe"), "{}", message);
+
+    // Bodies that already contain a signature delimiter are left untouched
+    let email_id = import_message(
+        &db,
+        account_id,
+        mailbox_id,
+        concat!(
+            "From: jdoe@example.com\r\n",
+            "To: jane@example.com\r\n",
+            "Subject: Already signed\r\n\r\n",
+            "Hello Jane,\r\n",
+            "-- \r\n",
+            "Johnny\r\n"
+        ),
+    );
+    let message = submit(&db, account_id, email_id, identity_id, true);
+    assert!(!message.contains("John Doe"), "{}", message);
+    assert_eq!(message.matches("-- \r\n").count(), 1, "{}", message);
+}
+
+fn import_message<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    mailbox_id: DocumentId,
+    message: &str,
+) -> JMAPId
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let message = message.as_bytes().to_vec();
+    let blob_id = BlobId::new_external(&message);
+    db.blob_store(&blob_id, message.clone()).unwrap();
+    *db.mail_import_item(
+        account_id,
+        blob_id,
+        &message,
+        vec![mailbox_id],
+        vec![],
+        None,
+    )
+    .unwrap()
+    .id()
+    .unwrap()
+}
+
+fn submit<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    email_id: JMAPId,
+    identity_id: DocumentId,
+    append_signature: bool,
+) -> String
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut create = VecMap::new();
+    create.append(
+        "s1".to_string(),
+        serde_json::from_str::<EmailSubmission>(&format!(
+            r#"{{"emailId": "{}", "identityId": "{}"}}"#,
+            email_id,
+            JMAPId::from(identity_id)
+        ))
+        .unwrap(),
+    );
+    let mut response = db
+        .email_submission_set(SetRequest {
+            acl: Arc::new(ACLToken {
+                member_of: vec![account_id],
+                access_to: vec![],
+            })
+            .into(),
+            account_id: JMAPId::new(account_id as u64),
+            if_in_state: None,
+            create: create.into(),
+            update: None,
+            destroy: None,
+            arguments: SetArguments {
+                append_signature,
+                ..Default::default()
+            },
+        })
+        .unwrap();
+    let submission_id = response
+        .created
+        .remove("s1")
+        .unwrap_or_else(|| panic!("{:?}", response.not_created))
+        .id()
+        .unwrap()
+        .get_document_id();
+
+    String::from_utf8(
+        db.blob_get(
+            &db.get_document_value::<BlobId>(
+                account_id,
+                Collection::EmailSubmission,
+                submission_id,
+                Property::EmailId.into(),
+            )
+            .unwrap()
+            .unwrap(),
+        )
+        .unwrap()
+        .unwrap(),
+    )
+    .unwrap()
+}
@@ -0,0 +1,207 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    orm::serialize::JMAPOrm,
+    request::changes::{ChangesRequest, ChangesResponse},
+    types::{jmap::JMAPId, state::JMAPState},
+};
+use jmap_mail::{
+    mail::import::JMAPMailImport,
+    thread::{changes::JMAPThreadChanges, schema::Thread},
+};
+use store::{
+    blob::BlobId,
+    core::{acl::ACLToken, collection::Collection},
+    write::batch::WriteBatch,
+    AccountId, DocumentId, JMAPStore, Store, ThreadId,
+};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Thread/changes on thread reassignment tests...");
+    let account_id = 1;
+
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+
+    // Two threads, the first one holding a message and its reply
+    let alpha = import_message(
+        &db,
+        account_id,
+        mailbox_id,
+        "Subject: Alpha\r\nMessage-ID: <alpha@example.com>\r\n",
+    );
+    let reply = import_message(
+        &db,
+        account_id,
+        mailbox_id,
+        concat!(
+            "Subject: Re: Alpha\r\nMessage-ID: <reply@example.com>\r\n",
+            "In-Reply-To: <alpha@example.com>\r\n"
+        ),
+    );
+    let beta = import_message(
+        &db,
+        account_id,
+        mailbox_id,
+        "Subject: Beta\r\nMessage-ID: <beta@example.com>\r\n",
+    );
+    let alpha_thread = alpha.get_prefix_id();
+    let beta_thread = beta.get_prefix_id();
+    assert_eq!(reply.get_prefix_id(), alpha_thread);
+    assert_ne!(alpha_thread, beta_thread);
+
+    // Moving a message between threads updates both of them
+    let state = thread_state(&db, account_id);
+    assert_eq!(
+        move_thread(&db, account_id, reply.get_document_id(), beta_thread),
+        Some(alpha_thread)
+    );
+    let changes = thread_changes(&db, account_id, state);
+    assert!(changes.created.is_empty(), "{:?}", changes.created);
+    assert!(changes.destroyed.is_empty(), "{:?}", changes.destroyed);
+    assert_eq!(
+        sorted(changes.updated),
+        sorted(vec![
+            JMAPId::new(alpha_thread as u64),
+            JMAPId::new(beta_thread as u64)
+        ])
+    );
+
+    // Assigning the current thread is a no-op
+    let state = thread_state(&db, account_id);
+    assert_eq!(
+        move_thread(&db, account_id, reply.get_document_id(), beta_thread),
+        None
+    );
+    assert_eq!(thread_state(&db, account_id), state);
+
+    // Moving the last message out of a thread destroys it
+    assert_eq!(
+        move_thread(&db, account_id, alpha.get_document_id(), beta_thread),
+        Some(alpha_thread)
+    );
+    let changes = thread_changes(&db, account_id, state);
+    assert_eq!(changes.destroyed, vec![JMAPId::new(alpha_thread as u64)]);
+    assert_eq!(changes.updated, vec![JMAPId::new(beta_thread as u64)]);
+
+    // Splitting a message into a new thread creates it
+    let state = thread_state(&db, account_id);
+    let new_thread = db
+        .assign_document_id(account_id, Collection::Thread)
+        .unwrap();
+    assert_eq!(
+        move_thread(&db, account_id, alpha.get_document_id(), new_thread),
+        Some(beta_thread)
+    );
+    let changes = thread_changes(&db, account_id, state);
+    assert_eq!(changes.created, vec![JMAPId::new(new_thread as u64)]);
+    assert_eq!(changes.updated, vec![JMAPId::new(beta_thread as u64)]);
+}
+
+fn import_message<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    mailbox_id: DocumentId,
+    headers: &str,
+) -> JMAPId
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let message = format!(
+        "From: john@example.com\r\nTo: jane@example.com\r\n{}\r\nHello.\r\n",
+        headers
+    )
+    .into_bytes();
+    let blob_id = BlobId::new_external(&message);
+    db.blob_store(&blob_id, message.clone()).unwrap();
+    *db.mail_import_item(
+        account_id,
+        blob_id,
+        &message,
+        vec![mailbox_id],
+        vec![],
+        None,
+    )
+    .unwrap()
+    .id()
+    .unwrap()
+}
+
+fn move_thread<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    document_id: DocumentId,
+    thread_id: ThreadId,
+) -> Option<ThreadId>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut batch = WriteBatch::new(account_id);
+    let prev_thread_id = db
+        .mail_move_thread(&mut batch, document_id, thread_id)
+        .unwrap();
+    if !batch.is_empty() {
+        db.write(batch).unwrap();
+    }
+    prev_thread_id
+}
+
+fn thread_changes<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    since_state: JMAPState,
+) -> ChangesResponse<Thread>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    db.thread_changes(ChangesRequest {
+        acl: Arc::new(ACLToken {
+            member_of: vec![account_id],
+            access_to: vec![],
+        })
+        .into(),
+        account_id: JMAPId::new(account_id as u64),
+        since_state,
+        max_changes: None,
+    })
+    .unwrap()
+}
+
+fn thread_state<T>(db: &JMAPStore<T>, account_id: AccountId) -> JMAPState
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    thread_changes(db, account_id, JMAPState::Initial).new_state
+}
+
+fn sorted(mut ids: Vec<JMAPId>) -> Vec<JMAPId> {
+    ids.sort_unstable_by_key(u64::from);
+    ids
+}
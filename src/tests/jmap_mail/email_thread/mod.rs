@@ -21,10 +21,14 @@
  * for more details.
 */
 
+pub mod changes;
+pub mod paging;
+pub mod references;
+
 use actix_web::web;
 
 use jmap::types::jmap::JMAPId;
-use jmap_client::{client::Client, mailbox::Role};
+use jmap_client::{client::Client, email, mailbox::Role};
 use store::Store;
 
 use crate::{tests::store::utils::StoreCompareWith, JMAPServer};
@@ -69,6 +73,69 @@ where
         expected_result
     );
 
+    // Import a reply chain that merges two threads
+    let mut imported_ids = Vec::new();
+    for (num, message) in [
+        "Message-ID: <chain-a@example.com>\nSubject: chain\n\nFirst",
+        "Message-ID: <chain-b@example.com>\nSubject: chain\n\nSecond",
+        concat!(
+            "Message-ID: <chain-c@example.com>\n",
+            "In-Reply-To: <chain-b@example.com>\n",
+            "References: <chain-a@example.com> <chain-b@example.com>\n",
+            "Subject: Re: chain\n\nReply"
+        ),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        imported_ids.push(
+            client
+                .email_import(
+                    message.as_bytes().to_vec(),
+                    [&mailbox_id],
+                    None::<Vec<String>>,
+                    Some(20000i64 + num as i64),
+                )
+                .await
+                .unwrap()
+                .take_id(),
+        );
+    }
+    let email_ids = client
+        .email_query(
+            email::query::Filter::in_mailbox(&mailbox_id).into(),
+            [email::query::Comparator::received_at()].into(),
+        )
+        .await
+        .unwrap()
+        .take_ids()
+        .split_off(5);
+    assert_eq!(email_ids.len(), 3);
+
+    // Every message, including those requested by a pre-merge id, should
+    // report the thread that lists all of them
+    for email_id in email_ids.iter().chain(imported_ids.iter()) {
+        let thread_id = client
+            .email_get(email_id, [email::Property::ThreadId].into())
+            .await
+            .unwrap()
+            .unwrap()
+            .thread_id()
+            .unwrap()
+            .to_string();
+        assert_eq!(
+            client
+                .thread_get(&thread_id)
+                .await
+                .unwrap()
+                .unwrap()
+                .email_ids(),
+            email_ids,
+            "{}",
+            email_id
+        );
+    }
+
     client.mailbox_destroy(&mailbox_id, true).await.unwrap();
 
     server.store.assert_is_empty();
@@ -0,0 +1,203 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    jmap_store::Object,
+    request::{get::GetRequest, MaybeResultReference},
+    types::jmap::JMAPId,
+};
+use jmap_mail::{
+    mail::import::JMAPMailImport,
+    thread::{
+        get::{GetArguments, JMAPGetThread},
+        schema::Thread,
+    },
+};
+use store::{ahash::AHashSet, blob::BlobId, core::acl::ACLToken, AccountId, JMAPStore, Store};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+const NUM_MESSAGES: usize = 50;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Thread/get emailIds paging tests...");
+    let account_id = 1;
+
+    // Create account and mailbox
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+
+    // Import a large thread out of order, so that document ids and
+    // received dates do not follow the same order
+    let mut ids_by_date = vec![JMAPId::new(0); NUM_MESSAGES];
+    for num in (0..NUM_MESSAGES).map(|num| (num * 17) % NUM_MESSAGES) {
+        let message = format!(
+            concat!(
+                "From: list@example.com\r\n",
+                "Message-ID: <{}@example.com>\r\n",
+                "References: <0@example.com>\r\n",
+                "Subject: Re: Long discussion\r\n\r\n",
+                "Message {}.\r\n"
+            ),
+            num, num
+        )
+        .into_bytes();
+        let blob_id = BlobId::new_external(&message);
+        db.blob_store(&blob_id, message.clone()).unwrap();
+        ids_by_date[num] = *db
+            .mail_import_item(
+                account_id,
+                blob_id,
+                &message,
+                vec![mailbox_id],
+                vec![],
+                Some(1_600_000_000 + (num as i64 * 60)),
+            )
+            .unwrap()
+            .id()
+            .unwrap();
+    }
+    let thread_id = JMAPId::from(ids_by_date[0].get_prefix_id());
+    assert!(ids_by_date
+        .iter()
+        .all(|id| id.get_prefix_id() == ids_by_date[0].get_prefix_id()));
+
+    // Without paging arguments all emailIds are returned and no total is included
+    let thread = get_thread(&db, account_id, thread_id, GetArguments::default());
+    assert_eq!(thread.email_ids, ids_by_date);
+    assert_eq!(thread.email_ids_total, None);
+
+    // Paging through the thread returns every message once, in received order
+    for page_size in [1, 7, 10, NUM_MESSAGES + 1] {
+        let mut paged_ids = Vec::with_capacity(NUM_MESSAGES);
+        let mut position = 0;
+        loop {
+            let thread = get_thread(
+                &db,
+                account_id,
+                thread_id,
+                GetArguments {
+                    email_ids_position: position.into(),
+                    email_ids_limit: page_size.into(),
+                },
+            );
+            assert_eq!(thread.email_ids_total, Some(NUM_MESSAGES));
+            assert!(thread.email_ids.len() <= page_size);
+            if thread.email_ids.is_empty() {
+                break;
+            }
+            position += thread.email_ids.len();
+            paged_ids.extend(thread.email_ids);
+        }
+        assert_eq!(
+            paged_ids.iter().collect::<AHashSet<_>>().len(),
+            NUM_MESSAGES,
+            "page size {}",
+            page_size
+        );
+        assert_eq!(paged_ids, ids_by_date, "page size {}", page_size);
+    }
+
+    // A limit alone returns the oldest messages, a position alone the rest
+    let thread = get_thread(
+        &db,
+        account_id,
+        thread_id,
+        GetArguments {
+            email_ids_position: None,
+            email_ids_limit: 3.into(),
+        },
+    );
+    assert_eq!(thread.email_ids, &ids_by_date[..3]);
+    let thread = get_thread(
+        &db,
+        account_id,
+        thread_id,
+        GetArguments {
+            email_ids_position: (NUM_MESSAGES - 3).into(),
+            email_ids_limit: None,
+        },
+    );
+    assert_eq!(thread.email_ids, &ids_by_date[NUM_MESSAGES - 3..]);
+    assert_eq!(thread.email_ids_total, Some(NUM_MESSAGES));
+
+    // The total is serialized only when paging was requested
+    let thread = serde_json::to_value(&thread).unwrap();
+    assert_eq!(thread["emailIdsTotal"], NUM_MESSAGES);
+    let thread = serde_json::to_value(&get_thread(
+        &db,
+        account_id,
+        thread_id,
+        GetArguments::default(),
+    ))
+    .unwrap();
+    assert!(thread.get("emailIdsTotal").is_none());
+
+    // Malformed paging arguments are rejected rather than ignored
+    for argument in [
+        "\"emailIdsPosition\": -1",
+        "\"emailIdsPosition\": \"2\"",
+        "\"emailIdsLimit\": 1.5",
+    ] {
+        assert!(
+            serde_json::from_str::<GetRequest<Thread>>(&format!(
+                "{{\"accountId\": \"{}\", {}}}",
+                JMAPId::new(account_id as u64),
+                argument
+            ))
+            .is_err(),
+            "{}",
+            argument
+        );
+    }
+}
+
+fn get_thread<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    thread_id: JMAPId,
+    arguments: GetArguments,
+) -> Thread
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    db.thread_get(GetRequest {
+        acl: Arc::new(ACLToken {
+            member_of: vec![account_id],
+            access_to: vec![],
+        })
+        .into(),
+        account_id: JMAPId::new(account_id as u64),
+        ids: MaybeResultReference::Value(vec![thread_id]).into(),
+        properties: None,
+        arguments,
+    })
+    .unwrap()
+    .list
+    .pop()
+    .unwrap()
+}
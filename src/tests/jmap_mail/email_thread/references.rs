@@ -0,0 +1,144 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{jmap_store::Object, orm::serialize::JMAPOrm};
+use jmap_mail::mail::{import::JMAPMailImport, MessageField};
+use store::{blob::BlobId, core::collection::Collection, AccountId, DocumentId, JMAPStore, Store};
+
+use crate::tests::jmap_mail::create_mailbox;
+
+const MESSAGES: [&str; 7] = [
+    "Message-ID: <a@example.com>\r\nSubject: Lunch\r\n\r\nmsg\r\n",
+    concat!(
+        "Message-ID: <b@example.com>\r\nIn-Reply-To: <a@example.com>\r\n",
+        "References: <a@example.com>\r\nSubject: Re: Lunch plans\r\n\r\nreply\r\n"
+    ),
+    concat!(
+        "Message-ID: <c@example.com>\r\nIn-Reply-To: <b@example.com>\r\n",
+        "References: <a@example.com> <b@example.com>\r\nSubject: Dinner instead?\r\n\r\nreply\r\n"
+    ),
+    concat!(
+        "Message-ID: <x@example.com>\r\nReferences: <missing@example.com>\r\n",
+        "Subject: Re: Party\r\n\r\nreply\r\n"
+    ),
+    concat!(
+        "Message-ID: <y@example.com>\r\nIn-Reply-To: <missing@example.com>\r\n",
+        "Subject: Re: Party\r\n\r\nreply\r\n"
+    ),
+    "Message-ID: <r1@example.com>\r\nSubject: Weekly report\r\n\r\nmsg\r\n",
+    "Message-ID: <r2@example.com>\r\nSubject: Weekly report\r\n\r\nmsg\r\n",
+];
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let subject_fallback = db.config.mail_thread_subject_fallback;
+    println!(
+        "Running Email threading by reference tests ({})...",
+        if subject_fallback {
+            "subject fallback"
+        } else {
+            "references only"
+        }
+    );
+
+    // Messages in the same group have to share a thread, no matter their subject
+    let expected_groups: &[&[usize]] = if subject_fallback {
+        &[&[0, 1, 2], &[3, 4], &[5, 6]]
+    } else {
+        &[&[0, 1, 2], &[3, 4], &[5], &[6]]
+    };
+
+    for (account_id, (name, order)) in (1..).zip([
+        ("forward", vec![0, 1, 2, 3, 4, 5, 6]),
+        ("reverse", vec![6, 5, 4, 3, 2, 1, 0]),
+        ("interleaved", vec![2, 4, 0, 6, 1, 3, 5]),
+    ]) {
+        let mailbox_id = create_mailbox(&db, account_id, "Inbox", "inbox");
+
+        let mut document_ids = vec![DocumentId::MAX; MESSAGES.len()];
+        for pos in order {
+            document_ids[pos] = import_message(&db, account_id, mailbox_id, MESSAGES[pos]);
+        }
+
+        let thread_ids = document_ids
+            .into_iter()
+            .map(|document_id| {
+                db.get_document_value::<DocumentId>(
+                    account_id,
+                    Collection::Mail,
+                    document_id,
+                    MessageField::ThreadId.into(),
+                )
+                .unwrap()
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        for (group_num, group) in expected_groups.iter().enumerate() {
+            for pos in group.iter() {
+                assert_eq!(
+                    thread_ids[*pos], thread_ids[group[0]],
+                    "{}: message {} not in the thread of message {}",
+                    name, pos, group[0]
+                );
+            }
+            for other_group in &expected_groups[group_num + 1..] {
+                assert_ne!(
+                    thread_ids[group[0]], thread_ids[other_group[0]],
+                    "{}: message {} shares a thread with message {}",
+                    name, group[0], other_group[0]
+                );
+            }
+        }
+    }
+}
+
+fn import_message<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    mailbox_id: DocumentId,
+    message: &str,
+) -> DocumentId
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let message = message.as_bytes().to_vec();
+    let blob_id = BlobId::new_external(&message);
+    db.blob_store(&blob_id, message.clone()).unwrap();
+    db.mail_import_item(
+        account_id,
+        blob_id,
+        &message,
+        vec![mailbox_id],
+        vec![],
+        None,
+    )
+    .unwrap()
+    .id()
+    .unwrap()
+    .get_document_id()
+}
@@ -0,0 +1,201 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+pub mod send_as;
+
+use std::sync::Arc;
+
+use jmap::{
+    error::set::SetErrorType,
+    orm::{serialize::JMAPOrm, TinyORM},
+    principal::schema::{self as principal, Principal},
+    request::{get::GetRequest, set::SetRequest, MaybeResultReference},
+    types::jmap::JMAPId,
+    SUPERUSER_ID,
+};
+use jmap_mail::identity::{
+    get::JMAPGetIdentity,
+    schema::{Identity, Property, Value},
+    set::JMAPSetIdentity,
+};
+use store::{
+    core::{acl::ACLToken, collection::Collection, document::Document},
+    write::batch::WriteBatch,
+    AccountId, JMAPStore, Store,
+};
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Identity tests...");
+    let account_id = 1;
+
+    // Create an account with an alias
+    let mut batch = WriteBatch::new(SUPERUSER_ID);
+    let mut document = Document::new(Collection::Principal, account_id);
+    let mut fields = TinyORM::<Principal>::new();
+    fields.set(
+        principal::Property::Email,
+        principal::Value::Text {
+            value: "jdoe@example.com".to_string(),
+        },
+    );
+    fields.set(
+        principal::Property::Aliases,
+        principal::Value::TextList {
+            value: vec!["john.doe@example.com".to_string()],
+        },
+    );
+    fields.insert(&mut document).unwrap();
+    batch.insert_document(document);
+    db.write(batch).unwrap();
+
+    // Create a primary, a secondary and an unverified identity
+    let primary_id = create_identity(&db, account_id, "jdoe@example.com");
+    let secondary_id = create_identity(&db, account_id, "john.doe@example.com");
+    let unverified_id = create_identity(&db, account_id, "jd@old-domain.org");
+
+    assert_eq!(
+        get_identities(
+            &db,
+            account_id,
+            vec![primary_id, secondary_id, unverified_id]
+        ),
+        vec![(false, true), (true, true), (true, false)]
+    );
+
+    // Addresses are verified against the principal's current aliases
+    let mut batch = WriteBatch::new(SUPERUSER_ID);
+    let mut document = Document::new(Collection::Principal, account_id);
+    let current_fields = db
+        .get_orm::<Principal>(SUPERUSER_ID, account_id)
+        .unwrap()
+        .unwrap();
+    let mut fields = TinyORM::track_changes(&current_fields);
+    fields.set(
+        principal::Property::Aliases,
+        principal::Value::TextList { value: vec![] },
+    );
+    current_fields.merge(&mut document, fields).unwrap();
+    batch.update_document(document);
+    db.write(batch).unwrap();
+    assert_eq!(
+        get_identities(&db, account_id, vec![primary_id, secondary_id]),
+        vec![(false, true), (true, false)]
+    );
+
+    // The identity of the primary address cannot be destroyed
+    let response = db
+        .identity_set(SetRequest {
+            acl: Arc::new(ACLToken {
+                member_of: vec![account_id],
+                access_to: vec![],
+            })
+            .into(),
+            account_id: JMAPId::new(account_id as u64),
+            if_in_state: None,
+            create: None,
+            update: None,
+            destroy: MaybeResultReference::Value(vec![primary_id, secondary_id]).into(),
+            arguments: (),
+        })
+        .unwrap();
+    assert_eq!(response.destroyed, vec![secondary_id]);
+    assert!(matches!(
+        response.not_destroyed.get(&primary_id).unwrap().type_,
+        SetErrorType::Forbidden
+    ));
+    assert_eq!(
+        get_identities(&db, account_id, vec![primary_id]),
+        vec![(false, true)]
+    );
+}
+
+fn create_identity<T>(db: &JMAPStore<T>, account_id: AccountId, email: &str) -> JMAPId
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut batch = WriteBatch::new(account_id);
+    let mut document = Document::new(
+        Collection::Identity,
+        db.assign_document_id(account_id, Collection::Identity)
+            .unwrap(),
+    );
+    let document_id = document.document_id;
+    let mut fields = TinyORM::<Identity>::new();
+    fields.set(
+        Property::Email,
+        Value::Text {
+            value: email.to_string(),
+        },
+    );
+    fields.insert(&mut document).unwrap();
+    batch.log_insert(Collection::Identity, document_id);
+    batch.insert_document(document);
+    db.write(batch).unwrap();
+    JMAPId::from(document_id)
+}
+
+fn get_identities<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    ids: Vec<JMAPId>,
+) -> Vec<(bool, bool)>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    db.identity_get(GetRequest {
+        acl: Arc::new(ACLToken {
+            member_of: vec![account_id],
+            access_to: vec![],
+        })
+        .into(),
+        account_id: JMAPId::new(account_id as u64),
+        ids: MaybeResultReference::Value(ids).into(),
+        properties: MaybeResultReference::Value(vec![
+            Property::Id,
+            Property::MayDelete,
+            Property::IsVerified,
+        ])
+        .into(),
+        arguments: (),
+    })
+    .unwrap()
+    .list
+    .iter()
+    .map(|identity| {
+        (
+            get_bool(identity, Property::MayDelete),
+            get_bool(identity, Property::IsVerified),
+        )
+    })
+    .collect()
+}
+
+fn get_bool(identity: &Identity, property: Property) -> bool {
+    match identity.properties.get(&property) {
+        Some(Value::Bool { value }) => *value,
+        other => panic!("Unexpected value for {}: {:?}", property, other),
+    }
+}
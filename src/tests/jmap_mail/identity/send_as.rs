@@ -0,0 +1,159 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    error::set::SetErrorType,
+    jmap_store::Object,
+    orm::TinyORM,
+    principal::schema::{self as principal, Principal},
+    request::set::SetRequest,
+    types::jmap::JMAPId,
+    SUPERUSER_ID,
+};
+use jmap_mail::identity::{schema::Identity, set::JMAPSetIdentity};
+use store::{
+    core::{acl::ACLToken, collection::Collection, document::Document, vec_map::VecMap},
+    write::batch::WriteBatch,
+    AccountId, JMAPStore, Store,
+};
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Identity/set send-as authorization tests...");
+    let account_id = 1;
+    let group_id = 2;
+    let other_id = 3;
+
+    // Create an account with an alias, a group it belongs to and an unrelated account
+    let mut batch = WriteBatch::new(SUPERUSER_ID);
+    for (id, email, aliases) in [
+        (account_id, "jdoe@example.com", vec!["john.doe@example.com"]),
+        (group_id, "sales@example.com", vec![]),
+        (other_id, "jane@example.com", vec!["jane.smith@example.com"]),
+    ] {
+        let mut document = Document::new(Collection::Principal, id);
+        let mut fields = TinyORM::<Principal>::new();
+        fields.set(
+            principal::Property::Email,
+            principal::Value::Text {
+                value: email.to_string(),
+            },
+        );
+        if !aliases.is_empty() {
+            fields.set(
+                principal::Property::Aliases,
+                principal::Value::TextList {
+                    value: aliases.into_iter().map(|alias| alias.to_string()).collect(),
+                },
+            );
+        }
+        fields.insert(&mut document).unwrap();
+        batch.insert_document(document);
+    }
+    db.write(batch).unwrap();
+
+    let send_as_groups = db.config.identity_send_as_groups;
+    for (email, expected_result) in [
+        // The account's own addresses are authorized
+        ("jdoe@example.com", Ok(())),
+        ("John.Doe@Example.com", Ok(())),
+        // So are the addresses of groups it belongs to, unless disabled
+        (
+            "sales@example.com",
+            if send_as_groups {
+                Ok(())
+            } else {
+                Err(SetErrorType::ForbiddenFrom)
+            },
+        ),
+        // Other accounts' and unknown addresses are not
+        ("jane@example.com", Err(SetErrorType::ForbiddenFrom)),
+        ("jane.smith@example.com", Err(SetErrorType::ForbiddenFrom)),
+        ("ceo@example.com", Err(SetErrorType::ForbiddenFrom)),
+        ("not an address", Err(SetErrorType::InvalidProperties)),
+    ] {
+        let result = create_identity(&db, account_id, &[account_id, group_id], email);
+        assert_eq!(
+            result.map(|_| ()).map_err(|err| err.as_str()),
+            expected_result.map_err(|err| err.as_str()),
+            "{}",
+            email
+        );
+    }
+
+    // Group addresses are not authorized when acting on a shared account
+    assert!(matches!(
+        create_identity(&db, other_id, &[account_id, group_id], "sales@example.com"),
+        Err(SetErrorType::ForbiddenFrom)
+    ));
+}
+
+fn create_identity<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    member_of: &[AccountId],
+    email: &str,
+) -> Result<JMAPId, SetErrorType>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut create = VecMap::new();
+    create.append(
+        "i1".to_string(),
+        serde_json::from_value::<Identity>(serde_json::json!({
+            "name": "John Doe",
+            "email": email,
+        }))
+        .unwrap(),
+    );
+
+    let mut response = db
+        .identity_set(SetRequest {
+            acl: Arc::new(ACLToken {
+                member_of: member_of.to_vec(),
+                access_to: vec![],
+            })
+            .into(),
+            account_id: JMAPId::new(account_id as u64),
+            if_in_state: None,
+            create: create.into(),
+            update: None,
+            destroy: None,
+            arguments: (),
+        })
+        .unwrap();
+
+    if let Some(identity) = response.created.remove("i1") {
+        Ok(*identity.id().unwrap())
+    } else {
+        Err(response
+            .not_created
+            .remove(&"i1".to_string())
+            .unwrap()
+            .type_)
+    }
+}
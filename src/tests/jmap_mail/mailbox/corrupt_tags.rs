@@ -0,0 +1,217 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    jmap_store::Object,
+    orm::{serialize::JMAPOrm, TinyORM},
+    request::{
+        set::{SetRequest, SetResponse},
+        MaybeResultReference,
+    },
+    types::jmap::JMAPId,
+    SUPERUSER_ID,
+};
+use jmap_mail::{
+    mail::{
+        import::JMAPMailImport,
+        schema::{Email, Property},
+    },
+    mailbox::{
+        schema::Mailbox,
+        set::{JMAPSetMailbox, SetArguments},
+    },
+};
+use store::{
+    blob::BlobId,
+    core::{acl::ACLToken, collection::Collection, document::Document, tag::Tag},
+    serialize::StoreSerialize,
+    write::{
+        batch::WriteBatch,
+        options::{IndexOptions, Options},
+    },
+    AccountId, DocumentId, JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::create_mailbox;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Mailbox/set corrupt tags tests...");
+    let account_id = 1;
+
+    let mut batch = WriteBatch::new(SUPERUSER_ID);
+    batch.insert_document(Document::new(Collection::Principal, account_id));
+    db.write(batch).unwrap();
+    create_mailbox(&db, account_id, "Inbox", "inbox");
+    create_mailbox(&db, account_id, "Deleted Items", "trash");
+    let archive_id = create_mailbox(&db, account_id, "Archive", "archive");
+    let projects_id = create_mailbox(&db, account_id, "Projects", "");
+
+    let only_projects = import_message(&db, account_id, vec![projects_id], "First");
+    let also_archived = import_message(&db, account_id, vec![projects_id, archive_id], "Second");
+
+    // An ORM that does not list the mailbox the message is indexed in
+    let mut orm = TinyORM::<Email>::new();
+    orm.tag(Property::MailboxIds, Tag::Id(archive_id));
+    write_orm(&db, account_id, only_projects, orm.serialize().unwrap());
+    assert_not_destroyed(destroy_mailbox(&db, account_id, projects_id), projects_id);
+    assert_unchanged(&db, account_id, only_projects, also_archived, projects_id);
+
+    // An ORM that cannot be deserialized
+    write_orm(&db, account_id, only_projects, vec![0xff; 3]);
+    assert_not_destroyed(destroy_mailbox(&db, account_id, projects_id), projects_id);
+    assert_unchanged(&db, account_id, only_projects, also_archived, projects_id);
+
+    // Once repaired, messages only in the mailbox are deleted and the rest untagged
+    let mut orm = TinyORM::<Email>::new();
+    orm.tag(Property::MailboxIds, Tag::Id(projects_id));
+    write_orm(&db, account_id, only_projects, orm.serialize().unwrap());
+    let response = destroy_mailbox(&db, account_id, projects_id);
+    assert!(
+        response.not_destroyed.is_empty(),
+        "{:?}",
+        response.not_destroyed
+    );
+    assert!(!db
+        .get_document_ids(account_id, Collection::Mail)
+        .unwrap()
+        .unwrap()
+        .contains(only_projects));
+    assert_eq!(
+        db.get_orm::<Email>(account_id, also_archived)
+            .unwrap()
+            .unwrap()
+            .get_tags(&Property::MailboxIds)
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>(),
+        vec![Tag::Id(archive_id)]
+    );
+}
+
+fn import_message<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    mailbox_ids: Vec<DocumentId>,
+    subject: &str,
+) -> DocumentId
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let message = format!(
+        "From: john@example.com\r\nSubject: {}\r\n\r\nHello.\r\n",
+        subject
+    )
+    .into_bytes();
+    let blob_id = BlobId::new_external(&message);
+    db.blob_store(&blob_id, message.clone()).unwrap();
+    db.mail_import_item(account_id, blob_id, &message, mailbox_ids, vec![], None)
+        .unwrap()
+        .id()
+        .unwrap()
+        .get_document_id()
+}
+
+fn write_orm<T>(db: &JMAPStore<T>, account_id: AccountId, document_id: DocumentId, bytes: Vec<u8>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut batch = WriteBatch::new(account_id);
+    let mut document = Document::new(Collection::Mail, document_id);
+    document.binary(
+        TinyORM::<Email>::FIELD_ID,
+        bytes,
+        IndexOptions::new().store(),
+    );
+    batch.update_document(document);
+    db.write(batch).unwrap();
+}
+
+fn destroy_mailbox<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    mailbox_id: DocumentId,
+) -> SetResponse<Mailbox>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    db.mailbox_set(SetRequest {
+        acl: Arc::new(ACLToken {
+            member_of: vec![account_id],
+            access_to: vec![],
+        })
+        .into(),
+        account_id: JMAPId::new(account_id as u64),
+        if_in_state: None,
+        create: None,
+        update: None,
+        destroy: MaybeResultReference::Value(vec![JMAPId::from(mailbox_id)]).into(),
+        arguments: SetArguments {
+            on_destroy_remove_emails: true.into(),
+        },
+    })
+    .unwrap()
+}
+
+fn assert_not_destroyed(response: SetResponse<Mailbox>, mailbox_id: DocumentId) {
+    assert!(response.destroyed.is_empty(), "{:?}", response.destroyed);
+    assert!(response
+        .not_destroyed
+        .get(&JMAPId::from(mailbox_id))
+        .is_some());
+}
+
+fn assert_unchanged<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    corrupt_id: DocumentId,
+    document_id: DocumentId,
+    mailbox_id: DocumentId,
+) where
+    T: for<'x> Store<'x> + 'static,
+{
+    // Neither the mailbox nor its messages were touched
+    assert!(db
+        .get_document_ids(account_id, Collection::Mailbox)
+        .unwrap()
+        .unwrap()
+        .contains(mailbox_id));
+    let stored_ids = db
+        .get_document_ids(account_id, Collection::Mail)
+        .unwrap()
+        .unwrap();
+    assert!(stored_ids.contains(corrupt_id));
+    assert!(stored_ids.contains(document_id));
+    assert!(db
+        .get_orm::<Email>(account_id, document_id)
+        .unwrap()
+        .unwrap()
+        .get_tags(&Property::MailboxIds)
+        .unwrap()
+        .contains(&Tag::Id(mailbox_id)));
+}
@@ -0,0 +1,360 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use std::sync::Arc;
+
+use jmap::{
+    jmap_store::Object,
+    orm::serialize::JMAPOrm,
+    request::{get::GetRequest, set::SetRequest, MaybeResultReference},
+    types::jmap::JMAPId,
+};
+use jmap_mail::{
+    mail::{
+        import::JMAPMailImport,
+        schema::{Email, Keyword},
+        set::{JMAPSetMail, SetArguments},
+        MessageField,
+    },
+    mailbox::{
+        get::JMAPGetMailbox,
+        schema::{Mailbox, Property, Value},
+    },
+};
+use store::{
+    ahash::AHashSet,
+    blob::BlobId,
+    core::{acl::ACLToken, collection::Collection, tag::Tag, vec_map::VecMap},
+    AccountId, DocumentId, JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::{create_account_mailbox, create_mailbox};
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Mailbox counters tests...");
+    let account_id = 1;
+
+    let inbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+    let archive_id = create_mailbox(&db, account_id, "Archive", "archive");
+    let mailbox_ids = [inbox_id, archive_id];
+
+    // Two threads in the Inbox, one of them with a single unread message,
+    // plus an unread message in the Archive
+    let first = import_message(
+        &db,
+        account_id,
+        vec![inbox_id],
+        "<a1@example.com>",
+        None,
+        true,
+    );
+    let second = import_message(
+        &db,
+        account_id,
+        vec![inbox_id],
+        "<a2@example.com>",
+        "<a1@example.com>".into(),
+        false,
+    );
+    let third = import_message(
+        &db,
+        account_id,
+        vec![inbox_id, archive_id],
+        "<b1@example.com>",
+        None,
+        true,
+    );
+    let fourth = import_message(
+        &db,
+        account_id,
+        vec![archive_id],
+        "<c1@example.com>",
+        None,
+        false,
+    );
+    assert_counters(&db, account_id, &mailbox_ids);
+    assert_eq!(
+        get_counters(&db, account_id, inbox_id),
+        [3, 1, 2, 1],
+        "totalEmails, unreadEmails, totalThreads, unreadThreads"
+    );
+
+    // A thread is read once all its messages are, and unread if any is
+    update(&db, account_id, second, r#"{"keywords/$seen": true}"#);
+    assert_counters(&db, account_id, &mailbox_ids);
+    assert_eq!(get_counters(&db, account_id, inbox_id), [3, 0, 2, 0]);
+    update(&db, account_id, first, r#"{"keywords/$seen": null}"#);
+    assert_counters(&db, account_id, &mailbox_ids);
+    assert_eq!(get_counters(&db, account_id, inbox_id), [3, 1, 2, 1]);
+
+    // Adding and removing messages from mailboxes
+    update(
+        &db,
+        account_id,
+        first,
+        &format!(r#"{{"mailboxIds/{}": true}}"#, JMAPId::from(archive_id)),
+    );
+    assert_counters(&db, account_id, &mailbox_ids);
+    update(
+        &db,
+        account_id,
+        third,
+        &format!(r#"{{"mailboxIds/{}": null}}"#, JMAPId::from(inbox_id)),
+    );
+    assert_counters(&db, account_id, &mailbox_ids);
+    assert_eq!(get_counters(&db, account_id, inbox_id), [2, 1, 1, 1]);
+    destroy(&db, account_id, fourth);
+    assert_counters(&db, account_id, &mailbox_ids);
+    assert_eq!(get_counters(&db, account_id, archive_id), [2, 1, 2, 1]);
+
+    // Merging two threads changes the thread counts of every mailbox holding
+    // messages from them, not only those of the new message
+    import_message(
+        &db,
+        account_id,
+        vec![inbox_id],
+        "<d1@example.com>",
+        "<a1@example.com> <b1@example.com>".into(),
+        true,
+    );
+    assert_counters(&db, account_id, &mailbox_ids);
+    assert_eq!(get_counters(&db, account_id, archive_id), [2, 1, 1, 1]);
+
+    // Counters are cached until the mailbox changes
+    let cached = db.mailbox_counters.get(&(account_id, archive_id)).unwrap();
+    assert_eq!(
+        cached.change_id,
+        db.get_last_change_id(account_id, Collection::Mailbox)
+            .unwrap()
+    );
+    assert_eq!(
+        [
+            cached.total_emails,
+            cached.unread_emails,
+            cached.total_threads,
+            cached.unread_threads
+        ],
+        get_counters(&db, account_id, archive_id)
+    );
+
+    // The changelog is read once per request for all mailboxes
+    assert_eq!(
+        db.mailbox_counters_validated.get(&account_id),
+        db.get_last_change_id(account_id, Collection::Mailbox)
+            .unwrap()
+    );
+}
+
+fn assert_counters<T>(db: &JMAPStore<T>, account_id: AccountId, mailbox_ids: &[DocumentId])
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    // Fetch counters twice, the second time from the cache
+    for _ in 0..2 {
+        for mailbox_id in mailbox_ids {
+            assert_eq!(
+                get_counters(db, account_id, *mailbox_id),
+                recount(db, account_id, *mailbox_id),
+                "mailbox {}",
+                mailbox_id
+            );
+        }
+    }
+}
+
+fn recount<T>(db: &JMAPStore<T>, account_id: AccountId, mailbox_id: DocumentId) -> [u32; 4]
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut total_emails = 0;
+    let mut unread_emails = 0;
+    let mut thread_ids = AHashSet::default();
+    let mut unread_thread_ids = AHashSet::default();
+
+    for document_id in db
+        .get_document_ids(account_id, Collection::Mail)
+        .unwrap()
+        .unwrap_or_default()
+    {
+        let fields = db
+            .get_orm::<Email>(account_id, document_id)
+            .unwrap()
+            .unwrap();
+        if !fields
+            .get_tags(&jmap_mail::mail::schema::Property::MailboxIds)
+            .map_or(false, |tags| tags.contains(&Tag::Id(mailbox_id)))
+        {
+            continue;
+        }
+        let thread_id = db
+            .get_document_value::<DocumentId>(
+                account_id,
+                Collection::Mail,
+                document_id,
+                MessageField::ThreadId.into(),
+            )
+            .unwrap()
+            .unwrap();
+        total_emails += 1;
+        thread_ids.insert(thread_id);
+        if !fields
+            .get_tags(&jmap_mail::mail::schema::Property::Keywords)
+            .map_or(false, |tags| tags.contains(&Tag::Static(Keyword::SEEN)))
+        {
+            unread_emails += 1;
+            unread_thread_ids.insert(thread_id);
+        }
+    }
+
+    [
+        total_emails,
+        unread_emails,
+        thread_ids.len() as u32,
+        unread_thread_ids.len() as u32,
+    ]
+}
+
+fn get_counters<T>(db: &JMAPStore<T>, account_id: AccountId, mailbox_id: DocumentId) -> [u32; 4]
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mailbox = db
+        .mailbox_get(GetRequest {
+            acl: acl(account_id).into(),
+            account_id: JMAPId::new(account_id as u64),
+            ids: MaybeResultReference::Value(vec![JMAPId::from(mailbox_id)]).into(),
+            properties: MaybeResultReference::Value(vec![
+                Property::TotalEmails,
+                Property::UnreadEmails,
+                Property::TotalThreads,
+                Property::UnreadThreads,
+            ])
+            .into(),
+            arguments: (),
+        })
+        .unwrap()
+        .list
+        .pop()
+        .unwrap();
+
+    [
+        Property::TotalEmails,
+        Property::UnreadEmails,
+        Property::TotalThreads,
+        Property::UnreadThreads,
+    ]
+    .map(|property| match mailbox.properties.get(&property) {
+        Some(Value::Number { value }) => *value,
+        other => panic!("Unexpected value {:?}", other),
+    })
+}
+
+fn import_message<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    mailbox_ids: Vec<DocumentId>,
+    message_id: &str,
+    references: Option<&str>,
+    is_seen: bool,
+) -> JMAPId
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let message = format!(
+        "From: john@example.com\r\nMessage-ID: {}\r\n{}Subject: Counters {}\r\n\r\nHello.\r\n",
+        message_id,
+        references
+            .map(|references| format!("References: {}\r\n", references))
+            .unwrap_or_default(),
+        message_id
+    )
+    .into_bytes();
+    let blob_id = BlobId::new_external(&message);
+    db.blob_store(&blob_id, message.clone()).unwrap();
+    *db.mail_import_item(
+        account_id,
+        blob_id,
+        &message,
+        mailbox_ids,
+        if is_seen {
+            vec![Tag::Static(Keyword::SEEN)]
+        } else {
+            vec![]
+        },
+        None,
+    )
+    .unwrap()
+    .id()
+    .unwrap()
+}
+
+fn update<T>(db: &JMAPStore<T>, account_id: AccountId, id: JMAPId, json: &str)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut update = VecMap::new();
+    update.append(id, serde_json::from_str::<Email>(json).unwrap());
+    let response = db
+        .mail_set(SetRequest {
+            acl: acl(account_id).into(),
+            account_id: JMAPId::new(account_id as u64),
+            if_in_state: None,
+            create: None,
+            update: update.into(),
+            destroy: None,
+            arguments: SetArguments::default(),
+        })
+        .unwrap();
+    assert!(
+        response.not_updated.is_empty(),
+        "{:?}",
+        response.not_updated
+    );
+}
+
+fn destroy<T>(db: &JMAPStore<T>, account_id: AccountId, id: JMAPId)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let response = db
+        .mail_set(SetRequest {
+            acl: acl(account_id).into(),
+            account_id: JMAPId::new(account_id as u64),
+            if_in_state: None,
+            create: None,
+            update: None,
+            destroy: MaybeResultReference::Value(vec![id]).into(),
+            arguments: SetArguments::default(),
+        })
+        .unwrap();
+    assert_eq!(response.destroyed, vec![id]);
+}
+
+fn acl(account_id: AccountId) -> Arc<ACLToken> {
+    Arc::new(ACLToken {
+        member_of: vec![account_id],
+        access_to: vec![],
+    })
+}
@@ -0,0 +1,208 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use std::sync::Arc;
+
+use jmap::{
+    error::set::SetErrorType,
+    jmap_store::Object,
+    orm::serialize::JMAPOrm,
+    request::{
+        set::{SetRequest, SetResponse},
+        MaybeResultReference,
+    },
+    types::jmap::JMAPId,
+    SUPERUSER_ID,
+};
+use jmap_mail::{
+    mail::{
+        import::JMAPMailImport,
+        schema::{Email, Property},
+    },
+    mailbox::{
+        schema::Mailbox,
+        set::{JMAPSetMailbox, SetArguments},
+    },
+};
+use store::{
+    blob::BlobId,
+    core::{acl::ACLToken, collection::Collection, document::Document, tag::Tag},
+    log::changes::{Change, Query},
+    write::batch::WriteBatch,
+    AccountId, DocumentId, JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::create_mailbox;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Mailbox/set destroy tests...");
+    let account_id = 1;
+
+    let mut batch = WriteBatch::new(SUPERUSER_ID);
+    batch.insert_document(Document::new(Collection::Principal, account_id));
+    db.write(batch).unwrap();
+    create_mailbox(&db, account_id, "Inbox", "inbox");
+    create_mailbox(&db, account_id, "Deleted Items", "trash");
+    let archive_id = create_mailbox(&db, account_id, "Archive", "archive");
+    let projects_id = create_mailbox(&db, account_id, "Projects", "");
+    let empty_id = create_mailbox(&db, account_id, "Empty", "");
+
+    // Empty mailboxes are destroyed regardless of onDestroyRemoveEmails
+    let response = destroy_mailbox(&db, account_id, empty_id, false);
+    assert_eq!(response.destroyed, vec![JMAPId::from(empty_id)]);
+    assert!(!mailbox_exists(&db, account_id, empty_id));
+
+    let only_projects = import_message(&db, account_id, vec![projects_id], "First");
+    let also_archived = import_message(&db, account_id, vec![projects_id, archive_id], "Second");
+
+    // Non-empty mailboxes are not destroyed by default
+    let response = destroy_mailbox(&db, account_id, projects_id, false);
+    assert!(response.destroyed.is_empty(), "{:?}", response.destroyed);
+    assert!(matches!(
+        response
+            .not_destroyed
+            .get(&JMAPId::from(projects_id))
+            .unwrap()
+            .type_,
+        SetErrorType::MailboxHasEmail
+    ));
+    assert!(mailbox_exists(&db, account_id, projects_id));
+    assert_eq!(
+        mailbox_tags(&db, account_id, only_projects),
+        vec![Tag::Id(projects_id)]
+    );
+
+    // With onDestroyRemoveEmails, messages only in the mailbox are deleted
+    // and messages in other mailboxes are untagged
+    let change_id = db
+        .get_last_change_id(account_id, Collection::Mail)
+        .unwrap()
+        .unwrap();
+    let response = destroy_mailbox(&db, account_id, projects_id, true);
+    assert_eq!(response.destroyed, vec![JMAPId::from(projects_id)]);
+    assert!(!mailbox_exists(&db, account_id, projects_id));
+    let stored_ids = db
+        .get_document_ids(account_id, Collection::Mail)
+        .unwrap()
+        .unwrap();
+    assert!(!stored_ids.contains(only_projects));
+    assert!(stored_ids.contains(also_archived));
+    assert_eq!(
+        mailbox_tags(&db, account_id, also_archived),
+        vec![Tag::Id(archive_id)]
+    );
+
+    // Both changes are logged so Email/changes and Email/queryChanges pick them up
+    let mut changes = db
+        .get_changes(account_id, Collection::Mail, Query::Since(change_id), 0)
+        .unwrap()
+        .unwrap()
+        .changes
+        .into_iter()
+        .map(|change| match change {
+            Change::Delete(id) => (JMAPId::new(id).get_document_id(), "delete"),
+            Change::Update(id) => (JMAPId::new(id).get_document_id(), "update"),
+            other => panic!("Unexpected change {:?}", other),
+        })
+        .collect::<Vec<_>>();
+    changes.sort_unstable();
+    let mut expected = vec![(only_projects, "delete"), (also_archived, "update")];
+    expected.sort_unstable();
+    assert_eq!(changes, expected);
+}
+
+fn import_message<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    mailbox_ids: Vec<DocumentId>,
+    subject: &str,
+) -> DocumentId
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let message = format!(
+        "From: john@example.com\r\nSubject: {}\r\n\r\nHello.\r\n",
+        subject
+    )
+    .into_bytes();
+    let blob_id = BlobId::new_external(&message);
+    db.blob_store(&blob_id, message.clone()).unwrap();
+    db.mail_import_item(account_id, blob_id, &message, mailbox_ids, vec![], None)
+        .unwrap()
+        .id()
+        .unwrap()
+        .get_document_id()
+}
+
+fn destroy_mailbox<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    mailbox_id: DocumentId,
+    on_destroy_remove_emails: bool,
+) -> SetResponse<Mailbox>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    db.mailbox_set(SetRequest {
+        acl: Arc::new(ACLToken {
+            member_of: vec![account_id],
+            access_to: vec![],
+        })
+        .into(),
+        account_id: JMAPId::new(account_id as u64),
+        if_in_state: None,
+        create: None,
+        update: None,
+        destroy: MaybeResultReference::Value(vec![JMAPId::from(mailbox_id)]).into(),
+        arguments: SetArguments {
+            on_destroy_remove_emails: on_destroy_remove_emails.into(),
+        },
+    })
+    .unwrap()
+}
+
+fn mailbox_exists<T>(db: &JMAPStore<T>, account_id: AccountId, mailbox_id: DocumentId) -> bool
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    db.get_document_ids(account_id, Collection::Mailbox)
+        .unwrap()
+        .unwrap()
+        .contains(mailbox_id)
+}
+
+fn mailbox_tags<T>(db: &JMAPStore<T>, account_id: AccountId, document_id: DocumentId) -> Vec<Tag>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    db.get_orm::<Email>(account_id, document_id)
+        .unwrap()
+        .unwrap()
+        .get_tags(&Property::MailboxIds)
+        .unwrap()
+        .iter()
+        .cloned()
+        .collect()
+}
@@ -0,0 +1,119 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    request::{get::GetRequest, MaybeResultReference},
+    types::jmap::JMAPId,
+};
+use jmap_mail::{
+    mail::import::JMAPMailImport,
+    mailbox::{
+        get::{JMAPGetMailbox, COUNTS_COMPUTED},
+        schema::{Mailbox, Property, Value},
+    },
+};
+use store::{blob::BlobId, core::acl::ACLToken, AccountId, JMAPStore, Store};
+
+use crate::tests::jmap_mail::create_account_mailbox;
+
+const MESSAGE: &[u8] = b"From: john@example.com\r\nSubject: Count me\r\n\r\nHi.\r\n";
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Mailbox/get property projection tests...");
+    let account_id = 1;
+
+    // Create account, mailbox and message
+    let mailbox_id = create_account_mailbox(&db, account_id, "Inbox", "inbox");
+    let blob_id = BlobId::new_external(MESSAGE);
+    db.blob_store(&blob_id, MESSAGE.to_vec()).unwrap();
+    db.mail_import_item(account_id, blob_id, MESSAGE, vec![mailbox_id], vec![], None)
+        .unwrap();
+
+    // Requesting only the id and name does not compute any counters
+    let counts_before = COUNTS_COMPUTED.with(|counts| counts.get());
+    let mailbox = get_mailbox(
+        &db,
+        account_id,
+        mailbox_id,
+        vec![Property::Id, Property::Name],
+    );
+    assert_eq!(COUNTS_COMPUTED.with(|counts| counts.get()), counts_before);
+    assert_eq!(mailbox.properties.len(), 2);
+    assert_eq!(
+        mailbox.properties.get(&Property::Name),
+        Some(&Value::Text {
+            value: "Inbox".to_string()
+        })
+    );
+
+    // Counters are computed only when requested
+    let mailbox = get_mailbox(
+        &db,
+        account_id,
+        mailbox_id,
+        vec![Property::TotalEmails, Property::UnreadEmails],
+    );
+    assert_eq!(
+        COUNTS_COMPUTED.with(|counts| counts.get()),
+        counts_before + 2
+    );
+    assert_eq!(
+        mailbox.properties.get(&Property::TotalEmails),
+        Some(&Value::Number { value: 1 })
+    );
+    assert_eq!(
+        mailbox.properties.get(&Property::UnreadEmails),
+        Some(&Value::Number { value: 1 })
+    );
+}
+
+fn get_mailbox<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    mailbox_id: store::DocumentId,
+    properties: Vec<Property>,
+) -> Mailbox
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    db.mailbox_get(GetRequest {
+        acl: Arc::new(ACLToken {
+            member_of: vec![account_id],
+            access_to: vec![],
+        })
+        .into(),
+        account_id: JMAPId::new(account_id as u64),
+        ids: MaybeResultReference::Value(vec![JMAPId::from(mailbox_id)]).into(),
+        properties: MaybeResultReference::Value(properties).into(),
+        arguments: (),
+    })
+    .unwrap()
+    .list
+    .pop()
+    .unwrap()
+}
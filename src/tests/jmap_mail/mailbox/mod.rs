@@ -21,6 +21,15 @@
  * for more details.
 */
 
+pub mod corrupt_tags;
+pub mod counters;
+pub mod destroy;
+pub mod get_properties;
+pub mod parent_cycle;
+pub mod query_filter;
+pub mod query_sort;
+pub mod roles;
+
 use actix_web::web;
 use jmap::types::{jmap::JMAPId, state::JMAPState};
 use jmap_client::{
@@ -607,6 +616,25 @@ where
         ["inbox", "sent", "spam"]
     );
 
+    // Computed properties should be returned on create
+    let mailbox = client
+        .mailbox_create("Computed", None::<String>, Role::None)
+        .await
+        .unwrap();
+    assert_eq!(mailbox.total_emails(), 0);
+    assert_eq!(mailbox.unread_emails(), 0);
+    assert_eq!(mailbox.total_threads(), 0);
+    assert_eq!(mailbox.unread_threads(), 0);
+    let rights = mailbox.my_rights().unwrap();
+    assert!(rights.may_read_items());
+    assert!(rights.may_add_items());
+    assert!(rights.may_create_child());
+    assert!(rights.may_delete());
+    client
+        .mailbox_destroy(mailbox.id().unwrap(), true)
+        .await
+        .unwrap();
+
     let mut request = client.build();
     request.query_mailbox().arguments().sort_as_tree(true);
     let mut ids = request.send_query_mailbox().await.unwrap().take_ids();
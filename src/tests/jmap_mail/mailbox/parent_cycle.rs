@@ -0,0 +1,235 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    error::set::SetErrorType,
+    jmap_store::Object,
+    orm::serialize::JMAPOrm,
+    request::set::{SetRequest, SetResponse},
+    types::jmap::JMAPId,
+    SUPERUSER_ID,
+};
+use jmap_mail::mailbox::{
+    schema::{Mailbox, Property, Value},
+    set::JMAPSetMailbox,
+};
+use store::{
+    core::{acl::ACLToken, collection::Collection, document::Document, vec_map::VecMap},
+    write::batch::WriteBatch,
+    AccountId, JMAPStore, Store,
+};
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Mailbox parent cycle tests...");
+    let account_id = 1;
+
+    let mut batch = WriteBatch::new(SUPERUSER_ID);
+    batch.insert_document(Document::new(Collection::Principal, account_id));
+    db.write(batch).unwrap();
+
+    // Build a three level tree and two extra root mailboxes
+    let root_id = create_mailbox(&db, account_id, "Root", None);
+    let middle_id = create_mailbox(&db, account_id, "Middle", root_id.into());
+    let leaf_id = create_mailbox(&db, account_id, "Leaf", middle_id.into());
+    let x_id = create_mailbox(&db, account_id, "X", None);
+    let y_id = create_mailbox(&db, account_id, "Y", None);
+
+    // A mailbox can't be moved under itself or any of its descendants
+    for (id, parent_id) in [
+        (root_id, leaf_id),
+        (root_id, middle_id),
+        (root_id, root_id),
+        (middle_id, leaf_id),
+    ] {
+        let response = mailbox_set(&db, account_id, [(id, Some(parent_id))]);
+        assert_cycle_error(&response, id);
+    }
+    assert_eq!(get_parent_id(&db, account_id, root_id), None);
+    assert_eq!(get_parent_id(&db, account_id, middle_id), Some(root_id));
+    assert_eq!(get_parent_id(&db, account_id, leaf_id), Some(middle_id));
+
+    // Cycles created across several updates of the same request are caught
+    let response = mailbox_set(&db, account_id, [(x_id, Some(y_id)), (y_id, Some(x_id))]);
+    assert!(response.updated.contains_key(&x_id));
+    assert_cycle_error(&response, y_id);
+    assert_eq!(get_parent_id(&db, account_id, x_id), Some(y_id));
+    assert_eq!(get_parent_id(&db, account_id, y_id), None);
+
+    // Moving the leaf to the root and then the old root under it is allowed
+    let response = mailbox_set(&db, account_id, [(leaf_id, None), (root_id, Some(leaf_id))]);
+    assert!(
+        response.not_updated.is_empty(),
+        "{:?}",
+        response.not_updated
+    );
+    assert_eq!(get_parent_id(&db, account_id, leaf_id), None);
+    assert_eq!(get_parent_id(&db, account_id, root_id), Some(leaf_id));
+
+    // Swapping two mailboxes' parents is validated against the final tree,
+    // even though the first update on its own would create a cycle
+    let response = mailbox_set(&db, account_id, [(y_id, Some(x_id)), (x_id, None)]);
+    assert!(
+        response.not_updated.is_empty(),
+        "{:?}",
+        response.not_updated
+    );
+    assert_eq!(get_parent_id(&db, account_id, x_id), None);
+    assert_eq!(get_parent_id(&db, account_id, y_id), Some(x_id));
+
+    // A name can be taken over from a sibling renamed in the same request
+    let mut update = VecMap::new();
+    for (id, name) in [(x_id, "Y"), (leaf_id, "X")] {
+        let mut mailbox = Mailbox::default();
+        mailbox.properties.append(
+            Property::Name,
+            Value::Text {
+                value: name.to_string(),
+            },
+        );
+        update.append(id, mailbox);
+    }
+    let response = db
+        .mailbox_set(SetRequest {
+            acl: acl(account_id).into(),
+            account_id: JMAPId::new(account_id as u64),
+            if_in_state: None,
+            create: None,
+            update: update.into(),
+            destroy: None,
+            arguments: Default::default(),
+        })
+        .unwrap();
+    assert!(
+        response.not_updated.is_empty(),
+        "{:?}",
+        response.not_updated
+    );
+}
+
+fn assert_cycle_error(response: &SetResponse<Mailbox>, id: JMAPId) {
+    let err = response
+        .not_updated
+        .get(&id)
+        .unwrap_or_else(|| panic!("{} was updated: {:?}", id, response.updated));
+    assert!(matches!(err.type_, SetErrorType::InvalidProperties));
+    assert_eq!(
+        serde_json::to_value(err).unwrap()["properties"],
+        serde_json::json!(["parentId"])
+    );
+}
+
+fn create_mailbox<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    name: &str,
+    parent_id: Option<JMAPId>,
+) -> JMAPId
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut mailbox = Mailbox::default();
+    mailbox.properties.append(
+        Property::Name,
+        Value::Text {
+            value: name.to_string(),
+        },
+    );
+    if let Some(parent_id) = parent_id {
+        mailbox
+            .properties
+            .append(Property::ParentId, Value::Id { value: parent_id });
+    }
+    let mut create = VecMap::new();
+    create.append(name.to_string(), mailbox);
+    let mut response = db
+        .mailbox_set(SetRequest {
+            acl: acl(account_id).into(),
+            account_id: JMAPId::new(account_id as u64),
+            if_in_state: None,
+            create: create.into(),
+            update: None,
+            destroy: None,
+            arguments: Default::default(),
+        })
+        .unwrap();
+    *response
+        .created
+        .remove(name)
+        .unwrap_or_else(|| panic!("{:?}", response.not_created))
+        .id()
+        .unwrap()
+}
+
+fn mailbox_set<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    parent_ids: impl IntoIterator<Item = (JMAPId, Option<JMAPId>)>,
+) -> SetResponse<Mailbox>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut update = VecMap::new();
+    for (id, parent_id) in parent_ids {
+        let mut mailbox = Mailbox::default();
+        mailbox.properties.append(
+            Property::ParentId,
+            parent_id.map_or(Value::Null, |value| Value::Id { value }),
+        );
+        update.append(id, mailbox);
+    }
+    db.mailbox_set(SetRequest {
+        acl: acl(account_id).into(),
+        account_id: JMAPId::new(account_id as u64),
+        if_in_state: None,
+        create: None,
+        update: update.into(),
+        destroy: None,
+        arguments: Default::default(),
+    })
+    .unwrap()
+}
+
+fn get_parent_id<T>(db: &JMAPStore<T>, account_id: AccountId, id: JMAPId) -> Option<JMAPId>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    db.get_orm::<Mailbox>(account_id, id.get_document_id())
+        .unwrap()
+        .unwrap()
+        .get(&Property::ParentId)
+        .and_then(|v| v.as_id())
+        .filter(|id| *id > 0)
+        .map(|id| JMAPId::from(id - 1))
+}
+
+fn acl(account_id: AccountId) -> Arc<ACLToken> {
+    Arc::new(ACLToken {
+        member_of: vec![account_id],
+        access_to: vec![],
+    })
+}
@@ -0,0 +1,157 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    jmap_store::Object,
+    request::{query::QueryRequest, set::SetRequest},
+    types::jmap::JMAPId,
+    SUPERUSER_ID,
+};
+use jmap_mail::mailbox::{
+    query::JMAPMailboxQuery,
+    schema::{Mailbox, Property, Value},
+    set::JMAPSetMailbox,
+};
+use store::{
+    ahash::AHashMap,
+    core::{acl::ACLToken, collection::Collection, document::Document, vec_map::VecMap},
+    write::batch::WriteBatch,
+    AccountId, JMAPStore, Store,
+};
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Mailbox/query filter operator tests...");
+    let account_id = 1;
+
+    let mut batch = WriteBatch::new(SUPERUSER_ID);
+    batch.insert_document(Document::new(Collection::Principal, account_id));
+    db.write(batch).unwrap();
+
+    let mut create = VecMap::new();
+    for (name, role) in [
+        ("Inbox", Some("inbox")),
+        ("Projects", Some("archive")),
+        ("Project Alpha", None),
+        ("Old projects", None),
+        ("Personal", None),
+    ] {
+        let mut mailbox = Mailbox::default();
+        mailbox.properties.append(
+            Property::Name,
+            Value::Text {
+                value: name.to_string(),
+            },
+        );
+        if let Some(role) = role {
+            mailbox.properties.append(
+                Property::Role,
+                Value::Text {
+                    value: role.to_string(),
+                },
+            );
+        }
+        create.append(name.to_string(), mailbox);
+    }
+    let response = db
+        .mailbox_set(SetRequest {
+            acl: acl(account_id).into(),
+            account_id: JMAPId::new(account_id as u64),
+            if_in_state: None,
+            create: create.into(),
+            update: None,
+            destroy: None,
+            arguments: Default::default(),
+        })
+        .unwrap();
+    assert!(
+        response.not_created.is_empty(),
+        "{:?}",
+        response.not_created
+    );
+    let names = response
+        .created
+        .into_iter()
+        .map(|(name, mailbox)| (*mailbox.id().unwrap(), name))
+        .collect::<AHashMap<_, _>>();
+
+    for (filter, expected_names) in [
+        // Non-special mailboxes whose name contains a string
+        (
+            r#"{"operator": "AND", "conditions": [{"role": null}, {"name": "proj"}]}"#,
+            vec!["Old projects", "Project Alpha"],
+        ),
+        (
+            r#"{"operator": "OR", "conditions": [{"role": "inbox"}, {"name": "ALPHA"}]}"#,
+            vec!["Inbox", "Project Alpha"],
+        ),
+        (
+            concat!(
+                r#"{"operator": "AND", "conditions": [{"hasAnyRole": false}, "#,
+                r#"{"operator": "NOT", "conditions": [{"name": "proj"}]}]}"#
+            ),
+            vec!["Personal"],
+        ),
+        (
+            concat!(
+                r#"{"operator": "OR", "conditions": [{"name": "personal"}, "#,
+                r#"{"operator": "AND", "conditions": [{"hasAnyRole": true}, "#,
+                r#"{"name": "proj"}]}]}"#
+            ),
+            vec!["Personal", "Projects"],
+        ),
+    ] {
+        let mut request: QueryRequest<Mailbox> = serde_json::from_str(&format!(
+            concat!(
+                "{{\"accountId\": \"{}\", \"filter\": {}, ",
+                "\"sort\": [{{\"property\": \"name\"}}]}}"
+            ),
+            JMAPId::new(account_id as u64),
+            filter
+        ))
+        .unwrap();
+        request.acl = acl(account_id).into();
+        assert_eq!(
+            db.mailbox_query(request)
+                .unwrap()
+                .ids
+                .iter()
+                .map(|id| names.get(id).unwrap().as_str())
+                .collect::<Vec<_>>(),
+            expected_names,
+            "{}",
+            filter
+        );
+    }
+}
+
+fn acl(account_id: AccountId) -> Arc<ACLToken> {
+    Arc::new(ACLToken {
+        member_of: vec![account_id],
+        access_to: vec![],
+    })
+}
@@ -0,0 +1,241 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    error::set::SetErrorType,
+    jmap_store::Object,
+    orm::serialize::JMAPOrm,
+    request::set::{SetRequest, SetResponse},
+    types::jmap::JMAPId,
+    SUPERUSER_ID,
+};
+use jmap_mail::mailbox::{
+    schema::{Mailbox, Property, Value},
+    set::JMAPSetMailbox,
+};
+use store::{
+    core::{acl::ACLToken, collection::Collection, document::Document, vec_map::VecMap},
+    write::batch::WriteBatch,
+    AccountId, JMAPStore, Store,
+};
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Mailbox role validation tests...");
+    let account_id = 1;
+
+    let mut batch = WriteBatch::new(SUPERUSER_ID);
+    batch.insert_document(Document::new(Collection::Principal, account_id));
+    db.write(batch).unwrap();
+
+    // Registered and configured roles are accepted, unknown ones are rejected
+    let mut create = VecMap::new();
+    for (create_id, name, role) in [
+        ("a", "Archive", "ARCHIVE"),
+        ("b", "Receipts", "receipts"),
+        ("c", "Fruit", "banana"),
+    ] {
+        let mut mailbox = Mailbox::default();
+        mailbox.properties.append(
+            Property::Name,
+            Value::Text {
+                value: name.to_string(),
+            },
+        );
+        mailbox.properties.append(
+            Property::Role,
+            Value::Text {
+                value: role.to_string(),
+            },
+        );
+        create.append(create_id.to_string(), mailbox);
+    }
+    let mut response = mailbox_set(&db, account_id, create.into(), None);
+    assert!(
+        matches!(
+            response.not_created.get("c").map(|err| &err.type_),
+            Some(SetErrorType::InvalidProperties)
+        ),
+        "{:?}",
+        response.not_created
+    );
+    assert_eq!(response.not_created.len(), 1);
+    let archive_id = *response.created.remove("a").unwrap().id().unwrap();
+    let receipts_id = *response.created.remove("b").unwrap().id().unwrap();
+    assert_eq!(
+        get_role(&db, account_id, archive_id),
+        Some("archive".into())
+    );
+    assert_eq!(
+        get_role(&db, account_id, receipts_id),
+        Some("receipts".into())
+    );
+
+    // Unknown roles can't be set on update either
+    let mut update = VecMap::new();
+    let mut mailbox = Mailbox::default();
+    mailbox.properties.append(
+        Property::Role,
+        Value::Text {
+            value: "banana".to_string(),
+        },
+    );
+    update.append(receipts_id, mailbox);
+    let response = mailbox_set(&db, account_id, None, update.into());
+    assert!(matches!(
+        response.not_updated.get(&receipts_id).map(|err| &err.type_),
+        Some(SetErrorType::InvalidProperties)
+    ));
+    assert_eq!(
+        get_role(&db, account_id, receipts_id),
+        Some("receipts".into())
+    );
+
+    // Roles can be cleared by setting them to null
+    let mut update = VecMap::new();
+    let mut mailbox = Mailbox::default();
+    mailbox.properties.append(Property::Role, Value::Null);
+    update.append(archive_id, mailbox);
+    let response = mailbox_set(&db, account_id, None, update.into());
+    assert!(
+        response.not_updated.is_empty(),
+        "{:?}",
+        response.not_updated
+    );
+    assert_eq!(get_role(&db, account_id, archive_id), None);
+
+    // Roles are unique within an account
+    let mut create = VecMap::new();
+    create.append("i".to_string(), new_mailbox("Inbox", Some("inbox")));
+    let mut response = mailbox_set(&db, account_id, create.into(), None);
+    let inbox_id = *response
+        .created
+        .remove("i")
+        .unwrap_or_else(|| panic!("{:?}", response.not_created))
+        .id()
+        .unwrap();
+    let mut create = VecMap::new();
+    create.append("i2".to_string(), new_mailbox("Inbox 2", Some("INBOX")));
+    create.append("s1".to_string(), new_mailbox("Sent", Some("sent")));
+    create.append("s2".to_string(), new_mailbox("Sent 2", Some("sent")));
+    let response = mailbox_set(&db, account_id, create.into(), None);
+    for create_id in ["i2", "s2"] {
+        let err = response.not_created.get(create_id).unwrap();
+        assert!(matches!(err.type_, SetErrorType::InvalidProperties));
+        assert_eq!(
+            serde_json::to_value(err).unwrap()["properties"],
+            serde_json::json!(["role"])
+        );
+    }
+    assert!(response.created.contains_key("s1"));
+
+    // Clearing a role frees it for other mailboxes in the same request
+    let mut create = VecMap::new();
+    create.append("i3".to_string(), new_mailbox("New Inbox", Some("inbox")));
+    let mut update = VecMap::new();
+    update.append(inbox_id, new_mailbox("Old Inbox", None));
+    let mut response = mailbox_set(&db, account_id, create.into(), update.into());
+    assert!(
+        response.not_created.is_empty() && response.not_updated.is_empty(),
+        "{:?} {:?}",
+        response.not_created,
+        response.not_updated
+    );
+    let new_inbox_id = *response.created.remove("i3").unwrap().id().unwrap();
+    assert_eq!(get_role(&db, account_id, inbox_id), None);
+    assert_eq!(
+        get_role(&db, account_id, new_inbox_id),
+        Some("inbox".into())
+    );
+
+    // A mailbox can keep its own role
+    let mut update = VecMap::new();
+    update.append(new_inbox_id, new_mailbox("Inbox", Some("inbox")));
+    let response = mailbox_set(&db, account_id, None, update.into());
+    assert!(
+        response.not_updated.is_empty(),
+        "{:?}",
+        response.not_updated
+    );
+}
+
+fn new_mailbox(name: &str, role: Option<&str>) -> Mailbox {
+    let mut mailbox = Mailbox::default();
+    mailbox.properties.append(
+        Property::Name,
+        Value::Text {
+            value: name.to_string(),
+        },
+    );
+    mailbox.properties.append(
+        Property::Role,
+        role.map_or(Value::Null, |role| Value::Text {
+            value: role.to_string(),
+        }),
+    );
+    mailbox
+}
+
+fn mailbox_set<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    create: Option<VecMap<String, Mailbox>>,
+    update: Option<VecMap<JMAPId, Mailbox>>,
+) -> SetResponse<Mailbox>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    db.mailbox_set(SetRequest {
+        acl: Arc::new(ACLToken {
+            member_of: vec![account_id],
+            access_to: vec![],
+        })
+        .into(),
+        account_id: JMAPId::new(account_id as u64),
+        if_in_state: None,
+        create,
+        update,
+        destroy: None,
+        arguments: Default::default(),
+    })
+    .unwrap()
+}
+
+fn get_role<T>(db: &JMAPStore<T>, account_id: AccountId, id: JMAPId) -> Option<String>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    match db
+        .get_orm::<Mailbox>(account_id, id.get_document_id())
+        .unwrap()
+        .unwrap()
+        .get(&Property::Role)
+    {
+        Some(Value::Text { value }) => Some(value.to_string()),
+        _ => None,
+    }
+}
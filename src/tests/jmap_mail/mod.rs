@@ -21,10 +21,26 @@
  * for more details.
 */
 
+use std::sync::Arc;
+
+use jmap::{orm::TinyORM, SUPERUSER_ID};
+use jmap_mail::mailbox::{schema::Mailbox, CreateMailbox};
+use store::{
+    core::{collection::Collection, document::Document},
+    write::batch::WriteBatch,
+    AccountId, DocumentId, JMAPStore, Store,
+};
 use store_rocksdb::RocksDB;
 
-use super::{jmap::init_jmap_tests, store::utils::destroy_temp_dir};
+use super::{
+    jmap::init_jmap_tests,
+    store::{
+        init_db, init_db_with_settings, open_db,
+        utils::{destroy_temp_dir, init_settings},
+    },
+};
 
+pub mod account_usage;
 pub mod email_changes;
 pub mod email_copy;
 pub mod email_get;
@@ -35,9 +51,11 @@ pub mod email_set;
 pub mod email_submission;
 pub mod email_thread;
 pub mod email_thread_merge;
+pub mod identity;
 pub mod lmtp;
 pub mod mailbox;
 pub mod search_snippet;
+pub mod sync_batch;
 pub mod vacation_response;
 
 #[actix_web::test]
@@ -54,6 +72,7 @@ async fn jmap_mail_tests() {
     email_parse::test(server.clone(), &mut client).await;
     email_set::test(server.clone(), &mut client).await;
     email_query::test(server.clone(), &mut client).await;
+    email_query::snapshot::test(server.clone(), &mut client).await;
     email_copy::test(server.clone(), &mut client).await;
     email_submission::test(server.clone(), &mut client).await;
     lmtp::test(server.clone(), &mut client).await;
@@ -64,6 +83,346 @@ async fn jmap_mail_tests() {
     destroy_temp_dir(&temp_dir);
 }
 
+#[test]
+#[ignore]
+fn jmap_mail_store_tests() {
+    run_store_test(
+        "jmap_mail_restore_tests",
+        &[("mail-destroy-grace-period", "1")],
+        email_set::restore::test,
+    );
+    run_store_test(
+        "jmap_mail_attachment_type_tests",
+        &[],
+        email_set::attachment_type::test,
+    );
+    run_store_test(
+        "jmap_mail_destroy_blobs_tests",
+        &[("blob-compress", "true")],
+        email_set::destroy_blobs::test,
+    );
+    run_store_test(
+        "jmap_mail_query_address_tests",
+        &[("mail-address-search", "true")],
+        email_query::address::test,
+    );
+    run_store_test(
+        "jmap_mail_query_default_sort_tests",
+        &[],
+        email_query::default_sort::test,
+    );
+    for dedup in [false, true] {
+        run_store_test(
+            "jmap_mail_duplicate_id_tests",
+            &[("import-dedup-by-message-id", &dedup.to_string())],
+            email_parse::duplicate_id::test,
+        );
+    }
+    for keep_bcc in [true, false] {
+        run_store_test(
+            "jmap_mail_submission_bcc_tests",
+            &[("mail-sent-keep-bcc", &keep_bcc.to_string())],
+            email_submission::bcc::test,
+        );
+    }
+    run_store_test(
+        "jmap_mail_submission_signature_tests",
+        &[],
+        email_submission::signature::test,
+    );
+    run_store_test(
+        "jmap_mail_query_conditions_tests",
+        &[("max-filter-conditions", "10")],
+        email_query::conditions::test,
+    );
+    run_store_test(
+        "jmap_mail_blob_access_tests",
+        &[],
+        email_get::blob_access::test,
+    );
+    run_store_test(
+        "jmap_mail_attachment_limit_tests",
+        &[("mail-attachments-max-size", "10000")],
+        email_set::attachment_limit::test,
+    );
+    run_store_test(
+        "jmap_mail_query_received_after_tests",
+        &[],
+        email_query::received_after::test,
+    );
+    run_store_test("jmap_mail_get_headers_tests", &[], email_get::headers::test);
+    run_store_test("jmap_mail_trash_tests", &[], email_set::trash::test);
+    for enforce in [false, true] {
+        run_store_test(
+            "jmap_mail_line_length_tests",
+            &[("enforce-line-length", &enforce.to_string())],
+            |db| email_set::line_length::test(db, enforce),
+        );
+    }
+    run_store_test(
+        "jmap_mail_mailbox_get_properties_tests",
+        &[],
+        mailbox::get_properties::test,
+    );
+    run_store_test("jmap_mail_forward_tests", &[], email_set::forward::test);
+    run_store_test(
+        "jmap_mail_thread_changes_tests",
+        &[],
+        email_thread::changes::test,
+    );
+    run_store_test(
+        "jmap_mail_server_set_tests",
+        &[],
+        email_set::server_set::test,
+    );
+    run_store_test(
+        "jmap_mail_mailbox_corrupt_tags_tests",
+        &[],
+        mailbox::corrupt_tags::test,
+    );
+    run_store_test("jmap_mail_query_sort_tests", &[], email_query::sort::test);
+    run_store_test("jmap_mail_set_empty_tests", &[], email_set::empty::test);
+    run_store_test(
+        "jmap_mail_reply_headers_tests",
+        &[],
+        email_set::reply_headers::test,
+    );
+    run_store_test("jmap_mail_redact_tests", &[], email_set::redact::test);
+    run_store_test(
+        "jmap_mail_mailbox_reference_tests",
+        &[],
+        email_set::mailbox_reference::test,
+    );
+    run_store_test("jmap_mail_part_size_tests", &[], email_set::part_size::test);
+    run_store_test("jmap_mail_copy_state_tests", &[], email_copy::state::test);
+    run_store_test(
+        "jmap_mail_has_attachment_tests",
+        &[],
+        email_get::has_attachment::test,
+    );
+    run_store_test(
+        "jmap_mail_preview_html_tests",
+        &[],
+        email_get::preview_html::test,
+    );
+    for normalize in [false, true] {
+        run_store_test(
+            "jmap_mail_sender_tests",
+            &[("mail-normalize-sender", &normalize.to_string())],
+            email_set::sender::test,
+        );
+    }
+    for dedup in [false, true] {
+        run_store_test(
+            "jmap_mail_recipients_tests",
+            &[
+                ("mail-max-recipients", "10"),
+                ("mail-dedup-recipients", &dedup.to_string()),
+            ],
+            email_set::recipients::test,
+        );
+    }
+    for subject_fallback in [false, true] {
+        run_store_test(
+            "jmap_mail_thread_references_tests",
+            &[(
+                "mail-thread-subject-fallback",
+                &subject_fallback.to_string(),
+            )],
+            email_thread::references::test,
+        );
+    }
+    run_store_test(
+        "jmap_mail_body_structure_stored_tests",
+        &[],
+        email_get::body_structure_stored::test,
+    );
+    run_store_test(
+        "jmap_mail_mailbox_query_filter_tests",
+        &[],
+        mailbox::query_filter::test,
+    );
+    run_store_test(
+        "jmap_mail_mailbox_parent_cycle_tests",
+        &[],
+        mailbox::parent_cycle::test,
+    );
+    run_store_test(
+        "jmap_mail_received_at_date_tests",
+        &[],
+        email_get::received_at_date::test,
+    );
+    run_store_test(
+        "jmap_mail_mailbox_destroy_tests",
+        &[],
+        mailbox::destroy::test,
+    );
+    run_store_test(
+        "jmap_mail_query_cursor_tests",
+        &[],
+        email_query::cursor::test,
+    );
+    run_store_test(
+        "jmap_mail_mailbox_counters_tests",
+        &[],
+        mailbox::counters::test,
+    );
+    run_store_test(
+        "jmap_mail_mailbox_query_sort_tests",
+        &[],
+        mailbox::query_sort::test,
+    );
+    run_store_test(
+        "jmap_mail_body_conflict_tests",
+        &[],
+        email_set::body_conflict::test,
+    );
+    for store_unparsed in [false, true] {
+        run_store_test(
+            "jmap_mail_import_unparsed_tests",
+            &[("import-store-unparsed", &store_unparsed.to_string())],
+            email_parse::import_unparsed::test,
+        );
+    }
+    for send_as_groups in [true, false] {
+        run_store_test(
+            "jmap_mail_identity_send_as_tests",
+            &[("identity-send-as-groups", &send_as_groups.to_string())],
+            identity::send_as::test,
+        );
+    }
+    run_store_test("jmap_mail_account_usage_tests", &[], account_usage::test);
+    run_store_test(
+        "jmap_mail_email_destroy_submission_tests",
+        &[],
+        email_submission::destroy_email::test,
+    );
+    run_store_test(
+        "jmap_mail_email_keywords_case_tests",
+        &[],
+        email_set::keywords_case::test,
+    );
+    run_store_test(
+        "jmap_mail_email_get_cache_tests",
+        &[("cache-size-objects", "1048576")],
+        email_get::cache::test,
+    );
+    run_store_test(
+        "jmap_mail_thread_paging_tests",
+        &[],
+        email_thread::paging::test,
+    );
+    run_store_test(
+        "jmap_mail_changelog_compaction_tests",
+        &[],
+        email_changes::compaction::test,
+    );
+    run_store_test(
+        "jmap_mail_shared_access_tests",
+        &[],
+        email_set::shared_access::test,
+    );
+    run_store_test(
+        "jmap_mail_keyword_patch_tests",
+        &[],
+        email_set::keyword_patch::test,
+    );
+    run_store_test("jmap_mail_list_tests", &[], email_get::list::test);
+    run_store_test(
+        "jmap_mail_mailbox_race_tests",
+        &[],
+        email_set::mailbox_race::test,
+    );
+    run_store_test("jmap_mail_set_serial_tests", &[], email_set::serial::test);
+    run_store_test("jmap_mail_identity_tests", &[], identity::test);
+    run_store_test(
+        "jmap_mail_mailbox_role_tests",
+        &[("mailbox-extra-roles", "receipts")],
+        mailbox::roles::test,
+    );
+}
+
+#[test]
+#[ignore]
+fn jmap_mail_preview_tests() {
+    let (mut settings, temp_dir) = init_settings("jmap_mail_preview_tests", 1, 1, true);
+    settings.set_value("mail-preview-length".to_string(), "20".to_string());
+
+    let id = email_get::preview::test(Arc::new(open_db::<RocksDB>(&settings)));
+
+    // Reopen the store with a shorter preview length
+    settings.set_value("mail-preview-length".to_string(), "10".to_string());
+
+    email_get::preview::test_length_change(Arc::new(open_db::<RocksDB>(&settings)), id);
+
+    destroy_temp_dir(&temp_dir);
+}
+
+#[test]
+#[ignore]
+fn jmap_mail_sync_batch_bench() {
+    let (db, temp_dir) = init_db::<RocksDB>("jmap_mail_sync_batch_bench", true);
+
+    sync_batch::test(Arc::new(db));
+
+    destroy_temp_dir(&temp_dir);
+}
+
+/// Runs a store-level test against a fresh database opened with the given settings.
+fn run_store_test(
+    name: &str,
+    settings: &[(&str, &str)],
+    test: impl FnOnce(Arc<JMAPStore<RocksDB>>),
+) {
+    let (db, temp_dir) = init_db_with_settings::<RocksDB>(name, settings);
+
+    test(Arc::new(db));
+
+    destroy_temp_dir(&temp_dir);
+}
+
+/// Creates an account along with a mailbox, returns the id of the mailbox.
+pub fn create_account_mailbox<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    name: &str,
+    role: &str,
+) -> DocumentId
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut batch = WriteBatch::new(SUPERUSER_ID);
+    batch.insert_document(Document::new(Collection::Principal, account_id));
+    db.write(batch).unwrap();
+
+    create_mailbox(db, account_id, name, role)
+}
+
+pub fn create_mailbox<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    name: &str,
+    role: &str,
+) -> DocumentId
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut batch = WriteBatch::new(account_id);
+    let mut document = Document::new(
+        Collection::Mailbox,
+        db.assign_document_id(account_id, Collection::Mailbox)
+            .unwrap(),
+    );
+    let mailbox_id = document.document_id;
+    TinyORM::<Mailbox>::new_mailbox(name, role)
+        .insert(&mut document)
+        .unwrap();
+    batch.log_insert(Collection::Mailbox, mailbox_id);
+    batch.insert_document(document);
+    db.write(batch).unwrap();
+    mailbox_id
+}
+
 pub fn find_values(string: &str, name: &str) -> Vec<String> {
     let mut last_pos = 0;
     let mut values = Vec::new();
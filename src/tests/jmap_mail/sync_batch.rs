@@ -0,0 +1,224 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{sync::Arc, time::Instant};
+
+use jmap::{
+    request::{get::GetRequest, query::QueryRequest, MaybeResultReference},
+    types::jmap::JMAPId,
+    SUPERUSER_ID,
+};
+use jmap_mail::{
+    mail::{
+        get::JMAPGetMail,
+        import::JMAPMailImport,
+        query::JMAPMailQuery,
+        schema::{Email, Property, Value},
+    },
+    mailbox::get::JMAPGetMailbox,
+    thread::get::JMAPGetThread,
+};
+use store::{
+    blob::BlobId,
+    core::{acl::ACLToken, collection::Collection, document::Document},
+    read::cache::ReadCache,
+    write::batch::WriteBatch,
+    AccountId, JMAPStore, Store,
+};
+
+use crate::tests::jmap_mail::create_mailbox;
+
+const NUM_MAILBOXES: usize = 10;
+const NUM_MESSAGES: usize = 500;
+const NUM_RUNS: usize = 50;
+
+pub fn test<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running initial sync batch benchmark...");
+    let account_id = 1;
+
+    // Create account, mailboxes and messages
+    let mut batch = WriteBatch::new(SUPERUSER_ID);
+    batch.insert_document(Document::new(Collection::Principal, account_id));
+    db.write(batch).unwrap();
+
+    let mailbox_ids = (0..NUM_MAILBOXES)
+        .map(|mailbox_num| create_mailbox(&db, account_id, &format!("Mailbox {}", mailbox_num), ""))
+        .collect::<Vec<_>>();
+
+    for message_num in 0..NUM_MESSAGES {
+        let message = format!(
+            concat!(
+                "From: sender{}@example.com\r\n",
+                "To: jdoe@example.com\r\n",
+                "Subject: Message {}\r\n\r\n",
+                "This is message number {}.\r\n"
+            ),
+            message_num % 20,
+            message_num,
+            message_num
+        )
+        .into_bytes();
+        let blob_id = BlobId::new_external(&message);
+        db.blob_store(&blob_id, message.clone()).unwrap();
+        db.mail_import_item(
+            account_id,
+            blob_id,
+            &message,
+            vec![mailbox_ids[message_num % NUM_MAILBOXES]],
+            vec![],
+            Some(message_num as i64 * 60),
+        )
+        .unwrap();
+    }
+
+    // Run the sync batch with and without a shared read cache
+    let expected_results = sync_batch(&db, account_id);
+    let mut elapsed = [0u128; 2];
+    for run in 0..NUM_RUNS * 2 {
+        let use_cache = run % 2 == 1;
+        let cache = Arc::new(ReadCache::default());
+        let time = Instant::now();
+        let results = if use_cache {
+            let _guard = cache.enter();
+            sync_batch(&db, account_id)
+        } else {
+            sync_batch(&db, account_id)
+        };
+        elapsed[use_cache as usize] += time.elapsed().as_micros();
+        assert_eq!(results, expected_results);
+    }
+
+    println!(
+        "Initial sync of {} messages: {} ms without read cache, {} ms with read cache (average of {} runs).",
+        NUM_MESSAGES,
+        elapsed[0] as f64 / NUM_RUNS as f64 / 1000.0,
+        elapsed[1] as f64 / NUM_RUNS as f64 / 1000.0,
+        NUM_RUNS
+    );
+
+    // Messages imported by other threads are visible to reads sharing a cache
+    let cache = Arc::new(ReadCache::default());
+    let _guard = cache.enter();
+    let num_messages = db
+        .get_document_ids(account_id, Collection::Mail)
+        .unwrap()
+        .unwrap()
+        .len();
+    let db_ = db.clone();
+    let mailbox_id = mailbox_ids[0];
+    std::thread::spawn(move || {
+        let message = b"Subject: Imported concurrently\r\n\r\nBody.\r\n".to_vec();
+        let blob_id = BlobId::new_external(&message);
+        db_.blob_store(&blob_id, message.clone()).unwrap();
+        db_.mail_import_item(
+            account_id,
+            blob_id,
+            &message,
+            vec![mailbox_id],
+            vec![],
+            None,
+        )
+        .unwrap();
+    })
+    .join()
+    .unwrap();
+    assert_eq!(
+        db.get_document_ids(account_id, Collection::Mail)
+            .unwrap()
+            .unwrap()
+            .len(),
+        num_messages + 1
+    );
+}
+
+fn sync_batch<T>(db: &JMAPStore<T>, account_id: AccountId) -> Vec<String>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let acl = Arc::new(ACLToken {
+        member_of: vec![account_id],
+        access_to: vec![],
+    });
+    let mut results = Vec::with_capacity(4);
+
+    let response = db
+        .mailbox_get(GetRequest {
+            acl: acl.clone().into(),
+            account_id: JMAPId::new(account_id as u64),
+            ids: None,
+            properties: None,
+            arguments: (),
+        })
+        .unwrap();
+    results.push(serde_json::to_string(&response).unwrap());
+
+    let mut request: QueryRequest<Email> = serde_json::from_str(&format!(
+        concat!(
+            "{{\"accountId\": \"{}\", ",
+            "\"sort\": [{{\"property\": \"receivedAt\", \"isAscending\": false}}], ",
+            "\"limit\": 100, \"calculateTotal\": true}}"
+        ),
+        JMAPId::new(account_id as u64)
+    ))
+    .unwrap();
+    request.acl = acl.clone().into();
+    let response = db.mail_query(request).unwrap();
+    results.push(serde_json::to_string(&response).unwrap());
+    let ids = response.ids;
+
+    let response = db
+        .mail_get(GetRequest {
+            acl: acl.clone().into(),
+            account_id: JMAPId::new(account_id as u64),
+            ids: MaybeResultReference::Value(ids).into(),
+            properties: None,
+            arguments: Default::default(),
+        })
+        .unwrap();
+    let thread_ids = response
+        .list
+        .iter()
+        .filter_map(|email| match email.properties.get(&Property::ThreadId) {
+            Some(Value::Id { value }) => Some(*value),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert!(!thread_ids.is_empty());
+    results.push(serde_json::to_string(&response).unwrap());
+
+    let response = db
+        .thread_get(GetRequest {
+            acl: acl.into(),
+            account_id: JMAPId::new(account_id as u64),
+            ids: MaybeResultReference::Value(thread_ids).into(),
+            properties: None,
+            arguments: Default::default(),
+        })
+        .unwrap();
+    results.push(serde_json::to_string(&response).unwrap());
+
+    results
+}
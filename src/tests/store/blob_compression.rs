@@ -0,0 +1,124 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use store::{
+    blob::{local::LocalBlobStore, BlobId, BlobStore},
+    config::env_settings::EnvSettings,
+};
+
+pub fn test(mut settings: EnvSettings) {
+    // A 10MB blob made of repeated lines with varying numbers
+    let mut blob = Vec::with_capacity(10 * 1024 * 1024);
+    let mut line_num = 0;
+    while blob.len() < 10 * 1024 * 1024 {
+        blob.extend_from_slice(
+            format!("Line {} of a very compressible message body.\r\n", line_num).as_bytes(),
+        );
+        line_num += 1;
+    }
+    blob.truncate(10 * 1024 * 1024);
+    let blob_id = BlobId::new_external(&blob);
+
+    settings.set_value("blob-compress".to_string(), "true".to_string());
+    let store = LocalBlobStore::new(&settings).unwrap();
+    assert!(store.put(&blob_id, &blob).unwrap());
+    assert!(!store.put(&blob_id, &blob).unwrap());
+    let stored_size = std::fs::metadata(blob_path(&store, &blob_id))
+        .unwrap()
+        .len();
+    assert!(stored_size < (blob.len() / 4) as u64, "{}", stored_size);
+
+    // Full and partial reads return the uncompressed bytes
+    assert_eq!(
+        store.get_range(&blob_id, 0..u32::MAX).unwrap().unwrap(),
+        blob
+    );
+    for range in [0..4_000_000u32, 3_000_000..7_000_000, 6_500_000..10_485_760] {
+        assert_eq!(
+            store.get_range(&blob_id, range.clone()).unwrap().unwrap(),
+            &blob[range.start as usize..range.end as usize],
+            "{:?}",
+            range
+        );
+    }
+
+    // Ranges past the end are truncated
+    assert_eq!(
+        store
+            .get_range(&blob_id, 10_485_000..20_000_000)
+            .unwrap()
+            .unwrap(),
+        &blob[10_485_000..]
+    );
+
+    // Blobs stored before enabling compression remain readable, and the
+    // other way around
+    let legacy_blob = b"Uncompressed legacy blob".to_vec();
+    let legacy_blob_id = BlobId::new_external(&legacy_blob);
+    settings.set_value("blob-compress".to_string(), "false".to_string());
+    let legacy_store = LocalBlobStore::new(&settings).unwrap();
+    assert!(legacy_store.put(&legacy_blob_id, &legacy_blob).unwrap());
+    assert_eq!(
+        std::fs::read(blob_path(&legacy_store, &legacy_blob_id)).unwrap(),
+        legacy_blob
+    );
+    assert_eq!(
+        store
+            .get_range(&legacy_blob_id, 0..u32::MAX)
+            .unwrap()
+            .unwrap(),
+        legacy_blob
+    );
+    assert_eq!(
+        store.get_range(&legacy_blob_id, 5..10).unwrap().unwrap(),
+        &legacy_blob[5..10]
+    );
+    assert_eq!(
+        legacy_store
+            .get_range(&blob_id, 3_000_000..7_000_000)
+            .unwrap()
+            .unwrap(),
+        &blob[3_000_000..7_000_000]
+    );
+
+    // Uncompressed blobs that look like compressed ones are still read back
+    let magic_blob = b"\0STZ1 is not a compressed blob".to_vec();
+    let magic_blob_id = BlobId::new_external(&magic_blob);
+    assert!(legacy_store.put(&magic_blob_id, &magic_blob).unwrap());
+    assert_eq!(
+        legacy_store
+            .get_range(&magic_blob_id, 0..u32::MAX)
+            .unwrap()
+            .unwrap(),
+        magic_blob
+    );
+}
+
+fn blob_path(store: &LocalBlobStore, blob_id: &BlobId) -> std::path::PathBuf {
+    let mut path = store.base_path.clone();
+    for byte in blob_id.hash().iter().take(store.hash_levels) {
+        path.push(format!("{:x}", byte));
+    }
+    path.push(blob_id.to_string());
+    path
+}
@@ -0,0 +1,101 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::io::Read;
+
+use store::{
+    blob::{local::LocalBlobStore, BlobId, BlobStore},
+    config::env_settings::EnvSettings,
+};
+
+pub fn test(mut settings: EnvSettings) {
+    // A 32MB blob that can't be compressed much
+    let mut blob = Vec::with_capacity(32 * 1024 * 1024);
+    let mut seed = 0x2545f491u32;
+    while blob.len() < 32 * 1024 * 1024 {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        blob.extend_from_slice(&seed.to_le_bytes());
+    }
+    let blob_id = BlobId::new_external(&blob);
+
+    for compress in ["false", "true"] {
+        settings.set_value("blob-compress".to_string(), compress.to_string());
+        let store = LocalBlobStore::new(&settings).unwrap();
+        store.put(&blob_id, &blob).unwrap();
+
+        // Stream the whole blob in small chunks, comparing each chunk without
+        // keeping the bytes read so far
+        let mut reader = store.get_reader(&blob_id, 0..u32::MAX).unwrap().unwrap();
+        let mut chunk = vec![0; 64 * 1024];
+        let mut bytes_read = 0;
+        loop {
+            let chunk_size = reader.read(&mut chunk).unwrap();
+            if chunk_size == 0 {
+                break;
+            }
+            assert_eq!(
+                &chunk[..chunk_size],
+                &blob[bytes_read..bytes_read + chunk_size],
+                "offset {}, compressed {}",
+                bytes_read,
+                compress
+            );
+            bytes_read += chunk_size;
+        }
+        assert_eq!(bytes_read, blob.len(), "compressed {}", compress);
+
+        // Ranges are bounded to the requested slice
+        for range in [
+            0..100u32,
+            1_000_000..1_065_536,
+            (blob.len() as u32 - 10)..u32::MAX,
+        ] {
+            let mut bytes = Vec::new();
+            store
+                .get_reader(&blob_id, range.clone())
+                .unwrap()
+                .unwrap()
+                .read_to_end(&mut bytes)
+                .unwrap();
+            assert_eq!(
+                bytes,
+                &blob[range.start as usize..std::cmp::min(range.end as usize, blob.len())],
+                "{:?}, compressed {}",
+                range,
+                compress
+            );
+        }
+
+        store.delete(&blob_id).unwrap();
+    }
+
+    // Missing blobs return no reader
+    settings.set_value("blob-compress".to_string(), "false".to_string());
+    assert!(LocalBlobStore::new(&settings)
+        .unwrap()
+        .get_reader(&BlobId::new_external(b"missing"), 0..u32::MAX)
+        .unwrap()
+        .is_none());
+}
@@ -0,0 +1,69 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{fs, thread, time::Duration};
+
+use store::{
+    blob::{local::LocalBlobStore, BlobId, BlobStore},
+    config::env_settings::EnvSettings,
+};
+
+pub fn test(mut settings: EnvSettings) {
+    settings.set_value("blob-temp-file-ttl".to_string(), "1".to_string());
+    let store = LocalBlobStore::new(&settings).unwrap();
+
+    // Completed writes leave no temporary files behind
+    let blob = b"Blob temporary file test".to_vec();
+    let blob_id = BlobId::new_external(&blob);
+    assert!(store.put(&blob_id, &blob).unwrap());
+    assert_eq!(store.get(&blob_id).unwrap().unwrap(), blob);
+    assert_eq!(temp_files(&store), 0);
+
+    // A file left behind by an interrupted write and one still being written
+    let mut stale_path = store.temp_path.clone();
+    stale_path.push("stale");
+    fs::write(&stale_path, b"partial").unwrap();
+    let in_progress_file = store.temp_file(&blob_id).unwrap();
+    fs::write(&in_progress_file.path, b"partial").unwrap();
+    thread::sleep(Duration::from_millis(1500));
+
+    // A recent file from an interrupted write
+    let mut fresh_path = store.temp_path.clone();
+    fresh_path.push("fresh");
+    fs::write(&fresh_path, b"partial").unwrap();
+
+    // Only the stale file is reaped
+    assert_eq!(temp_files(&store), 3);
+    assert_eq!(store.purge_temp_files().unwrap(), 1);
+    assert!(!stale_path.exists());
+    assert!(in_progress_file.path.exists());
+    assert!(fresh_path.exists());
+
+    // Abandoned writes are removed once dropped
+    drop(in_progress_file);
+    assert_eq!(temp_files(&store), 1);
+}
+
+fn temp_files(store: &LocalBlobStore) -> usize {
+    fs::read_dir(&store.temp_path).map_or(0, |entries| entries.count())
+}
@@ -0,0 +1,92 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use store::{
+    blob::{local::LocalBlobStore, BlobId, BlobStore},
+    config::env_settings::EnvSettings,
+    core::error::StoreError,
+};
+
+pub fn test(mut settings: EnvSettings) {
+    settings.set_value("blob-verify-on-read".to_string(), "true".to_string());
+
+    for compress in ["false", "true"] {
+        settings.set_value("blob-compress".to_string(), compress.to_string());
+        let store = LocalBlobStore::new(&settings).unwrap();
+
+        let blob = format!("Blob verification test (compressed: {})", compress).into_bytes();
+        let blob_id = BlobId::new_external(&blob);
+        assert!(store.put(&blob_id, &blob).unwrap());
+        assert_eq!(
+            store.get_range(&blob_id, 0..u32::MAX).unwrap().unwrap(),
+            blob
+        );
+
+        // Replace the file on disk with a valid blob holding other contents
+        let mut corrupted_blob = blob.clone();
+        corrupted_blob[0] = b'X';
+        let corrupted_blob_id = BlobId::new_external(&corrupted_blob);
+        assert!(store.put(&corrupted_blob_id, &corrupted_blob).unwrap());
+        std::fs::copy(
+            blob_path(&store, &corrupted_blob_id),
+            blob_path(&store, &blob_id),
+        )
+        .unwrap();
+
+        assert!(
+            matches!(
+                store.get_range(&blob_id, 0..u32::MAX),
+                Err(StoreError::InternalError(_))
+            ),
+            "compressed: {}",
+            compress
+        );
+
+        // Partial reads can't be verified
+        assert_eq!(
+            store.get_range(&blob_id, 1..10).unwrap().unwrap(),
+            &blob[1..10]
+        );
+
+        // Corrupted blobs are returned as they are when verification is disabled
+        settings.set_value("blob-verify-on-read".to_string(), "false".to_string());
+        let unverified_store = LocalBlobStore::new(&settings).unwrap();
+        assert_eq!(
+            unverified_store
+                .get_range(&blob_id, 0..u32::MAX)
+                .unwrap()
+                .unwrap(),
+            corrupted_blob
+        );
+        settings.set_value("blob-verify-on-read".to_string(), "true".to_string());
+    }
+}
+
+fn blob_path(store: &LocalBlobStore, blob_id: &BlobId) -> std::path::PathBuf {
+    let mut path = store.base_path.clone();
+    for byte in blob_id.hash().iter().take(store.hash_levels) {
+        path.push(format!("{:x}", byte));
+    }
+    path.push(blob_id.to_string());
+    path
+}
@@ -0,0 +1,161 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use store::{
+    core::{collection::Collection, document::Document, error::StoreError},
+    read::{
+        comparator::Comparator,
+        deadline::Deadline,
+        filter::{ComparisonOperator, Filter, Query},
+        FilterMapper,
+    },
+    write::{
+        batch::WriteBatch,
+        options::{IndexOptions, Options},
+    },
+    JMAPId, JMAPStore, Store,
+};
+
+const NUM_DOCUMENTS: u32 = 1000;
+
+pub fn test<T>(db: JMAPStore<T>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let account_id = 1;
+    let field = 0;
+
+    let mut batch = WriteBatch::new(account_id);
+    for value in 0..NUM_DOCUMENTS {
+        let mut document = Document::new(
+            Collection::Mail,
+            db.assign_document_id(account_id, Collection::Mail).unwrap(),
+        );
+        document.number(field, value, IndexOptions::new().store().index());
+        batch.insert_document(document);
+    }
+    db.write(batch).unwrap();
+
+    // Without a deadline the whole index is walked
+    assert_eq!(
+        db.query_store::<FilterMapper>(
+            account_id,
+            Collection::Mail,
+            Filter::None,
+            Comparator::ascending(field),
+        )
+        .unwrap()
+        .count(),
+        NUM_DOCUMENTS as usize
+    );
+
+    // Iterators that do not opt in are not truncated by an expired deadline
+    let deadline = Deadline::new(None);
+    let _guard = deadline.enter();
+    let results_it = db
+        .query_store::<FilterMapper>(
+            account_id,
+            Collection::Mail,
+            Filter::None,
+            Comparator::ascending(field),
+        )
+        .unwrap();
+    deadline.cancel();
+    assert_eq!(results_it.count(), NUM_DOCUMENTS as usize);
+    drop(_guard);
+
+    // Cancelling the request stops the iteration
+    let deadline = Deadline::new(None);
+    let _guard = deadline.enter();
+    let mut processed = 0;
+    let results = db
+        .query_store(
+            account_id,
+            Collection::Mail,
+            Filter::None,
+            Comparator::ascending(field),
+        )
+        .unwrap()
+        .with_deadline()
+        .set_filter_map(|document_id| {
+            processed += 1;
+            if processed == 10 {
+                deadline.cancel();
+            }
+            Ok(Some(document_id as JMAPId))
+        })
+        .count();
+    assert_eq!(results, 10);
+    assert_eq!(processed, 10);
+    assert!(matches!(Deadline::check(), Err(StoreError::Cancelled)));
+
+    // New queries are rejected once the deadline has passed
+    assert!(matches!(
+        db.query_store::<FilterMapper>(
+            account_id,
+            Collection::Mail,
+            Filter::new_condition(
+                field,
+                ComparisonOperator::GreaterThan,
+                Query::Integer(NUM_DOCUMENTS / 2),
+            ),
+            Comparator::ascending(field),
+        ),
+        Err(StoreError::Cancelled)
+    ));
+    drop(_guard);
+
+    // An expired timeout behaves like a cancellation
+    let deadline = Deadline::new(Some(Duration::ZERO));
+    let _guard = deadline.enter();
+    assert!(matches!(
+        db.query_store::<FilterMapper>(
+            account_id,
+            Collection::Mail,
+            Filter::None,
+            Comparator::ascending(field),
+        ),
+        Err(StoreError::Cancelled)
+    ));
+    drop(_guard);
+
+    // Leaving the deadline scope restores unrestricted reads
+    assert!(Deadline::check().is_ok());
+    assert_eq!(
+        db.query_store::<FilterMapper>(
+            account_id,
+            Collection::Mail,
+            Filter::new_condition(
+                field,
+                ComparisonOperator::GreaterEqualThan,
+                Query::Integer(NUM_DOCUMENTS / 2),
+            ),
+            Comparator::ascending(field),
+        )
+        .unwrap()
+        .count(),
+        (NUM_DOCUMENTS / 2) as usize
+    );
+}
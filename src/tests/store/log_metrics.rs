@@ -0,0 +1,68 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use store::{core::collection::Collection, write::batch::WriteBatch, JMAPStore, Store};
+
+pub fn test<T>(db: JMAPStore<T>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let account_id = 1;
+
+    // Write changes to two collections
+    for document_id in 0..10 {
+        let mut batch = WriteBatch::new(account_id);
+        batch.log_insert(Collection::Mail, document_id);
+        db.write(batch).unwrap();
+    }
+    for document_id in 0..5 {
+        let mut batch = WriteBatch::new(account_id);
+        batch.log_insert(Collection::Mailbox, document_id);
+        db.write(batch).unwrap();
+    }
+
+    let metrics = db.log_metrics().unwrap();
+    assert_eq!(metrics.changes_written.get(&Collection::Mail), Some(&10));
+    assert_eq!(metrics.changes_written.get(&Collection::Mailbox), Some(&5));
+    assert_eq!(metrics.changes_written.get(&Collection::Thread), None);
+    assert!(metrics.last_compaction.is_none());
+    let logs_size = metrics.logs_size;
+    assert!(logs_size > 0);
+
+    // Compact up to the last change, all Mail changes and all but one of the
+    // Mailbox changes and raft entries are removed
+    let last_change_id = db
+        .get_last_change_id(account_id, Collection::Mailbox)
+        .unwrap()
+        .unwrap();
+    db.compact_log_up_to(last_change_id).unwrap();
+
+    let metrics = db.log_metrics().unwrap();
+    let compaction = metrics.last_compaction.unwrap();
+    assert_eq!(compaction.up_to, last_change_id);
+    assert_eq!(compaction.entries_deleted, 10 + 4 + 14);
+    assert!(compaction.snapshot_size > 0);
+    assert!(metrics.logs_size < logs_size);
+
+    // Counters keep tracking entries written since startup
+    assert_eq!(metrics.changes_written.get(&Collection::Mail), Some(&10));
+}
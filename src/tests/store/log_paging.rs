@@ -0,0 +1,110 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use store::{
+    ahash::AHashSet,
+    core::collection::Collection,
+    log::changes::{Change, Query},
+    write::batch::WriteBatch,
+    AccountId, JMAPId, JMAPStore, Store,
+};
+
+pub fn test<T>(db: JMAPStore<T>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    // 50 changes logged one at a time are returned in five pages of 10
+    let account_id = 1;
+    for document_id in 0..50u64 {
+        let mut batch = WriteBatch::new(account_id);
+        batch.log_insert(Collection::Mail, document_id);
+        db.write(batch).unwrap();
+    }
+    let pages = read_pages(&db, account_id, 10);
+    assert_eq!(
+        pages.iter().map(|page| page.len()).collect::<Vec<_>>(),
+        vec![10; 5]
+    );
+    assert_eq!(
+        pages.into_iter().flatten().collect::<Vec<_>>(),
+        (0..50u64).collect::<Vec<_>>()
+    );
+
+    // Changes logged under the same change id are never split across pages
+    let account_id = 2;
+    for document_id in (0..51u64).step_by(3) {
+        let mut batch = WriteBatch::new(account_id);
+        for document_id in document_id..document_id + 3 {
+            batch.log_insert(Collection::Mail, document_id);
+        }
+        db.write(batch).unwrap();
+    }
+    let pages = read_pages(&db, account_id, 10);
+    assert_eq!(
+        pages.iter().map(|page| page.len()).collect::<Vec<_>>(),
+        vec![12, 12, 12, 12, 3]
+    );
+    assert_eq!(
+        pages.into_iter().flatten().collect::<AHashSet<_>>(),
+        (0..51u64).collect::<AHashSet<_>>()
+    );
+
+    // Without a limit everything is returned at once
+    let changes = db
+        .get_changes(account_id, Collection::Mail, Query::All, 0)
+        .unwrap()
+        .unwrap();
+    assert_eq!(changes.changes.len(), 51);
+    assert!(!changes.has_more_changes);
+}
+
+fn read_pages<T>(db: &JMAPStore<T>, account_id: AccountId, max_changes: usize) -> Vec<Vec<JMAPId>>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut pages = Vec::new();
+    let mut query = Query::All;
+
+    loop {
+        let changes = db
+            .get_changes(account_id, Collection::Mail, query, max_changes)
+            .unwrap()
+            .unwrap();
+        pages.push(
+            changes
+                .changes
+                .iter()
+                .map(|change| match change {
+                    Change::Insert(id) => *id,
+                    change => panic!("Unexpected change {:?}", change),
+                })
+                .collect(),
+        );
+
+        if changes.has_more_changes {
+            query = Query::Since(changes.to_change_id);
+        } else {
+            return pages;
+        }
+    }
+}
@@ -0,0 +1,77 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use store::{core::collection::Collection, write::batch::WriteBatch, JMAPStore, Store};
+
+pub fn test<T>(mut db: JMAPStore<T>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let account_id = 1;
+    db.config.log_compact_threshold = 20;
+    db.config.log_compact_interval = 3600;
+
+    // Nothing to do without changes
+    assert!(!db.compact_log_scheduled(5).unwrap());
+
+    // Below the threshold the changelog is left untouched
+    write_changes(&db, account_id, 0..10);
+    assert!(!db.compact_log_scheduled(5).unwrap());
+    let logs_size = db.log_metrics().unwrap().logs_size;
+    assert!(db.log_metrics().unwrap().last_compaction.is_none());
+
+    // Exceeding the threshold triggers a compaction that shrinks the changelog
+    write_changes(&db, account_id, 10..30);
+    let logs_size_before = db.log_metrics().unwrap().logs_size;
+    assert!(logs_size_before > logs_size);
+    assert!(db.compact_log_scheduled(5).unwrap());
+    let metrics = db.log_metrics().unwrap();
+    assert!(metrics.last_compaction.is_some());
+    assert!(
+        metrics.logs_size < logs_size_before,
+        "{} >= {}",
+        metrics.logs_size,
+        logs_size_before
+    );
+
+    // Accounts without further growth are skipped
+    assert!(!db.compact_log_scheduled(5).unwrap());
+    write_changes(&db, account_id, 30..35);
+    assert!(!db.compact_log_scheduled(5).unwrap());
+
+    // Once the interval elapses any growth is enough
+    db.config.log_compact_interval = 0;
+    assert!(db.compact_log_scheduled(5).unwrap());
+    assert!(!db.compact_log_scheduled(5).unwrap());
+}
+
+fn write_changes<T>(db: &JMAPStore<T>, account_id: u32, document_ids: std::ops::Range<u32>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    for document_id in document_ids {
+        let mut batch = WriteBatch::new(account_id);
+        batch.log_insert(Collection::Mail, document_id);
+        db.write(batch).unwrap();
+    }
+}
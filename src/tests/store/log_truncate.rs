@@ -0,0 +1,176 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    error::method::MethodError,
+    request::changes::{ChangesRequest, ChangesResponse},
+    types::{jmap::JMAPId, state::JMAPState},
+};
+use jmap_mail::mail::{changes::JMAPMailChanges, schema::Email};
+use store::{
+    core::{acl::ACLToken, collection::Collection},
+    log::changes::{Change, ChangeId, Query},
+    serialize::key::LogKey,
+    write::batch::WriteBatch,
+    AccountId, ColumnFamily, Direction, JMAPStore, Store,
+};
+
+pub fn test<T>(db: JMAPStore<T>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let account_id = 1;
+
+    // Write 1000 changes
+    let mut change_ids = Vec::with_capacity(1000);
+    for document_id in 0..1000 {
+        let mut batch = WriteBatch::new(account_id);
+        batch.log_insert(Collection::Mail, document_id);
+        db.write(batch).unwrap();
+        change_ids.push(
+            db.get_last_change_id(account_id, Collection::Mail)
+                .unwrap()
+                .unwrap(),
+        );
+    }
+    let cutoff = change_ids[900];
+
+    // Changes still referenced by the raft log are kept
+    db.truncate_changelog(account_id, Collection::Mail, cutoff)
+        .unwrap();
+    assert_eq!(count_changes(&db, account_id), 1000);
+    assert_eq!(
+        db.get_changes(account_id, Collection::Mail, Query::Since(change_ids[0]), 0)
+            .unwrap()
+            .unwrap()
+            .changes
+            .len(),
+        999
+    );
+
+    // Once the raft log no longer needs them, only the last 100 changes are kept
+    trim_raft_log(&db, cutoff);
+    db.truncate_changelog(account_id, Collection::Mail, cutoff)
+        .unwrap();
+    assert_eq!(count_changes(&db, account_id), 101);
+
+    // States older than the cutoff can no longer be synchronized
+    for change_id in [change_ids[0], change_ids[500], change_ids[898]] {
+        assert!(db
+            .get_changes(account_id, Collection::Mail, Query::Since(change_id), 0)
+            .unwrap()
+            .is_none());
+        assert!(matches!(
+            mail_changes(&db, account_id, JMAPState::Exact(change_id)),
+            Err(MethodError::CannotCalculateChanges)
+        ));
+    }
+
+    // The oldest retained state and newer ones still return their changes
+    for (pos, expected_changes) in [(899, 100), (950, 49), (999, 0)] {
+        let changes = db
+            .get_changes(
+                account_id,
+                Collection::Mail,
+                Query::Since(change_ids[pos]),
+                0,
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(changes.changes.len(), expected_changes);
+        assert_eq!(
+            mail_changes(&db, account_id, JMAPState::Exact(change_ids[pos]))
+                .unwrap()
+                .total_changes,
+            expected_changes
+        );
+    }
+
+    // Clients syncing from scratch still get every document
+    let changes = db
+        .get_changes(account_id, Collection::Mail, Query::All, 0)
+        .unwrap()
+        .unwrap();
+    assert_eq!(changes.changes.len(), 1000);
+    assert!(changes
+        .changes
+        .iter()
+        .all(|change| matches!(change, Change::Insert(_))));
+    assert_eq!(changes.to_change_id, change_ids[999]);
+}
+
+fn mail_changes<T>(
+    db: &JMAPStore<T>,
+    account_id: AccountId,
+    since_state: JMAPState,
+) -> jmap::Result<ChangesResponse<Email>>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    db.mail_changes(ChangesRequest {
+        acl: Some(Arc::new(ACLToken {
+            member_of: vec![account_id],
+            access_to: vec![],
+        })),
+        account_id: JMAPId::new(account_id as u64),
+        since_state,
+        max_changes: None,
+    })
+}
+
+fn count_changes<T>(db: &JMAPStore<T>, account_id: AccountId) -> usize
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let key = LogKey::serialize_change(account_id, Collection::Mail, 0);
+    let prefix = &key[0..LogKey::CHANGE_ID_POS];
+    db.db
+        .iterator(ColumnFamily::Logs, &key, Direction::Forward)
+        .unwrap()
+        .take_while(|(key, _)| key.starts_with(prefix))
+        .count()
+}
+
+fn trim_raft_log<T>(db: &JMAPStore<T>, before: ChangeId)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    for (key, _) in db
+        .db
+        .iterator(
+            ColumnFamily::Logs,
+            &[LogKey::RAFT_KEY_PREFIX],
+            Direction::Forward,
+        )
+        .unwrap()
+    {
+        if !key.starts_with(&[LogKey::RAFT_KEY_PREFIX])
+            || LogKey::deserialize_raft(&key).unwrap().index >= before
+        {
+            break;
+        }
+        db.db.delete(ColumnFamily::Logs, &key).unwrap();
+    }
+}
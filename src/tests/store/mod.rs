@@ -21,14 +21,27 @@
  * for more details.
 */
 
+pub mod blob_compression;
+pub mod blob_reader;
+pub mod blob_temp;
+pub mod blob_verify;
 pub mod blobs;
+pub mod deadline;
 pub mod log;
+pub mod log_metrics;
+pub mod log_paging;
+pub mod log_scheduler;
+pub mod log_truncate;
 pub mod query;
+pub mod state;
 pub mod utils;
 
 use std::{path::PathBuf, sync::Arc};
 
-use store::{config::jmap::JMAPConfig, JMAPStore, Store};
+use store::{
+    config::{env_settings::EnvSettings, jmap::JMAPConfig},
+    JMAPStore, Store,
+};
 use store_rocksdb::RocksDB;
 
 use self::utils::{destroy_temp_dir, init_settings};
@@ -51,13 +64,29 @@ where
 {
     let (settings, temp_dir) = init_settings(name, peer_num, total_peers, delete_if_exists);
 
-    (
-        JMAPStore::new(
-            T::open(&settings).unwrap(),
-            JMAPConfig::from(&settings),
-            &settings,
-        ),
-        temp_dir,
+    (open_db(&settings), temp_dir)
+}
+
+pub fn init_db_with_settings<T>(name: &str, values: &[(&str, &str)]) -> (JMAPStore<T>, PathBuf)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let (mut settings, temp_dir) = init_settings(name, 1, 1, true);
+    for (key, value) in values {
+        settings.set_value(key.to_string(), value.to_string());
+    }
+
+    (open_db(&settings), temp_dir)
+}
+
+pub fn open_db<T>(settings: &EnvSettings) -> JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    JMAPStore::new(
+        T::open(settings).unwrap(),
+        JMAPConfig::from(settings),
+        settings,
     )
 }
 
@@ -73,3 +102,105 @@ fn store_tests() {
 
     destroy_temp_dir(&temp_dir);
 }
+
+#[test]
+#[ignore]
+fn store_state_tests() {
+    let (leader, leader_temp_dir) = init_db::<RocksDB>("strdb_state_leader", true);
+    let (follower, follower_temp_dir) = init_db::<RocksDB>("strdb_state_follower", true);
+
+    state::test(leader, follower);
+
+    destroy_temp_dir(&leader_temp_dir);
+    destroy_temp_dir(&follower_temp_dir);
+}
+
+#[test]
+#[ignore]
+fn store_log_metrics_tests() {
+    let (db, temp_dir) = init_db::<RocksDB>("strdb_log_metrics", true);
+
+    log_metrics::test(db);
+
+    destroy_temp_dir(&temp_dir);
+}
+
+#[test]
+#[ignore]
+fn store_log_paging_tests() {
+    let (db, temp_dir) = init_db::<RocksDB>("strdb_log_paging", true);
+
+    log_paging::test(db);
+
+    destroy_temp_dir(&temp_dir);
+}
+
+#[test]
+#[ignore]
+fn store_log_scheduler_tests() {
+    let (db, temp_dir) = init_db::<RocksDB>("strdb_log_scheduler", true);
+
+    log_scheduler::test(db);
+
+    destroy_temp_dir(&temp_dir);
+}
+
+#[test]
+#[ignore]
+fn store_log_truncate_tests() {
+    let (db, temp_dir) = init_db::<RocksDB>("strdb_log_truncate", true);
+
+    log_truncate::test(db);
+
+    destroy_temp_dir(&temp_dir);
+}
+
+#[test]
+#[ignore]
+fn store_deadline_tests() {
+    let (db, temp_dir) = init_db::<RocksDB>("strdb_deadline", true);
+
+    deadline::test(db);
+
+    destroy_temp_dir(&temp_dir);
+}
+
+#[test]
+#[ignore]
+fn store_blob_compression_tests() {
+    let (settings, temp_dir) = init_settings("strdb_blob_compression", 1, 1, true);
+
+    blob_compression::test(settings);
+
+    destroy_temp_dir(&temp_dir);
+}
+
+#[test]
+#[ignore]
+fn store_blob_verify_tests() {
+    let (settings, temp_dir) = init_settings("strdb_blob_verify", 1, 1, true);
+
+    blob_verify::test(settings);
+
+    destroy_temp_dir(&temp_dir);
+}
+
+#[test]
+#[ignore]
+fn store_blob_temp_tests() {
+    let (settings, temp_dir) = init_settings("strdb_blob_temp", 1, 1, true);
+
+    blob_temp::test(settings);
+
+    destroy_temp_dir(&temp_dir);
+}
+
+#[test]
+#[ignore]
+fn store_blob_reader_tests() {
+    let (settings, temp_dir) = init_settings("strdb_blob_reader", 1, 1, true);
+
+    blob_reader::test(settings);
+
+    destroy_temp_dir(&temp_dir);
+}
@@ -210,7 +210,10 @@ where
     test_filter(db.clone());
 
     println!("Running sort tests...");
-    test_sort(db);
+    test_sort(db.clone());
+
+    println!("Running query stats tests...");
+    test_stats(db);
 }
 
 pub fn test_filter<T>(db: Arc<JMAPStore<T>>)
@@ -470,6 +473,57 @@ where
     }
 }
 
+pub fn test_stats<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut fields = AHashMap::default();
+    for (field_num, field) in FIELDS.iter().enumerate() {
+        fields.insert(field.to_string(), field_num as u8);
+    }
+
+    let conditions = |num_conditions: usize| {
+        Filter::and(
+            vec![
+                Filter::gt(fields["year"], Query::Integer(2000)),
+                Filter::lt(fields["width"], Query::Integer(180)),
+                Filter::gt(fields["width"], Query::Integer(0)),
+            ]
+            .into_iter()
+            .take(num_conditions)
+            .collect(),
+        )
+    };
+
+    // Expected candidate set sizes after applying each condition
+    let mut expected_counts = Vec::with_capacity(3);
+    for num_conditions in 1..=3 {
+        expected_counts.push(
+            db.query_store::<FilterMapper>(
+                0,
+                Collection::Mail,
+                conditions(num_conditions),
+                Comparator::None,
+            )
+            .unwrap()
+            .len() as u64,
+        );
+    }
+    assert!(expected_counts[0] > expected_counts[2]);
+
+    let results = db
+        .query_store::<FilterMapper>(
+            0,
+            Collection::Mail,
+            conditions(3),
+            Comparator::ascending(fields["accession_number"]),
+        )
+        .unwrap();
+    let stats = results.stats.as_ref().unwrap();
+    assert_eq!(stats.conditions, expected_counts);
+    assert_eq!(results.len() as u64, expected_counts[2]);
+}
+
 pub fn test_sort<T>(db: Arc<JMAPStore<T>>)
 where
     T: for<'x> Store<'x> + 'static,
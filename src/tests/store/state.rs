@@ -0,0 +1,94 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use jmap::{jmap_store::changes::JMAPChanges, types::state::JMAPState};
+use store::{
+    core::collection::Collection,
+    serialize::key::LogKey,
+    write::{batch::WriteBatch, operation::WriteOperation},
+    AccountId, ColumnFamily, Direction, JMAPStore, Store,
+};
+
+pub fn test<T>(leader: JMAPStore<T>, follower: JMAPStore<T>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let account_id: AccountId = 1;
+
+    // Both stores start without changes
+    for store in [&leader, &follower] {
+        assert_eq!(
+            store.get_state(account_id, Collection::Mail).unwrap(),
+            JMAPState::Initial
+        );
+    }
+
+    // Advance the follower's local raft index so it no longer matches the leader
+    for document_id in 0..5 {
+        let mut batch = WriteBatch::new(account_id + 1);
+        batch.log_insert(Collection::Mailbox, document_id);
+        follower.write(batch).unwrap();
+    }
+
+    for run in 0..3 {
+        // Commit changes on the leader
+        for document_id in 0..3 {
+            let mut batch = WriteBatch::new(account_id);
+            batch.log_insert(Collection::Mail, run * 3 + document_id);
+            leader.write(batch).unwrap();
+        }
+
+        // Replicate the leader's changelog entries to the follower
+        let prefix = LogKey::serialize_change(account_id, Collection::Mail, 0);
+        let prefix = &prefix[..LogKey::CHANGE_ID_POS];
+        let mut ops = Vec::new();
+        for (key, value) in leader
+            .db
+            .iterator(ColumnFamily::Logs, prefix, Direction::Forward)
+            .unwrap()
+        {
+            if !key.starts_with(prefix) {
+                break;
+            }
+            ops.push(WriteOperation::set(
+                ColumnFamily::Logs,
+                key.to_vec(),
+                value.to_vec(),
+            ));
+        }
+        follower.db.write(ops).unwrap();
+
+        // Both stores have to produce the same state string
+        let leader_state = leader.get_state(account_id, Collection::Mail).unwrap();
+        let follower_state = follower.get_state(account_id, Collection::Mail).unwrap();
+        let change_id = leader
+            .get_last_change_id(account_id, Collection::Mail)
+            .unwrap()
+            .unwrap();
+        assert_eq!(leader_state, JMAPState::Exact(change_id));
+        assert_eq!(leader_state.to_string(), follower_state.to_string());
+        assert_eq!(
+            JMAPState::parse(&follower_state.to_string()).unwrap(),
+            leader_state
+        );
+    }
+}
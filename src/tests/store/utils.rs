@@ -36,7 +36,10 @@ use jmap_mail::vacation_response::schema::VacationResponse;
 use store::ahash::AHashSet;
 use store::serialize::key::ValueKey;
 use store::serialize::leb128::Leb128Reader;
-use store::{ahash::AHashMap, blob::BLOB_HASH_LEN};
+use store::{
+    ahash::AHashMap,
+    blob::{BlobId, BLOB_HASH_LEN},
+};
 use store::{
     config::env_settings::EnvSettings,
     core::collection::Collection,
@@ -177,7 +180,11 @@ pub fn init_settings(
             ),
             ("lmtp-port".to_string(), (11200 + peer_num).to_string()),
             ("max-objects-in-set".to_string(), "100000".to_string()),
+            ("mail-normalize-sender".to_string(), "false".to_string()),
+            ("mail-strict-part-types".to_string(), "true".to_string()),
             ("query-max-results".to_string(), "100000".to_string()),
+            ("query-stats".to_string(), "true".to_string()),
+            ("raft-linearizable-reads".to_string(), "true".to_string()),
             ("jmap-port".to_string(), (8000 + peer_num).to_string()),
             ("smtp-relay-host".to_string(), "127.0.0.1".to_string()),
             ("smtp-relay-port".to_string(), "9999".to_string()),
@@ -603,6 +610,19 @@ where
                             } else {
                                 println!("Missing Blob key: [{:?}]", key);
                             }
+                        } else if key.len() == BLOB_HASH_LEN + 1
+                            && other.db.exists(cf, &key).unwrap()
+                        {
+                            // Compare logical contents rather than their on-disk representation
+                            let blob_id = BlobId::deserialize(&key).unwrap();
+                            let contents = self.blob_get_range(&blob_id, 0..u32::MAX).unwrap();
+                            let other_contents =
+                                other.blob_get_range(&blob_id, 0..u32::MAX).unwrap();
+                            if ASSERT {
+                                assert_eq!(contents, other_contents, "{:?} {:?}", cf, blob_id);
+                            } else if contents != other_contents {
+                                println!("Blob contents mismatch: {:?}", blob_id);
+                            }
                         }
                     }
                 }
@@ -617,6 +637,7 @@ where
             ColumnFamily::Bitmaps,
             ColumnFamily::Values,
             ColumnFamily::Indexes,
+            ColumnFamily::Blobs,
         ] {
             let mut total_keys = 0;
             for (key, value) in self
@@ -635,6 +656,13 @@ where
                             value
                         );
                     }
+                    ColumnFamily::Values
+                        if key.len() == 3
+                            && key[1] == Collection::None as u8
+                            && i64::deserialize(&value) == Some(0) =>
+                    {
+                        // Usage counters are back to zero once all blobs are unlinked
+                    }
                     ColumnFamily::Values if (0..=9).contains(&key[0]) => {
                         panic!("{:?} {:?}={:?}", cf, key, value);
                     }
@@ -648,9 +676,13 @@ where
                         );
                     }
                     ColumnFamily::Blobs => {
-                        if key.len()
-                            > BLOB_HASH_LEN
-                                + (&key[BLOB_HASH_LEN..]).read_leb128::<u32>().unwrap().1
+                        // Ephemeral links expire on their own, but every document
+                        // link has to be gone once its owner is deleted.
+                        if key.len() > BLOB_HASH_LEN + 1
+                            && key.len()
+                                > BLOB_HASH_LEN
+                                    + (&key[BLOB_HASH_LEN + 1..]).read_leb128::<u32>().unwrap().1
+                                    + 1
                         {
                             panic!("{:?} {:?}={:?}", cf, key, value);
                         }